@@ -0,0 +1,43 @@
+//! Compare the throughput and latency of the crate's backends on the native file system.
+//!
+//! Run with `cargo bench`. The wasm variant, which exercises real OPFS, lives in `examples/bench`. The `+copy` runs
+//! stage every read and write through a buffer of their own, as a baseline for the backend's zero-copy I/O.
+
+mod workloads;
+
+use pollster::block_on;
+use redb_opfs::{MemoryBackend, OpfsBackend};
+use workloads::Result;
+
+fn main() -> Result<()> {
+    let path = std::env::temp_dir().join("redb-opfs-bench.redb");
+    let path = path.to_str().ok_or("temp dir is not valid UTF-8")?;
+
+    let mut reports = Vec::new();
+    reports.extend(block_on(workloads::run_all("memory", async || {
+        Ok(MemoryBackend::new())
+    }))?);
+    reports.extend(block_on(workloads::run_all("native", async || {
+        Ok(OpfsBackend::reset(path).await?)
+    }))?);
+    reports.extend(block_on(workloads::run_all("native+copy", async || {
+        Ok(workloads::Copying(OpfsBackend::reset(path).await?))
+    }))?);
+    reports.extend(block_on(workloads::run_all("native+cache", async || {
+        let builder = OpfsBackend::builder().create(true).truncate(true);
+        Ok(builder.cache_pages(4096).open(path).await?)
+    }))?);
+    reports.extend(block_on(workloads::run_all(
+        "native+write-buffer",
+        async || {
+            let builder = OpfsBackend::builder().create(true).truncate(true);
+            Ok(builder.write_buffer(4 << 20).open(path).await?)
+        },
+    ))?);
+    let _ = std::fs::remove_file(path);
+
+    for report in reports {
+        println!("{report}");
+    }
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//! Workloads shared by the native benchmark harness and the wasm one in `examples/bench`.
+//!
+//! Each workload runs against a freshly opened backend and produces a [`Report`].
+
+use std::{error::Error, fmt, time::Duration};
+
+use redb::{Database, ReadableDatabase as _, StorageBackend, TableDefinition};
+
+pub type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+const BLOCK_SIZE: usize = 4096;
+const FILE_SIZE: u64 = 16 << 20;
+const RANDOM_OPS: u64 = 4096;
+const COMMITS: u64 = 200;
+const LARGE_VALUE_SIZE: usize = 1 << 20;
+const LARGE_VALUES: u64 = 16;
+
+const TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("bench");
+
+/// The outcome of running one workload against one backend.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub backend: String,
+    pub workload: &'static str,
+    pub ops: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn micros_per_op(&self) -> f64 {
+        self.elapsed.as_secs_f64() * 1e6 / self.ops as f64
+    }
+
+    pub fn mib_per_sec(&self) -> f64 {
+        self.bytes as f64 / (1 << 20) as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<20} {:<18} {:>8} ops {:>12.1} µs/op {:>10.1} MiB/s",
+            self.backend,
+            self.workload,
+            self.ops,
+            self.micros_per_op(),
+            self.mib_per_sec(),
+        )
+    }
+}
+
+/// Run every workload, opening a fresh, empty backend for each with `open`.
+pub async fn run_all<B: StorageBackend + 'static>(
+    backend: &str,
+    mut open: impl AsyncFnMut() -> Result<B>,
+) -> Result<Vec<Report>> {
+    let mut reports = Vec::new();
+    let mut report = |workload, ops, bytes, elapsed| {
+        reports.push(Report {
+            backend: backend.to_owned(),
+            workload,
+            ops,
+            bytes,
+            elapsed,
+        })
+    };
+
+    let storage = open().await?;
+    let elapsed = sequential_write(&storage)?;
+    report(
+        "sequential write",
+        FILE_SIZE / BLOCK_SIZE as u64,
+        FILE_SIZE,
+        elapsed,
+    );
+    let elapsed = sequential_read(&storage)?;
+    report(
+        "sequential read",
+        FILE_SIZE / BLOCK_SIZE as u64,
+        FILE_SIZE,
+        elapsed,
+    );
+    let elapsed = random_write(&storage)?;
+    report(
+        "random write",
+        RANDOM_OPS,
+        RANDOM_OPS * BLOCK_SIZE as u64,
+        elapsed,
+    );
+    let elapsed = random_read(&storage)?;
+    report(
+        "random read",
+        RANDOM_OPS,
+        RANDOM_OPS * BLOCK_SIZE as u64,
+        elapsed,
+    );
+    drop(storage);
+
+    let elapsed = commit_latency(open().await?)?;
+    report("commit", COMMITS, COMMITS * 8, elapsed);
+    let elapsed = large_values(open().await?)?;
+    let bytes = LARGE_VALUES * LARGE_VALUE_SIZE as u64 * 2;
+    report("large values", LARGE_VALUES * 2, bytes, elapsed);
+
+    Ok(reports)
+}
+
+/// Fill the file block by block, then sync.
+fn sequential_write(backend: &impl StorageBackend) -> Result<Duration> {
+    let block = vec![0xa5; BLOCK_SIZE];
+    let stopwatch = Stopwatch::start();
+    for offset in (0..FILE_SIZE).step_by(BLOCK_SIZE) {
+        backend.write(offset, &block)?;
+    }
+    backend.sync_data()?;
+    Ok(stopwatch.elapsed())
+}
+
+/// Read the whole file block by block.
+fn sequential_read(backend: &impl StorageBackend) -> Result<Duration> {
+    let mut block = vec![0; BLOCK_SIZE];
+    let stopwatch = Stopwatch::start();
+    for offset in (0..FILE_SIZE).step_by(BLOCK_SIZE) {
+        backend.read(offset, &mut block)?;
+    }
+    Ok(stopwatch.elapsed())
+}
+
+/// Overwrite randomly chosen blocks, then sync.
+fn random_write(backend: &impl StorageBackend) -> Result<Duration> {
+    let block = vec![0x5a; BLOCK_SIZE];
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let stopwatch = Stopwatch::start();
+    for _ in 0..RANDOM_OPS {
+        backend.write(rng.block_offset(), &block)?;
+    }
+    backend.sync_data()?;
+    Ok(stopwatch.elapsed())
+}
+
+/// Read randomly chosen blocks.
+fn random_read(backend: &impl StorageBackend) -> Result<Duration> {
+    let mut block = vec![0; BLOCK_SIZE];
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let stopwatch = Stopwatch::start();
+    for _ in 0..RANDOM_OPS {
+        backend.read(rng.block_offset(), &mut block)?;
+    }
+    Ok(stopwatch.elapsed())
+}
+
+/// Commit many small, durable transactions.
+fn commit_latency(backend: impl StorageBackend + 'static) -> Result<Duration> {
+    let database = Database::builder().create_with_backend(backend)?;
+    let stopwatch = Stopwatch::start();
+    for key in 0..COMMITS {
+        let tx = database.begin_write()?;
+        tx.open_table(TABLE)?
+            .insert(key, key.to_le_bytes().as_slice())?;
+        tx.commit()?;
+    }
+    Ok(stopwatch.elapsed())
+}
+
+/// Write large values, one per transaction, then read them all back.
+fn large_values(backend: impl StorageBackend + 'static) -> Result<Duration> {
+    let database = Database::builder().create_with_backend(backend)?;
+    let value = vec![0x3c; LARGE_VALUE_SIZE];
+    let stopwatch = Stopwatch::start();
+    for key in 0..LARGE_VALUES {
+        let tx = database.begin_write()?;
+        tx.open_table(TABLE)?.insert(key, value.as_slice())?;
+        tx.commit()?;
+    }
+    let tx = database.begin_read()?;
+    let table = tx.open_table(TABLE)?;
+    for key in 0..LARGE_VALUES {
+        let read = table.get(key)?.ok_or("value went missing")?;
+        assert_eq!(read.value().len(), LARGE_VALUE_SIZE);
+    }
+    Ok(stopwatch.elapsed())
+}
+
+/// A baseline which moves every read and write through a buffer of its own, as a binding which doesn't pass slices
+/// straight to the file would.
+///
+/// On wasm, the buffer is a JS `Uint8Array`, like the one such a binding would allocate for OPFS; natively, a `Vec`.
+/// Comparing it against the backend it wraps shows what the backend's zero-copy reads and writes save.
+#[derive(Debug)]
+pub struct Copying<B>(pub B);
+
+impl<B: StorageBackend> StorageBackend for Copying<B> {
+    fn len(&self) -> std::io::Result<u64> {
+        self.0.len()
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> std::io::Result<()> {
+        let mut staged = vec![0; out.len()];
+        self.0.read(offset, &mut staged)?;
+        copy(&staged, out);
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> std::io::Result<()> {
+        self.0.set_len(len)
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        self.0.sync_data()
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> std::io::Result<()> {
+        let mut staged = vec![0; data.len()];
+        copy(data, &mut staged);
+        self.0.write(offset, &staged)
+    }
+}
+
+/// Copy `from` into `to`, through a JS buffer on wasm.
+#[cfg(target_family = "wasm")]
+fn copy(from: &[u8], to: &mut [u8]) {
+    js_sys::Uint8Array::from(from).copy_to(to);
+}
+
+/// Copy `from` into `to`.
+#[cfg(not(target_family = "wasm"))]
+fn copy(from: &[u8], to: &mut [u8]) {
+    to.copy_from_slice(std::hint::black_box(from));
+}
+
+/// Deterministic offsets, so that every backend sees the same access pattern.
+struct XorShift(u64);
+
+impl XorShift {
+    fn block_offset(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % (FILE_SIZE / BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+    }
+}
+
+/// `std::time::Instant` panics on `wasm32-unknown-unknown`, so the browser's `performance.now()` is used there.
+struct Stopwatch(
+    #[cfg(target_family = "wasm")] f64,
+    #[cfg(not(target_family = "wasm"))] std::time::Instant,
+);
+
+impl Stopwatch {
+    #[cfg(not(target_family = "wasm"))]
+    fn start() -> Self {
+        Self(std::time::Instant::now())
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+
+    #[cfg(target_family = "wasm")]
+    fn start() -> Self {
+        Self(now_millis())
+    }
+
+    #[cfg(target_family = "wasm")]
+    fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64((now_millis() - self.0).max(0.0) / 1000.0)
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn now_millis() -> f64 {
+    use js_sys::{Function, Reflect};
+    use wasm_bindgen::JsCast as _;
+
+    let performance =
+        Reflect::get(&js_sys::global(), &"performance".into()).expect("performance is available");
+    let now: Function = Reflect::get(&performance, &"now".into())
+        .expect("performance.now is available")
+        .unchecked_into();
+    now.call0(&performance)
+        .ok()
+        .and_then(|now| now.as_f64())
+        .expect("performance.now returns a number")
+}
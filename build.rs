@@ -0,0 +1,12 @@
+//! Defines `cfg(opfs)` for targets which run in a JS host with OPFS.
+//!
+//! `wasm32-wasip1` is a wasm target too, but has no JS host; it uses `std::fs` like native targets.
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(opfs)");
+    let family = std::env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    let os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if family.split(',').any(|family| family == "wasm") && os != "wasi" {
+        println!("cargo::rustc-cfg=opfs");
+    }
+}
@@ -0,0 +1,57 @@
+//! Runs the crate's benchmark workloads against real OPFS, in a web worker.
+//!
+//! The workloads are shared with the native harness in `benches/`.
+
+#[path = "../../../benches/workloads/mod.rs"]
+mod workloads;
+
+use redb_opfs::{MemoryBackend, OpfsBackend, OpfsBackendBuilder};
+use wasm_bindgen::prelude::*;
+
+const PATH: &str = "redb-opfs-bench.redb";
+
+/// Run every workload against every backend, returning one formatted line per result.
+#[wasm_bindgen(js_name = runBenchmarks)]
+pub async fn run_benchmarks() -> Result<Vec<String>, JsValue> {
+    run()
+        .await
+        .map_err(|err| js_sys::Error::new(&err.to_string()).into())
+}
+
+async fn run() -> workloads::Result<Vec<String>> {
+    let mut reports = Vec::new();
+    reports.extend(workloads::run_all("memory", async || Ok(MemoryBackend::new())).await?);
+    reports.extend(workloads::run_all("opfs", async || Ok(builder().open(PATH).await?)).await?);
+    reports.extend(
+        workloads::run_all("opfs+copy", async || {
+            Ok(workloads::Copying(builder().open(PATH).await?))
+        })
+        .await?,
+    );
+    reports.extend(
+        workloads::run_all("opfs+cache", async || {
+            Ok(builder().cache_pages(4096).open(PATH).await?)
+        })
+        .await?,
+    );
+    reports.extend(
+        workloads::run_all("opfs+write-buffer", async || {
+            Ok(builder().write_buffer(4 << 20).open(PATH).await?)
+        })
+        .await?,
+    );
+    OpfsBackend::delete(PATH).await?;
+
+    Ok(reports.iter().map(ToString::to_string).collect())
+}
+
+/// Open an empty file for each workload.
+///
+/// Web Locks are released asynchronously once the previous backend is dropped, so they are disabled here rather than
+/// racing the next open.
+fn builder() -> OpfsBackendBuilder {
+    OpfsBackend::builder()
+        .create(true)
+        .truncate(true)
+        .lock(false)
+}
@@ -0,0 +1,13 @@
+declare var self: Worker;
+
+import init, { runBenchmarks } from '../../../ts/gen/bench';
+
+await init();
+
+try {
+    const lines = await runBenchmarks();
+    self.postMessage({ type: 'done', lines });
+} catch (err) {
+    console.error("Benchmark error:", err);
+    self.postMessage({ type: 'error', msg: String(err) });
+}
@@ -0,0 +1,149 @@
+//! Inspect database files exported from OPFS, natively.
+//!
+//! Support engineers receive databases which users exported from their browsers, as by `OpfsBackend::export`. This
+//! prints what they contain, checks them, and writes repaired copies, without ever modifying the file given to it:
+//!
+//! ```text
+//! redb-opfs info <file>            print the header, the tables and their entry counts, and page statistics
+//! redb-opfs verify <file>          run redb's integrity check; exits with 1 if the database is damaged
+//! redb-opfs export <file> <dest>   write a repaired and compacted copy to <dest>, which must not exist
+//! ```
+
+use std::{
+    error::Error,
+    io::{self, ErrorKind},
+    process::ExitCode,
+};
+
+use pollster::block_on;
+
+use redb::{
+    Database, MultimapTableHandle as _, ReadableDatabase as _, StorageBackend as _,
+    TableHandle as _,
+};
+use redb_opfs::{MemoryBackend, OpfsBackend, VerifyReport};
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+const USAGE: &str = "\
+usage:
+  redb-opfs info <file>
+  redb-opfs verify <file>
+  redb-opfs export <file> <dest>";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let result = match args.as_slice() {
+        ["info", file] => info(file),
+        ["verify", file] => verify(file),
+        ["export", file, dest] => export(file, dest),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn info(file: &str) -> Result<ExitCode> {
+    let header = block_on(redb_opfs::verify(file, false))?;
+    print_header(&header);
+    if !header.is_redb() {
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let database = open_in_memory(file)?;
+    let tx = database.begin_read()?;
+    println!("tables:");
+    for handle in tx.list_tables()? {
+        let name = handle.name().to_owned();
+        let entries = tx.open_untyped_table(handle)?.len()?;
+        println!("  {name}: {entries} entries");
+    }
+    for handle in tx.list_multimap_tables()? {
+        let name = handle.name().to_owned();
+        let entries = tx.open_untyped_multimap_table(handle)?.len()?;
+        println!("  {name} (multimap): {entries} entries");
+    }
+    drop(tx);
+
+    let stats = database.begin_write()?.stats()?;
+    println!("tree height: {}", stats.tree_height());
+    println!("allocated pages: {}", stats.allocated_pages());
+    println!("leaf pages: {}", stats.leaf_pages());
+    println!("branch pages: {}", stats.branch_pages());
+    println!("stored bytes: {}", stats.stored_bytes());
+    println!("metadata bytes: {}", stats.metadata_bytes());
+    println!("fragmented bytes: {}", stats.fragmented_bytes());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(file: &str) -> Result<ExitCode> {
+    let header = block_on(redb_opfs::verify(file, false))?;
+    print_header(&header);
+    if !header.is_redb() || header.page_size().is_none() {
+        println!("integrity: not checked, the header is invalid");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    // redb repairs what it can while checking, so the check runs against a copy
+    let intact = open_in_memory(file).and_then(|mut database| Ok(database.check_integrity()?));
+    match intact {
+        Ok(true) => {
+            println!("integrity: ok");
+            Ok(ExitCode::SUCCESS)
+        }
+        Ok(false) => {
+            println!("integrity: corrupted");
+            Ok(ExitCode::FAILURE)
+        }
+        Err(err) => {
+            println!("integrity: failed to open: {err}");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn export(file: &str, dest: &str) -> Result<ExitCode> {
+    if std::fs::exists(dest)? {
+        return Err(
+            io::Error::new(ErrorKind::AlreadyExists, format!("{dest} already exists")).into(),
+        );
+    }
+    block_on(OpfsBackend::copy(file, dest))?;
+    let backend = block_on(OpfsBackend::builder().create(false).open(dest))?;
+    let mut database = Database::builder().create_with_backend(backend)?;
+    if !database.check_integrity()? {
+        eprintln!("warning: {file} is corrupted; the copy holds what could be repaired");
+    }
+    while database.compact()? {}
+    drop(database);
+    println!("exported {file} to {dest}");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_header(header: &VerifyReport) {
+    println!("size: {} bytes", header.len());
+    println!(
+        "redb header: {}",
+        if header.is_redb() { "valid" } else { "missing" }
+    );
+    if let Some(page_size) = header.page_size() {
+        println!("page size: {page_size} bytes");
+    }
+    println!("recovery required: {}", header.recovery_required());
+}
+
+/// Open a copy of the database at `file` in memory, so that redb's repairs leave the file alone.
+fn open_in_memory(file: &str) -> Result<Database> {
+    let backend = MemoryBackend::new();
+    backend.write(0, &std::fs::read(file)?)?;
+    Ok(Database::builder().create_with_backend(backend)?)
+}
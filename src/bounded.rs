@@ -0,0 +1,98 @@
+//! A [`StorageBackend`] wrapper which caps the size of the database.
+//!
+//! Browsers only report running out of quota once a write fails, which may be halfway through a commit. Capping the
+//! database below the quota lets applications enforce a storage budget, such as one per account, and fail predictably
+//! instead.
+
+use std::{
+    io::{self, ErrorKind},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{IoResult, StorageBackend};
+
+/// Implementation of a [`StorageBackend`] which rejects growing an inner backend beyond a maximum length.
+///
+/// Writes and truncations which would take the database beyond the maximum fail with [`ErrorKind::StorageFull`],
+/// before reaching the inner backend. On wasm, this becomes [`Error::StorageFull`][crate::Error::StorageFull], which
+/// is thrown to JS as a `StorageFullError`. Shrinking, and writes within the current length, always go through, so a
+/// database which is already too large can still be cleaned up.
+#[derive(Debug)]
+pub struct BoundedBackend<B> {
+    inner: B,
+    max_len: AtomicU64,
+}
+
+impl<B: StorageBackend> BoundedBackend<B> {
+    /// Wrap `inner`, capping its length at `max_len` bytes.
+    pub fn new(inner: B, max_len: u64) -> Self {
+        Self {
+            inner,
+            max_len: AtomicU64::new(max_len),
+        }
+    }
+
+    /// The maximum length of the database, in bytes.
+    pub fn max_len(&self) -> u64 {
+        self.max_len.load(Ordering::Relaxed)
+    }
+
+    /// Change the maximum length of the database, for example when the user's storage plan changes.
+    ///
+    /// Lowering it below the current length doesn't truncate anything, but prevents further growth.
+    pub fn set_max_len(&self, max_len: u64) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+    }
+
+    /// Get a reference to the inner backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwrap this backend, returning the inner backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Fail if growing the database to `len` bytes would exceed the maximum.
+    fn check(&self, len: u64) -> IoResult<()> {
+        let max_len = self.max_len();
+        if len > max_len && len > self.inner.len()? {
+            return Err(io::Error::new(
+                ErrorKind::StorageFull,
+                format!("requested size {len} exceeds the maximum of {max_len} bytes"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for BoundedBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        self.inner.len()
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.check(len)?;
+        self.inner.set_len(len)
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        self.inner.sync_data()
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        self.inner.read(offset, out)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            )
+        })?;
+        self.check(end)?;
+        self.inner.write(offset, data)
+    }
+}
@@ -0,0 +1,886 @@
+//! Configurable construction of an [`OpfsBackend`].
+
+use std::{
+    io::{self, ErrorKind},
+    path::Path,
+    time::Duration,
+};
+
+use parking_lot::{Mutex, RwLock};
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    InsufficientQuota, MemoryBackend, OpfsBackend, Result,
+    busy::{self, OpenStep},
+    changes::{ChangeTracker, DirtyPages},
+    console_log::debug_log,
+    file::File,
+    file_abstraction::{FileAbstraction, OpenOptions},
+    lazy::{Acquired, Deferred},
+    page_cache::PageCache,
+    registry::Registration,
+    retry::{self, RetryPolicy},
+    sync_mode::{DEFAULT_FLUSH_INTERVAL_MS, FlushSchedule, SyncMode},
+    timestamp::Timestamp,
+    unique_name,
+    write_buffer::WriteBuffer,
+};
+#[cfg(opfs)]
+use crate::{
+    file::{path_of, virtualize_path},
+    notify::Notifier,
+    thread_bound::ThreadBound,
+    web_lock::WebLock,
+};
+#[cfg(opfs)]
+use web_sys::{FileSystemDirectoryHandle, FileSystemFileHandle};
+
+/// Builder for an [`OpfsBackend`] with configurable open options.
+///
+/// By default, the file is opened read+write, created if it does not exist, and not truncated.
+/// On wasm, a Web Lock is acquired for it without waiting. This matches [`OpfsBackend::new`].
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone)]
+pub struct OpfsBackendBuilder {
+    options: OpenOptions,
+    lock: bool,
+    lock_timeout_ms: Option<u32>,
+    busy_timeout_ms: Option<u32>,
+    cache_pages: u32,
+    read_ahead_pages: u32,
+    write_buffer_bytes: u32,
+    retry: RetryPolicy,
+    root_dir: Option<String>,
+    #[cfg(opfs)]
+    root_handle: Option<FileSystemDirectoryHandle>,
+    lazy: bool,
+    create_on_write: bool,
+    #[cfg_attr(not(opfs), allow(dead_code))]
+    notify_channel: Option<String>,
+    sync_mode: SyncMode,
+    flush_interval_ms: u32,
+    min_free_space: Option<u32>,
+    track_changes: bool,
+    dirty_page_size: Option<u32>,
+}
+
+impl Default for OpfsBackendBuilder {
+    fn default() -> Self {
+        Self {
+            options: OpenOptions::default(),
+            lock: true,
+            lock_timeout_ms: None,
+            busy_timeout_ms: None,
+            cache_pages: 0,
+            read_ahead_pages: 0,
+            write_buffer_bytes: 0,
+            retry: RetryPolicy::default(),
+            root_dir: None,
+            #[cfg(opfs)]
+            root_handle: None,
+            lazy: false,
+            create_on_write: false,
+            notify_channel: None,
+            sync_mode: SyncMode::Full,
+            flush_interval_ms: DEFAULT_FLUSH_INTERVAL_MS,
+            min_free_space: None,
+            track_changes: false,
+            dirty_page_size: None,
+        }
+    }
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl OpfsBackendBuilder {
+    /// Create the file if it does not already exist.
+    ///
+    /// Missing parent directories are created along with it.
+    ///
+    /// Default: `true`. Ignored when opening read-only.
+    pub fn create(mut self, create: bool) -> Self {
+        self.options.create = create;
+        self
+    }
+
+    /// Create a new file, failing with [`ErrorKind::AlreadyExists`] if it already exists.
+    ///
+    /// This distinguishes a fresh database from an accidentally reused one.
+    /// When set, [`create`][Self::create] is implied.
+    ///
+    /// Default: `false`. Incompatible with [`read_only`][Self::read_only].
+    #[cfg_attr(opfs, wasm_bindgen(js_name = createNew))]
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.options.create_new = create_new;
+        self
+    }
+
+    /// Truncate the file to 0 length when opening it.
+    ///
+    /// Default: `false`. Incompatible with [`read_only`][Self::read_only].
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.options.truncate = truncate;
+        self
+    }
+
+    /// Open the file without write access.
+    ///
+    /// Writes and truncation through the resulting backend fail with [`ErrorKind::PermissionDenied`].
+    /// On wasm, the sync access handle is requested in `read-only` mode where the browser supports it,
+    /// which allows several read-only handles to the same file to coexist.
+    ///
+    /// Default: `false`.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = readOnly))]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+
+    /// Acquire a [Web Lock] named after the file before opening it, and hold it for the lifetime of the backend.
+    ///
+    /// This gives deterministic single-writer semantics across tabs and workers: if another context holds the
+    /// lock, opening fails with [`ErrorKind::ResourceBusy`] (see [`lock_timeout`][Self::lock_timeout]) instead of
+    /// racing for the sync access handle. Read-only backends share the lock with each other.
+    /// Only contexts which also use this lock are excluded.
+    ///
+    /// Natively, this takes an advisory lock on the file itself instead (`flock` on Unix, `LockFileEx` on Windows),
+    /// shared for read-only backends and exclusive otherwise, so that two processes, or two backends in one process,
+    /// can't open the same database for writing and corrupt it. It is released when the backend is closed. File
+    /// systems which don't support locking, such as some network file systems, open the file unlocked.
+    ///
+    /// Default: `true`.
+    ///
+    /// [Web Lock]: https://developer.mozilla.org/en-US/docs/Web/API/Web_Locks_API
+    pub fn lock(mut self, lock: bool) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// How long to wait for the lock if it is held elsewhere, in milliseconds.
+    ///
+    /// Default: `None`, failing immediately. Has no effect if [`lock`][Self::lock] is disabled.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = lockTimeout))]
+    pub fn lock_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.lock_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// How long to wait for the file if another handle holds it, in milliseconds, checking every 50 ms.
+    ///
+    /// Without a [lock][Self::lock], or if the other context doesn't use it, the file is only found busy when its
+    /// sync access handle is created. Opening then fails with a [`FileBusy`][crate::FileBusy] error, unless this is
+    /// set and the handle frees up in time. Unlike [`retries`][Self::retries], this waits for a fixed time, however
+    /// many attempts that takes. Waiting doesn't block the executor, so other tasks on its thread carry on meanwhile.
+    ///
+    /// Default: `None`, failing immediately.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = busyTimeout))]
+    pub fn busy_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.busy_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Cache up to `pages` recently read pages of 4 KiB in memory.
+    ///
+    /// Every uncached read crosses into OPFS, so caching redb's hot B-tree pages cuts read latency considerably.
+    /// Pages are evicted least recently used first. Writes which go straight to the file invalidate the cached pages
+    /// they touch. With a [write buffer][Self::write_buffer], buffered writes are overlaid on the cached pages when
+    /// read, and invalidate the pages they touch once they are written out.
+    ///
+    /// Default: `0`, disabling the cache.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = cachePages))]
+    pub fn cache_pages(mut self, pages: u32) -> Self {
+        self.cache_pages = pages;
+        self
+    }
+
+    /// When reads look sequential, fetch up to `pages` pages following a missing page into the cache along with it.
+    ///
+    /// redb range scans over large tables read page after page, each an OPFS call of its own; prefetching turns
+    /// these into one call per `pages + 1` pages. A read counts as sequential if it starts on, or right after, the
+    /// last page touched by the previous read. Pages already cached are not fetched again, and at most all but one
+    /// of the cached pages are prefetched.
+    ///
+    /// Default: `0`, disabling read-ahead. Has no effect unless [`cache_pages`][Self::cache_pages] are enabled.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = readAhead))]
+    pub fn read_ahead(mut self, pages: u32) -> Self {
+        self.read_ahead_pages = pages;
+        self
+    }
+
+    /// Split reads and writes larger than `bytes` into several calls to the sync access handle, of at most `bytes`
+    /// each.
+    ///
+    /// Large values, such as attachments, make redb read and write hundreds of megabytes at once. Some browsers reject
+    /// a single operation of that size, or pause for garbage collection around it. `0` is treated as `1`.
+    ///
+    /// Default: 16 MiB. Has no effect natively.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = maxChunkSize))]
+    pub fn max_chunk_size(mut self, bytes: u32) -> Self {
+        #[cfg(opfs)]
+        {
+            self.options.max_chunk_size = bytes.max(1) as usize;
+        }
+        #[cfg(not(opfs))]
+        let _ = bytes;
+        self
+    }
+
+    /// Buffer up to `bytes` of writes in memory, and write them out together on `sync_data`.
+    ///
+    /// Writes which touch or overlap are coalesced, so a commit's many small writes become a few large ones.
+    /// Buffered writes are visible to reads immediately, and written out in offset order before the file is flushed,
+    /// which preserves redb's durability guarantees. If the buffer outgrows `bytes`, it is written out early.
+    /// Buffered writes are also written out when the backend is closed or dropped.
+    ///
+    /// Default: `0`, disabling the buffer.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = writeBuffer))]
+    pub fn write_buffer(mut self, bytes: u32) -> Self {
+        self.write_buffer_bytes = bytes;
+        self
+    }
+
+    /// Retry operations which fail transiently up to `retries` times.
+    ///
+    /// Right after another context releases the file, acquiring its sync access handle can briefly fail. With
+    /// retries enabled, opening retries such failures, and those to acquire the Web Lock, instead of failing outright.
+    /// Opening waits between attempts without blocking the executor. Natively, individual operations are also
+    /// retried if the file system reports it is busy; they block the calling thread in between, as operations are
+    /// synchronous, but release the backend's internal lock so that other threads carry on. On wasm, operations
+    /// are synchronous, so a busy handle can't be re-acquired until control returns to the event loop; they fail with
+    /// [`ErrorKind::ResourceBusy`] as before.
+    ///
+    /// Default: `0`, disabling retries.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// How long to wait before the first retry, in milliseconds, doubling for each retry after it.
+    ///
+    /// Default: `10`. Has no effect unless [`retries`][Self::retries] are enabled.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = retryBackoff))]
+    pub fn retry_backoff(mut self, backoff_ms: u32) -> Self {
+        self.retry.backoff_ms = backoff_ms;
+        self
+    }
+
+    /// Resolve the path passed to [`open`][Self::open] relative to the directory `dir`.
+    ///
+    /// This sandboxes the database inside an app-specific subtree: on wasm, `..` components in the path can't
+    /// escape `dir`. Natively, the path is simply joined onto `dir`.
+    ///
+    /// Default: `None`, resolving paths from the OPFS root on wasm, and from the current directory natively.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = rootDir))]
+    pub fn root_dir(mut self, dir: Option<String>) -> Self {
+        self.root_dir = dir;
+        self
+    }
+
+    /// Defer opening the file, and acquiring the Web Lock and the sync access handle, until the backend is first
+    /// used, or [`acquire`][OpfsBackend::acquire]d.
+    ///
+    /// This lets components construct backends early without taking the exclusive OPFS lock before they actually
+    /// need the file. Failures to open, such as a missing file or a lock held elsewhere, are then reported by the
+    /// first operation rather than by [`open`][Self::open].
+    ///
+    /// On wasm, opening is asynchronous, so the first operation only starts it, and operations fail with
+    /// [`ErrorKind::ResourceBusy`] until it completes. Await [`OpfsBackend::acquire`] before handing the backend to
+    /// redb, which does not retry. Natively, the first operation opens the file.
+    ///
+    /// Default: `false`. Has no effect on [`open_file_handle`][Self::open_file_handle].
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// If the file does not exist, only create it once it is first written to, or [`create`][OpfsBackend::create]d.
+    ///
+    /// Until then, the backend reads as an empty file, and syncing does nothing, so probing whether a database has
+    /// any content leaves nothing behind. Writing or setting the length creates the file, as does any operation which
+    /// needs the file itself, such as [`snapshot`][OpfsBackend::snapshot]. Like a [lazy][Self::lazy] backend, a
+    /// backend whose file is yet to be created acquires the Web Lock along with the file. On wasm, creating it is
+    /// asynchronous, so the first write only starts it, and fails with [`ErrorKind::ResourceBusy`]; await
+    /// [`OpfsBackend::create`] before handing the backend to redb to create a database.
+    ///
+    /// Default: `false`. Has no effect on lazy or read-only backends, nor on
+    /// [`open_file_handle`][Self::open_file_handle], or if [`create`][Self::create] is disabled.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = createOnWrite))]
+    pub fn create_on_write(mut self, create_on_write: bool) -> Self {
+        self.create_on_write = create_on_write;
+        self
+    }
+
+    /// How `sync_data`, which redb calls on every durable commit, persists changes; see [`SyncMode`].
+    ///
+    /// Default: [`SyncMode::Full`], flushing on every commit.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = syncMode))]
+    pub fn sync_mode(mut self, mode: SyncMode) -> Self {
+        self.sync_mode = mode;
+        self
+    }
+
+    /// How long [`SyncMode::Deferred`] waits after a flush before flushing again, in milliseconds.
+    ///
+    /// Default: 1000. Has no effect in other modes.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = flushInterval))]
+    pub fn flush_interval(mut self, interval_ms: u32) -> Self {
+        self.flush_interval_ms = interval_ms;
+        self
+    }
+
+    /// Before opening the file, check that at least `bytes` of storage are available, and fail with
+    /// [`InsufficientQuota`] otherwise.
+    ///
+    /// Browsers only report running out of quota once a write fails, which may be halfway through the first commit.
+    /// Checking up front lets applications tell the user to free up space before anything is written. On wasm,
+    /// availability is the origin's, as reported by [`OpfsBackend::quota`]; natively, it is that of the file system
+    /// the database is stored on. If the estimate isn't supported, as natively on platforms other than Unix, the check
+    /// is skipped. The estimate may be imprecise.
+    ///
+    /// Default: `None`, not checking. Has no effect when opening [read-only][Self::read_only].
+    #[cfg_attr(opfs, wasm_bindgen(js_name = minFreeSpace))]
+    pub fn min_free_space(mut self, bytes: Option<u32>) -> Self {
+        self.min_free_space = bytes;
+        self
+    }
+
+    /// Track which parts of the file are written, so that
+    /// [`export_incremental`][OpfsBackend::export_incremental] can export only what changed since an earlier export.
+    ///
+    /// Changes are tracked in memory, in 64 KiB chunks, from when the backend is opened; the first export after
+    /// opening is always a full one. Tracking costs a map lookup per write, and memory proportional to the number of
+    /// chunks written.
+    ///
+    /// Default: `false`.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = trackChanges))]
+    pub fn track_changes(mut self, track_changes: bool) -> Self {
+        self.track_changes = track_changes;
+        self
+    }
+
+    /// Record which pages of `page_size` bytes are written, for [`take_dirty_pages`][OpfsBackend::take_dirty_pages]
+    /// to hand out once they are synced, so that sync engines can replicate just what changed.
+    ///
+    /// Choose redb's page size, 4096 bytes by default, to replicate whole pages. The pages are kept as bitmaps in
+    /// memory, one bit per page of the file.
+    ///
+    /// Default: `None`, recording nothing.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = dirtyPages))]
+    pub fn dirty_pages(mut self, page_size: Option<u32>) -> Self {
+        self.dirty_page_size = page_size;
+        self
+    }
+
+    /// Post a message on the [`BroadcastChannel`] named `channel` whenever a change is committed, that is whenever
+    /// redb calls `sync_data`, so that other tabs and workers can refresh their views of the database.
+    ///
+    /// The message is an object `{ path }`, where `path` is the path the file was opened with, or the name of the
+    /// file handle for [`open_file_handle`][Self::open_file_handle]. Read-only backends post nothing.
+    ///
+    /// Default: `None`, posting nothing. Has no effect natively.
+    ///
+    /// [`BroadcastChannel`]: https://developer.mozilla.org/en-US/docs/Web/API/BroadcastChannel
+    #[cfg_attr(opfs, wasm_bindgen(js_name = notifyChannel))]
+    pub fn notify_channel(mut self, channel: Option<String>) -> Self {
+        self.notify_channel = channel;
+        self
+    }
+
+    /// Open the file at the specified path with the configured options.
+    ///
+    /// Fails with [`AlreadyOpen`][crate::AlreadyOpen] if another backend in this process has the file open, unless
+    /// both are read-only.
+    pub async fn open(self, path: &str) -> Result<OpfsBackend> {
+        self.validate()?;
+        #[cfg(opfs)]
+        crate::file::check_context()?;
+        self.check_quota(path).await?;
+        // a lazy backend claims its file right away, so that opening it twice fails as early as it does otherwise
+        let registration = Registration::new(self.registry_key(path)?, self.options.read_only)?;
+        let mut backend = if self.lazy {
+            self.build_lazy(path.to_owned())?
+        } else if let Some(acquired) = self.acquire_existing(path).await? {
+            #[cfg(opfs)]
+            let (file, web_lock) = acquired;
+            #[cfg(not(opfs))]
+            let file = acquired;
+            self.build(
+                file,
+                #[cfg(opfs)]
+                web_lock,
+                #[cfg(opfs)]
+                path,
+            )?
+        } else {
+            let mut backend = self.build_lazy(path.to_owned())?;
+            backend.create_on_write = true;
+            backend
+        };
+        backend.registration = Mutex::new(Some(registration));
+        debug_log!("opened {path}");
+        Ok(backend)
+    }
+
+    /// Open an empty [`MemoryBackend`] instead of a file, as for tests and ephemeral sessions, so that switching
+    /// between the two only changes this call.
+    ///
+    /// Nothing is ever persisted, so the options concerning the file, its locking and its durability don't apply.
+    /// Fails with [`ErrorKind::InvalidInput`] if the options are invalid, or [read-only][Self::read_only], as an empty
+    /// read-only database is of no use.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = inMemory))]
+    pub fn in_memory(self) -> Result<MemoryBackend> {
+        self.validate()?;
+        if self.options.read_only {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "an in-memory backend can't be read-only",
+            )
+            .into());
+        }
+        Ok(MemoryBackend::new())
+    }
+}
+
+impl OpfsBackendBuilder {
+    /// Acquire the Web Lock, if configured, and open the file at `path`.
+    pub(crate) async fn acquire(&self, path: &str) -> Result<Acquired> {
+        let transient = |kind| kind == ErrorKind::ResourceBusy;
+        let path = self.resolve(path)?;
+        let path = path.as_str();
+
+        // Node has no Web Locks
+        #[cfg(all(opfs, feature = "node"))]
+        let locking = self.lock && !crate::node::detected();
+        #[cfg(all(opfs, not(feature = "node")))]
+        let locking = self.lock;
+        #[cfg(opfs)]
+        let web_lock = if locking {
+            let path = virtualize_path(path)?;
+            // handles have no path; locking files with the same name in different directories together is harmless
+            let name = match &self.root_handle {
+                Some(root) => format!("redb-opfs:[{}]/{}", root.name(), path.display()),
+                None => format!("redb-opfs:{}", path.display()),
+            };
+            let lock = self
+                .retry
+                .run(transient, async || {
+                    WebLock::acquire(&name, self.options.read_only, self.lock_timeout_ms).await
+                })
+                .await
+                .map_err(|err| busy::at_step(OpenStep::Lock, path, err))?;
+            debug_log!("acquired Web Lock {name}");
+            Some(lock)
+        } else {
+            None
+        };
+
+        #[cfg(opfs)]
+        let options = self.options;
+        // truncating before the lock is taken would destroy the content of a file another process has open
+        #[cfg(not(opfs))]
+        let options = OpenOptions {
+            truncate: self.options.truncate && !self.lock,
+            ..self.options
+        };
+        let file = self
+            .wait_while_busy(async || {
+                self.retry
+                    .run(transient, async || self.open_file(path, &options).await)
+                    .await
+            })
+            .await
+            .map_err(|err| busy::at_step(OpenStep::Open, path, err))
+            .inspect_err(|_err| debug_log!("failed to open {path}: {_err}"))?;
+        debug_log!("acquired handle for {path}");
+        #[cfg(not(opfs))]
+        if self.lock {
+            self.retry
+                .run(transient, async || self.lock_file(&file, path).await)
+                .await
+                .map_err(|err| busy::at_step(OpenStep::Lock, path, err))?;
+            if self.options.truncate {
+                file.set_len(0)?;
+            }
+        }
+        #[cfg(opfs)]
+        {
+            Ok((file, web_lock))
+        }
+
+        #[cfg(not(opfs))]
+        {
+            Ok(file)
+        }
+    }
+
+    /// Fail with [`InsufficientQuota`] if less storage is available for the file at `path` than
+    /// [`min_free_space`][Self::min_free_space].
+    async fn check_quota(&self, path: &str) -> Result<()> {
+        let Some(required) = self.min_free_space else {
+            return Ok(());
+        };
+        if self.options.read_only {
+            return Ok(());
+        }
+        let available = match <File as FileAbstraction>::estimate(&self.resolve(path)?).await {
+            Ok(estimate) => estimate.available(),
+            // opening goes ahead as it would without the check
+            Err(err) if err.kind() == ErrorKind::Unsupported => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let required = u64::from(required);
+        if available < required {
+            debug_log!("only {available} of {required} bytes available");
+            let err = InsufficientQuota {
+                required,
+                available,
+            };
+            return Err(io::Error::new(ErrorKind::QuotaExceeded, err).into());
+        }
+        Ok(())
+    }
+
+    /// Like [`acquire`][Self::acquire], but returning `None` instead of creating the file if it is to be
+    /// [created on write][Self::create_on_write].
+    async fn acquire_existing(&self, path: &str) -> Result<Option<Acquired>> {
+        if !self.create_on_write || !self.options.creates() {
+            return self.acquire(path).await.map(Some);
+        }
+        let mut existing = self.clone();
+        existing.options.create = false;
+        existing.options.create_new = false;
+        match existing.acquire(path).await {
+            Ok(_) if self.options.create_new => Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{path} already exists"),
+            )
+            .into()),
+            Ok(acquired) => Ok(Some(acquired)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reject incompatible options.
+    fn validate(&self) -> Result<()> {
+        if self.options.read_only && (self.options.truncate || self.options.create_new) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "cannot truncate or create a file opened read-only",
+            )
+            .into());
+        }
+        if self.dirty_page_size == Some(0) {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "page size must not be 0").into());
+        }
+        Ok(())
+    }
+
+    /// Wrap an opened file in a backend with the configured cache, buffering and notifications.
+    ///
+    /// `path` is what notifications name the file by.
+    fn build(
+        self,
+        file: File,
+        #[cfg(opfs)] web_lock: Option<WebLock>,
+        #[cfg(opfs)] path: &str,
+    ) -> Result<OpfsBackend> {
+        self.build_with(
+            Some(file),
+            #[cfg(opfs)]
+            web_lock,
+            #[cfg(opfs)]
+            path,
+        )
+    }
+
+    /// Create a backend which opens the file at `path` once it is first used.
+    fn build_lazy(self, path: String) -> Result<OpfsBackend> {
+        let deferred = Deferred::new(self.clone(), path.clone());
+        let mut backend = self.build_with(
+            None,
+            #[cfg(opfs)]
+            None,
+            #[cfg(opfs)]
+            &path,
+        )?;
+        backend.deferred = Some(deferred);
+        Ok(backend)
+    }
+
+    fn build_with(
+        self,
+        file: Option<File>,
+        #[cfg(opfs)] web_lock: Option<WebLock>,
+        #[cfg(opfs)] path: &str,
+    ) -> Result<OpfsBackend> {
+        #[cfg(opfs)]
+        let notifier = match &self.notify_channel {
+            Some(channel) => Some(ThreadBound::new(Notifier::new(channel, path)?)),
+            None => None,
+        };
+        #[cfg(opfs)]
+        let file = RwLock::new(ThreadBound::new(file));
+        #[cfg(not(opfs))]
+        let file = RwLock::new(file);
+        let cache = (self.cache_pages > 0).then(|| {
+            Mutex::new(PageCache::new(
+                self.cache_pages as usize,
+                self.read_ahead_pages as usize,
+            ))
+        });
+        let write_buffer = (self.write_buffer_bytes > 0)
+            .then(|| Mutex::new(WriteBuffer::new(self.write_buffer_bytes as usize)));
+        let changes = if self.track_changes {
+            #[cfg(opfs)]
+            let session = unique_name()?;
+            #[cfg(not(opfs))]
+            let session = unique_name();
+            Some(Mutex::new(ChangeTracker::new(session)))
+        } else {
+            None
+        };
+        let dirty_pages = self
+            .dirty_page_size
+            .map(|page_size| Mutex::new(DirtyPages::new(page_size.into())));
+        Ok(OpfsBackend {
+            file,
+            read_only: self.options.read_only,
+            cache,
+            write_buffer,
+            retry: self.retry,
+            flush: FlushSchedule::new(self.sync_mode, self.flush_interval_ms),
+            #[cfg(opfs)]
+            web_lock: Mutex::new(ThreadBound::new(web_lock)),
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+            temp_path: None,
+            deferred: None,
+            create_on_write: false,
+            registration: Mutex::new(None),
+            #[cfg(opfs)]
+            notifier,
+            changes,
+            dirty_pages,
+        })
+    }
+
+    /// Identify the file at `path` in the registry of open files; this also rejects invalid paths.
+    fn registry_key(&self, path: &str) -> Result<String> {
+        let path = self.resolve(path)?;
+        #[cfg(opfs)]
+        {
+            let path = virtualize_path(&path)?;
+            // like the Web Lock, files in different directories with the same name are registered together
+            Ok(match &self.root_handle {
+                Some(root) => format!("[{}]/{}", root.name(), path.display()),
+                None => path.display().to_string(),
+            })
+        }
+
+        #[cfg(not(opfs))]
+        {
+            Ok(std::path::absolute(path)?.display().to_string())
+        }
+    }
+
+    /// Apply [`root_dir`][Self::root_dir] to `path`.
+    fn resolve(&self, path: &str) -> Result<String> {
+        let Some(root_dir) = &self.root_dir else {
+            return Ok(path.to_owned());
+        };
+        // resolving `..` first keeps it from escaping the root
+        #[cfg(opfs)]
+        let path = virtualize_path(path)?;
+        Ok(Path::new(root_dir)
+            .join(path)
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    /// Take the advisory lock on the native `file`, waiting for up to [`lock_timeout`][Self::lock_timeout].
+    ///
+    /// `std::fs` can only try to take the lock without blocking, so this tries again every 10 ms, sleeping in between
+    /// without blocking the executor.
+    #[cfg(not(opfs))]
+    async fn lock_file(&self, file: &File, path: &str) -> Result<()> {
+        use std::{fs::TryLockError, time::Instant};
+
+        const POLL_INTERVAL_MS: u32 = 10;
+
+        let timeout = Duration::from_millis(self.lock_timeout_ms.unwrap_or_default().into());
+        let started = Instant::now();
+        loop {
+            let locked = match self.options.read_only {
+                true => file.try_lock_shared(),
+                false => file.try_lock(),
+            };
+            match locked {
+                Ok(()) => return Ok(()),
+                Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                    retry::sleep(POLL_INTERVAL_MS).await?;
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(io::Error::new(
+                        ErrorKind::ResourceBusy,
+                        format!("{path} is locked by another backend, in this or another process"),
+                    ));
+                }
+                Err(TryLockError::Error(err)) if err.kind() == ErrorKind::Unsupported => {
+                    return Ok(());
+                }
+                Err(TryLockError::Error(err)) => return Err(err),
+            }
+        }
+    }
+
+    /// Run `open`, running it again while it fails with [`ErrorKind::ResourceBusy`] until
+    /// [`busy_timeout`][Self::busy_timeout] elapses.
+    ///
+    /// Attempts are spaced by [`retry::sleep`], which yields to the executor rather than blocking it.
+    async fn wait_while_busy<T>(&self, mut open: impl AsyncFnMut() -> Result<T>) -> Result<T> {
+        const POLL_INTERVAL_MS: u32 = 50;
+
+        let timeout = Duration::from_millis(self.busy_timeout_ms.unwrap_or_default().into());
+        let started = Timestamp::now();
+        loop {
+            match open().await {
+                Err(err)
+                    if err.kind() == ErrorKind::ResourceBusy && started.elapsed() < timeout =>
+                {
+                    retry::sleep(POLL_INTERVAL_MS).await?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Open the file itself with `options`, relative to [`root_handle`][Self::root_handle] if set.
+    async fn open_file(&self, path: &str, options: &OpenOptions) -> Result<File> {
+        #[cfg(opfs)]
+        if let Some(root) = &self.root_handle {
+            return File::open_in(Some(root), path, options).await;
+        }
+        let file = <File as FileAbstraction>::open(path, options).await?;
+        Ok(file)
+    }
+}
+
+/// Options passed through to [`std::fs::OpenOptions`], for tuning server deployments.
+///
+/// They apply to the database file only, not to the files [`OpfsBackend::snapshot`] and the like create.
+#[cfg(unix)]
+impl OpfsBackendBuilder {
+    /// Pass `flags`, such as `libc::O_NOATIME`, to `open` in addition to those the other options imply; see
+    /// [`OpenOptionsExt::custom_flags`][std::os::unix::fs::OpenOptionsExt::custom_flags].
+    ///
+    /// Note that redb's buffers have no particular alignment, so `O_DIRECT` may make operations fail with
+    /// [`ErrorKind::InvalidInput`] on file systems which require aligned buffers.
+    pub fn custom_flags(mut self, flags: i32) -> Self {
+        self.options.native.custom_flags = flags;
+        self
+    }
+
+    /// Create the file with the permissions `mode`, before the umask is applied; defaults to `0o666`.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.options.native.mode = Some(mode);
+        self
+    }
+}
+
+/// Options passed through to [`std::fs::OpenOptions`], for tuning server deployments.
+///
+/// They apply to the database file only, not to the files [`OpfsBackend::snapshot`] and the like create.
+#[cfg(windows)]
+impl OpfsBackendBuilder {
+    /// Pass `flags`, such as `FILE_FLAG_WRITE_THROUGH`, to `CreateFileW`; see
+    /// [`OpenOptionsExt::custom_flags`][std::os::windows::fs::OpenOptionsExt::custom_flags].
+    pub fn custom_flags(mut self, flags: u32) -> Self {
+        self.options.native.custom_flags = flags;
+        self
+    }
+
+    /// Share the file with other handles according to `share_mode`, as passed to `CreateFileW`; defaults to sharing
+    /// for reading, writing and deleting. Pass 0 to keep other processes from opening the file at all.
+    pub fn share_mode(mut self, share_mode: u32) -> Self {
+        self.options.native.share_mode = Some(share_mode);
+        self
+    }
+
+    /// Create the file with the attributes `attributes`, such as `FILE_ATTRIBUTE_TEMPORARY`.
+    pub fn attributes(mut self, attributes: u32) -> Self {
+        self.options.native.attributes = attributes;
+        self
+    }
+}
+
+#[cfg(opfs)]
+#[wasm_bindgen]
+impl OpfsBackendBuilder {
+    /// Resolve paths relative to the directory `handle`, rather than the OPFS root.
+    ///
+    /// This lets embedders which manage their own OPFS subtree sandbox the database inside it. Combines with
+    /// [`root_dir`][Self::root_dir], which is then resolved relative to `handle`.
+    #[wasm_bindgen(js_name = rootHandle)]
+    pub fn root_handle(mut self, handle: FileSystemDirectoryHandle) -> Self {
+        self.root_handle = Some(handle);
+        self
+    }
+
+    /// Open the file `handle` refers to with the configured options, bypassing path resolution.
+    ///
+    /// This suits hosts which obtained the handle themselves, for example from a directory they manage, or which
+    /// want to share one handle between this crate and other code. [`create`][Self::create],
+    /// [`create_new`][Self::create_new], [`root_dir`][Self::root_dir] and [`root_handle`][Self::root_handle] have
+    /// no effect. If `handle` lies within OPFS, the Web Lock is the same one [`open`][Self::open] acquires for its
+    /// path, so both ways of opening the file exclude each other. Like `open`, this fails with
+    /// [`AlreadyOpen`][crate::AlreadyOpen] if another backend in this worker has the file open, unless both are
+    /// read-only.
+    #[wasm_bindgen(js_name = openFileHandle)]
+    pub async fn open_file_handle(self, handle: FileSystemFileHandle) -> Result<OpfsBackend> {
+        self.validate()?;
+        crate::file::check_context()?;
+        self.check_quota(&handle.name()).await?;
+        let transient = |kind| kind == ErrorKind::ResourceBusy;
+
+        let name = handle.name();
+        let key = match path_of(&handle).await? {
+            Some(path) => path.display().to_string(),
+            // outside OPFS, only the name is known; over-locking is harmless
+            None => format!("[handle]/{name}"),
+        };
+        // registered under the same key as the path, so that opening the file both ways fails as clearly
+        let registration = Registration::new(key.clone(), self.options.read_only)?;
+        let web_lock = if self.lock {
+            let lock_name = format!("redb-opfs:{key}");
+            let lock = self
+                .retry
+                .run(transient, async || {
+                    WebLock::acquire(&lock_name, self.options.read_only, self.lock_timeout_ms).await
+                })
+                .await
+                .map_err(|err| busy::at_step(OpenStep::Lock, &name, err))?;
+            debug_log!("acquired Web Lock {lock_name}");
+            Some(lock)
+        } else {
+            None
+        };
+
+        let file = self
+            .wait_while_busy(async || {
+                self.retry
+                    .run(transient, async || {
+                        File::from_file_handle(handle.clone(), &self.options).await
+                    })
+                    .await
+            })
+            .await
+            .map_err(|err| busy::at_step(OpenStep::Open, &name, err))
+            .inspect_err(|_err| debug_log!("failed to open file handle {name}: {_err}"))?;
+        debug_log!("acquired handle for file handle {name}");
+        let mut backend = self.build(file, web_lock, &name)?;
+        backend.registration = Mutex::new(Some(registration));
+        Ok(backend)
+    }
+}
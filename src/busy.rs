@@ -0,0 +1,81 @@
+//! Reporting which step of opening a file found it in use elsewhere.
+
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+};
+
+use crate::Error;
+
+/// A step of opening a file, which may find the file in use elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenStep {
+    /// Acquiring the Web Lock on wasm, or the advisory file lock natively; see
+    /// [`OpfsBackendBuilder::lock`][crate::OpfsBackendBuilder::lock].
+    Lock,
+    /// Opening the file itself; on wasm, creating its sync access handle, which fails while another handle is open.
+    Open,
+}
+
+impl fmt::Display for OpenStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lock => "acquiring the lock",
+            Self::Open => "opening the file",
+        })
+    }
+}
+
+/// The error which opening a file fails with while another worker, tab or process holds it.
+///
+/// The error has the kind [`ErrorKind::ResourceBusy`]; on wasm, it becomes
+/// `Error::HandleBusy`, which is thrown to JS as a `HandleBusyError`. Find it with
+/// [`FileBusy::of`] to learn which step failed, for example to tell the user to close another tab.
+#[derive(Debug)]
+pub struct FileBusy {
+    /// The file, as resolved by the builder which tried to open it.
+    pub path: String,
+    /// The step which found the file in use.
+    pub step: OpenStep,
+    source: io::Error,
+}
+
+impl fmt::Display for FileBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is in use elsewhere; {} failed: {}",
+            self.path, self.step, self.source
+        )
+    }
+}
+
+impl std::error::Error for FileBusy {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl FileBusy {
+    /// Find the [`FileBusy`] error within `err`, if it has one.
+    pub fn of(err: &Error) -> Option<&FileBusy> {
+        #[cfg(opfs)]
+        let Error::HandleBusy(err) = err else {
+            return None;
+        };
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+/// Attribute `err` to `step` of opening the file at `path`, if it means that the file is in use elsewhere.
+pub(crate) fn at_step(step: OpenStep, path: &str, err: Error) -> Error {
+    if err.kind() != ErrorKind::ResourceBusy {
+        return err;
+    }
+    let busy = FileBusy {
+        path: path.to_owned(),
+        step,
+        source: err.into(),
+    };
+    io::Error::new(ErrorKind::ResourceBusy, busy).into()
+}
@@ -0,0 +1,275 @@
+//! Tracking which parts of a file changed, for incremental exports and replication; see
+//! [`OpfsBackendBuilder::track_changes`][crate::OpfsBackendBuilder::track_changes] and
+//! [`OpfsBackendBuilder::dirty_pages`][crate::OpfsBackendBuilder::dirty_pages].
+//!
+//! For incremental exports, the file is divided into chunks of [`CHUNK_SIZE`] bytes, and each chunk remembers the
+//! generation in which it was last modified. Every incremental export hands out a token naming the current
+//! generation, and starts a new one, so the next export only needs the chunks modified in a later generation.
+//!
+//! For replication, [`DirtyPages`] keeps bitmaps of the pages written, which move from pending to synced with each
+//! `sync_data`, until they are taken.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind},
+    ops::Range,
+};
+
+use crate::IoResult;
+
+/// Granularity of change tracking, in bytes.
+pub(crate) const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// The chunks modified in each generation of a backend's file.
+#[derive(Debug)]
+pub(crate) struct ChangeTracker {
+    /// Distinguishes the tokens of this backend from those of earlier sessions, whose generations are unrelated.
+    session: String,
+    generation: u64,
+    /// The generation in which each chunk was last modified.
+    modified: BTreeMap<u64, u64>,
+}
+
+impl ChangeTracker {
+    pub(crate) fn new(session: String) -> Self {
+        Self {
+            session,
+            generation: 0,
+            modified: BTreeMap::new(),
+        }
+    }
+
+    /// Record that `len` bytes were written at `offset`.
+    pub(crate) fn record_write(&mut self, offset: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let end = offset.saturating_add(len);
+        self.mark(offset / CHUNK_SIZE..end.div_ceil(CHUNK_SIZE));
+    }
+
+    /// Record that the file was truncated or extended from `old_len` to `new_len` bytes.
+    ///
+    /// Everything after the new end changed: shrinking discards it, and extending the file again later fills it with
+    /// zeros, which the backup must not miss.
+    pub(crate) fn record_set_len(&mut self, old_len: u64, new_len: u64) {
+        let end = old_len.max(new_len).div_ceil(CHUNK_SIZE);
+        self.mark(new_len / CHUNK_SIZE..end);
+    }
+
+    /// The ranges of a file of `len` bytes which changed since the export which returned `since`, as
+    /// `(offset, len)` pairs, coalesced and in order; all of the file if `since` is `None`.
+    pub(crate) fn changed_since(&self, since: Option<&str>, len: u64) -> IoResult<Vec<(u64, u64)>> {
+        let Some(since) = since else {
+            return Ok(if len == 0 { Vec::new() } else { vec![(0, len)] });
+        };
+        let since = self.parse_token(since)?;
+
+        let mut ranges = Vec::<(u64, u64)>::new();
+        for (&chunk, _) in self
+            .modified
+            .iter()
+            .filter(|&(_, &generation)| generation > since)
+        {
+            let start = chunk * CHUNK_SIZE;
+            if start >= len {
+                break;
+            }
+            let end = (start + CHUNK_SIZE).min(len);
+            match ranges.last_mut() {
+                Some((offset, range_len)) if *offset + *range_len == start => {
+                    *range_len = end - *offset
+                }
+                _ => ranges.push((start, end - start)),
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Hand out the token of the current generation, and start a new one.
+    pub(crate) fn advance(&mut self) -> String {
+        let token = format!("{}.{}", self.session, self.generation);
+        self.generation += 1;
+        token
+    }
+
+    fn mark(&mut self, chunks: Range<u64>) {
+        for chunk in chunks {
+            self.modified.insert(chunk, self.generation);
+        }
+    }
+
+    /// The generation named by `token`, failing if this backend did not hand it out.
+    fn parse_token(&self, token: &str) -> IoResult<u64> {
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidInput, message.to_owned());
+        let (session, generation) = token
+            .rsplit_once('.')
+            .ok_or_else(|| invalid("malformed incremental export token"))?;
+        if session != self.session {
+            return Err(invalid(
+                "the token is from another session, whose changes weren't tracked; take a full export",
+            ));
+        }
+        generation
+            .parse()
+            .ok()
+            .filter(|&generation| generation < self.generation)
+            .ok_or_else(|| invalid("malformed incremental export token"))
+    }
+}
+
+/// The pages of a backend's file written since they were last taken.
+#[derive(Debug)]
+pub(crate) struct DirtyPages {
+    page_size: u64,
+    /// Pages written since the last sync, one bit each.
+    pending: Vec<u64>,
+    /// Pages written before the last sync, and not taken since.
+    synced: Vec<u64>,
+}
+
+impl DirtyPages {
+    pub(crate) fn new(page_size: u64) -> Self {
+        Self {
+            page_size,
+            pending: Vec::new(),
+            synced: Vec::new(),
+        }
+    }
+
+    /// Record that `len` bytes were written at `offset`.
+    pub(crate) fn record_write(&mut self, offset: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let end = offset.saturating_add(len);
+        set_bits(
+            &mut self.pending,
+            offset / self.page_size..end.div_ceil(self.page_size),
+        );
+    }
+
+    /// Record that the file was truncated or extended from `old_len` to `new_len` bytes.
+    ///
+    /// Pages past the new end no longer exist, so they are forgotten; the page the file now ends in, and any pages
+    /// it was extended by, are dirty.
+    pub(crate) fn record_set_len(&mut self, old_len: u64, new_len: u64) {
+        let pages = new_len.div_ceil(self.page_size);
+        for bits in [&mut self.pending, &mut self.synced] {
+            clear_bits_from(bits, pages);
+        }
+        let first = old_len.min(new_len) / self.page_size;
+        set_bits(&mut self.pending, first..pages);
+    }
+
+    /// Record that the pending pages were synced.
+    pub(crate) fn sync(&mut self) {
+        if self.synced.len() < self.pending.len() {
+            self.synced.resize(self.pending.len(), 0);
+        }
+        for (synced, pending) in self.synced.iter_mut().zip(self.pending.drain(..)) {
+            *synced |= pending;
+        }
+    }
+
+    /// Take the indices of the synced pages, in order, leaving none.
+    pub(crate) fn take(&mut self) -> Vec<u64> {
+        let mut pages = Vec::new();
+        for (i, mut word) in std::mem::take(&mut self.synced).into_iter().enumerate() {
+            while word != 0 {
+                pages.push(i as u64 * 64 + u64::from(word.trailing_zeros()));
+                word &= word - 1;
+            }
+        }
+        pages
+    }
+}
+
+/// Set the bits of the `pages` in `bits`, growing it as needed.
+fn set_bits(bits: &mut Vec<u64>, pages: Range<u64>) {
+    if pages.is_empty() {
+        return;
+    }
+    let words = pages.end.div_ceil(64) as usize;
+    if bits.len() < words {
+        bits.resize(words, 0);
+    }
+    for page in pages {
+        bits[(page / 64) as usize] |= 1 << (page % 64);
+    }
+}
+
+/// Clear the bits of all pages from `first` on in `bits`.
+fn clear_bits_from(bits: &mut Vec<u64>, first: u64) {
+    let word = (first / 64) as usize;
+    if word < bits.len() {
+        bits[word] &= (1 << (first % 64)) - 1;
+        bits.truncate(word + 1);
+    }
+}
+
+/// What an incremental export, as by [`OpfsBackend::export_incremental`][crate::OpfsBackend::export_incremental],
+/// contains.
+///
+/// The exported data is the content of [`ranges`][Self::ranges], one after another. Store the manifest along with it;
+/// [`OpfsBackend::apply_incremental`][crate::OpfsBackend::apply_incremental] needs both to restore the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncrementalManifest {
+    /// Pass this to the next incremental export, to export only what changed after this one.
+    pub token: String,
+    /// The length of the file at the time of the export, in bytes.
+    pub len: u64,
+    /// Whether this is a full export, which later incremental exports build on, rather than the changes since an
+    /// earlier one.
+    pub full: bool,
+    /// The ranges exported, as `(offset, len)` pairs, in order.
+    pub ranges: Vec<(u64, u64)>,
+}
+
+#[cfg(opfs)]
+impl IncrementalManifest {
+    /// Convert to a plain JS object `{ token, len, full, ranges }`, with `ranges` an array of `[offset, len]` pairs.
+    pub(crate) fn to_js(&self) -> js_sys::Object {
+        use js_sys::{Array, Object, Reflect};
+
+        let object = Object::new();
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|&(offset, len)| Array::of2(&(offset as f64).into(), &(len as f64).into()))
+            .collect::<Array>();
+        let _ = Reflect::set(&object, &"token".into(), &self.token.as_str().into());
+        let _ = Reflect::set(&object, &"len".into(), &(self.len as f64).into());
+        let _ = Reflect::set(&object, &"full".into(), &self.full.into());
+        let _ = Reflect::set(&object, &"ranges".into(), &ranges);
+        object
+    }
+
+    /// Convert from a plain JS object, as created by [`to_js`][Self::to_js].
+    pub(crate) fn from_js(value: &wasm_bindgen::JsValue) -> crate::Result<Self> {
+        use js_sys::{Array, Reflect};
+        use wasm_bindgen::JsCast as _;
+
+        let invalid = || io::Error::new(ErrorKind::InvalidInput, "malformed incremental manifest");
+        let number = |value: wasm_bindgen::JsValue| {
+            value.as_f64().map(|value| value as u64).ok_or_else(invalid)
+        };
+        let ranges = Reflect::get(value, &"ranges".into())?
+            .dyn_into::<Array>()
+            .map_err(|_| invalid())?
+            .iter()
+            .map(|range| {
+                let range = range.dyn_into::<Array>().map_err(|_| invalid())?;
+                Ok((number(range.get(0))?, number(range.get(1))?))
+            })
+            .collect::<IoResult<_>>()?;
+        Ok(Self {
+            token: Reflect::get(value, &"token".into())?
+                .as_string()
+                .unwrap_or_default(),
+            len: number(Reflect::get(value, &"len".into())?)?,
+            full: Reflect::get(value, &"full".into())?.is_truthy(),
+            ranges,
+        })
+    }
+}
@@ -0,0 +1,317 @@
+//! A [`StorageBackend`] wrapper which detects corrupted data with a CRC32 checksum per block.
+//!
+//! OPFS files have been observed to come back partially zeroed or truncated, after eviction or a flush which didn't
+//! complete. redb doesn't checksum every page, so such damage can go unnoticed until it has spread. This backend
+//! checks every block it reads, and fails with a [`ChecksumMismatch`] instead of returning damaged data.
+//!
+//! ## On-disk layout
+//!
+//! The file begins with a fixed-size header of [`HEADER_LEN`] bytes:
+//!
+//! | offset | len | content                                |
+//! | ------ | --- | -------------------------------------- |
+//! | 0      | 8   | magic: `RDBOPFSK`                      |
+//! | 8      | 4   | format version, u32 LE                 |
+//! | 12     | 4   | block size, u32 LE                     |
+//! | 16     | 8   | logical length of the database, u64 LE |
+//! | 24     | 4   | CRC32 of bytes `0..24`, u32 LE         |
+//! | 28     | 4   | reserved, zero                         |
+//!
+//! The header is followed by a dense sequence of blocks. Each block holds [`BLOCK_SIZE`] bytes of logical data, and is
+//! laid out as `data (BLOCK_SIZE) | CRC32 of data (4, LE)`, so a block and its checksum are written together.
+//!
+//! Every block within the logical length is written with its checksum, including the blocks the file is extended by,
+//! which hold zeros. So a block which has been zeroed entirely, checksum included, fails its checksum like any other
+//! damage, and so does a file which is shorter than its header claims.
+
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, Result, StorageBackend, chunks::for_each_chunk};
+
+/// Size of the logical data contained in each block.
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Size of the header region at the start of the file.
+pub const HEADER_LEN: usize = 32;
+
+const MAGIC: &[u8; 8] = b"RDBOPFSK";
+const VERSION: u32 = 2;
+const CHECKSUM_LEN: usize = 4;
+const PHYSICAL_BLOCK_SIZE: usize = BLOCK_SIZE + CHECKSUM_LEN;
+
+/// The error wrapped by the [`io::Error`]s, of kind [`ErrorKind::InvalidData`], reporting a block which failed its
+/// checksum.
+///
+/// On wasm, such errors become [`Error::Corrupted`][crate::Error::Corrupted], which is thrown to JS as a
+/// `CorruptedError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// The index of the block, counting from the start of the logical data.
+    pub block: u64,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block {} failed its checksum", self.block)
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+impl ChecksumMismatch {
+    fn into_io(self) -> io::Error {
+        io::Error::new(ErrorKind::InvalidData, self)
+    }
+}
+
+fn encode_header(len: u64) -> [u8; HEADER_LEN] {
+    let mut out = [0; HEADER_LEN];
+    out[..8].copy_from_slice(MAGIC);
+    out[8..12].copy_from_slice(&VERSION.to_le_bytes());
+    out[12..16].copy_from_slice(&(BLOCK_SIZE as u32).to_le_bytes());
+    out[16..24].copy_from_slice(&len.to_le_bytes());
+    let crc = crc32fast::hash(&out[..24]);
+    out[24..28].copy_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// Decode the header, returning the logical length of the database.
+fn decode_header(bytes: &[u8; HEADER_LEN]) -> IoResult<u64> {
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().expect("len 4"));
+
+    if &bytes[..8] != MAGIC {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "not a checksummed redb-opfs database",
+        ));
+    }
+    if u32_at(24) != crc32fast::hash(&bytes[..24]) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "header failed its checksum",
+        ));
+    }
+    let version = u32_at(8);
+    if version != VERSION {
+        return Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("unsupported checksummed format version {version}"),
+        ));
+    }
+    let block_size = u32_at(12);
+    if block_size as usize != BLOCK_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("unsupported checksummed block size {block_size}"),
+        ));
+    }
+    Ok(u64::from_le_bytes(bytes[16..24].try_into().expect("len 8")))
+}
+
+/// Implementation of a [`StorageBackend`] which checksums all data before handing it to an inner backend, and
+/// verifies it when reading it back.
+///
+/// See the [module documentation][self] for the on-disk layout.
+pub struct ChecksummedBackend<B> {
+    inner: B,
+    /// Logical length of the database.
+    ///
+    /// This mutex also serializes all operations, as writes perform read-modify-write cycles on whole blocks.
+    len: Mutex<u64>,
+}
+
+impl<B: fmt::Debug> fmt::Debug for ChecksummedBackend<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChecksummedBackend")
+            .field("inner", &self.inner)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<B: StorageBackend> ChecksummedBackend<B> {
+    /// Wrap `inner`, checksumming all data.
+    ///
+    /// If `inner` is empty, a new header is written. Otherwise the existing header is checked, and so is the length
+    /// of `inner`.
+    pub fn new(inner: B) -> Result<Self> {
+        let physical_len = inner.len()?;
+        let len = if physical_len == 0 {
+            inner.write(0, &encode_header(0))?;
+            0
+        } else {
+            let mut header = [0; HEADER_LEN];
+            if physical_len < HEADER_LEN as u64 {
+                return Err(io::Error::new(ErrorKind::InvalidData, "header is truncated").into());
+            }
+            inner.read(0, &mut header)?;
+            let len = decode_header(&header)?;
+            if physical_len < block_offset(block_count(len)) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "file is truncated: {physical_len} bytes cannot hold {len} bytes of data"
+                    ),
+                )
+                .into());
+            }
+            len
+        };
+
+        Ok(Self {
+            inner,
+            len: Mutex::new(len),
+        })
+    }
+
+    /// Check every block, returning the indices of those which fail their checksums.
+    ///
+    /// Errors other than checksum mismatches, such as I/O errors, abort the check.
+    pub fn corrupted_blocks(&self) -> IoResult<Vec<u64>> {
+        let len = self.len.lock();
+        let mut block = [0; BLOCK_SIZE];
+        let mut corrupted = Vec::new();
+        for index in 0..block_count(*len) {
+            match self.read_block(index, &mut block) {
+                Ok(()) => {}
+                Err(err) if is_checksum_mismatch(&err) => corrupted.push(index),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(corrupted)
+    }
+
+    /// Unwrap this backend, returning the inner backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Read and verify block `index`.
+    ///
+    /// Must only be called for blocks which exist physically, i.e. `index < block_count(len)`.
+    fn read_block(&self, index: u64, out: &mut [u8; BLOCK_SIZE]) -> IoResult<()> {
+        let mut buf = [0; PHYSICAL_BLOCK_SIZE];
+        self.inner.read(block_offset(index), &mut buf)?;
+        let (data, checksum) = buf.split_at(BLOCK_SIZE);
+        let checksum = u32::from_le_bytes(checksum.try_into().expect("len 4"));
+        if checksum != crc32fast::hash(data) {
+            return Err(ChecksumMismatch { block: index }.into_io());
+        }
+        out.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Checksum and write block `index`.
+    fn write_block(&self, index: u64, data: &[u8; BLOCK_SIZE]) -> IoResult<()> {
+        let mut buf = [0; PHYSICAL_BLOCK_SIZE];
+        buf[..BLOCK_SIZE].copy_from_slice(data);
+        buf[BLOCK_SIZE..].copy_from_slice(&crc32fast::hash(data).to_le_bytes());
+        self.inner.write(block_offset(index), &buf)
+    }
+
+    /// Write checksummed zeros to the blocks `blocks`, which the file is being extended by.
+    fn write_zero_blocks(&self, blocks: std::ops::Range<u64>) -> IoResult<()> {
+        let zeros = [0; BLOCK_SIZE];
+        blocks
+            .into_iter()
+            .try_for_each(|index| self.write_block(index, &zeros))
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for ChecksummedBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        Ok(*self.len.lock())
+    }
+
+    fn set_len(&self, new_len: u64) -> IoResult<()> {
+        let mut len = self.len.lock();
+
+        // See [`crate::chunks`] for why the tail of the last block is zeroed.
+        let tail = (new_len % BLOCK_SIZE as u64) as usize;
+        if new_len < *len && tail != 0 {
+            let index = new_len / BLOCK_SIZE as u64;
+            let mut block = [0; BLOCK_SIZE];
+            self.read_block(index, &mut block)?;
+            block[tail..].fill(0);
+            self.write_block(index, &block)?;
+        }
+
+        self.inner.set_len(block_offset(block_count(new_len)))?;
+        self.write_zero_blocks(block_count(*len)..block_count(new_len))?;
+        self.inner.write(0, &encode_header(new_len))?;
+        *len = new_len;
+        Ok(())
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let _guard = self.len.lock();
+        self.inner.sync_data()
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let len = self.len.lock();
+        let end = offset
+            .checked_add(out.len() as u64)
+            .filter(|&end| end <= *len)
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+
+        let mut block = [0; BLOCK_SIZE];
+        for_each_chunk(BLOCK_SIZE, offset, end, |index, in_block, in_buf| {
+            self.read_block(index, &mut block)?;
+            out[in_buf].copy_from_slice(&block[in_block]);
+            Ok(())
+        })
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let mut len = self.len.lock();
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            )
+        })?;
+        let existing_blocks = block_count(*len);
+        self.write_zero_blocks(existing_blocks..offset / BLOCK_SIZE as u64)?;
+
+        let mut block = [0; BLOCK_SIZE];
+        for_each_chunk(BLOCK_SIZE, offset, end, |index, in_block, in_buf| {
+            if in_block.len() != BLOCK_SIZE {
+                if index < existing_blocks {
+                    self.read_block(index, &mut block)?;
+                } else {
+                    block.fill(0);
+                }
+            }
+            block[in_block].copy_from_slice(&data[in_buf]);
+            self.write_block(index, &block)
+        })?;
+
+        if end > *len {
+            self.inner.write(0, &encode_header(end))?;
+            *len = end;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `err` reports a block which failed its checksum.
+pub fn is_checksum_mismatch(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<ChecksumMismatch>())
+}
+
+/// Physical offset of the block with the given index.
+fn block_offset(index: u64) -> u64 {
+    HEADER_LEN as u64 + index * PHYSICAL_BLOCK_SIZE as u64
+}
+
+/// Number of blocks required to hold `len` bytes of logical data.
+fn block_count(len: u64) -> u64 {
+    len.div_ceil(BLOCK_SIZE as u64)
+}
@@ -0,0 +1,30 @@
+//! Logical ranges split into the fixed-size chunks which the checksummed, compressed and encrypted backends store:
+//! blocks, groups and pages respectively.
+//!
+//! Chunks must never contain data beyond the logical end, so that extending the file later correctly reveals zeros.
+//! Each of those backends therefore zeroes the tail of the last chunk when it truncates into the middle of it.
+
+use std::ops::Range;
+
+use crate::IoResult;
+
+/// Call `f` for each chunk of `chunk_size` bytes touched by the logical range `start..end`.
+///
+/// `f` receives the chunk index, the range within the chunk, and the range within a buffer which starts at `start`.
+pub(crate) fn for_each_chunk(
+    chunk_size: usize,
+    start: u64,
+    end: u64,
+    mut f: impl FnMut(u64, Range<usize>, Range<usize>) -> IoResult<()>,
+) -> IoResult<()> {
+    let mut pos = start;
+    while pos < end {
+        let index = pos / chunk_size as u64;
+        let within = (pos % chunk_size as u64) as usize;
+        let n = (chunk_size - within).min((end - pos) as usize);
+        let buf_start = (pos - start) as usize;
+        f(index, within..within + n, buf_start..buf_start + n)?;
+        pos += n as u64;
+    }
+    Ok(())
+}
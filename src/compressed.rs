@@ -0,0 +1,535 @@
+//! A [`StorageBackend`] wrapper which transparently compresses data with LZ4.
+//!
+//! Logical data is split into groups of [`GROUP_SIZE`] bytes, each of which is compressed independently.
+//! Compressed groups are variable-sized, so they are never overwritten in place: a modified group is written to
+//! free space, and a directory mapping group indices to their location is written on every [`sync_data`].
+//!
+//! ## On-disk layout
+//!
+//! The file begins with two header slots of [`HEADER_SLOT_LEN`] bytes each. On open, the valid slot with the
+//! highest generation is used; commits alternate between slots, so a torn header write never loses the previous
+//! commit. Each slot is laid out as:
+//!
+//! | offset | len | content                                        |
+//! | ------ | --- | ---------------------------------------------- |
+//! | 0      | 8   | magic: `RDBOPFSC`                              |
+//! | 8      | 4   | format version, u32 LE                         |
+//! | 12     | 4   | group size, u32 LE                             |
+//! | 16     | 8   | generation, u64 LE                             |
+//! | 24     | 8   | logical length of the database, u64 LE         |
+//! | 32     | 8   | directory offset, u64 LE                       |
+//! | 40     | 8   | directory length in bytes, u64 LE              |
+//! | 48     | 4   | CRC32 of bytes `0..48`, u32 LE                 |
+//! | 52     | 12  | reserved, zero                                 |
+//!
+//! The rest of the file holds compressed groups and the directory, in no particular order. The directory holds one
+//! 16-byte entry per group: `offset (u64 LE) | length (u64 LE)`. A length of 0 means that the group is entirely zero
+//! and has no stored data.
+//!
+//! Space which is no longer referenced becomes reusable only after the next commit, so the previous commit stays
+//! intact until its successor is durable.
+//!
+//! ## Memory use
+//!
+//! Up to [`CACHED_GROUPS`] groups are held decompressed in memory. Modified groups are compressed and written out
+//! when the cache is full, and always on [`sync_data`].
+//!
+//! [`sync_data`]: StorageBackend::sync_data
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, ErrorKind},
+};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, Result, StorageBackend, chunks::for_each_chunk};
+
+/// Size of the logical data contained in each compressed group.
+pub const GROUP_SIZE: usize = 64 * 1024;
+
+/// Size of each of the two header slots at the start of the file.
+pub const HEADER_SLOT_LEN: usize = 64;
+
+/// Maximum number of groups held decompressed in memory.
+pub const CACHED_GROUPS: usize = 16;
+
+const MAGIC: &[u8; 8] = b"RDBOPFSC";
+const VERSION: u32 = 1;
+const DATA_START: u64 = 2 * HEADER_SLOT_LEN as u64;
+const DIRECTORY_ENTRY_LEN: usize = 16;
+
+/// A region of the physical file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    offset: u64,
+    len: u64,
+}
+
+impl Span {
+    const EMPTY: Self = Self { offset: 0, len: 0 };
+
+    fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+}
+
+/// Decoded form of a header slot.
+struct HeaderSlot {
+    generation: u64,
+    len: u64,
+    directory: Span,
+}
+
+impl HeaderSlot {
+    fn encode(&self) -> [u8; HEADER_SLOT_LEN] {
+        let mut out = [0; HEADER_SLOT_LEN];
+        out[..8].copy_from_slice(MAGIC);
+        out[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        out[12..16].copy_from_slice(&(GROUP_SIZE as u32).to_le_bytes());
+        out[16..24].copy_from_slice(&self.generation.to_le_bytes());
+        out[24..32].copy_from_slice(&self.len.to_le_bytes());
+        out[32..40].copy_from_slice(&self.directory.offset.to_le_bytes());
+        out[40..48].copy_from_slice(&self.directory.len.to_le_bytes());
+        let crc = crc32fast::hash(&out[..48]);
+        out[48..52].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    /// Decode a slot, returning `None` if it is invalid.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().expect("len 4"));
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().expect("len 8"));
+
+        if &bytes[..8] != MAGIC
+            || u32_at(8) != VERSION
+            || u32_at(12) as usize != GROUP_SIZE
+            || u32_at(48) != crc32fast::hash(&bytes[..48])
+        {
+            return None;
+        }
+
+        Some(Self {
+            generation: u64_at(16),
+            len: u64_at(24),
+            directory: Span {
+                offset: u64_at(32),
+                len: u64_at(40),
+            },
+        })
+    }
+}
+
+/// A decompressed group held in memory.
+struct CachedGroup {
+    data: Box<[u8]>,
+    dirty: bool,
+}
+
+/// Mutable state of a [`CompressedBackend`].
+struct State {
+    /// Logical length of the database.
+    len: u64,
+    /// Location of each group's compressed data.
+    directory: Vec<Span>,
+    /// Location of the directory referenced by the most recent commit.
+    committed_directory: Span,
+    generation: u64,
+    /// Sorted, non-adjacent spans which may be reused immediately.
+    free: Vec<Span>,
+    /// Spans which have been released since the most recent commit, and may still be referenced by it.
+    pending_free: Vec<Span>,
+    /// End of the allocated area of the physical file.
+    end: u64,
+    groups: BTreeMap<u64, CachedGroup>,
+}
+
+impl State {
+    fn restore(slot: HeaderSlot, directory: Vec<Span>) -> Self {
+        let mut live = directory
+            .iter()
+            .copied()
+            .chain([slot.directory])
+            .filter(|span| span.len > 0)
+            .collect::<Vec<_>>();
+        live.sort_by_key(|span| span.offset);
+
+        let mut free = Vec::new();
+        let mut end = DATA_START;
+        for span in live {
+            if span.offset > end {
+                free.push(Span {
+                    offset: end,
+                    len: span.offset - end,
+                });
+            }
+            end = end.max(span.end());
+        }
+
+        Self {
+            len: slot.len,
+            directory,
+            committed_directory: slot.directory,
+            generation: slot.generation,
+            free,
+            pending_free: Vec::new(),
+            end,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    fn allocate(&mut self, len: u64) -> u64 {
+        match self.free.iter().position(|span| span.len >= len) {
+            Some(idx) => {
+                let span = &mut self.free[idx];
+                let offset = span.offset;
+                span.offset += len;
+                span.len -= len;
+                if span.len == 0 {
+                    self.free.remove(idx);
+                }
+                offset
+            }
+            None => {
+                let offset = self.end;
+                self.end += len;
+                offset
+            }
+        }
+    }
+
+    fn release(&mut self, span: Span) {
+        if span.len > 0 {
+            self.pending_free.push(span);
+        }
+    }
+
+    /// Make all spans released before the most recent commit reusable, and trim free space from the end.
+    fn reclaim(&mut self) {
+        for span in std::mem::take(&mut self.pending_free) {
+            let idx = self.free.partition_point(|free| free.offset < span.offset);
+            self.free.insert(idx, span);
+            // merge with the following span, then with the preceding one
+            if idx + 1 < self.free.len() && self.free[idx].end() == self.free[idx + 1].offset {
+                self.free[idx].len += self.free[idx + 1].len;
+                self.free.remove(idx + 1);
+            }
+            if idx > 0 && self.free[idx - 1].end() == self.free[idx].offset {
+                self.free[idx - 1].len += self.free[idx].len;
+                self.free.remove(idx);
+            }
+        }
+
+        while let Some(last) = self.free.last().copied() {
+            if last.end() != self.end {
+                break;
+            }
+            self.end = last.offset;
+            self.free.pop();
+        }
+    }
+
+    fn read_group(&self, inner: &impl StorageBackend, index: u64) -> IoResult<Box<[u8]>> {
+        let mut data = vec![0; GROUP_SIZE].into_boxed_slice();
+        let span = self
+            .directory
+            .get(index as usize)
+            .copied()
+            .unwrap_or(Span::EMPTY);
+        if span.len == 0 {
+            return Ok(data);
+        }
+
+        let mut compressed = vec![0; span.len as usize];
+        inner.read(span.offset, &mut compressed)?;
+        let decompressed = lz4_flex::block::decompress_into(&compressed, &mut data)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        if decompressed != GROUP_SIZE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("group {index} decompressed to {decompressed} bytes"),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Get a mutable reference to the cached group `index`.
+    ///
+    /// If `load` is false, the group's existing content is not read, as the caller will overwrite all of it.
+    fn group_mut(
+        &mut self,
+        inner: &impl StorageBackend,
+        index: u64,
+        load: bool,
+    ) -> IoResult<&mut CachedGroup> {
+        if !self.groups.contains_key(&index) {
+            self.make_room(inner)?;
+            let data = if load {
+                self.read_group(inner, index)?
+            } else {
+                vec![0; GROUP_SIZE].into_boxed_slice()
+            };
+            self.groups
+                .insert(index, CachedGroup { data, dirty: false });
+        }
+        Ok(self.groups.get_mut(&index).expect("group is cached"))
+    }
+
+    fn group(&mut self, inner: &impl StorageBackend, index: u64) -> IoResult<&[u8]> {
+        self.group_mut(inner, index, true)
+            .map(|group| &group.data[..])
+    }
+
+    /// Ensure there is space for one more group in the cache.
+    fn make_room(&mut self, inner: &impl StorageBackend) -> IoResult<()> {
+        if self.groups.len() < CACHED_GROUPS {
+            return Ok(());
+        }
+        if !self.groups.values().any(|group| !group.dirty) {
+            self.spill(inner)?;
+        }
+        let clean = self
+            .groups
+            .iter()
+            .find_map(|(&index, group)| (!group.dirty).then_some(index))
+            .expect("a clean group exists after spilling");
+        self.groups.remove(&clean);
+        Ok(())
+    }
+
+    /// Compress and write out all dirty groups.
+    fn spill(&mut self, inner: &impl StorageBackend) -> IoResult<()> {
+        let dirty = self
+            .groups
+            .iter()
+            .filter_map(|(&index, group)| group.dirty.then_some(index))
+            .collect::<Vec<_>>();
+
+        for index in dirty {
+            let group = self.groups.get(&index).expect("group is cached");
+            let span = if group.data.iter().all(|&b| b == 0) {
+                Span::EMPTY
+            } else {
+                let compressed = lz4_flex::block::compress(&group.data);
+                let len = compressed.len() as u64;
+                let offset = self.allocate(len);
+                inner.write(offset, &compressed)?;
+                Span { offset, len }
+            };
+
+            self.groups.get_mut(&index).expect("group is cached").dirty = false;
+            let old = std::mem::replace(&mut self.directory[index as usize], span);
+            self.release(old);
+        }
+        Ok(())
+    }
+}
+
+/// Implementation of a [`StorageBackend`] which compresses all data before handing it to an inner backend.
+///
+/// See the [module documentation][self] for details.
+pub struct CompressedBackend<B> {
+    inner: B,
+    state: Mutex<State>,
+}
+
+impl<B: fmt::Debug> fmt::Debug for CompressedBackend<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedBackend")
+            .field("inner", &self.inner)
+            .field("len", &self.state.lock().len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: StorageBackend> CompressedBackend<B> {
+    /// Wrap `inner`, compressing all data.
+    ///
+    /// If `inner` is empty, new headers are written. Otherwise the most recent commit is loaded.
+    pub fn new(inner: B) -> Result<Self> {
+        let state = if inner.len()? == 0 {
+            let slot = HeaderSlot {
+                generation: 1,
+                len: 0,
+                directory: Span::EMPTY,
+            };
+            let mut headers = [0; DATA_START as usize];
+            let at = slot_offset(slot.generation) as usize;
+            headers[at..at + HEADER_SLOT_LEN].copy_from_slice(&slot.encode());
+            inner.write(0, &headers)?;
+            State::restore(slot, Vec::new())
+        } else {
+            let mut headers = [0; DATA_START as usize];
+            inner.read(0, &mut headers)?;
+            let (first, second) = headers.split_at(HEADER_SLOT_LEN);
+            let slot = [HeaderSlot::decode(first), HeaderSlot::decode(second)]
+                .into_iter()
+                .flatten()
+                .max_by_key(|slot| slot.generation)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        "not a compressed redb-opfs database, or both headers are corrupted",
+                    )
+                })?;
+
+            let mut bytes = vec![0; slot.directory.len as usize];
+            inner.read(slot.directory.offset, &mut bytes)?;
+            let directory = bytes
+                .chunks_exact(DIRECTORY_ENTRY_LEN)
+                .map(|entry| Span {
+                    offset: u64::from_le_bytes(entry[..8].try_into().expect("len 8")),
+                    len: u64::from_le_bytes(entry[8..].try_into().expect("len 8")),
+                })
+                .collect::<Vec<_>>();
+            if directory.len() as u64 != group_count(slot.len) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "directory does not match database length",
+                )
+                .into());
+            }
+
+            State::restore(slot, directory)
+        };
+
+        Ok(Self {
+            inner,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Unwrap this backend, returning the inner backend.
+    ///
+    /// Changes since the last [`sync_data`][StorageBackend::sync_data] are discarded.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for CompressedBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        Ok(self.state.lock().len)
+    }
+
+    fn set_len(&self, new_len: u64) -> IoResult<()> {
+        let mut state = self.state.lock();
+        let count = group_count(new_len);
+
+        if new_len < state.len {
+            // See [`crate::chunks`] for why the tail of the last group is zeroed.
+            let tail = (new_len % GROUP_SIZE as u64) as usize;
+            if tail != 0 {
+                let group = state.group_mut(&self.inner, new_len / GROUP_SIZE as u64, true)?;
+                group.data[tail..].fill(0);
+                group.dirty = true;
+            }
+
+            let dropped = state.directory.split_off(count as usize);
+            for span in dropped {
+                state.release(span);
+            }
+            state.groups.retain(|&index, _| index < count);
+        } else {
+            state.directory.resize(count as usize, Span::EMPTY);
+        }
+
+        state.len = new_len;
+        Ok(())
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let mut state = self.state.lock();
+        state.spill(&self.inner)?;
+
+        let bytes = state
+            .directory
+            .iter()
+            .flat_map(|span| {
+                let mut entry = [0; DIRECTORY_ENTRY_LEN];
+                entry[..8].copy_from_slice(&span.offset.to_le_bytes());
+                entry[8..].copy_from_slice(&span.len.to_le_bytes());
+                entry
+            })
+            .collect::<Vec<_>>();
+        let directory = if bytes.is_empty() {
+            Span::EMPTY
+        } else {
+            let len = bytes.len() as u64;
+            let offset = state.allocate(len);
+            self.inner.write(offset, &bytes)?;
+            Span { offset, len }
+        };
+        self.inner.sync_data()?;
+
+        // Only now that everything the new header references is durable may it be written.
+        let slot = HeaderSlot {
+            generation: state.generation + 1,
+            len: state.len,
+            directory,
+        };
+        self.inner
+            .write(slot_offset(slot.generation), &slot.encode())?;
+        self.inner.sync_data()?;
+
+        state.generation = slot.generation;
+        let previous = std::mem::replace(&mut state.committed_directory, directory);
+        state.release(previous);
+        state.reclaim();
+        if self.inner.len()? > state.end {
+            self.inner.set_len(state.end)?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let mut state = self.state.lock();
+        let end = offset
+            .checked_add(out.len() as u64)
+            .filter(|&end| end <= state.len)
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+
+        for_each_chunk(GROUP_SIZE, offset, end, |index, in_group, in_buf| {
+            let group = state.group(&self.inner, index)?;
+            out[in_buf].copy_from_slice(&group[in_group]);
+            Ok(())
+        })
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let mut state = self.state.lock();
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            )
+        })?;
+        // Extend first, and together, so that the directory always matches the length, even if writing a group fails
+        // and the next commit includes whatever was written so far.
+        if end > state.len {
+            state
+                .directory
+                .resize(group_count(end) as usize, Span::EMPTY);
+            state.len = end;
+        }
+
+        for_each_chunk(GROUP_SIZE, offset, end, |index, in_group, in_buf| {
+            let load = in_group.len() != GROUP_SIZE;
+            let group = state.group_mut(&self.inner, index, load)?;
+            group.data[in_group].copy_from_slice(&data[in_buf]);
+            group.dirty = true;
+            Ok(())
+        })
+    }
+}
+
+/// Offset of the header slot used by the commit with the given generation.
+fn slot_offset(generation: u64) -> u64 {
+    (generation % 2) * HEADER_SLOT_LEN as u64
+}
+
+/// Number of groups required to hold `len` bytes of logical data.
+fn group_count(len: u64) -> u64 {
+    len.div_ceil(GROUP_SIZE as u64)
+}
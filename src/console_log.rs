@@ -0,0 +1,58 @@
+//! Debug logs through the [`log`](https://docs.rs/log) facade, enabled by the `console-log` feature.
+//!
+//! Backends log when they open files, acquire handles and Web Locks, and flush, and errors log as they are converted
+//! from and to JS. Any `log` implementation receives them; on wasm, [`init_console_log`] installs one which writes to
+//! the browser console.
+
+#[cfg(all(opfs, feature = "console-log"))]
+use wasm_bindgen::prelude::*;
+
+/// Log a debug message under the `redb_opfs` target, if the `console-log` feature is enabled.
+///
+/// Without the feature, this expands to nothing, so the arguments are not evaluated.
+macro_rules! debug_log {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "console-log")]
+        log::debug!(target: "redb_opfs", $($arg)*);
+    }};
+}
+
+pub(crate) use debug_log;
+
+/// A `log` implementation writing each record to the console method of its level.
+#[cfg(all(opfs, feature = "console-log"))]
+struct ConsoleLogger;
+
+#[cfg(all(opfs, feature = "console-log"))]
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let message = JsValue::from(format!("[{}] {}", record.target(), record.args()));
+        match record.level() {
+            log::Level::Error => web_sys::console::error_1(&message),
+            log::Level::Warn => web_sys::console::warn_1(&message),
+            log::Level::Info => web_sys::console::info_1(&message),
+            log::Level::Debug | log::Level::Trace => web_sys::console::debug_1(&message),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Write log records up to debug level to the browser console, returning whether this installed the logger.
+///
+/// Does nothing if a logger is already installed, for example by another crate in the same module. Browsers hide
+/// debug messages by default; enable the "Verbose" level in the developer tools to see them.
+#[cfg(all(opfs, feature = "console-log"))]
+#[wasm_bindgen(js_name = initConsoleLog)]
+pub fn init_console_log() -> bool {
+    static LOGGER: ConsoleLogger = ConsoleLogger;
+    if log::set_logger(&LOGGER).is_err() {
+        return false;
+    }
+    log::set_max_level(log::LevelFilter::Debug);
+    true
+}
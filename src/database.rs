@@ -0,0 +1,46 @@
+//! Opening a [`redb::Database`] in one step.
+
+use std::io;
+
+use redb::{Database, StorageBackend};
+
+use crate::{OpfsBackend, OpfsBackendBuilder, Result};
+
+/// Size of redb's page cache for databases opened by [`open_database`] on wasm, in bytes.
+///
+/// redb's default of 1 GiB suits servers; browsers may kill a tab long before its memory use gets there.
+pub const BROWSER_CACHE_SIZE: usize = 16 << 20;
+
+/// Open the database at `path`, creating it if it does not exist.
+///
+/// This is shorthand for opening an [`OpfsBackend`] and handing it to [`redb::Builder::create_with_backend`]. On
+/// wasm, redb's cache is limited to [`BROWSER_CACHE_SIZE`]; natively, redb's defaults apply. For other options,
+/// use [`open_database_with`], or open the backend and the database separately.
+pub async fn open_database(path: &str) -> Result<Database> {
+    open_database_with(OpfsBackend::builder(), path).await
+}
+
+/// Like [`open_database`], but opening the backend with `builder`.
+pub async fn open_database_with(builder: OpfsBackendBuilder, path: &str) -> Result<Database> {
+    let backend = builder.open(path).await?;
+    create_database(backend)
+}
+
+/// Open the database stored in `backend`, with the cache size [`open_database`] uses.
+pub(crate) fn create_database(backend: impl StorageBackend) -> Result<Database> {
+    let mut database = Database::builder();
+    #[cfg(opfs)]
+    database.set_cache_size(BROWSER_CACHE_SIZE);
+    let database = database.create_with_backend(backend).map_err(redb_error)?;
+    Ok(database)
+}
+
+/// Convert an error from redb into an [`io::Error`].
+///
+/// I/O errors are unwrapped, which keeps the variants of [`crate::Error`] which went through redb.
+pub(crate) fn redb_error(err: impl Into<redb::Error>) -> io::Error {
+    match err.into() {
+        redb::Error::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
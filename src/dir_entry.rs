@@ -0,0 +1,46 @@
+//! Entries of a directory, as listed by [`OpfsBackend::read_dir`][crate::OpfsBackend::read_dir].
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+/// An entry in a directory.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub(crate) name: String,
+    pub(crate) is_dir: bool,
+    pub(crate) size: Option<u64>,
+    pub(crate) last_modified: Option<u64>,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl DirEntry {
+    /// Name of the entry within its directory.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Whether this entry is a directory.
+    #[cfg_attr(opfs, wasm_bindgen(getter = isDirectory))]
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Size of the file in bytes.
+    ///
+    /// `None` for directories, and for files whose metadata is unavailable
+    /// (on wasm, for example, while another worker holds a sync access handle to them).
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Time of the last modification of the file, in milliseconds since the Unix epoch.
+    ///
+    /// `None` when unavailable, as for [`size`][Self::size].
+    #[cfg_attr(opfs, wasm_bindgen(getter = lastModified))]
+    pub fn last_modified(&self) -> Option<u64> {
+        self.last_modified
+    }
+}
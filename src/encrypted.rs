@@ -0,0 +1,1068 @@
+//! A [`StorageBackend`] wrapper which transparently encrypts all data with AES-256-GCM.
+//!
+//! ## On-disk layout
+//!
+//! The file begins with a fixed-size header of [`HEADER_LEN`] bytes:
+//!
+//! | offset | len | content                                                                        |
+//! | ------ | --- | ------------------------------------------------------------------------------ |
+//! | 0      | 8   | magic: `RDBOPFSE`                                                              |
+//! | 8      | 4   | format version, u32 LE                                                         |
+//! | 12     | 4   | page size, u32 LE                                                              |
+//! | 16     | 8   | logical length of the database, u64 LE                                         |
+//! | 24     | 16  | random file id                                                                 |
+//! | 40     | 16  | KDF salt, or zero if the key was supplied directly                              |
+//! | 56     | 4   | KDF iterations, u32 LE                                                         |
+//! | 60     | 1   | KDF algorithm: 0 = none, 1 = PBKDF2-HMAC-SHA256                                |
+//! | 61     | 1   | key rotation state: 0 = none, 1 = to a raw key, 2 = to a password              |
+//! | 62     | 16  | pending KDF salt, when rotating to a password                                  |
+//! | 78     | 4   | pending KDF iterations, u32 LE, when rotating to a password                    |
+//! | 82     | 18  | reserved, zero                                                                 |
+//! | 100    | 12  | header nonce                                                                   |
+//! | 112    | 16  | header tag: AES-GCM over the empty message, with bytes `0..100` as associated data |
+//!
+//! The header is followed by a dense sequence of encrypted pages. Each page holds [`PAGE_SIZE`] bytes of logical data,
+//! and is laid out as `nonce (12) | ciphertext (PAGE_SIZE) | tag (16)`. The associated data of each page is the file id
+//! followed by the page index as u64 LE, so pages cannot be moved within or between files without detection.
+//!
+//! Every page within the logical length is encrypted, including the pages the file is extended by, which hold zeros,
+//! so a page which was zeroed or otherwise modified fails authentication.
+//!
+//! Every page write uses a fresh random 96-bit nonce. Keys should therefore be rotated well before
+//! 2<sup>32</sup> page writes have been performed.
+//!
+//! ## Passwords
+//!
+//! [`EncryptedBackend::open_with_password`] derives the key from a passphrase with PBKDF2-HMAC-SHA256, using a random
+//! salt and the iteration count stored in the header. Because the header is authenticated with the derived key,
+//! a wrong password is detected at open.
+//!
+//! ## Key rotation
+//!
+//! [`EncryptedBackend::rotate_key`] and [`EncryptedBackend::rotate_password`] re-encrypt every page in place.
+//! Before any page is touched, the header (still sealed with the old key) records that a rotation is in progress,
+//! along with the parameters of the new key. If rotation is interrupted, restarting it with the same arguments
+//! resumes it: pages which no longer authenticate with the old key are decrypted with the new key instead.
+//! A database with an unfinished rotation cannot be opened normally.
+//!
+//! ## WebCrypto keys
+//!
+//! SubtleCrypto is asynchronous, while [`StorageBackend`] is synchronous, so pages cannot be encrypted by WebCrypto
+//! directly. Instead, when built for wasm, the data key may be stored wrapped by a WebCrypto AES-GCM [`CryptoKey`]
+//! (see [`wrap_key`] and [`EncryptedBackend::with_wrapped_key`]). The `CryptoKey` may be non-extractable; its key
+//! material never enters wasm memory. The data key is unwrapped once, at open, and lives in wasm memory only for the
+//! lifetime of the backend.
+//!
+//! The wrapped key is laid out as `iv (12) | ciphertext (32) | tag (16)`.
+//!
+//! ## Backups
+//!
+//! [`BackupWriter`] encrypts a stream, such as an export from
+//! [`OpfsBackend::export_to`][crate::OpfsBackend::export_to], so that backups leaving the browser aren't plaintext,
+//! and [`BackupReader`] decrypts it again. Unlike the file of an [`EncryptedBackend`], which is accessed randomly, a
+//! backup is written once, from start to end. It begins with a header of [`BACKUP_HEADER_LEN`] bytes:
+//!
+//! | offset | len | content                                                    |
+//! | ------ | --- | ---------------------------------------------------------- |
+//! | 0      | 8   | magic: `RDBOPFSB`                                          |
+//! | 8      | 4   | format version, u32 LE                                     |
+//! | 12     | 4   | chunk size, u32 LE                                         |
+//! | 16     | 16  | random backup id                                           |
+//! | 32     | 16  | KDF salt, or zero if the key was supplied directly         |
+//! | 48     | 4   | KDF iterations, u32 LE                                     |
+//! | 52     | 1   | KDF algorithm: 0 = none, 1 = PBKDF2-HMAC-SHA256            |
+//! | 53     | 11  | reserved, zero                                             |
+//!
+//! The header is followed by chunks, each laid out as `len (4, u32 LE) | nonce (12) | ciphertext (len) | tag (16)`.
+//! Every chunk but the last holds exactly the chunk size of plaintext; the last holds less, possibly nothing, which
+//! marks the end of the backup. The associated data of each chunk is the header, followed by the chunk index as u64
+//! LE and `len`, so the header is authenticated along with the first chunk, and chunks cannot be reordered, dropped
+//! or truncated without detection.
+//!
+//! [`CryptoKey`]: web_sys::CryptoKey
+
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use aes_gcm::{
+    Aes256Gcm, Nonce, Tag,
+    aead::{AeadInPlace as _, KeyInit as _},
+};
+#[cfg(opfs)]
+use js_sys::{Object, Reflect, Uint8Array};
+use parking_lot::Mutex;
+use sha2::Sha256;
+#[cfg(opfs)]
+use wasm_bindgen::JsCast as _;
+#[cfg(opfs)]
+use wasm_bindgen_futures::JsFuture;
+#[cfg(opfs)]
+use web_sys::CryptoKey;
+
+use crate::{IoResult, Result, StorageBackend, chunks::for_each_chunk};
+
+/// Length of an encryption key in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// An AES-256 key.
+pub type Key = [u8; KEY_LEN];
+
+/// Size of the plaintext contained in each encrypted page.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Size of the header region at the start of the file.
+pub const HEADER_LEN: usize = 128;
+
+/// Number of PBKDF2 iterations used when creating a password-protected database.
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Largest PBKDF2 iteration count accepted from a header, which is read before anything is authenticated.
+const MAX_PBKDF2_ITERATIONS: u32 = 4 * PBKDF2_ITERATIONS;
+
+const MAGIC: &[u8; 8] = b"RDBOPFSE";
+const VERSION: u32 = 2;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const FILE_ID_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+const KDF_NONE: u8 = 0;
+const KDF_PBKDF2_SHA256: u8 = 1;
+const ROTATION_NONE: u8 = 0;
+const ROTATION_TO_KEY: u8 = 1;
+const ROTATION_TO_PASSWORD: u8 = 2;
+const HEADER_AAD_LEN: usize = 100;
+const PHYSICAL_PAGE_SIZE: usize = NONCE_LEN + PAGE_SIZE + TAG_LEN;
+
+/// Size of the header at the start of a backup.
+pub const BACKUP_HEADER_LEN: usize = 64;
+
+/// Size of the plaintext contained in each chunk of a backup, but the last.
+const BACKUP_CHUNK_SIZE: usize = 64 * 1024;
+const BACKUP_MAGIC: &[u8; 8] = b"RDBOPFSB";
+const BACKUP_VERSION: u32 = 1;
+
+/// Parameters for deriving a key from a password.
+#[derive(Debug, Clone, Copy)]
+struct KdfParams {
+    salt: [u8; SALT_LEN],
+    iterations: u32,
+}
+
+impl KdfParams {
+    fn generate() -> IoResult<Self> {
+        let mut salt = [0; SALT_LEN];
+        fill_random(&mut salt)?;
+        Ok(Self {
+            salt,
+            iterations: PBKDF2_ITERATIONS,
+        })
+    }
+
+    fn derive(&self, password: &[u8]) -> Key {
+        let mut key = [0; KEY_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password, &self.salt, self.iterations, &mut key);
+        key
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..SALT_LEN].copy_from_slice(&self.salt);
+        out[SALT_LEN..SALT_LEN + 4].copy_from_slice(&self.iterations.to_le_bytes());
+    }
+
+    /// Decode parameters read from an unauthenticated header, rejecting iteration counts which would make deriving
+    /// the key take unreasonably long.
+    fn decode(bytes: &[u8]) -> IoResult<Self> {
+        let iterations = u32::from_le_bytes(
+            bytes[SALT_LEN..SALT_LEN + 4]
+                .try_into()
+                .expect("slice has len 4"),
+        );
+        if iterations > MAX_PBKDF2_ITERATIONS {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("too many key derivation iterations: {iterations}"),
+            ));
+        }
+        Ok(Self {
+            salt: bytes[..SALT_LEN].try_into().expect("slice has len 16"),
+            iterations,
+        })
+    }
+}
+
+/// An in-progress key rotation, recording the KDF parameters of the new key.
+#[derive(Debug, Clone, Copy)]
+struct Rotation {
+    kdf: Option<KdfParams>,
+}
+
+/// Where the encryption key comes from.
+#[derive(Clone, Copy)]
+pub(crate) enum KeySource<'a> {
+    Key(&'a Key),
+    Password(&'a [u8]),
+}
+
+impl KeySource<'_> {
+    /// Construct the cipher for this key source, given the KDF parameters of the file.
+    fn cipher(self, kdf: Option<&KdfParams>) -> IoResult<Aes256Gcm> {
+        let key = match (self, kdf) {
+            (KeySource::Key(key), _) => *key,
+            (KeySource::Password(password), Some(kdf)) => kdf.derive(password),
+            (KeySource::Password(_), None) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "database is not password-protected",
+                ));
+            }
+        };
+        Ok(new_cipher(&key))
+    }
+}
+
+/// Decoded form of the file header.
+struct Header {
+    len: u64,
+    file_id: [u8; FILE_ID_LEN],
+    kdf: Option<KdfParams>,
+    rotation: Option<Rotation>,
+}
+
+impl Header {
+    fn encode(&self, cipher: &Aes256Gcm) -> IoResult<[u8; HEADER_LEN]> {
+        let mut out = [0; HEADER_LEN];
+        out[..8].copy_from_slice(MAGIC);
+        out[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        out[12..16].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        out[16..24].copy_from_slice(&self.len.to_le_bytes());
+        out[24..40].copy_from_slice(&self.file_id);
+        if let Some(kdf) = &self.kdf {
+            kdf.encode(&mut out[40..60]);
+            out[60] = KDF_PBKDF2_SHA256;
+        }
+        match &self.rotation {
+            None => {}
+            Some(Rotation { kdf: None }) => out[61] = ROTATION_TO_KEY,
+            Some(Rotation { kdf: Some(kdf) }) => {
+                kdf.encode(&mut out[62..82]);
+                out[61] = ROTATION_TO_PASSWORD;
+            }
+        }
+
+        let (aad, seal) = out.split_at_mut(HEADER_AAD_LEN);
+        let (nonce, tag) = seal.split_at_mut(NONCE_LEN);
+        fill_random(nonce)?;
+        let computed = cipher
+            .encrypt_in_place_detached(Nonce::from_slice(nonce), aad, &mut [])
+            .map_err(|_| io::Error::other("failed to seal header"))?;
+        tag.copy_from_slice(&computed);
+
+        Ok(out)
+    }
+
+    /// Parse the header without authenticating it.
+    fn parse(bytes: &[u8; HEADER_LEN]) -> IoResult<Self> {
+        if &bytes[..8] != MAGIC {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "not an encrypted redb-opfs database",
+            ));
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().expect("slice has len 4"));
+        if version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported encrypted format version {version}"),
+            ));
+        }
+        let page_size = u32::from_le_bytes(bytes[12..16].try_into().expect("slice has len 4"));
+        if page_size as usize != PAGE_SIZE {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported encrypted page size {page_size}"),
+            ));
+        }
+
+        let len = u64::from_le_bytes(bytes[16..24].try_into().expect("slice has len 8"));
+        let file_id = bytes[24..40].try_into().expect("slice has len 16");
+        let kdf = match bytes[60] {
+            KDF_NONE => None,
+            KDF_PBKDF2_SHA256 => Some(KdfParams::decode(&bytes[40..60])?),
+            other => {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported key derivation algorithm {other}"),
+                ));
+            }
+        };
+        let rotation = match bytes[61] {
+            ROTATION_NONE => None,
+            ROTATION_TO_KEY => Some(Rotation { kdf: None }),
+            ROTATION_TO_PASSWORD => Some(Rotation {
+                kdf: Some(KdfParams::decode(&bytes[62..82])?),
+            }),
+            other => {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported key rotation state {other}"),
+                ));
+            }
+        };
+
+        Ok(Self {
+            len,
+            file_id,
+            kdf,
+            rotation,
+        })
+    }
+
+    /// Verify that `bytes` were sealed with `cipher`.
+    fn authenticate(bytes: &[u8; HEADER_LEN], cipher: &Aes256Gcm) -> IoResult<()> {
+        let (aad, seal) = bytes.split_at(HEADER_AAD_LEN);
+        let (nonce, tag) = seal.split_at(NONCE_LEN);
+        cipher
+            .decrypt_in_place_detached(Nonce::from_slice(nonce), aad, &mut [], Tag::from_slice(tag))
+            .map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    "header authentication failed: wrong key or corrupted header",
+                )
+            })
+    }
+}
+
+/// Implementation of a [`StorageBackend`] which encrypts all data before handing it to an inner backend.
+///
+/// See the [module documentation][self] for the on-disk layout.
+pub struct EncryptedBackend<B> {
+    inner: B,
+    cipher: Aes256Gcm,
+    file_id: [u8; FILE_ID_LEN],
+    kdf: Option<KdfParams>,
+    rotation: Option<Rotation>,
+    /// Logical length of the database.
+    ///
+    /// This mutex also serializes all operations, as writes perform read-modify-write cycles on whole pages.
+    len: Mutex<u64>,
+}
+
+impl<B: fmt::Debug> fmt::Debug for EncryptedBackend<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedBackend")
+            .field("inner", &self.inner)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: StorageBackend> EncryptedBackend<B> {
+    /// Wrap `inner`, encrypting all data with `key`.
+    ///
+    /// If `inner` is empty, a new header is written. Otherwise the existing header is authenticated
+    /// with `key`; this fails if the key is wrong or the header is corrupted.
+    pub fn new(inner: B, key: &Key) -> Result<Self> {
+        Self::open(inner, KeySource::Key(key))
+    }
+
+    /// Wrap `inner`, encrypting all data with a key derived from `password`.
+    ///
+    /// If `inner` is empty, a new random salt is generated and stored in the header. Otherwise the key is derived
+    /// using the salt and iteration count from the existing header; this fails if the password is wrong,
+    /// or if the database was created with a raw key. A header asking for more than four times [`PBKDF2_ITERATIONS`]
+    /// iterations is rejected as [`ErrorKind::InvalidData`] before deriving anything.
+    ///
+    /// Key derivation is deliberately slow, and blocks for the duration.
+    pub fn open_with_password(inner: B, password: impl AsRef<[u8]>) -> Result<Self> {
+        Self::open(inner, KeySource::Password(password.as_ref()))
+    }
+
+    fn open(inner: B, source: KeySource<'_>) -> Result<Self> {
+        let (cipher, header) = if inner.len()? == 0 {
+            let kdf = match source {
+                KeySource::Key(_) => None,
+                KeySource::Password(_) => Some(KdfParams::generate()?),
+            };
+            let cipher = source.cipher(kdf.as_ref())?;
+            let mut file_id = [0; FILE_ID_LEN];
+            fill_random(&mut file_id)?;
+            let header = Header {
+                len: 0,
+                file_id,
+                kdf,
+                rotation: None,
+            };
+            inner.write(0, &header.encode(&cipher)?)?;
+            (cipher, header)
+        } else {
+            let mut bytes = [0; HEADER_LEN];
+            inner.read(0, &mut bytes)?;
+            let header = Header::parse(&bytes)?;
+            let cipher = source.cipher(header.kdf.as_ref())?;
+            Header::authenticate(&bytes, &cipher)?;
+            if header.rotation.is_some() {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "an interrupted key rotation must be resumed before opening",
+                )
+                .into());
+            }
+            (cipher, header)
+        };
+
+        Ok(Self {
+            inner,
+            cipher,
+            file_id: header.file_id,
+            kdf: header.kdf,
+            rotation: None,
+            len: Mutex::new(header.len),
+        })
+    }
+
+    /// Re-encrypt `inner` in place, replacing the raw key `old` with `new`.
+    ///
+    /// If a previous rotation with the same keys was interrupted, this resumes it.
+    /// See the [module documentation][self#key-rotation] for details.
+    ///
+    /// This rewrites every page, so it takes time proportional to the size of the database.
+    pub fn rotate_key(inner: B, old: &Key, new: &Key) -> Result<Self> {
+        Self::rotate(inner, KeySource::Key(old), KeySource::Key(new))
+    }
+
+    /// Re-encrypt `inner` in place, replacing a key derived from `old` with one derived from `new`.
+    ///
+    /// A fresh salt is generated for the new password.
+    /// If a previous rotation with the same passwords was interrupted, this resumes it.
+    pub fn rotate_password(inner: B, old: impl AsRef<[u8]>, new: impl AsRef<[u8]>) -> Result<Self> {
+        Self::rotate(
+            inner,
+            KeySource::Password(old.as_ref()),
+            KeySource::Password(new.as_ref()),
+        )
+    }
+
+    fn rotate(inner: B, old: KeySource<'_>, new: KeySource<'_>) -> Result<Self> {
+        let mut bytes = [0; HEADER_LEN];
+        inner.read(0, &mut bytes)?;
+        let header = Header::parse(&bytes)?;
+
+        let old_cipher = old.cipher(header.kdf.as_ref())?;
+        if Header::authenticate(&bytes, &old_cipher).is_err() {
+            // An interrupted rotation may have completed everything but returning.
+            return Self::open(inner, new);
+        }
+
+        let rotation = match (header.rotation, new) {
+            (Some(rotation), _) => rotation,
+            (None, KeySource::Key(_)) => Rotation { kdf: None },
+            (None, KeySource::Password(_)) => Rotation {
+                kdf: Some(KdfParams::generate()?),
+            },
+        };
+        let new_cipher = new.cipher(rotation.kdf.as_ref())?;
+
+        let mut backend = Self {
+            inner,
+            cipher: old_cipher,
+            file_id: header.file_id,
+            kdf: header.kdf,
+            rotation: Some(rotation),
+            len: Mutex::new(header.len),
+        };
+
+        // Persist the rotation parameters before touching any page, so that the new key can be re-derived on resume.
+        backend.write_header(header.len)?;
+        backend.inner.sync_data()?;
+
+        let mut page = [0; PAGE_SIZE];
+        for index in 0..page_count(header.len) {
+            if backend.read_page(index, &mut page).is_err() {
+                // rotated by an interrupted run
+                backend.read_page_with(&new_cipher, index, &mut page)?;
+            }
+            backend.write_page_with(&new_cipher, index, &page)?;
+        }
+        backend.inner.sync_data()?;
+
+        backend.cipher = new_cipher;
+        backend.kdf = rotation.kdf;
+        backend.rotation = None;
+        backend.write_header(header.len)?;
+        backend.inner.sync_data()?;
+
+        Ok(backend)
+    }
+
+    /// Unwrap this backend, returning the inner backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn aad(&self, index: u64) -> [u8; FILE_ID_LEN + 8] {
+        let mut aad = [0; FILE_ID_LEN + 8];
+        aad[..FILE_ID_LEN].copy_from_slice(&self.file_id);
+        aad[FILE_ID_LEN..].copy_from_slice(&index.to_le_bytes());
+        aad
+    }
+
+    /// Read and decrypt page `index`.
+    ///
+    /// Must only be called for pages which exist physically, i.e. `index < page_count(len)`.
+    fn read_page(&self, index: u64, out: &mut [u8; PAGE_SIZE]) -> IoResult<()> {
+        self.read_page_with(&self.cipher, index, out)
+    }
+
+    fn read_page_with(
+        &self,
+        cipher: &Aes256Gcm,
+        index: u64,
+        out: &mut [u8; PAGE_SIZE],
+    ) -> IoResult<()> {
+        let mut buf = [0; PHYSICAL_PAGE_SIZE];
+        self.inner.read(page_offset(index), &mut buf)?;
+
+        let (nonce, rest) = buf.split_at_mut(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at_mut(PAGE_SIZE);
+        cipher
+            .decrypt_in_place_detached(
+                Nonce::from_slice(nonce),
+                &self.aad(index),
+                ciphertext,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("page {index} failed authentication"),
+                )
+            })?;
+        out.copy_from_slice(ciphertext);
+        Ok(())
+    }
+
+    /// Encrypt and write page `index`.
+    fn write_page(&self, index: u64, plaintext: &[u8; PAGE_SIZE]) -> IoResult<()> {
+        self.write_page_with(&self.cipher, index, plaintext)
+    }
+
+    fn write_page_with(
+        &self,
+        cipher: &Aes256Gcm,
+        index: u64,
+        plaintext: &[u8; PAGE_SIZE],
+    ) -> IoResult<()> {
+        let mut buf = [0; PHYSICAL_PAGE_SIZE];
+        let (nonce, rest) = buf.split_at_mut(NONCE_LEN);
+        fill_random(nonce)?;
+        let (ciphertext, tag) = rest.split_at_mut(PAGE_SIZE);
+        ciphertext.copy_from_slice(plaintext);
+        let computed = cipher
+            .encrypt_in_place_detached(Nonce::from_slice(nonce), &self.aad(index), ciphertext)
+            .map_err(|_| io::Error::other(format!("failed to encrypt page {index}")))?;
+        tag.copy_from_slice(&computed);
+        self.inner.write(page_offset(index), &buf)
+    }
+
+    /// Encrypt zeros into the pages `pages`, which the file is being extended by.
+    fn write_zero_pages(&self, pages: std::ops::Range<u64>) -> IoResult<()> {
+        let zeros = [0; PAGE_SIZE];
+        pages
+            .into_iter()
+            .try_for_each(|index| self.write_page(index, &zeros))
+    }
+
+    fn write_header(&self, len: u64) -> IoResult<()> {
+        let header = Header {
+            len,
+            file_id: self.file_id,
+            kdf: self.kdf,
+            rotation: self.rotation,
+        };
+        self.inner.write(0, &header.encode(&self.cipher)?)
+    }
+}
+
+#[cfg(opfs)]
+impl<B: StorageBackend> EncryptedBackend<B> {
+    /// Wrap `inner`, encrypting all data with a data key which is stored wrapped by `wrapping_key`.
+    ///
+    /// `wrapped_key` must have been produced by [`wrap_key`] with the same `wrapping_key`.
+    /// See the [module documentation][self#webcrypto-keys] for details.
+    pub async fn with_wrapped_key(
+        inner: B,
+        wrapped_key: &[u8],
+        wrapping_key: &CryptoKey,
+    ) -> Result<Self> {
+        let key = unwrap_key(wrapped_key, wrapping_key).await?;
+        Self::new(inner, &key)
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for EncryptedBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        Ok(*self.len.lock())
+    }
+
+    fn set_len(&self, new_len: u64) -> IoResult<()> {
+        let mut len = self.len.lock();
+
+        // See [`crate::chunks`] for why the tail of the last page is zeroed.
+        let tail = (new_len % PAGE_SIZE as u64) as usize;
+        if new_len < *len && tail != 0 {
+            let index = new_len / PAGE_SIZE as u64;
+            let mut page = [0; PAGE_SIZE];
+            self.read_page(index, &mut page)?;
+            page[tail..].fill(0);
+            self.write_page(index, &page)?;
+        }
+
+        self.inner.set_len(page_offset(page_count(new_len)))?;
+        self.write_zero_pages(page_count(*len)..page_count(new_len))?;
+        self.write_header(new_len)?;
+        *len = new_len;
+        Ok(())
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let _guard = self.len.lock();
+        self.inner.sync_data()
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let len = self.len.lock();
+        let end = offset
+            .checked_add(out.len() as u64)
+            .filter(|&end| end <= *len)
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+
+        let mut page = [0; PAGE_SIZE];
+        for_each_chunk(PAGE_SIZE, offset, end, |index, in_page, in_buf| {
+            self.read_page(index, &mut page)?;
+            out[in_buf].copy_from_slice(&page[in_page]);
+            Ok(())
+        })
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let mut len = self.len.lock();
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            )
+        })?;
+        let existing_pages = page_count(*len);
+        self.write_zero_pages(existing_pages..offset / PAGE_SIZE as u64)?;
+
+        let mut page = [0; PAGE_SIZE];
+        for_each_chunk(PAGE_SIZE, offset, end, |index, in_page, in_buf| {
+            if in_page.len() != PAGE_SIZE {
+                if index < existing_pages {
+                    self.read_page(index, &mut page)?;
+                } else {
+                    page.fill(0);
+                }
+            }
+            page[in_page].copy_from_slice(&data[in_buf]);
+            self.write_page(index, &page)
+        })?;
+
+        if end > *len {
+            self.write_header(end)?;
+            *len = end;
+        }
+        Ok(())
+    }
+}
+
+/// A [`Write`] which encrypts everything written to it into a backup, written to an inner writer.
+///
+/// Call [`finish`][Self::finish] once everything is written; a backup which isn't finished lacks its last chunk, and
+/// [`BackupReader`] rejects it as truncated. See the [module documentation][self#backups] for the format.
+///
+/// ```rust
+/// # fn backup(backend: &redb_opfs::OpfsBackend, key: &redb_opfs::encrypted::Key) -> std::io::Result<()> {
+/// use redb_opfs::encrypted::BackupWriter;
+///
+/// let mut writer = BackupWriter::new(Vec::new(), key)?;
+/// backend.export_to(&mut writer, |_, _| {})?;
+/// let backup: Vec<u8> = writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BackupWriter<W: Write> {
+    inner: W,
+    cipher: Aes256Gcm,
+    header: [u8; BACKUP_HEADER_LEN],
+    /// Plaintext of the current chunk, which is sealed once full.
+    chunk: Vec<u8>,
+    index: u64,
+}
+
+impl<W: Write> fmt::Debug for BackupWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackupWriter")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W: Write> BackupWriter<W> {
+    /// Start a backup encrypted with `key`, writing its header to `inner`.
+    pub fn new(inner: W, key: &Key) -> Result<Self> {
+        Self::with_source(inner, KeySource::Key(key))
+    }
+
+    /// Start a backup encrypted with a key derived from `password`, writing its header to `inner`.
+    ///
+    /// Deriving the key takes [`PBKDF2_ITERATIONS`] iterations, which is deliberately slow.
+    pub fn with_password(inner: W, password: impl AsRef<[u8]>) -> Result<Self> {
+        Self::with_source(inner, KeySource::Password(password.as_ref()))
+    }
+
+    pub(crate) fn with_source(mut inner: W, source: KeySource<'_>) -> Result<Self> {
+        let kdf = match source {
+            KeySource::Key(_) => None,
+            KeySource::Password(_) => Some(KdfParams::generate()?),
+        };
+        let cipher = source.cipher(kdf.as_ref())?;
+
+        let mut header = [0; BACKUP_HEADER_LEN];
+        header[..8].copy_from_slice(BACKUP_MAGIC);
+        header[8..12].copy_from_slice(&BACKUP_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(BACKUP_CHUNK_SIZE as u32).to_le_bytes());
+        fill_random(&mut header[16..32])?;
+        if let Some(kdf) = &kdf {
+            kdf.encode(&mut header[32..52]);
+            header[52] = KDF_PBKDF2_SHA256;
+        }
+        inner.write_all(&header)?;
+
+        Ok(Self {
+            inner,
+            cipher,
+            header,
+            chunk: Vec::with_capacity(BACKUP_CHUNK_SIZE),
+            index: 0,
+        })
+    }
+
+    /// Write the last chunk, flush, and return the inner writer.
+    pub fn finish(mut self) -> IoResult<W> {
+        self.seal()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Encrypt and write the current chunk.
+    fn seal(&mut self) -> IoResult<()> {
+        let len = self.chunk.len() as u32;
+        let mut nonce = [0; NONCE_LEN];
+        fill_random(&mut nonce)?;
+        let aad = backup_aad(&self.header, self.index, len);
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), &aad, &mut self.chunk)
+            .map_err(|_| io::Error::other(format!("failed to encrypt chunk {}", self.index)))?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.inner.write_all(&nonce)?;
+        self.inner.write_all(&self.chunk)?;
+        self.inner.write_all(&tag)?;
+        self.chunk.clear();
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BackupWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let n = buf.len().min(BACKUP_CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..n]);
+        if self.chunk.len() == BACKUP_CHUNK_SIZE {
+            self.seal()?;
+        }
+        Ok(n)
+    }
+
+    /// Flush the inner writer; the current chunk is only written once full, or by [`finish`][Self::finish].
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// A [`Read`] which decrypts a backup written by a [`BackupWriter`], read from an inner reader.
+///
+/// Reading fails with [`ErrorKind::InvalidData`] if the backup was tampered with, and with
+/// [`ErrorKind::UnexpectedEof`] if it was truncated. Data is only returned once the chunk holding it was
+/// authenticated, but a failure may come after earlier chunks were returned, so restore into a file which is only put
+/// to use once reading succeeded, as [`OpfsBackend::import_staged`][crate::OpfsBackend::import_staged] does.
+pub struct BackupReader<R: Read> {
+    inner: R,
+    cipher: Aes256Gcm,
+    header: [u8; BACKUP_HEADER_LEN],
+    chunk_size: usize,
+    /// Plaintext of the current chunk, of which `pos..` is yet to be read.
+    chunk: Vec<u8>,
+    pos: usize,
+    index: u64,
+    /// Whether the last chunk was read.
+    done: bool,
+}
+
+impl<R: Read> fmt::Debug for BackupReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackupReader")
+            .field("index", &self.index)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read> BackupReader<R> {
+    /// Read the header of a backup encrypted with `key` from `inner`.
+    ///
+    /// Fails if the backup was encrypted with a password instead. A wrong key is detected by the first read.
+    pub fn new(inner: R, key: &Key) -> Result<Self> {
+        Self::with_source(inner, KeySource::Key(key))
+    }
+
+    /// Read the header of a backup encrypted with `password` from `inner`.
+    ///
+    /// Fails if the backup was encrypted with a raw key instead. A wrong password is detected by the first read.
+    pub fn with_password(inner: R, password: impl AsRef<[u8]>) -> Result<Self> {
+        Self::with_source(inner, KeySource::Password(password.as_ref()))
+    }
+
+    pub(crate) fn with_source(mut inner: R, source: KeySource<'_>) -> Result<Self> {
+        let mut header = [0; BACKUP_HEADER_LEN];
+        inner.read_exact(&mut header)?;
+        if &header[..8] != BACKUP_MAGIC {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "not an encrypted redb-opfs backup",
+            )
+            .into());
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().expect("slice has len 4"));
+        if version != BACKUP_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported backup format version {version}"),
+            )
+            .into());
+        }
+        let chunk_size = u32::from_le_bytes(header[12..16].try_into().expect("slice has len 4"));
+        if chunk_size == 0 || chunk_size as usize > BACKUP_CHUNK_SIZE {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported backup chunk size {chunk_size}"),
+            )
+            .into());
+        }
+        let kdf = match (header[52], source) {
+            (KDF_NONE, KeySource::Key(_)) => None,
+            (KDF_PBKDF2_SHA256, KeySource::Password(_)) => {
+                Some(KdfParams::decode(&header[32..52])?)
+            }
+            (KDF_NONE, KeySource::Password(_)) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "backup is not password-protected",
+                )
+                .into());
+            }
+            (KDF_PBKDF2_SHA256, KeySource::Key(_)) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "backup is password-protected",
+                )
+                .into());
+            }
+            (other, _) => {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported key derivation algorithm {other}"),
+                )
+                .into());
+            }
+        };
+        let cipher = source.cipher(kdf.as_ref())?;
+
+        Ok(Self {
+            inner,
+            cipher,
+            header,
+            chunk_size: chunk_size as usize,
+            chunk: Vec::new(),
+            pos: 0,
+            index: 0,
+            done: false,
+        })
+    }
+
+    /// Read and decrypt the next chunk.
+    fn open_chunk(&mut self) -> IoResult<()> {
+        let mut len = [0; 4];
+        self.inner.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+        if len as usize > self.chunk_size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("chunk {} is too long", self.index),
+            ));
+        }
+        let mut nonce = [0; NONCE_LEN];
+        self.inner.read_exact(&mut nonce)?;
+        self.chunk.resize(len as usize, 0);
+        self.inner.read_exact(&mut self.chunk)?;
+        let mut tag = [0; TAG_LEN];
+        self.inner.read_exact(&mut tag)?;
+
+        let aad = backup_aad(&self.header, self.index, len);
+        self.cipher
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&nonce),
+                &aad,
+                &mut self.chunk,
+                Tag::from_slice(&tag),
+            )
+            .map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "chunk {} failed authentication: wrong key or corrupted backup",
+                        self.index
+                    ),
+                )
+            })?;
+        self.pos = 0;
+        self.index += 1;
+        if (len as usize) < self.chunk_size {
+            self.done = true;
+            if self.inner.read(&mut [0])? != 0 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "trailing data after the end of the backup",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BackupReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.open_chunk()?;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The associated data of chunk `index` of a backup, holding `len` bytes of plaintext.
+fn backup_aad(
+    header: &[u8; BACKUP_HEADER_LEN],
+    index: u64,
+    len: u32,
+) -> [u8; BACKUP_HEADER_LEN + 12] {
+    let mut aad = [0; BACKUP_HEADER_LEN + 12];
+    aad[..BACKUP_HEADER_LEN].copy_from_slice(header);
+    aad[BACKUP_HEADER_LEN..BACKUP_HEADER_LEN + 8].copy_from_slice(&index.to_le_bytes());
+    aad[BACKUP_HEADER_LEN + 8..].copy_from_slice(&len.to_le_bytes());
+    aad
+}
+
+/// Physical offset of the page with the given index.
+fn page_offset(index: u64) -> u64 {
+    HEADER_LEN as u64 + index * PHYSICAL_PAGE_SIZE as u64
+}
+
+/// Number of pages required to hold `len` bytes of logical data.
+fn page_count(len: u64) -> u64 {
+    len.div_ceil(PAGE_SIZE as u64)
+}
+
+/// Generate a new random key.
+pub fn generate_key() -> Result<Key> {
+    let mut key = [0; KEY_LEN];
+    fill_random(&mut key)?;
+    Ok(key)
+}
+
+/// Encrypt `key` with the WebCrypto AES-GCM key `wrapping_key`.
+///
+/// The output can be stored alongside the database, and is consumed by [`EncryptedBackend::with_wrapped_key`].
+#[cfg(opfs)]
+pub async fn wrap_key(key: &Key, wrapping_key: &CryptoKey) -> Result<Vec<u8>> {
+    let mut iv = [0; NONCE_LEN];
+    fill_random(&mut iv)?;
+    let ciphertext = subtle_crypt(SubtleOp::Encrypt, &iv, key, wrapping_key).await?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&iv);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt a key produced by [`wrap_key`].
+#[cfg(opfs)]
+pub async fn unwrap_key(wrapped_key: &[u8], wrapping_key: &CryptoKey) -> Result<Key> {
+    if wrapped_key.len() != NONCE_LEN + KEY_LEN + TAG_LEN {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "wrapped key has wrong length").into());
+    }
+
+    let (iv, ciphertext) = wrapped_key.split_at(NONCE_LEN);
+    let mut plaintext = subtle_crypt(SubtleOp::Decrypt, iv, ciphertext, wrapping_key).await?;
+    let key = Key::try_from(plaintext.as_slice())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "unwrapped key has wrong length"));
+    plaintext.fill(0);
+    Ok(key?)
+}
+
+#[cfg(opfs)]
+enum SubtleOp {
+    Encrypt,
+    Decrypt,
+}
+
+/// Run an AES-GCM operation through SubtleCrypto.
+#[cfg(opfs)]
+async fn subtle_crypt(op: SubtleOp, iv: &[u8], data: &[u8], key: &CryptoKey) -> Result<Vec<u8>> {
+    let subtle = Reflect::get(&js_sys::global(), &"crypto".into())?
+        .dyn_into::<web_sys::Crypto>()?
+        .subtle();
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    Reflect::set(&algorithm, &"iv".into(), &Uint8Array::from(iv))?;
+    let data = Uint8Array::from(data);
+
+    let promise = match op {
+        SubtleOp::Encrypt => {
+            subtle.encrypt_with_object_and_buffer_source(&algorithm, key, &data)?
+        }
+        SubtleOp::Decrypt => {
+            subtle.decrypt_with_object_and_buffer_source(&algorithm, key, &data)?
+        }
+    };
+    let output = JsFuture::from(promise).await?;
+    Ok(Uint8Array::new(&output).to_vec())
+}
+
+fn new_cipher(key: &Key) -> Aes256Gcm {
+    Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(key))
+}
+
+fn fill_random(buf: &mut [u8]) -> IoResult<()> {
+    getrandom::getrandom(buf)
+        .map_err(|err| io::Error::other(format!("failed to obtain randomness: {err}")))
+}
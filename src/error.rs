@@ -1,13 +1,94 @@
-use std::io::{self, ErrorKind};
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+};
 
 use js_sys::{self, JsString, Object};
 use wasm_bindgen::{JsCast, JsValue};
 use web_sys::DomException;
 
-#[derive(Debug, derive_more::Display, derive_more::Error, derive_more::From)]
-pub struct Error(pub(crate) io::Error);
+use crate::console_log::debug_log;
+
+/// An error, categorized so that callers can react to specific conditions.
+///
+/// In JS, this becomes an `Error` whose `name` identifies the variant, e.g. `QuotaExceededError` or
+/// `HandleBusyError`, so there is no need to match on messages.
+///
+/// redb requires [`io::Error`]s; converting to one and back preserves the variant.
+#[derive(Debug)]
+pub enum Error {
+    /// The file or directory does not exist.
+    NotFound(io::Error),
+    /// The origin has run out of storage.
+    QuotaExceeded(io::Error),
+    /// Less storage is available than required to open the file; see
+    /// [`InsufficientQuota`][crate::InsufficientQuota].
+    InsufficientQuota(io::Error),
+    /// The database has reached a maximum length configured for it, as by a [`BoundedBackend`][crate::BoundedBackend],
+    /// or the disk is full.
+    StorageFull(io::Error),
+    /// The file is in use elsewhere, in this or another worker, or its sync access handle was invalidated.
+    HandleBusy(io::Error),
+    /// Another backend in this worker has the file open; see [`AlreadyOpen`][crate::AlreadyOpen].
+    AlreadyOpen(io::Error),
+    /// Data failed an integrity check, such as the checksums of a `ChecksummedBackend`.
+    Corrupted(io::Error),
+    /// The backend has been closed.
+    Closed,
+    /// The browser doesn't support the operation.
+    Unsupported(io::Error),
+    /// The backend can't run in the current JS context, such as the main thread; the message explains why.
+    UnsupportedContext(String),
+    /// An exception thrown by JS which fits none of the other categories.
+    Js { name: String, message: String },
+    /// Any other I/O error.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(err)
+            | Self::QuotaExceeded(err)
+            | Self::InsufficientQuota(err)
+            | Self::StorageFull(err)
+            | Self::HandleBusy(err)
+            | Self::AlreadyOpen(err)
+            | Self::Corrupted(err)
+            | Self::Unsupported(err)
+            | Self::Io(err) => err.fmt(f),
+            Self::Closed => f.write_str("backend is closed"),
+            Self::UnsupportedContext(message) => f.write_str(message),
+            Self::Js { name, message } => write!(f, "{name}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::NotFound(err)
+            | Self::QuotaExceeded(err)
+            | Self::InsufficientQuota(err)
+            | Self::StorageFull(err)
+            | Self::Corrupted(err)
+            | Self::HandleBusy(err)
+            | Self::AlreadyOpen(err)
+            | Self::Unsupported(err)
+            | Self::Io(err) => err.source(),
+            Self::Closed | Self::UnsupportedContext(_) | Self::Js { .. } => None,
+        }
+    }
+}
 
 impl From<Error> for JsValue {
+    #[cfg(feature = "minimal")]
+    fn from(value: Error) -> Self {
+        debug_log!("throwing {}: {value}", value.name());
+        format!("{}: {value}", value.name()).into()
+    }
+
+    #[cfg(not(feature = "minimal"))]
     fn from(value: Error) -> Self {
         fn construct_error_stack(err: &dyn std::error::Error) -> js_sys::Error {
             let out = js_sys::Error::new(&err.to_string());
@@ -18,44 +99,196 @@ impl From<Error> for JsValue {
             out
         }
 
+        debug_log!("throwing {}: {value}", value.name());
         let stacked_error = construct_error_stack(&value);
-        stacked_error.set_name(&format!("{}Error", value.0.kind()));
+        stacked_error.set_name(&value.name());
         stacked_error.into()
     }
 }
 
 impl From<JsValue> for Error {
     fn from(value: JsValue) -> Self {
-        match value.dyn_ref::<DomException>() {
-            Some(dom) => match dom.code() {
-                DomException::NOT_FOUND_ERR => io::Error::from(ErrorKind::NotFound),
-                DomException::NO_DATA_ALLOWED_ERR | DomException::NO_MODIFICATION_ALLOWED_ERR => {
-                    io::Error::from(ErrorKind::PermissionDenied)
+        let err = Self::from_js(value);
+        debug_log!("converted JS error into {}: {err}", err.name());
+        err
+    }
+}
+
+impl Error {
+    /// Categorize a JS exception, by its name if it is a `DOMException`.
+    fn from_js(value: JsValue) -> Self {
+        if let Some(dom) = value.dyn_ref::<DomException>() {
+            return Self::from_dom_exception(dom);
+        }
+        match value.dyn_ref::<js_sys::Error>() {
+            Some(err) => {
+                #[cfg(feature = "node")]
+                if let Some(err) = crate::node::system_error(err) {
+                    return err;
                 }
-                DomException::TYPE_MISMATCH_ERR => io::Error::other("type mismatch"),
-                _ => {
-                    let name = dom.name();
-                    let message = dom.message();
-                    io::Error::other(format!("{name}: {message}"))
+                Self::Js {
+                    name: err.name().into(),
+                    message: err.message().into(),
                 }
-            },
+            }
             None => {
                 let js_serialization = Object::from(value).to_string();
-                let str = <JsString as ToString>::to_string(&js_serialization);
-                io::Error::other(str)
+                Self::Js {
+                    name: "Error".into(),
+                    message: <JsString as ToString>::to_string(&js_serialization),
+                }
             }
         }
-        .into()
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // restore the variant of an `Error` which went through redb
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().expect("checked above");
+            return *inner.downcast::<Error>().expect("checked above");
+        }
+        #[cfg(feature = "checksums")]
+        if crate::checksummed::is_checksum_mismatch(&err) {
+            return Self::Corrupted(err);
+        }
+        if crate::registry::is_already_open(&err) {
+            return Self::AlreadyOpen(err);
+        }
+        if crate::storage_estimate::is_insufficient_quota(&err) {
+            return Self::InsufficientQuota(err);
+        }
+        match err.kind() {
+            ErrorKind::NotFound => Self::NotFound(err),
+            ErrorKind::QuotaExceeded => Self::QuotaExceeded(err),
+            ErrorKind::StorageFull => Self::StorageFull(err),
+            ErrorKind::ResourceBusy => Self::HandleBusy(err),
+            ErrorKind::NotConnected => Self::Closed,
+            ErrorKind::Unsupported => Self::Unsupported(err),
+            _ => Self::Io(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            // unboxed, so that the checksum mismatch it wraps stays recognizable
+            Error::Io(err) | Error::Corrupted(err) => err,
+            // boxed, so that converting back restores the variant
+            err => io::Error::new(err.kind(), err),
+        }
     }
 }
 
 impl Error {
+    /// Categorize a `DOMException` by its name; numeric codes are deprecated, and newer names have none.
+    ///
+    /// See <https://webidl.spec.whatwg.org/#idl-DOMException-error-names> for the meaning of each name, and
+    /// <https://fs.spec.whatwg.org/> for when OPFS throws it.
+    fn from_dom_exception(dom: &DomException) -> Self {
+        let name = dom.name();
+        let kind = match name.as_str() {
+            "NotFoundError" => ErrorKind::NotFound,
+            "QuotaExceededError" => ErrorKind::QuotaExceeded,
+            // another sync access handle, or a writable stream, holds the file's lock
+            "NoModificationAllowedError" => ErrorKind::ResourceBusy,
+            // the sync access handle was invalidated, or the file was released by another context only just now; this
+            // is not the backend being closed, which must never be retried
+            "InvalidStateError" => ErrorKind::ResourceBusy,
+            "NotAllowedError" | "SecurityError" => ErrorKind::PermissionDenied,
+            // a file was found where a directory was expected, or vice versa
+            "TypeMismatchError" | "InvalidModificationError" | "SyntaxError" => {
+                ErrorKind::InvalidInput
+            }
+            "NotSupportedError" => ErrorKind::Unsupported,
+            "TimeoutError" => ErrorKind::TimedOut,
+            _ => {
+                return Self::Js {
+                    name,
+                    message: dom.message(),
+                };
+            }
+        };
+        let message = dom.message();
+        let message = if message.is_empty() {
+            name
+        } else {
+            format!("{name}: {message}")
+        };
+        io::Error::new(kind, message).into()
+    }
+
+    /// Reconstruct an error from the `name` and `message` of the JS `Error` it became, as far as possible.
+    pub(crate) fn from_name(name: &str, message: String) -> Self {
+        let kind = match name {
+            "NotFoundError" => ErrorKind::NotFound,
+            "QuotaExceededError" => ErrorKind::QuotaExceeded,
+            "InsufficientQuotaError" => {
+                return Self::InsufficientQuota(io::Error::new(ErrorKind::QuotaExceeded, message));
+            }
+            "StorageFullError" => ErrorKind::StorageFull,
+            "HandleBusyError" => ErrorKind::ResourceBusy,
+            "AlreadyOpenError" => {
+                return Self::AlreadyOpen(io::Error::new(ErrorKind::ResourceBusy, message));
+            }
+            "CorruptedError" => {
+                return Self::Corrupted(io::Error::new(ErrorKind::InvalidData, message));
+            }
+            "ClosedError" => return Self::Closed,
+            "UnsupportedError" => ErrorKind::Unsupported,
+            "UnsupportedContextError" => return Self::UnsupportedContext(message),
+            _ => {
+                return Self::Js {
+                    name: name.into(),
+                    message,
+                };
+            }
+        };
+        io::Error::new(kind, message).into()
+    }
+
     pub(crate) fn ad_hoc(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
         io::Error::other(err).into()
     }
 
+    /// The closest [`ErrorKind`] to this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound(_) => ErrorKind::NotFound,
+            Self::QuotaExceeded(_) | Self::InsufficientQuota(_) => ErrorKind::QuotaExceeded,
+            Self::StorageFull(_) => ErrorKind::StorageFull,
+            Self::HandleBusy(_) | Self::AlreadyOpen(_) => ErrorKind::ResourceBusy,
+            Self::Corrupted(_) => ErrorKind::InvalidData,
+            Self::Closed => ErrorKind::NotConnected,
+            Self::Unsupported(_) | Self::UnsupportedContext(_) => ErrorKind::Unsupported,
+            Self::Js { .. } => ErrorKind::Other,
+            Self::Io(err) => err.kind(),
+        }
+    }
+
+    /// The `name` of the JS `Error` this becomes.
+    pub fn name(&self) -> String {
+        match self {
+            Self::NotFound(_) => "NotFoundError".into(),
+            Self::QuotaExceeded(_) => "QuotaExceededError".into(),
+            Self::InsufficientQuota(_) => "InsufficientQuotaError".into(),
+            Self::StorageFull(_) => "StorageFullError".into(),
+            Self::HandleBusy(_) => "HandleBusyError".into(),
+            Self::AlreadyOpen(_) => "AlreadyOpenError".into(),
+            Self::Corrupted(_) => "CorruptedError".into(),
+            Self::Closed => "ClosedError".into(),
+            Self::Unsupported(_) => "UnsupportedError".into(),
+            Self::UnsupportedContext(_) => "UnsupportedContextError".into(),
+            Self::Js { name, .. } => name.clone(),
+            Self::Io(err) => format!("{:?}Error", err.kind()),
+        }
+    }
+
+    /// Convert into an [`io::Error`], as required by redb.
     pub fn into_inner(self) -> io::Error {
-        self.0
+        self.into()
     }
 
     pub(crate) fn to_io(value: JsValue) -> io::Error {
@@ -1,81 +1,283 @@
 //! Implementation of a simple blocking File abstraction backed by OPFS.
 //!
 //! Because it is based on OPFS, this will only work in a web worker.
+//! With the `node` feature, it works under Node.js too, on top of `node:fs`; see [`crate::node`].
 //!
 //! This implementation makes several assumptions and simplifications:
 //!
 //! - no handle caching is performed
-//! - the "current directory" is always the root and cannot be changed
+//! - the sync access handle is flushed and closed when the file is dropped
+//! - if the sync access handle is invalidated, it is re-acquired in the background
+//! - the file's length is only queried when the handle is acquired, and tracked from then on
+//! - paths are resolved from the OPFS root, unless the caller provides another directory handle
 //! - fs prefixes (`c:\`, `//share`, etc) are unsupported in paths
-//! - parent directory annotations (`..`) are unsupported in paths
-//! - files are always opened with (effectively) read+write+create mode
-//! - files are never automatically truncated on creation
+//! - parent directory annotations (`..`) remove the preceding component, and may not escape the root
+//! - files are always readable; they are writable and created unless opened read-only
 //! - cursor position is always initialized at 0
-//! - necessary parent directories are always silently implicitly created
+//! - when creating a file, necessary parent directories are silently implicitly created
 
 use std::{
+    cell::RefCell,
     io::{self, ErrorKind, Read, Seek, Write},
     path::{Component, Path, PathBuf},
+    rc::Rc,
 };
 
-use js_sys::{Function, Promise, Reflect};
+use js_sys::{Function, Object, Promise, Reflect};
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    DedicatedWorkerGlobalScope, FileSystemDirectoryHandle, FileSystemFileHandle,
-    FileSystemGetDirectoryOptions, FileSystemGetFileOptions, FileSystemReadWriteOptions,
-    FileSystemSyncAccessHandle,
+    DomException, FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemGetDirectoryOptions,
+    FileSystemGetFileOptions, FileSystemReadWriteOptions, FileSystemRemoveOptions,
+    FileSystemSyncAccessHandle, ServiceWorkerGlobalScope, StorageManager, WorkerGlobalScope,
 };
 
 use super::{Error, Result};
+#[cfg(feature = "node")]
+use crate::node;
+use crate::{
+    DirEntry, Metadata, StorageEstimate,
+    console_log::debug_log,
+    file_abstraction::{DEFAULT_MAX_CHUNK_SIZE, OpenOptions, copy},
+};
 
 /// A blocking File abstraction that operates on OPFS via a [`FileSystemSyncAccessHandle`].
 ///
-/// Because this is blocking, it can only run in the context of a web worker, i.e. a [`DedicatedWorkerGlobalScope`].
+/// Because this is blocking, it can only run in the context of a web worker: a `DedicatedWorkerGlobalScope`, or a
+/// `SharedWorkerGlobalScope` in browsers which expose sync access handles there.
 #[derive(Debug)]
 pub(crate) struct File {
-    pub(crate) handle: FileSystemSyncAccessHandle,
+    handle: Rc<RefCell<HandleSlot>>,
+    /// How to re-acquire the handle if it is invalidated, if possible.
+    reopen: Option<Rc<Reopen>>,
     pos: u64,
+    /// Reused for every read and write; see [`File::options`].
+    options: FileSystemReadWriteOptions,
+    /// Largest number of bytes passed to a single read or write of the handle.
+    max_chunk_size: usize,
+}
+
+/// The sync access handle of a [`File`], shared with the task re-acquiring it.
+#[derive(Debug)]
+struct HandleSlot {
+    /// `None` while the handle is being re-acquired, or if re-acquiring it failed.
+    handle: Option<FileSystemSyncAccessHandle>,
+    /// Length of the file, tracked so that it needn't be queried from OPFS on every use.
+    len: u64,
+    reacquiring: bool,
+}
+
+/// Where a [`File`] was opened from, and with which options.
+#[derive(Debug)]
+struct Reopen {
+    location: Location,
+    options: OpenOptions,
+}
+
+#[derive(Debug)]
+enum Location {
+    Path {
+        /// `None` for the OPFS root.
+        root: Option<FileSystemDirectoryHandle>,
+        path: PathBuf,
+    },
+    /// A file handle provided by the caller.
+    Handle(FileSystemFileHandle),
+}
+
+impl Location {
+    /// Acquire a new sync access handle to the file.
+    async fn open(&self, options: &OpenOptions) -> Result<FileSystemSyncAccessHandle> {
+        match self {
+            #[cfg(feature = "node")]
+            Self::Path { root: None, path } if node::detected() => node::open(path, options),
+            Self::Path { root, path } => {
+                let (parent, name) =
+                    parent_and_name_in(root.as_ref(), path, options.creates()).await?;
+                get_file_handle(&name, &parent, options).await
+            }
+            Self::Handle(file_handle) => create_sync_access_handle(file_handle, options).await,
+        }
+    }
 }
 
 impl File {
-    pub async fn open(path: impl AsRef<Path>) -> Result<File> {
-        let path = virtualize_path(path)?;
-        let name = path
-            .file_name()
-            .ok_or(io::Error::new(
-                ErrorKind::InvalidFilename,
-                "no filename detected",
-            ))?
-            .to_str()
-            .ok_or(io::Error::new(
-                ErrorKind::InvalidFilename,
-                "non utf-8 chars in filename",
-            ))?;
-
-        // in a perfect world, it would be
-        //   let parent_handle = path.parent().map(open_dir).unwrap_or_else(root).await?;
-        // but we can't do that as each `impl Future` is a different type, even if the
-        // outputs resolve to the same type.
-        let parent_handle = match path.parent() {
-            Some(parent) if parent != Path::new("") => open_dir(parent).await?,
-            // Some case below must be empty
-            Some(_) | None => root().await?,
+    pub async fn open(path: impl AsRef<Path>, options: &OpenOptions) -> Result<File> {
+        Self::open_in(None, path, options).await
+    }
+
+    /// Open the file at `path` relative to the directory `root`, or to the OPFS root if `None`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display()), err)
+    )]
+    pub async fn open_in(
+        root: Option<&FileSystemDirectoryHandle>,
+        path: impl AsRef<Path>,
+        options: &OpenOptions,
+    ) -> Result<File> {
+        let location = Location::Path {
+            root: root.cloned(),
+            path: virtualize_path(path)?,
         };
+        Self::open_location(location, options).await
+    }
 
-        let file_handle = get_file_handle(name, &parent_handle).await?;
+    /// Open the file a caller-provided file handle refers to, bypassing path resolution.
+    ///
+    /// `options.create` and `options.create_new` are meaningless here, and ignored.
+    pub async fn from_file_handle(
+        file_handle: FileSystemFileHandle,
+        options: &OpenOptions,
+    ) -> Result<File> {
+        Self::open_location(Location::Handle(file_handle), options).await
+    }
+
+    async fn open_location(location: Location, options: &OpenOptions) -> Result<File> {
+        let handle = location.open(options).await?;
+        if options.truncate {
+            handle.truncate_with_f64(0.0)?;
+        }
+
+        // The file may have been replaced or removed since; re-acquiring must never clobber or recreate it.
+        let reopen = Reopen {
+            location,
+            options: OpenOptions {
+                create: false,
+                create_new: false,
+                truncate: false,
+                ..*options
+            },
+        };
+        let mut file = File::from_handle(handle)?;
+        file.max_chunk_size = options.max_chunk_size;
+        file.reopen = Some(Rc::new(reopen));
+        Ok(file)
+    }
 
+    /// Wrap a sync access handle which is never re-acquired.
+    fn from_handle(handle: FileSystemSyncAccessHandle) -> Result<File> {
+        let slot = HandleSlot {
+            len: handle.get_size()? as _,
+            handle: Some(handle),
+            reacquiring: false,
+        };
         Ok(File {
-            handle: file_handle,
+            handle: Rc::new(RefCell::new(slot)),
+            reopen: None,
             pos: 0,
+            options: FileSystemReadWriteOptions::new(),
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+        })
+    }
+
+    /// Run `f` against the sync access handle.
+    ///
+    /// If the handle has been invalidated, for example because the file was removed and recreated or the handle was
+    /// closed behind our back, this starts re-acquiring it in the background and fails with
+    /// [`ErrorKind::ResourceBusy`]. Once re-acquired, operations succeed again.
+    fn with_handle<T>(
+        &self,
+        f: impl FnOnce(&FileSystemSyncAccessHandle) -> Result<T, JsValue>,
+    ) -> io::Result<T> {
+        let result = self.handle.borrow().handle.as_ref().map(f);
+        let Some(result) = result else {
+            return Err(self.reacquire());
+        };
+        result.map_err(|err| {
+            let invalidated = err
+                .dyn_ref::<DomException>()
+                .is_some_and(|dom| dom.name() == "InvalidStateError");
+            if invalidated && self.reopen.is_some() {
+                if let Some(handle) = self.handle.borrow_mut().handle.take() {
+                    handle.close();
+                }
+                self.reacquire()
+            } else {
+                Error::to_io(err)
+            }
         })
     }
 
+    /// Start re-acquiring the sync access handle in the background, unless that's already underway.
+    ///
+    /// Returns the error to report for the current operation.
+    fn reacquire(&self) -> io::Error {
+        let Some(reopen) = self.reopen.clone() else {
+            return io::Error::new(
+                ErrorKind::ResourceBusy,
+                "sync access handle was invalidated; open the file again",
+            );
+        };
+
+        let slot = Rc::clone(&self.handle);
+        if !std::mem::replace(&mut slot.borrow_mut().reacquiring, true) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(location = ?reopen.location, "re-acquiring sync access handle");
+            debug_log!("re-acquiring sync access handle for {:?}", reopen.location);
+            wasm_bindgen_futures::spawn_local(async move {
+                let handle = reopen.location.open(&reopen.options).await;
+                // the file may have been replaced, so its length must be queried anew
+                let handle = handle.and_then(|handle| {
+                    let len = handle.get_size()?;
+                    Ok((handle, len as u64))
+                });
+                let mut slot_ref = slot.borrow_mut();
+                slot_ref.reacquiring = false;
+                match handle {
+                    // the file was dropped in the meantime
+                    Ok((handle, _)) if Rc::strong_count(&slot) == 1 => handle.close(),
+                    Ok((handle, len)) => {
+                        debug_log!("re-acquired sync access handle for {:?}", reopen.location);
+                        slot_ref.handle = Some(handle);
+                        slot_ref.len = len;
+                    }
+                    // the next operation tries again
+                    Err(_err) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(
+                            location = ?reopen.location,
+                            err = %_err,
+                            "failed to re-acquire sync access handle"
+                        );
+                        debug_log!(
+                            "failed to re-acquire sync access handle for {:?}: {_err}",
+                            reopen.location
+                        );
+                    }
+                }
+            });
+        }
+
+        io::Error::new(
+            ErrorKind::ResourceBusy,
+            "sync access handle was invalidated and is being re-acquired; retry later",
+        )
+    }
+
+    /// Run `f` against the sync access handle, then query the length of the file anew, as `f` may have changed it.
+    pub(crate) fn with_raw_handle<T>(
+        &self,
+        f: impl FnOnce(&FileSystemSyncAccessHandle) -> T,
+    ) -> io::Result<T> {
+        let output = self.with_handle(|handle| Ok(f(handle)))?;
+        let len = self.with_handle(|handle| handle.get_size())?;
+        self.handle.borrow_mut().len = len as u64;
+        Ok(output)
+    }
+
+    /// Get the length of the file.
+    ///
+    /// This is tracked as the file is written, so OPFS is only queried when the handle is acquired.
     pub fn size(&self) -> io::Result<u64> {
-        self.handle
-            .get_size()
-            .map(|size| size as _)
-            .map_err(Error::to_io)
+        let slot = self.handle.borrow();
+        match slot.handle {
+            Some(_) => Ok(slot.len),
+            None => {
+                drop(slot);
+                Err(self.reacquire())
+            }
+        }
     }
 
     /// Truncates or extends the underlying file, updating the size of this file to become `size`.
@@ -89,6 +291,10 @@ impl File {
     ///
     /// If the requested length is greater than 9007199254740991 (max safe integer in a floating-point context),
     /// this will produce an error.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err)
+    )]
     pub fn set_len(&mut self, size: u64) -> io::Result<()> {
         const MAX_SAFE_INT: u64 = js_sys::Number::MAX_SAFE_INTEGER as _;
         if size > MAX_SAFE_INT {
@@ -97,20 +303,339 @@ impl File {
                 format!("requested size {size} too large, max allowed is {MAX_SAFE_INT}"),
             ));
         }
-        self.handle
-            .truncate_with_f64(size as _)
-            .map_err(Error::to_io)
+        self.with_handle(|handle| handle.truncate_with_f64(size as _))?;
+        self.handle.borrow_mut().len = size;
+        Ok(())
+    }
+
+    /// Remove the file at `path`.
+    ///
+    /// This fails if the file does not exist, or if a sync access handle to it is currently open.
+    pub async fn delete(path: impl AsRef<Path>) -> Result<()> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::delete(&virtualize_path(path)?);
+        }
+        let (parent_handle, name) = parent_and_name(path, false).await?;
+        JsFuture::from(parent_handle.remove_entry(&name)).await?;
+        Ok(())
+    }
+
+    /// Move the file at `from` to `to`, replacing `to` if it already exists.
+    ///
+    /// Missing parent directories of `to` are created. This uses `FileSystemHandle.move` where the
+    /// browser supports it, and otherwise copies the content to `to` before removing `from`.
+    /// Either way, this fails if a sync access handle to `from` is currently open.
+    pub async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+        // the copy fallback below would otherwise truncate the file it's copying from
+        if virtualize_path(from.as_ref())? == virtualize_path(to.as_ref())? {
+            return Ok(());
+        }
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::rename(&virtualize_path(from)?, &virtualize_path(to)?);
+        }
+
+        let (from_parent, from_name) = parent_and_name(from, false).await?;
+        let (to_parent, to_name) = parent_and_name(to, true).await?;
+
+        let file_handle = JsFuture::from(from_parent.get_file_handle(&from_name)).await?;
+        if let Ok(move_fn) = Reflect::get(&file_handle, &"move".into())?.dyn_into::<Function>() {
+            let move_promise = move_fn
+                .call2(&file_handle, &to_parent, &to_name.into())?
+                .dyn_into::<Promise>()?;
+            JsFuture::from(move_promise).await?;
+            return Ok(());
+        }
+
+        copy_entry(&from_parent, &from_name, &to_parent, &to_name).await?;
+        JsFuture::from(from_parent.remove_entry(&from_name)).await?;
+        Ok(())
+    }
+
+    /// Copy the file at `from` to `to`, replacing `to` if it already exists, and return the number of bytes copied.
+    ///
+    /// Missing parent directories of `to` are created. Where the browser supports `createWritable`, the browser
+    /// pipes the content from `from` to `to` itself, without it passing through wasm memory, and replaces the content
+    /// of `to` only once all of it was copied. Otherwise, it is copied through sync access handles in large chunks.
+    /// Either way, this fails if a sync access handle to either file is currently open, and a `to` which didn't exist
+    /// before is removed again if copying fails.
+    pub async fn copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+        // copying would truncate the file it's copying from
+        if virtualize_path(from.as_ref())? == virtualize_path(to.as_ref())? {
+            return Err(
+                io::Error::new(ErrorKind::InvalidInput, "cannot copy a file onto itself").into(),
+            );
+        }
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::copy(&virtualize_path(from)?, &virtualize_path(to)?);
+        }
+
+        let (from_parent, from_name) = parent_and_name(from, false).await?;
+        let (to_parent, to_name) = parent_and_name(to, true).await?;
+        copy_entry(&from_parent, &from_name, &to_parent, &to_name).await
+    }
+
+    /// Remove the file or directory at `path`, recursively.
+    ///
+    /// Succeeds if nothing exists at `path`. Fails if a sync access handle to any affected file is currently open.
+    pub async fn remove_all(path: impl AsRef<Path>) -> Result<()> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::remove_all(&virtualize_path(path)?);
+        }
+        let (parent_handle, name) = match parent_and_name(path, false).await {
+            Ok(resolved) => resolved,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        let options = FileSystemRemoveOptions::new();
+        options.set_recursive(true);
+        match JsFuture::from(parent_handle.remove_entry_with_options(&name, &options))
+            .await
+            .map_err(Error::from)
+        {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Determine whether a file exists at `path`, without creating it or any parent directories.
+    ///
+    /// Returns `false` if `path` names a directory.
+    pub async fn exists(path: impl AsRef<Path>) -> Result<bool> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::exists(&virtualize_path(path)?);
+        }
+        let (parent_handle, name) = match parent_and_name(path, false).await {
+            Ok(resolved) => resolved,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        match JsFuture::from(parent_handle.get_file_handle(&name)).await {
+            Ok(_) => Ok(true),
+            Err(err) => match err.dyn_ref::<DomException>().map(DomException::name) {
+                Some(name) if name == "NotFoundError" || name == "TypeMismatchError" => Ok(false),
+                _ => Err(err.into()),
+            },
+        }
+    }
+
+    /// List the entries of the directory at `path`, without creating it.
+    ///
+    /// An empty path lists the root directory.
+    pub async fn read_dir(path: impl AsRef<Path>) -> Result<Vec<DirEntry>> {
+        let path = virtualize_path(path)?;
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::read_dir(&path);
+        }
+        let dir = if path.as_os_str().is_empty() {
+            root().await?
+        } else {
+            open_dir(&path, false).await?
+        };
+
+        // `FileSystemDirectoryHandle` is an async iterable of its child handles
+        let iterator = Reflect::get(&dir, &"values".into())?
+            .dyn_into::<Function>()?
+            .call0(&dir)?;
+        let next = Reflect::get(&iterator, &"next".into())?.dyn_into::<Function>()?;
+
+        let mut entries = Vec::new();
+        loop {
+            let result = JsFuture::from(next.call0(&iterator)?.dyn_into::<Promise>()?).await?;
+            if Reflect::get(&result, &"done".into())?.is_truthy() {
+                break;
+            }
+            let handle = Reflect::get(&result, &"value".into())?;
+            let name = Reflect::get(&handle, &"name".into())?
+                .as_string()
+                .unwrap_or_default();
+
+            let entry = match handle.dyn_into::<FileSystemFileHandle>() {
+                Ok(file_handle) => {
+                    // `getFile` fails while the file is open elsewhere; that doesn't make it unlisted
+                    let file = JsFuture::from(file_handle.get_file())
+                        .await
+                        .ok()
+                        .and_then(|file| file.dyn_into::<web_sys::File>().ok());
+                    DirEntry {
+                        name,
+                        is_dir: false,
+                        size: file.as_ref().map(|file| file.size() as _),
+                        last_modified: file.as_ref().map(|file| file.last_modified() as _),
+                    }
+                }
+                Err(_) => DirEntry {
+                    name,
+                    is_dir: true,
+                    size: None,
+                    last_modified: None,
+                },
+            };
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Get the metadata of the file at `path`, without creating it or any parent directories.
+    ///
+    /// This fails if a sync access handle to the file is currently open, as `getFile` does.
+    pub async fn metadata(path: impl AsRef<Path>) -> Result<Metadata> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::metadata(&virtualize_path(path)?);
+        }
+        let (parent_handle, name) = parent_and_name(path, false).await?;
+        let file_handle = JsFuture::from(parent_handle.get_file_handle(&name))
+            .await?
+            .dyn_into::<FileSystemFileHandle>()?;
+        let file = JsFuture::from(file_handle.get_file())
+            .await?
+            .dyn_into::<web_sys::File>()?;
+        Ok(Metadata {
+            size: file.size() as _,
+            last_modified: file.last_modified() as _,
+            created: None,
+        })
+    }
+
+    /// Estimate the storage used and available to the origin.
+    ///
+    /// Under Node, this is the file system where the file at `path` is, or would be, stored instead.
+    pub async fn estimate(path: impl AsRef<Path>) -> Result<StorageEstimate> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return node::estimate(&virtualize_path(path)?);
+        }
+        #[cfg(not(feature = "node"))]
+        let _ = path;
+        let estimate = JsFuture::from(storage()?.estimate()?).await?;
+        // both members are optional, though every browser supporting OPFS reports them
+        let member = |name: &str| -> Result<u64> {
+            let value = Reflect::get(&estimate, &name.into())?;
+            Ok(value.as_f64().unwrap_or_default() as _)
+        };
+        Ok(StorageEstimate {
+            usage: member("usage")?,
+            quota: member("quota")?,
+        })
+    }
+
+    /// Request that the origin's storage is never evicted, returning whether it is now persistent.
+    pub async fn persist() -> Result<bool> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return Ok(true);
+        }
+        let persisted = JsFuture::from(storage()?.persist()?).await?;
+        Ok(persisted.is_truthy())
+    }
+
+    /// Determine whether the origin's storage is persistent.
+    pub async fn persisted() -> Result<bool> {
+        #[cfg(feature = "node")]
+        if node::detected() {
+            return Ok(true);
+        }
+        let persisted = JsFuture::from(storage()?.persisted()?).await?;
+        Ok(persisted.is_truthy())
     }
 
     /// Flush any pending changes to the file system.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err)
+    )]
     pub fn flush(&self) -> io::Result<()> {
-        self.handle.flush().map_err(Error::to_io)
+        self.with_handle(FileSystemSyncAccessHandle::flush)
+    }
+
+    /// Flush and release the sync access handle, so that the file can be opened again.
+    ///
+    /// This is equivalent to dropping the file.
+    pub fn close(self) {
+        drop(self);
+    }
+
+    /// Options to read or write at `offset`.
+    ///
+    /// The same JS object is reused by every call, as allocating one per operation shows up in profiles.
+    fn options_at(&self, offset: u64) -> &FileSystemReadWriteOptions {
+        self.options.set_at(offset as _);
+        &self.options
     }
 
-    fn options(&self) -> FileSystemReadWriteOptions {
-        let options = FileSystemReadWriteOptions::new();
-        options.set_at(self.pos as _);
-        options
+    /// Read into `buf` from `offset`, leaving the cursor alone, and return the number of bytes read.
+    ///
+    /// The handle may read fewer bytes than requested, so this reads again until `buf` is full or the file ends:
+    /// fewer bytes than requested means that the file ended. An error after some bytes were read is reported by the
+    /// next call instead. Each read is at most [`max_chunk_size`][crate::OpfsBackendBuilder::max_chunk_size] bytes.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mut total = 0;
+        while total < buf.len() {
+            let options = self.options_at(offset + total as u64);
+            let end = buf.len().min(total + self.max_chunk_size);
+            let rest = &mut buf[total..end];
+            let read = self.with_handle(|handle| {
+                let bytes_read = handle.read_with_u8_array_and_options(rest, options)?;
+                Ok(bytes_read as usize)
+            });
+            match read {
+                Ok(0) => break,
+                Ok(bytes_read) => total += bytes_read,
+                Err(err) if total == 0 => return Err(err),
+                Err(_) => break,
+            }
+        }
+        Ok(total)
+    }
+
+    /// Write `buf` at `offset`, leaving the cursor alone, and return the number of bytes written.
+    ///
+    /// The handle may write fewer bytes than requested, so this writes the rest until all of `buf` is written, or the
+    /// handle writes nothing. An error after some bytes were written is reported by the next call instead. Each write
+    /// is at most [`max_chunk_size`][crate::OpfsBackendBuilder::max_chunk_size] bytes.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let mut total = 0;
+        while total < buf.len() {
+            let options = self.options_at(offset + total as u64);
+            let end = buf.len().min(total + self.max_chunk_size);
+            let rest = &buf[total..end];
+            let written = self.with_handle(|handle| {
+                let bytes_written = handle.write_with_u8_array_and_options(rest, options)?;
+                Ok(bytes_written as usize)
+            });
+            match written {
+                Ok(0) => break,
+                Ok(bytes_written) => total += bytes_written,
+                Err(err) if total == 0 => return Err(err),
+                Err(_) => break,
+            }
+        }
+        if total > 0 {
+            let mut slot = self.handle.borrow_mut();
+            slot.len = slot.len.max(offset + total as u64);
+        }
+        Ok(total)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        // Errors can't be reported from here; callers who care flush explicitly beforehand.
+        // Read-only handles can't be flushed, but have nothing to flush either.
+        if let Some(handle) = self.handle.borrow_mut().handle.take() {
+            let _ = handle.flush();
+            handle.close();
+        }
     }
 }
 
@@ -140,34 +665,41 @@ impl Seek for File {
     }
 }
 
+// Reads and writes are zero-copy: wasm-bindgen passes slices to imported functions as `Uint8Array` views directly
+// over wasm linear memory, so OPFS reads into, and writes from, the caller's buffer. The views stay valid because
+// nothing can grow the memory during the synchronous call. The `+copy` runs of the benchmarks measure what this saves.
 impl Read for File {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(offset = self.pos, len = buf.len()), err)
+    )]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let bytes_read = self
-            .handle
-            .read_with_u8_array_and_options(buf, &self.options())
-            .map_err(Error::to_io)? as u64;
-        self.pos += bytes_read;
-        Ok(bytes_read as _)
+        let bytes_read = self.read_at(buf, self.pos)?;
+        self.pos += bytes_read as u64;
+        Ok(bytes_read)
     }
 }
 
 impl Write for File {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(offset = self.pos, len = buf.len()), err)
+    )]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let bytes_written = self
-            .handle
-            .write_with_u8_array_and_options(buf, &self.options())
-            .map_err(Error::to_io)? as u64;
-        self.pos += bytes_written;
-        Ok(bytes_written as _)
+        let bytes_written = self.write_at(buf, self.pos)?;
+        self.pos += bytes_written as u64;
+        Ok(bytes_written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.handle.flush().map_err(Error::to_io)
+        File::flush(self)
     }
 }
 
 /// Construct a normalized version of the input path
-fn virtualize_path(path: impl AsRef<Path>) -> Result<PathBuf> {
+///
+/// `..` removes the preceding component, so `a/tmp/../b` becomes `a/b`; it is an error for it to escape the root.
+pub(crate) fn virtualize_path(path: impl AsRef<Path>) -> Result<PathBuf> {
     let mut out = PathBuf::new();
 
     for component in path.as_ref().components() {
@@ -175,10 +707,19 @@ fn virtualize_path(path: impl AsRef<Path>) -> Result<PathBuf> {
             std::path::Component::RootDir => out.clear(),
             std::path::Component::CurDir => {}
             std::path::Component::Normal(normal) => out.push(normal),
-            std::path::Component::Prefix(_) | std::path::Component::ParentDir => {
+            std::path::Component::ParentDir => {
+                if !out.pop() {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} escapes the OPFS root", path.as_ref().display()),
+                    )
+                    .into());
+                }
+            }
+            std::path::Component::Prefix(_) => {
                 return Err(io::Error::new(
                     ErrorKind::InvalidInput,
-                    "only normal path components are supported",
+                    "path prefixes are not supported",
                 )
                 .into());
             }
@@ -188,25 +729,172 @@ fn virtualize_path(path: impl AsRef<Path>) -> Result<PathBuf> {
     Ok(out)
 }
 
-async fn root() -> Result<FileSystemDirectoryHandle> {
-    let storage = DedicatedWorkerGlobalScope::from(JsValue::from(js_sys::global()))
-        .navigator()
-        .storage();
+/// Resolve the handle of the directory containing `path`, and the file name within it.
+///
+/// If `create` is set, missing directories are created.
+async fn parent_and_name(
+    path: impl AsRef<Path>,
+    create: bool,
+) -> Result<(FileSystemDirectoryHandle, String)> {
+    parent_and_name_in(None, path, create).await
+}
+
+/// Like [`parent_and_name`], but relative to the directory `root`, or to the OPFS root if `None`.
+async fn parent_and_name_in(
+    root: Option<&FileSystemDirectoryHandle>,
+    path: impl AsRef<Path>,
+    create: bool,
+) -> Result<(FileSystemDirectoryHandle, String)> {
+    let path = virtualize_path(path)?;
+    let name = path
+        .file_name()
+        .ok_or(io::Error::new(
+            ErrorKind::InvalidFilename,
+            "no filename detected",
+        ))?
+        .to_str()
+        .ok_or(io::Error::new(
+            ErrorKind::InvalidFilename,
+            "non utf-8 chars in filename",
+        ))?
+        .to_owned();
+
+    // in a perfect world, it would be
+    //   let parent_handle = path.parent().map(open_dir).unwrap_or_else(root).await?;
+    // but we can't do that as each `impl Future` is a different type, even if the
+    // outputs resolve to the same type.
+    let root = match root {
+        Some(root) => root.clone(),
+        None => self::root().await?,
+    };
+    let parent_handle = match path.parent() {
+        Some(parent) if parent != Path::new("") => open_dir_in(root, parent, create).await?,
+        // Some case below must be empty
+        Some(_) | None => root,
+    };
 
-    let root_handle = JsFuture::from(storage.get_directory())
+    Ok((parent_handle, name))
+}
+
+/// Check that the current JS context can use sync access handles, explaining why not if it can't.
+///
+/// Without this, the backend fails deep inside some JS call, with an exception which rarely hints at the cause.
+pub(crate) fn check_context() -> Result<()> {
+    #[cfg(feature = "node")]
+    if node::detected() {
+        return Ok(());
+    }
+
+    let global = js_sys::global();
+    if !is_worker(&global) {
+        return Err(Error::UnsupportedContext(format!(
+            "`OpfsBackend` must run in a web worker, but is running in {}; \
+             on the main thread, use `MainThreadBackend`",
+            context_name(&global),
+        )));
+    }
+
+    if is_service_worker(&global) {
+        return Err(Error::UnsupportedContext(
+            "`OpfsBackend` can't run in a service worker; open the database in a dedicated worker, \
+             or use `MainThreadBackend`, which also works in service workers"
+                .into(),
+        ));
+    }
+
+    if !has_sync_access_handles(&global) {
+        // browsers only expose them to dedicated workers, though some also to shared workers
+        return Err(Error::UnsupportedContext(format!(
+            "this browser does not support OPFS sync access handles \
+             (`FileSystemFileHandle.createSyncAccessHandle`) in {}",
+            context_name(&global),
+        )));
+    }
+
+    storage()?;
+    if !has_opfs() {
+        return Err(Error::UnsupportedContext(
+            "OPFS is unavailable (`navigator.storage.getDirectory`); \
+             browsers disable it in insecure contexts, and some in private browsing"
+                .into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `global` is the scope of a worker, of whichever kind.
+pub(crate) fn is_worker(global: &Object) -> bool {
+    global.dyn_ref::<WorkerGlobalScope>().is_some()
+}
+
+/// Whether `global` is the scope of a service worker.
+///
+/// Service workers must never block, so no browser exposes sync access handles to them.
+pub(crate) fn is_service_worker(global: &Object) -> bool {
+    global.dyn_ref::<ServiceWorkerGlobalScope>().is_some()
+}
+
+/// Whether `global` exposes `FileSystemFileHandle.createSyncAccessHandle`.
+pub(crate) fn has_sync_access_handles(global: &Object) -> bool {
+    Reflect::get(global, &"FileSystemFileHandle".into())
+        .and_then(|class| Reflect::get(&class, &"prototype".into()))
+        .and_then(|prototype| Reflect::has(&prototype, &"createSyncAccessHandle".into()))
+        .unwrap_or(false)
+}
+
+/// Whether `navigator.storage.getDirectory` exists in the current global scope.
+pub(crate) fn has_opfs() -> bool {
+    storage().is_ok_and(|storage| Reflect::has(&storage, &"getDirectory".into()).unwrap_or(false))
+}
+
+/// The name of the class of the global scope, such as `DedicatedWorkerGlobalScope` or `Window`.
+fn context_name(global: &Object) -> String {
+    Reflect::get(global, &"constructor".into())
+        .and_then(|constructor| Reflect::get(&constructor, &"name".into()))
+        .ok()
+        .and_then(|name| name.as_string())
+        .unwrap_or_else(|| "an unknown context".into())
+}
+
+/// `navigator.storage` of the current global scope, whichever kind of worker, or window, it is.
+fn storage() -> Result<StorageManager> {
+    let global = js_sys::global();
+    let storage = Reflect::get(&global, &"navigator".into())
+        .and_then(|navigator| Reflect::get(&navigator, &"storage".into()))?;
+    if storage.is_undefined() {
+        return Err(Error::UnsupportedContext(format!(
+            "`navigator.storage` is unavailable in {}",
+            context_name(&global),
+        )));
+    }
+    Ok(storage.unchecked_into())
+}
+
+async fn root() -> Result<FileSystemDirectoryHandle> {
+    let root_handle = JsFuture::from(storage()?.get_directory())
         .await?
         .dyn_into::<FileSystemDirectoryHandle>()?;
 
     Ok(root_handle)
 }
 
-async fn open_dir(path: impl AsRef<Path>) -> Result<FileSystemDirectoryHandle> {
+async fn open_dir(path: impl AsRef<Path>, create: bool) -> Result<FileSystemDirectoryHandle> {
+    open_dir_in(root().await?, path, create).await
+}
+
+/// Like [`open_dir`], but relative to the directory `root`.
+async fn open_dir_in(
+    root: FileSystemDirectoryHandle,
+    path: impl AsRef<Path>,
+    create: bool,
+) -> Result<FileSystemDirectoryHandle> {
     async fn get_dir_handle(
         parent: &FileSystemDirectoryHandle,
         path: &str,
+        create: bool,
     ) -> Result<FileSystemDirectoryHandle> {
         let options = FileSystemGetDirectoryOptions::new();
-        options.set_create(true);
+        options.set_create(create);
 
         JsFuture::from(parent.get_directory_handle_with_options(path, &options))
             .await?
@@ -214,7 +902,7 @@ async fn open_dir(path: impl AsRef<Path>) -> Result<FileSystemDirectoryHandle> {
             .map_err(Into::into)
     }
 
-    let mut handle = root().await?;
+    let mut handle = root;
     for component in path.as_ref().components() {
         let Component::Normal(component) = component else {
             // shouldn't happen though because we always virtualize ahead of time
@@ -226,7 +914,7 @@ async fn open_dir(path: impl AsRef<Path>) -> Result<FileSystemDirectoryHandle> {
             ErrorKind::InvalidFilename,
             "non utf-8 chars in dir name",
         ))?;
-        handle = get_dir_handle(&handle, component).await?;
+        handle = get_dir_handle(&handle, component, create).await?;
     }
 
     Ok(handle)
@@ -235,21 +923,158 @@ async fn open_dir(path: impl AsRef<Path>) -> Result<FileSystemDirectoryHandle> {
 async fn get_file_handle(
     name: &str,
     dir: &FileSystemDirectoryHandle,
+    options: &OpenOptions,
 ) -> Result<FileSystemSyncAccessHandle> {
-    let options = FileSystemGetFileOptions::new();
-    options.set_create(true);
-    let file_handle = JsFuture::from(dir.get_file_handle_with_options(name, &options))
+    let file_handle = lookup_file_handle(name, dir, options).await?;
+    create_sync_access_handle(&file_handle, options).await
+}
+
+/// Resolve `path` from the OPFS root to a file handle, without acquiring a sync access handle.
+pub(crate) async fn file_handle(
+    path: impl AsRef<Path>,
+    options: &OpenOptions,
+) -> Result<FileSystemFileHandle> {
+    let path = virtualize_path(path)?;
+    let (parent, name) = parent_and_name(&path, options.creates()).await?;
+    lookup_file_handle(&name, &parent, options).await
+}
+
+async fn lookup_file_handle(
+    name: &str,
+    dir: &FileSystemDirectoryHandle,
+    options: &OpenOptions,
+) -> Result<FileSystemFileHandle> {
+    if options.create_new {
+        match JsFuture::from(dir.get_file_handle(name))
+            .await
+            .map_err(Error::from)
+        {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{name} already exists"),
+                )
+                .into());
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    let get_file_options = FileSystemGetFileOptions::new();
+    get_file_options.set_create(options.creates());
+    let file_handle = JsFuture::from(dir.get_file_handle_with_options(name, &get_file_options))
         .await?
         .dyn_into::<FileSystemFileHandle>()?;
+    Ok(file_handle)
+}
 
-    let file_handle = JsValue::from(file_handle);
-    let create_sync_access_handle_promise =
-        Reflect::get(&file_handle, &"createSyncAccessHandle".into())?
-            .dyn_into::<Function>()?
-            .call0(&file_handle)?
-            .dyn_into::<Promise>()?;
+/// Copy the file `from_name` in `from_parent` to `to_name` in `to_parent`, replacing it, and return the number of
+/// bytes copied; see [`File::copy_file`].
+async fn copy_entry(
+    from_parent: &FileSystemDirectoryHandle,
+    from_name: &str,
+    to_parent: &FileSystemDirectoryHandle,
+    to_name: &str,
+) -> Result<u64> {
+    let source: FileSystemFileHandle = JsFuture::from(from_parent.get_file_handle(from_name))
+        .await?
+        .unchecked_into();
+    let existed = match JsFuture::from(to_parent.get_file_handle(to_name))
+        .await
+        .map_err(Error::from)
+    {
+        Ok(_) => true,
+        Err(err) if err.kind() == ErrorKind::NotFound => false,
+        Err(err) => return Err(err),
+    };
+    let create = FileSystemGetFileOptions::new();
+    create.set_create(true);
+    let dest: FileSystemFileHandle =
+        JsFuture::from(to_parent.get_file_handle_with_options(to_name, &create))
+            .await?
+            .unchecked_into();
+
+    let copied = copy_handle(&source, &dest).await;
+    if copied.is_err() && !existed {
+        // rather than leave behind the file created above, empty or holding part of the content
+        let _ = JsFuture::from(to_parent.remove_entry(to_name)).await;
+    }
+    copied
+}
+
+/// Copy the content of `source` to `dest`, replacing it; see [`copy_entry`].
+async fn copy_handle(source: &FileSystemFileHandle, dest: &FileSystemFileHandle) -> Result<u64> {
+    if let Ok(create_writable) =
+        Reflect::get(dest, &"createWritable".into())?.dyn_into::<Function>()
+    {
+        let blob: web_sys::File = JsFuture::from(source.get_file()).await?.unchecked_into();
+        let writable = create_writable.call0(dest)?.dyn_into::<Promise>()?;
+        let writable = JsFuture::from(writable).await?;
+        // once the stream ends, the writable is closed, which commits it; on failure, it is aborted instead
+        let stream = blob.stream();
+        let pipe_to = Reflect::get(&stream, &"pipeTo".into())?.dyn_into::<Function>()?;
+        let piped = pipe_to.call1(&stream, &writable)?.dyn_into::<Promise>()?;
+        JsFuture::from(piped).await?;
+        return Ok(blob.size() as u64);
+    }
+
+    let read_only = OpenOptions {
+        read_only: true,
+        create: false,
+        ..OpenOptions::default()
+    };
+    let mut source = File::from_handle(create_sync_access_handle(source, &read_only).await?)?;
+    let dest = create_sync_access_handle(dest, &OpenOptions::default()).await?;
+    dest.truncate_with_f64(0.0)?;
+    let mut dest = File::from_handle(dest)?;
+    let copied = copy(&mut source, &mut dest).and_then(|copied| {
+        dest.flush()?;
+        Ok(copied)
+    });
+    source.close();
+    dest.close();
+    Ok(copied?)
+}
+
+async fn create_sync_access_handle(
+    file_handle: &FileSystemFileHandle,
+    options: &OpenOptions,
+) -> Result<FileSystemSyncAccessHandle> {
+    // Browsers which do not support `mode` ignore it and open read+write.
+    let sync_options = Object::new();
+    if options.read_only {
+        Reflect::set(&sync_options, &"mode".into(), &"read-only".into())?;
+    }
+
+    let file_handle = JsValue::from(file_handle.clone());
+    let Ok(create_sync_access_handle) =
+        Reflect::get(&file_handle, &"createSyncAccessHandle".into())?.dyn_into::<Function>()
+    else {
+        return Err(Error::UnsupportedContext(
+            "sync access handles are only available in workers; \
+             on the main thread, use `MainThreadBackend`"
+                .into(),
+        ));
+    };
+    let create_sync_access_handle_promise = create_sync_access_handle
+        .call1(&file_handle, &sync_options)?
+        .dyn_into::<Promise>()?;
     let sync_access_handle = JsFuture::from(create_sync_access_handle_promise)
         .await?
         .dyn_into::<FileSystemSyncAccessHandle>()?;
     Ok(sync_access_handle)
 }
+
+/// Find the path of `file_handle` relative to the OPFS root, or `None` if it lies outside OPFS.
+pub(crate) async fn path_of(file_handle: &FileSystemFileHandle) -> Result<Option<PathBuf>> {
+    let components = JsFuture::from(root().await?.resolve(file_handle)).await?;
+    if components.is_null() {
+        return Ok(None);
+    }
+    let path = js_sys::Array::from(&components)
+        .iter()
+        .filter_map(|component| component.as_string())
+        .collect();
+    Ok(Some(path))
+}
@@ -1,44 +1,500 @@
-use std::io::Result;
+use std::io::{ErrorKind, Read, Result, Write};
+
+use crate::{DirEntry, Metadata, StorageEstimate};
+
+/// Options governing how a file is opened.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OpenOptions {
+    /// Open without write access.
+    pub(crate) read_only: bool,
+    /// Create the file if it does not exist. Ignored when `read_only` is set.
+    pub(crate) create: bool,
+    /// Create the file, failing with `AlreadyExists` if it already exists.
+    pub(crate) create_new: bool,
+    /// Truncate the file to 0 length on open.
+    pub(crate) truncate: bool,
+    /// Largest number of bytes passed to a single read or write of the sync access handle.
+    #[cfg(opfs)]
+    pub(crate) max_chunk_size: usize,
+    /// Further native flags; see [`NativeOptions`].
+    #[cfg(not(opfs))]
+    pub(crate) native: NativeOptions,
+}
+
+/// Platform-specific options passed through to [`std::fs::OpenOptions`].
+#[cfg(not(opfs))]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct NativeOptions {
+    /// Flags OR'ed into those passed to `open`, such as `O_NOATIME`.
+    #[cfg(unix)]
+    pub(crate) custom_flags: i32,
+    /// Permissions of a created file, before the umask is applied.
+    #[cfg(unix)]
+    pub(crate) mode: Option<u32>,
+    /// Flags OR'ed into those passed to `CreateFileW`, such as `FILE_FLAG_WRITE_THROUGH`.
+    #[cfg(windows)]
+    pub(crate) custom_flags: u32,
+    /// The share mode passed to `CreateFileW`.
+    #[cfg(windows)]
+    pub(crate) share_mode: Option<u32>,
+    /// File attributes of a created file.
+    #[cfg(windows)]
+    pub(crate) attributes: u32,
+}
+
+/// Largest number of bytes passed to a single OPFS read or write, unless configured otherwise.
+#[cfg(opfs)]
+pub(crate) const DEFAULT_MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            create: true,
+            create_new: false,
+            truncate: false,
+            #[cfg(opfs)]
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            #[cfg(not(opfs))]
+            native: NativeOptions::default(),
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Whether a missing file should be created.
+    pub(crate) fn creates(&self) -> bool {
+        (self.create || self.create_new) && !self.read_only
+    }
+}
 
 pub(crate) trait FileAbstraction: Sized {
-    /// Open the specified path.
+    /// Open the specified path according to `options`.
     ///
     /// Must have the following conditions/flags set at initialization:
     ///
     /// - readable
-    /// - writeable
-    /// - created if does not exist
-    /// - _not_ truncated
+    /// - writeable unless `options.read_only`
+    /// - created if does not exist if `options.creates()`
+    /// - failing with `AlreadyExists` if it exists and `options.create_new`
+    /// - truncated if `options.truncate`
     /// - initial cursor position at 0
-    async fn open(path: &str) -> Result<Self>;
+    async fn open(path: &str, options: &OpenOptions) -> Result<Self>;
 
     /// Get the length of this file in bytes.
     fn len(&self) -> Result<u64>;
+
+    /// Read into `buf` from `offset`, regardless of the cursor, returning the number of bytes read.
+    ///
+    /// Unlike seeking and reading, this takes `&self`, so reads needn't be serialized.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize>;
+
+    /// Write from `buf` at `offset`, regardless of the cursor, returning the number of bytes written.
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize>;
+
+    /// Fill `buf` from `offset`, failing with [`ErrorKind::UnexpectedEof`] if the file ends first.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        read_exact_with(|buf, offset| self.read_at(buf, offset), buf, offset)
+    }
+
+    /// Write all of `buf` at `offset`.
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<()> {
+        write_all_with(|buf, offset| self.write_at(buf, offset), buf, offset)
+    }
+
+    /// Release this file, so that it can be opened again.
+    fn close(self);
+
+    /// Remove the file at the specified path.
+    async fn delete(path: &str) -> Result<()>;
+
+    /// Move the file at `from` to `to`, replacing `to` if it already exists.
+    async fn rename(from: &str, to: &str) -> Result<()>;
+
+    /// Copy the file at `from` to `to`, replacing `to` if it already exists, and return the number of bytes copied.
+    async fn copy_file(from: &str, to: &str) -> Result<u64>;
+
+    /// Remove the file or directory at the specified path, recursively.
+    ///
+    /// Succeeds if nothing exists at the path.
+    async fn remove_all(path: &str) -> Result<()>;
+
+    /// Determine whether a file exists at the specified path, without creating anything.
+    async fn exists(path: &str) -> Result<bool>;
+
+    /// List the entries of the directory at the specified path, without creating anything.
+    ///
+    /// An empty path lists the current (native) or root (OPFS) directory.
+    async fn read_dir(path: &str) -> Result<Vec<DirEntry>>;
+
+    /// Get the metadata of the file at the specified path, without creating anything.
+    async fn metadata(path: &str) -> Result<Metadata>;
+
+    /// Estimate the storage used and available where the file at the specified path is, or would be, stored.
+    ///
+    /// In OPFS, that is the storage of the whole origin, wherever the file is.
+    async fn estimate(path: &str) -> Result<StorageEstimate>;
+
+    /// Request that storage is never evicted, returning whether it is now persistent.
+    async fn persist() -> Result<bool>;
+
+    /// Determine whether storage is persistent.
+    async fn persisted() -> Result<bool>;
 }
 
-#[cfg(not(target_family = "wasm"))]
+/// Held while seeking and reading or writing, where files have no positioned I/O.
+#[cfg(not(any(opfs, unix, windows)))]
+static CURSOR: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+#[cfg(not(opfs))]
 impl FileAbstraction for std::fs::File {
-    async fn open(path: &str) -> Result<Self> {
-        std::fs::OpenOptions::new()
+    async fn open(path: &str, options: &OpenOptions) -> Result<Self> {
+        let mut std_options = std::fs::OpenOptions::new();
+        std_options
             .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(path)
+            .write(!options.read_only)
+            .create(options.creates())
+            .create_new(options.create_new)
+            .truncate(options.truncate);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+
+            std_options.custom_flags(options.native.custom_flags);
+            if let Some(mode) = options.native.mode {
+                std_options.mode(mode);
+            }
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt as _;
+
+            std_options
+                .custom_flags(options.native.custom_flags)
+                .attributes(options.native.attributes);
+            if let Some(share_mode) = options.native.share_mode {
+                std_options.share_mode(share_mode);
+            }
+        }
+        std_options.open(path)
     }
 
     fn len(&self) -> Result<u64> {
         self.metadata().map(|metadata| metadata.len())
     }
+
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+
+    // moves the cursor, which is fine, as nothing relies on it between seeking and reading or writing
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        std::os::windows::fs::FileExt::seek_write(self, buf, offset)
+    }
+
+    // elsewhere, e.g. on WASI, positioned I/O is unstable, so seeking and reading or writing must not interleave
+    #[cfg(not(any(unix, windows)))]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        use std::io::{Seek as _, SeekFrom};
+
+        let _cursor = CURSOR.lock();
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        use std::io::{Seek as _, SeekFrom};
+
+        let _cursor = CURSOR.lock();
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write(buf)
+    }
+
+    fn close(self) {
+        drop(self);
+    }
+
+    async fn delete(path: &str) -> Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    async fn rename(from: &str, to: &str) -> Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    async fn copy_file(from: &str, to: &str) -> Result<u64> {
+        // `std::fs::copy` would truncate the file it's copying from
+        if std::path::absolute(from)? == std::path::absolute(to)? {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "cannot copy a file onto itself",
+            ));
+        }
+        std::fs::copy(from, to)
+    }
+
+    async fn remove_all(path: &str) -> Result<()> {
+        let removed = match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+            Ok(_) => std::fs::remove_file(path),
+            Err(err) => Err(err),
+        };
+        match removed {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            removed => removed,
+        }
+    }
+
+    async fn exists(path: &str) -> Result<bool> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn read_dir(path: &str) -> Result<Vec<DirEntry>> {
+        let path = if path.is_empty() { "." } else { path };
+        std::fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                Ok(DirEntry {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    is_dir: metadata.is_dir(),
+                    size: metadata.is_file().then(|| metadata.len()),
+                    last_modified: metadata
+                        .modified()
+                        .ok()
+                        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|since_epoch| since_epoch.as_millis() as _),
+                })
+            })
+            .collect()
+    }
+
+    async fn metadata(path: &str) -> Result<Metadata> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::IsADirectory,
+                format!("{path} is a directory"),
+            ));
+        }
+        let millis = |time: std::time::SystemTime| {
+            time.duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_millis() as _)
+        };
+        Ok(Metadata {
+            size: metadata.len(),
+            last_modified: millis(metadata.modified()?),
+            created: metadata.created().ok().map(millis),
+        })
+    }
+
+    #[cfg(unix)]
+    async fn estimate(path: &str) -> Result<StorageEstimate> {
+        use std::{os::unix::ffi::OsStrExt as _, path::Path};
+
+        // the file, and the directories leading to it, may not exist yet
+        let dir = Path::new(path)
+            .ancestors()
+            .skip(1)
+            .find(|dir| !dir.as_os_str().is_empty() && dir.is_dir())
+            .unwrap_or(Path::new("."));
+        let dir = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // Safety: the path is a valid nul-terminated string, and `stat` is only read if the call succeeds
+        let stat = unsafe {
+            if libc::statvfs(dir.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            stat.assume_init()
+        };
+
+        let fragment_size = u64::from(stat.f_frsize);
+        let quota = u64::from(stat.f_blocks) * fragment_size;
+        let available = u64::from(stat.f_bavail) * fragment_size;
+        Ok(StorageEstimate {
+            usage: quota.saturating_sub(available),
+            quota,
+        })
+    }
+
+    #[cfg(not(unix))]
+    async fn estimate(_path: &str) -> Result<StorageEstimate> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "storage estimates are only supported on unix",
+        ))
+    }
+
+    async fn persist() -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn persisted() -> Result<bool> {
+        Ok(true)
+    }
 }
 
-#[cfg(target_family = "wasm")]
+#[cfg(opfs)]
 impl FileAbstraction for crate::file::File {
-    async fn open(path: &str) -> Result<Self> {
-        <Self>::open(path).await.map_err(crate::Error::into_inner)
+    async fn open(path: &str, options: &OpenOptions) -> Result<Self> {
+        <Self>::open(path, options)
+            .await
+            .map_err(crate::Error::into_inner)
     }
 
     fn len(&self) -> Result<u64> {
         self.size()
     }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        <Self>::read_at(self, buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        <Self>::write_at(self, buf, offset)
+    }
+
+    fn close(self) {
+        <Self>::close(self);
+    }
+
+    async fn delete(path: &str) -> Result<()> {
+        <Self>::delete(path).await.map_err(crate::Error::into_inner)
+    }
+
+    async fn rename(from: &str, to: &str) -> Result<()> {
+        <Self>::rename(from, to)
+            .await
+            .map_err(crate::Error::into_inner)
+    }
+
+    async fn copy_file(from: &str, to: &str) -> Result<u64> {
+        <Self>::copy_file(from, to)
+            .await
+            .map_err(crate::Error::into_inner)
+    }
+
+    async fn remove_all(path: &str) -> Result<()> {
+        <Self>::remove_all(path)
+            .await
+            .map_err(crate::Error::into_inner)
+    }
+
+    async fn exists(path: &str) -> Result<bool> {
+        <Self>::exists(path).await.map_err(crate::Error::into_inner)
+    }
+
+    async fn read_dir(path: &str) -> Result<Vec<DirEntry>> {
+        <Self>::read_dir(path)
+            .await
+            .map_err(crate::Error::into_inner)
+    }
+
+    async fn metadata(path: &str) -> Result<Metadata> {
+        <Self>::metadata(path)
+            .await
+            .map_err(crate::Error::into_inner)
+    }
+
+    async fn estimate(path: &str) -> Result<StorageEstimate> {
+        <Self>::estimate(path)
+            .await
+            .map_err(crate::Error::into_inner)
+    }
+
+    async fn persist() -> Result<bool> {
+        <Self>::persist().await.map_err(crate::Error::into_inner)
+    }
+
+    async fn persisted() -> Result<bool> {
+        <Self>::persisted().await.map_err(crate::Error::into_inner)
+    }
+}
+
+/// Fill `buf` from `offset` by calling `read_at` until it is full, failing with [`ErrorKind::UnexpectedEof`] if it
+/// returns 0 first.
+pub(crate) fn read_exact_with(
+    mut read_at: impl FnMut(&mut [u8], u64) -> Result<usize>,
+    mut buf: &mut [u8],
+    mut offset: u64,
+) -> Result<()> {
+    while !buf.is_empty() {
+        match read_at(buf, offset) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Write all of `buf` at `offset` by calling `write_at` until everything is written.
+pub(crate) fn write_all_with(
+    mut write_at: impl FnMut(&[u8], u64) -> Result<usize>,
+    mut buf: &[u8],
+    mut offset: u64,
+) -> Result<()> {
+    while !buf.is_empty() {
+        match write_at(buf, offset) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Copy the remaining content of `from` into `to`, returning the number of bytes copied.
+///
+/// This uses a larger buffer than [`std::io::copy`], as on wasm every read and write is a call into the browser.
+pub(crate) fn copy(from: &mut impl Read, to: &mut impl Write) -> Result<u64> {
+    copy_with_progress(from, to, |_| {})
+}
+
+/// Like [`copy`], calling `progress` with the number of bytes copied so far after each chunk.
+pub(crate) fn copy_with_progress(
+    from: &mut impl Read,
+    to: &mut impl Write,
+    mut progress: impl FnMut(u64),
+) -> Result<u64> {
+    const CHUNK_SIZE: usize = 1 << 20;
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut copied = 0;
+    loop {
+        let bytes_read = from.read(&mut buf)?;
+        if bytes_read == 0 {
+            return Ok(copied);
+        }
+        to.write_all(&buf[..bytes_read])?;
+        copied += bytes_read as u64;
+        progress(copied);
+    }
 }
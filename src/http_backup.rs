@@ -0,0 +1,237 @@
+//! Backing up a database to, and restoring it from, a server over HTTP.
+//!
+//! Transfers are split into chunks, one request each, so that an interrupted transfer can resume from the last chunk
+//! which completed rather than from the start:
+//!
+//! - Uploads `PUT` each chunk to the URL, with a `Content-Range: bytes <first>-<last>/<total>` header. The server
+//!   must accept chunks in order, and respond with a 2xx status once a chunk is stored.
+//! - Downloads `GET` each chunk with a `Range: bytes=<first>-<last>` header. Servers which don't support ranges may
+//!   respond with the whole file instead, in which case the download starts over.
+//!
+//! Requests go through the global `fetch`, so they carry the context's cookies and are subject to CORS as usual.
+
+use std::io;
+
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Headers, Request, RequestInit, Response};
+
+use crate::{OpfsBackend, Result, StorageBackend, report_progress};
+
+/// Size of the chunks transferred by each request, unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: u32 = 8 << 20;
+
+/// A backup location on a server, and how to transfer to and from it.
+///
+/// Configure it like a builder, then [`upload`][Self::upload] or [`download`][Self::download]:
+///
+/// ```js
+/// const backup = new HttpBackup("https://example.com/backups/my-db")
+///     .header("Authorization", `Bearer ${token}`)
+///     .onProgress((done, total) => console.log(`${done} of ${total} bytes`));
+/// await backup.upload("my-db.snapshot", 0);
+/// ```
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct HttpBackup {
+    url: String,
+    headers: Vec<(String, String)>,
+    chunk_size: u32,
+    on_progress: Option<Function>,
+}
+
+#[wasm_bindgen]
+impl HttpBackup {
+    /// Back up to, and restore from, `url`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            headers: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            on_progress: None,
+        }
+    }
+
+    /// Send the header `name: value` with every request, for example to authenticate.
+    pub fn header(mut self, name: String, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Transfer up to `bytes` per request; defaults to [`DEFAULT_CHUNK_SIZE`].
+    #[wasm_bindgen(js_name = chunkSize)]
+    pub fn chunk_size(mut self, bytes: u32) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Call `callback` after each chunk with the number of bytes transferred so far and, if known, the total.
+    ///
+    /// After a failure, the last number reported is where to resume an upload from. Exceptions thrown by the
+    /// callback are ignored.
+    #[wasm_bindgen(js_name = onProgress)]
+    pub fn on_progress(mut self, callback: Function) -> Self {
+        self.on_progress = Some(callback);
+        self
+    }
+
+    /// Upload the file at `path`, starting at byte `from`, and return its size.
+    ///
+    /// Pass 0 to upload the whole file, or the last progress reported to resume an interrupted upload. The file must
+    /// not change in between, so upload a [snapshot][OpfsBackend::snapshot] rather than a database in use.
+    pub async fn upload(&self, path: &str, from: u64) -> Result<u64> {
+        let backend = OpfsBackend::builder()
+            .read_only(true)
+            .create(false)
+            .open(path)
+            .await?;
+        let uploaded = self.upload_from(&backend, from).await;
+        backend.close()?;
+        uploaded
+    }
+
+    /// Download the backup into the file at `path`, creating it if necessary, and return its size.
+    ///
+    /// If `resume` is set, the download continues after the current content of the file, as left by an interrupted
+    /// download; otherwise the file is replaced. The file must not be open elsewhere.
+    pub async fn download(&self, path: &str, resume: bool) -> Result<u64> {
+        let backend = OpfsBackend::builder().truncate(!resume).open(path).await?;
+        let downloaded = self.download_into(&backend).await;
+        backend.close()?;
+        downloaded
+    }
+}
+
+impl HttpBackup {
+    async fn upload_from(&self, backend: &OpfsBackend, from: u64) -> Result<u64> {
+        let total = backend.len()?;
+        let mut offset = from.min(total);
+        if total == 0 {
+            self.fetch(
+                "PUT",
+                Some(("Content-Range", "bytes */0".into())),
+                Some(&[][..]),
+            )
+            .await?;
+        }
+
+        let mut buf = vec![0; self.chunk_size as usize];
+        while offset < total {
+            let chunk = &mut buf[..(total - offset).min(self.chunk_size.into()) as usize];
+            backend.read(offset, chunk)?;
+            let end = offset + chunk.len() as u64;
+            let range = format!("bytes {offset}-{}/{total}", end - 1);
+            self.fetch("PUT", Some(("Content-Range", range)), Some(chunk))
+                .await?;
+            offset = end;
+            report_progress(self.on_progress.as_ref(), offset, Some(total));
+        }
+        Ok(total)
+    }
+
+    async fn download_into(&self, backend: &OpfsBackend) -> Result<u64> {
+        let mut offset = backend.len()?;
+        let mut total = None;
+        loop {
+            if total.is_some_and(|total| offset >= total) {
+                break;
+            }
+            let last = offset + u64::from(self.chunk_size) - 1;
+            let range = format!("bytes={offset}-{last}");
+            let response = match self.fetch("GET", Some(("Range", range)), None).await {
+                // the range starts at the end of the file: the previous download completed
+                Err(HttpError { status: 416, .. }) if offset > 0 => break,
+                response => response?,
+            };
+
+            let whole = response.status() != 206;
+            if whole {
+                backend.set_len(0)?;
+                offset = 0;
+            } else {
+                total = response
+                    .headers()
+                    .get("Content-Range")?
+                    .and_then(|range| range.rsplit_once('/')?.1.parse().ok());
+            }
+            let body = JsFuture::from(response.array_buffer()?).await?;
+            let body = Uint8Array::new(&body).to_vec();
+            backend.write(offset, &body)?;
+            offset += body.len() as u64;
+            if whole {
+                total = Some(offset);
+            }
+            report_progress(self.on_progress.as_ref(), offset, total);
+
+            // without a known total, a short chunk is the last one
+            if whole || (total.is_none() && (body.len() as u64) < u64::from(self.chunk_size)) {
+                break;
+            }
+        }
+        backend.sync_data()?;
+        Ok(offset)
+    }
+
+    /// Send a request with the configured headers and `header`, failing unless the response has a 2xx status.
+    async fn fetch(
+        &self,
+        method: &str,
+        header: Option<(&str, String)>,
+        body: Option<&[u8]>,
+    ) -> Result<Response, HttpError> {
+        let headers = Headers::new()?;
+        for (name, value) in &self.headers {
+            headers.append(name, value)?;
+        }
+        if let Some((name, value)) = header {
+            headers.set(name, &value)?;
+        }
+        let init = RequestInit::new();
+        init.set_method(method);
+        init.set_headers(&headers);
+        if let Some(body) = body {
+            init.set_body(&Uint8Array::from(body));
+        }
+        let request = Request::new_with_str_and_init(&self.url, &init)?;
+
+        let global = js_sys::global();
+        let fetch = Reflect::get(&global, &"fetch".into())?.dyn_into::<Function>()?;
+        let promise = fetch.call1(&global, &request)?.dyn_into::<Promise>()?;
+        let response = JsFuture::from(promise).await?.dyn_into::<Response>()?;
+        if !response.ok() {
+            return Err(HttpError {
+                status: response.status(),
+                error: io::Error::other(format!(
+                    "{method} {} failed with status {}",
+                    self.url,
+                    response.status()
+                ))
+                .into(),
+            });
+        }
+        Ok(response)
+    }
+}
+
+/// A failed request, and the status of its response, or 0 if there was none.
+struct HttpError {
+    status: u16,
+    error: crate::Error,
+}
+
+impl From<JsValue> for HttpError {
+    fn from(value: JsValue) -> Self {
+        Self {
+            status: 0,
+            error: value.into(),
+        }
+    }
+}
+
+impl From<HttpError> for crate::Error {
+    fn from(err: HttpError) -> Self {
+        err.error
+    }
+}
@@ -0,0 +1,262 @@
+//! Moving the records of an IndexedDB object store into a redb table.
+//!
+//! Records are read in batches, in key order, and each batch is inserted in its own write transaction, along with
+//! the key of the last record it contains. A migration which is interrupted, for example because the tab was closed,
+//! therefore resumes after the last batch which was committed when it is run again. Running a completed migration
+//! again only copies the records added to the object store since.
+
+use std::io::{self, ErrorKind};
+
+use js_sys::{Array, Function, JSON, Promise, Reflect, Uint8Array};
+use redb::{Database, ReadableTable as _, TableDefinition};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbKeyRange, IdbObjectStore, IdbRequest};
+
+use crate::{Error, Result, database::redb_error, open_database};
+
+/// Number of records migrated per transaction, unless configured otherwise.
+pub const DEFAULT_BATCH_SIZE: u32 = 1000;
+
+/// The last key migrated, as JSON, by `"{database}/{store}/{table}"`.
+const PROGRESS: TableDefinition<&str, &str> = TableDefinition::new("redb-opfs:idb-migrations");
+
+/// A migration of the records of an IndexedDB object store into a redb table.
+///
+/// Configure it like a builder, then [`run`][Self::run] it:
+///
+/// ```js
+/// const migrated = await new IdbMigration("legacy-db", "messages")
+///     .batchSize(500)
+///     .onProgress((done, total) => console.log(`${done} of ${total} records`))
+///     .run("my-db");
+/// ```
+///
+/// By default, records go to the table `kv`, where [`KvStore`][crate::KvStore] finds them. Keys which are strings
+/// are kept as they are, and others are converted to JSON. Values which are `Uint8Array`s or `ArrayBuffer`s are
+/// stored as they are, strings are encoded as UTF-8, and other values are converted to JSON. A
+/// [`convert`][Self::convert] function may map records differently.
+///
+/// Resuming relies on finding the key of the last record migrated, which is recorded as JSON, so keys must survive
+/// a round trip through JSON: strings, numbers, and arrays of them do, but `Date`s and binary keys don't.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct IdbMigration {
+    database: String,
+    store: String,
+    table: String,
+    batch_size: u32,
+    on_progress: Option<Function>,
+    convert: Option<Function>,
+}
+
+#[wasm_bindgen]
+impl IdbMigration {
+    /// Migrate the records of the object store `store` of the IndexedDB database `database`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(database: String, store: String) -> Self {
+        Self {
+            database,
+            store,
+            table: "kv".into(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            on_progress: None,
+            convert: None,
+        }
+    }
+
+    /// Insert the records into the redb table `table`, of type `TableDefinition<&str, &[u8]>`; defaults to `kv`.
+    pub fn table(mut self, table: String) -> Self {
+        self.table = table;
+        self
+    }
+
+    /// Migrate up to `records` records per transaction; defaults to [`DEFAULT_BATCH_SIZE`].
+    #[wasm_bindgen(js_name = batchSize)]
+    pub fn batch_size(mut self, records: u32) -> Self {
+        self.batch_size = records.max(1);
+        self
+    }
+
+    /// Call `callback` after each batch with the number of records migrated so far, including those migrated by
+    /// previous runs, and the number of records in the object store.
+    ///
+    /// Exceptions thrown by the callback are ignored.
+    #[wasm_bindgen(js_name = onProgress)]
+    pub fn on_progress(mut self, callback: Function) -> Self {
+        self.on_progress = Some(callback);
+        self
+    }
+
+    /// Map each record with `convert(key, value)`, which returns `[key, value]`, where `key` is a string and `value`
+    /// is a `Uint8Array`, or anything else converted as by default.
+    ///
+    /// An exception thrown by `convert` aborts the migration; batches committed before are kept.
+    pub fn convert(mut self, convert: Function) -> Self {
+        self.convert = Some(convert);
+        self
+    }
+
+    /// Run the migration into the database at `path`, creating it if it does not exist, and return the number of
+    /// records migrated by this run.
+    pub async fn run(&self, path: &str) -> Result<u32> {
+        let database = open_database(path).await?;
+        self.run_into(&database).await
+    }
+}
+
+impl IdbMigration {
+    /// Run the migration into `database`, and return the number of records migrated by this run.
+    pub async fn run_into(&self, database: &Database) -> Result<u32> {
+        let idb = open_idb(&self.database).await?;
+        let migrated = self.migrate(&idb, database).await;
+        idb.close();
+        migrated
+    }
+
+    async fn migrate(&self, idb: &IdbDatabase, database: &Database) -> Result<u32> {
+        if !idb.object_store_names().contains(&self.store) {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "IndexedDB database {:?} has no object store {:?}",
+                    self.database, self.store
+                ),
+            )
+            .into());
+        }
+        let progress_key = format!("{}/{}/{}", self.database, self.store, self.table);
+        let table = TableDefinition::<&str, &[u8]>::new(&self.table);
+
+        let mut last_key = {
+            let tx = database.begin_write().map_err(redb_error)?;
+            let last_key = {
+                let progress = tx.open_table(PROGRESS).map_err(redb_error)?;
+                let last_key = progress.get(progress_key.as_str()).map_err(redb_error)?;
+                last_key
+                    .map(|last_key| JSON::parse(last_key.value()))
+                    .transpose()?
+            };
+            tx.open_table(table).map_err(redb_error)?;
+            tx.commit().map_err(redb_error)?;
+            last_key
+        };
+
+        let total = request(&self.object_store(idb)?.count()?).await?;
+        let mut migrated = 0;
+        loop {
+            // a transaction ends once it has no pending requests, so each batch needs its own
+            let store = self.object_store(idb)?;
+            let range = match &last_key {
+                Some(last_key) => IdbKeyRange::lower_bound_with_open(last_key, true)?.into(),
+                None => JsValue::UNDEFINED,
+            };
+            let keys = store.get_all_keys_with_key_and_limit(&range, self.batch_size)?;
+            let values = store.get_all_with_key_and_limit(&range, self.batch_size)?;
+            let keys = request(&keys).await?.dyn_into::<Array>()?;
+            let values = request(&values).await?.dyn_into::<Array>()?;
+            if keys.length() == 0 {
+                break;
+            }
+
+            let last = keys.get(keys.length() - 1);
+            let tx = database.begin_write().map_err(redb_error)?;
+            {
+                let mut table = tx.open_table(table).map_err(redb_error)?;
+                for (key, value) in keys.iter().zip(values.iter()) {
+                    let (key, value) = self.record(key, value)?;
+                    table
+                        .insert(key.as_str(), value.as_slice())
+                        .map_err(redb_error)?;
+                }
+                let json = String::from(JSON::stringify(&last)?);
+                let mut progress = tx.open_table(PROGRESS).map_err(redb_error)?;
+                progress
+                    .insert(progress_key.as_str(), json.as_str())
+                    .map_err(redb_error)?;
+            }
+            tx.commit().map_err(redb_error)?;
+            migrated += keys.length();
+
+            if let Some(on_progress) = &self.on_progress {
+                // counting what is left covers records migrated by previous runs too
+                let after = IdbKeyRange::lower_bound_with_open(&last, true)?;
+                let remaining = request(&self.object_store(idb)?.count_with_key(&after)?).await?;
+                let total = total.as_f64().unwrap_or_default();
+                let done = total - remaining.as_f64().unwrap_or_default();
+                let _ = on_progress.call2(&JsValue::NULL, &done.into(), &total.into());
+            }
+            last_key = Some(last);
+        }
+        Ok(migrated)
+    }
+
+    fn object_store(&self, idb: &IdbDatabase) -> Result<IdbObjectStore> {
+        Ok(idb
+            .transaction_with_str(&self.store)?
+            .object_store(&self.store)?)
+    }
+
+    /// Map an IndexedDB record to a key and value for redb.
+    fn record(&self, key: JsValue, value: JsValue) -> Result<(String, Vec<u8>)> {
+        let (key, value) = match &self.convert {
+            Some(convert) => {
+                let record = convert.call2(&JsValue::NULL, &key, &value)?;
+                let record = record.dyn_into::<Array>()?;
+                let key = record.get(0).as_string().ok_or_else(|| {
+                    io::Error::new(ErrorKind::InvalidData, "`convert` must return a string key")
+                })?;
+                (key, record.get(1))
+            }
+            None => {
+                let key = match key.as_string() {
+                    Some(key) => key,
+                    None => JSON::stringify(&key)?.into(),
+                };
+                (key, value)
+            }
+        };
+        Ok((key, bytes(&value)?))
+    }
+}
+
+/// Encode a record's value as bytes.
+fn bytes(value: &JsValue) -> Result<Vec<u8>> {
+    if value.is_instance_of::<Uint8Array>() || value.is_instance_of::<js_sys::ArrayBuffer>() {
+        return Ok(Uint8Array::new(value).to_vec());
+    }
+    let string = match value.as_string() {
+        Some(string) => string,
+        None => JSON::stringify(value)?.into(),
+    };
+    Ok(string.into_bytes())
+}
+
+/// Open the IndexedDB database `name`.
+async fn open_idb(name: &str) -> Result<IdbDatabase> {
+    let factory = Reflect::get(&js_sys::global(), &"indexedDB".into())?;
+    if factory.is_undefined() {
+        return Err(io::Error::new(ErrorKind::Unsupported, "IndexedDB is unavailable").into());
+    }
+    let factory = factory.unchecked_into::<IdbFactory>();
+    let idb = request(&factory.open(name)?).await?;
+    Ok(idb.unchecked_into())
+}
+
+/// Wait for `request` to complete, and return its result.
+async fn request(request: &IdbRequest) -> Result<JsValue> {
+    let done = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    let completed = JsFuture::from(done).await;
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+    if completed.is_err() {
+        return Err(match request.error()? {
+            Some(err) => Error::from(JsValue::from(err)),
+            None => io::Error::other("IndexedDB request failed").into(),
+        });
+    }
+    Ok(request.result()?)
+}
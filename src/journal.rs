@@ -0,0 +1,256 @@
+//! A [`StorageBackend`] wrapper which records every operation to a [`Journal`], for bug reports.
+
+use std::{collections::VecDeque, fmt, sync::Arc};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, Operation, StorageBackend};
+
+/// An operation recorded by a [`JournaledBackend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The position of the operation among all operations recorded by the journal, starting at 0.
+    pub seq: u64,
+    /// The operation, with its offset and length.
+    pub operation: Operation,
+    /// The FNV-1a hash of the data written, or of the data read if reading succeeded.
+    pub hash: Option<u64>,
+    /// Whether the operation failed.
+    pub failed: bool,
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.seq)?;
+        match self.operation {
+            Operation::Read { offset, len } => write!(f, "read offset={offset} len={len}")?,
+            Operation::Write { offset, len } => write!(f, "write offset={offset} len={len}")?,
+            Operation::SetLen { len } => write!(f, "set_len len={len}")?,
+            Operation::Sync => write!(f, "sync")?,
+        }
+        if let Some(hash) = self.hash {
+            write!(f, " hash={hash:016x}")?;
+        }
+        f.write_str(if self.failed { " failed" } else { " ok" })
+    }
+}
+
+/// Where a [`Journal`] keeps its entries.
+#[derive(Debug)]
+enum Sink {
+    /// The most recent `capacity` entries, in memory.
+    Ring {
+        entries: VecDeque<JournalEntry>,
+        capacity: usize,
+    },
+    /// Every entry, as a line of text appended to `backend`, which is `len` bytes long.
+    Backend {
+        backend: Box<dyn StorageBackend>,
+        len: u64,
+    },
+}
+
+#[derive(Debug)]
+struct State {
+    sink: Sink,
+    next_seq: u64,
+}
+
+/// A record of the operations on a [`JournaledBackend`], with their offsets, lengths and data hashes.
+///
+/// Reproducing a corrupted database from a browser is nearly impossible without knowing which operations led up to
+/// it. Attach [`dump`][Self::dump] to bug reports; it has one line per operation, such as
+/// `12 write offset=4096 len=4096 hash=8c5b0a3f1e2d4c69 ok`. The hashes are not cryptographic, and only tell whether
+/// two operations read or wrote the same data.
+#[derive(Debug)]
+pub struct Journal {
+    state: Mutex<State>,
+}
+
+impl Journal {
+    /// Keep the most recent `capacity` entries in memory, discarding older ones.
+    pub fn in_memory(capacity: usize) -> Self {
+        Self::with_sink(Sink::Ring {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    /// Append every entry as a line of text to `backend`, such as an [`OpfsBackend`][crate::OpfsBackend] opened on a
+    /// file next to the database, which keeps the journal across reloads and crashes.
+    ///
+    /// Entries are appended after any existing content of `backend`. Failing to append one does not fail the
+    /// operation it records; the entry is lost. The journal grows without bound, so remove the file once it is no
+    /// longer needed.
+    pub fn to_backend(backend: impl StorageBackend) -> IoResult<Self> {
+        let len = backend.len()?;
+        Ok(Self::with_sink(Sink::Backend {
+            backend: Box::new(backend),
+            len,
+        }))
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        Self {
+            state: Mutex::new(State { sink, next_seq: 0 }),
+        }
+    }
+
+    /// The entries kept in memory, oldest first; empty if the journal appends to a backend.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        match &self.state.lock().sink {
+            Sink::Ring { entries, .. } => entries.iter().copied().collect(),
+            Sink::Backend { .. } => Vec::new(),
+        }
+    }
+
+    /// The journal as text, one entry per line, oldest first.
+    ///
+    /// If the journal appends to a backend, this reads the backend back, including entries recorded before it was
+    /// attached.
+    pub fn dump(&self) -> IoResult<String> {
+        let state = self.state.lock();
+        match &state.sink {
+            Sink::Ring { entries, .. } => {
+                Ok(entries.iter().map(|entry| format!("{entry}\n")).collect())
+            }
+            Sink::Backend { backend, len } => {
+                let mut out = vec![0; *len as usize];
+                backend.read(0, &mut out)?;
+                Ok(String::from_utf8_lossy(&out).into_owned())
+            }
+        }
+    }
+
+    /// Discard all entries kept in memory; entries appended to a backend are kept.
+    pub fn clear(&self) {
+        if let Sink::Ring { entries, .. } = &mut self.state.lock().sink {
+            entries.clear();
+        }
+    }
+
+    /// Record `operation`, with the hash of its data, if any.
+    fn record(&self, operation: Operation, hash: Option<u64>, failed: bool) {
+        let mut state = self.state.lock();
+        let entry = JournalEntry {
+            seq: state.next_seq,
+            operation,
+            hash,
+            failed,
+        };
+        state.next_seq += 1;
+        match &mut state.sink {
+            Sink::Ring { entries, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+            Sink::Backend { backend, len } => {
+                let line = format!("{entry}\n");
+                if backend.write(*len, line.as_bytes()).is_ok() {
+                    *len += line.len() as u64;
+                }
+            }
+        }
+    }
+}
+
+/// Implementation of a [`StorageBackend`] which records every read, write, truncation and sync to a [`Journal`].
+///
+/// The journal is shared, so it can still be dumped after the backend was handed to redb:
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use redb_opfs::{Journal, JournaledBackend, MemoryBackend};
+///
+/// let journal = Arc::new(Journal::in_memory(10_000));
+/// let backend = JournaledBackend::new(MemoryBackend::new(), Arc::clone(&journal));
+/// // ... hand `backend` to redb, then on failure:
+/// let report = journal.dump()?;
+/// # Ok::<_, std::io::Error>(())
+/// ```
+///
+/// Hashing the data of every read and write costs time proportional to its length, so only enable this while
+/// investigating a problem. Calls to [`len`][StorageBackend::len] are passed through without being recorded.
+#[derive(Debug)]
+pub struct JournaledBackend<B> {
+    inner: B,
+    journal: Arc<Journal>,
+}
+
+impl<B: StorageBackend> JournaledBackend<B> {
+    /// Record every operation on `inner` to `journal`.
+    pub fn new(inner: B, journal: Arc<Journal>) -> Self {
+        Self { inner, journal }
+    }
+
+    /// Get a reference to the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get a reference to the journal.
+    pub fn journal(&self) -> &Arc<Journal> {
+        &self.journal
+    }
+
+    /// Unwrap this backend, returning the wrapped backend and the journal.
+    pub fn into_inner(self) -> (B, Arc<Journal>) {
+        (self.inner, self.journal)
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for JournaledBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        self.inner.len()
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        let result = self.inner.set_len(len);
+        self.journal
+            .record(Operation::SetLen { len }, None, result.is_err());
+        result
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let result = self.inner.sync_data();
+        self.journal.record(Operation::Sync, None, result.is_err());
+        result
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let result = self.inner.read(offset, out);
+        let op = Operation::Read {
+            offset,
+            len: out.len(),
+        };
+        let hash = result.is_ok().then(|| fnv1a(out));
+        self.journal.record(op, hash, result.is_err());
+        result
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let result = self.inner.write(offset, data);
+        let op = Operation::Write {
+            offset,
+            len: data.len(),
+        };
+        self.journal.record(op, Some(fnv1a(data)), result.is_err());
+        result
+    }
+}
+
+/// The 64-bit FNV-1a hash of `data`.
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    data.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
@@ -0,0 +1,174 @@
+//! A [`StorageBackend`] whose storage is provided by callbacks from JS.
+
+use std::io::{self, ErrorKind};
+
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::{Error, IoResult, Result, StorageBackend, thread_bound::ThreadBound};
+
+/// Largest size and offset which cross into JS exactly, as a number.
+const MAX_SAFE_INTEGER: u64 = js_sys::Number::MAX_SAFE_INTEGER as _;
+
+#[derive(Debug)]
+struct Callbacks {
+    /// The object the callbacks are methods of, passed to them as `this`.
+    target: JsValue,
+    len: Function,
+    read: Function,
+    write: Function,
+    set_len: Function,
+    sync: Option<Function>,
+}
+
+impl Callbacks {
+    /// Call `callback` with `args`, failing if it throws or returns a promise.
+    fn call(&self, name: &str, callback: &Function, args: &[JsValue]) -> IoResult<JsValue> {
+        let args = args.iter().collect::<Array>();
+        let value = callback
+            .apply(&self.target, &args)
+            .map_err(|err| io::Error::from(Error::from(err)))?;
+        if value.is_instance_of::<Promise>() {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("`{name}` returned a promise; storage callbacks must be synchronous"),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+/// Implementation of a [`StorageBackend`] which delegates every operation to methods of an object supplied from JS.
+///
+/// This puts redb on top of an existing storage layer, such as a custom encrypted store or a bridge to native code,
+/// without writing Rust. The object must have these methods, which must be synchronous, as redb is:
+///
+/// - `len(): number` returns the size of the storage in bytes.
+/// - `read(offset: number, len: number): Uint8Array` returns the `len` bytes at `offset`. Returning fewer means that
+///   the storage ends first.
+/// - `write(offset: number, data: Uint8Array)` stores `data` at `offset`, extending the storage if needed, with zeros
+///   in any gap. `data` is a copy, which the method may keep.
+/// - `setLen(len: number)` truncates or extends the storage to `len` bytes, with zeros.
+/// - `sync()`, which is optional, makes everything written so far durable.
+///
+/// An exception thrown by a method fails the operation with it. Like all JS objects, the callbacks can only be used
+/// on the thread which created the backend.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct JsCallbackBackend {
+    callbacks: ThreadBound<Callbacks>,
+}
+
+#[wasm_bindgen]
+impl JsCallbackBackend {
+    /// Delegate to the methods of `callbacks`, failing if a required one is missing.
+    #[wasm_bindgen(constructor)]
+    pub fn new(callbacks: JsValue) -> Result<JsCallbackBackend> {
+        let method = |name: &str| -> Result<Option<Function>> {
+            let value = Reflect::get(&callbacks, &name.into())?;
+            if value.is_undefined() {
+                return Ok(None);
+            }
+            let function = value.dyn_into::<Function>().map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("`{name}` is not a function"),
+                )
+            })?;
+            Ok(Some(function))
+        };
+        let required = |name: &str| -> Result<Function> {
+            let function = method(name)?.ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidInput, format!("`{name}` is missing"))
+            })?;
+            Ok(function)
+        };
+        let callbacks = Callbacks {
+            len: required("len")?,
+            read: required("read")?,
+            write: required("write")?,
+            set_len: required("setLen")?,
+            sync: method("sync")?,
+            target: callbacks,
+        };
+        Ok(Self {
+            callbacks: ThreadBound::new(callbacks),
+        })
+    }
+}
+
+impl StorageBackend for JsCallbackBackend {
+    fn len(&self) -> IoResult<u64> {
+        let callbacks = self.callbacks.get()?;
+        let len = callbacks.call("len", &callbacks.len, &[])?;
+        len.as_f64()
+            .filter(|len| *len >= 0.0 && len.fract() == 0.0 && *len <= MAX_SAFE_INTEGER as f64)
+            .map(|len| len as u64)
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    "`len` did not return a non-negative integer",
+                )
+            })
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let callbacks = self.callbacks.get()?;
+        let end = offset.checked_add(out.len() as u64);
+        if end.is_none_or(|end| end > MAX_SAFE_INTEGER) {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let args = [(offset as f64).into(), (out.len() as f64).into()];
+        let data = callbacks
+            .call("read", &callbacks.read, &args)?
+            .dyn_into::<Uint8Array>()
+            .map_err(|_| {
+                io::Error::new(ErrorKind::InvalidData, "`read` did not return a Uint8Array")
+            })?;
+        match (data.length() as usize).cmp(&out.len()) {
+            std::cmp::Ordering::Less => Err(ErrorKind::UnexpectedEof.into()),
+            std::cmp::Ordering::Equal => {
+                data.copy_to(out);
+                Ok(())
+            }
+            std::cmp::Ordering::Greater => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "`read` returned more bytes than requested",
+            )),
+        }
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        let callbacks = self.callbacks.get()?;
+        check_size(len)?;
+        callbacks.call("setLen", &callbacks.set_len, &[(len as f64).into()])?;
+        Ok(())
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let callbacks = self.callbacks.get()?;
+        if let Some(sync) = &callbacks.sync {
+            callbacks.call("sync", sync, &[])?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let callbacks = self.callbacks.get()?;
+        check_size(offset.saturating_add(data.len() as u64))?;
+        let args = [(offset as f64).into(), Uint8Array::from(data).into()];
+        callbacks.call("write", &callbacks.write, &args)?;
+        Ok(())
+    }
+}
+
+/// Sizes and offsets are passed as JS numbers, which are only exact up to [`MAX_SAFE_INTEGER`].
+fn check_size(size: u64) -> IoResult<()> {
+    if size > MAX_SAFE_INTEGER {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("size {size} too large, max allowed is {MAX_SAFE_INTEGER}"),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! A persistent key-value store, for JS applications which don't otherwise use Rust.
+
+use redb::{Database, ReadableDatabase as _, ReadableTable as _, TableDefinition};
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+#[cfg(opfs)]
+use crate::{JsCallbackBackend, database::create_database};
+use crate::{Result, database::redb_error, open_database};
+
+const TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("kv");
+
+/// A persistent map from string keys to byte values, stored in a redb database in OPFS.
+///
+/// Every modification is its own transaction, and durable once it returns. Like [`OpfsBackend`][crate::OpfsBackend],
+/// this must run in a web worker.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug)]
+pub struct KvStore {
+    database: Database,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl KvStore {
+    /// Open the store at `path`, creating it if it does not exist.
+    pub async fn open(path: &str) -> Result<KvStore> {
+        let database = open_database(path).await?;
+        Self::with_database(database)
+    }
+
+    /// Open the store kept by `backend`, whose storage is provided by JS callbacks, creating it if it is empty.
+    #[cfg(opfs)]
+    #[wasm_bindgen(js_name = withBackend)]
+    pub fn with_backend(backend: JsCallbackBackend) -> Result<KvStore> {
+        Self::with_database(create_database(backend)?)
+    }
+
+    /// The value stored under `key`, if any.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let tx = self.database.begin_read().map_err(redb_error)?;
+        let table = tx.open_table(TABLE).map_err(redb_error)?;
+        let value = table.get(key).map_err(redb_error)?;
+        Ok(value.map(|value| value.value().to_vec()))
+    }
+
+    /// Store `value` under `key`, replacing any previous value.
+    pub fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        let tx = self.database.begin_write().map_err(redb_error)?;
+        {
+            let mut table = tx.open_table(TABLE).map_err(redb_error)?;
+            table.insert(key, value).map_err(redb_error)?;
+        }
+        tx.commit().map_err(redb_error)?;
+        Ok(())
+    }
+
+    /// Remove the value stored under `key`, returning whether there was one.
+    pub fn delete(&self, key: &str) -> Result<bool> {
+        let tx = self.database.begin_write().map_err(redb_error)?;
+        let removed = {
+            let mut table = tx.open_table(TABLE).map_err(redb_error)?;
+            table.remove(key).map_err(redb_error)?.is_some()
+        };
+        tx.commit().map_err(redb_error)?;
+        Ok(removed)
+    }
+
+    /// The keys starting with `prefix`, in lexicographic order.
+    ///
+    /// An empty prefix lists every key.
+    pub fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let tx = self.database.begin_read().map_err(redb_error)?;
+        let table = tx.open_table(TABLE).map_err(redb_error)?;
+        let mut keys = Vec::new();
+        for entry in table.range(prefix..).map_err(redb_error)? {
+            let (key, _) = entry.map_err(redb_error)?;
+            let key = key.value();
+            if !key.starts_with(prefix) {
+                break;
+            }
+            keys.push(key.to_owned());
+        }
+        Ok(keys)
+    }
+}
+
+impl KvStore {
+    fn with_database(database: Database) -> Result<Self> {
+        let tx = database.begin_write().map_err(redb_error)?;
+        tx.open_table(TABLE).map_err(redb_error)?;
+        tx.commit().map_err(redb_error)?;
+        Ok(Self { database })
+    }
+}
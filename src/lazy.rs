@@ -0,0 +1,229 @@
+//! Deferring the opening of a backend's file until it is first used; see [`OpfsBackendBuilder::lazy`].
+//!
+//! Natively, the first operation opens the file itself, blocking the calling thread until it is open. On wasm,
+//! acquiring the Web Lock and the sync access handle is asynchronous, so the first operation only starts it in the
+//! background, and fails with [`ErrorKind::ResourceBusy`] until it completes, like a handle being re-acquired.
+
+use std::fmt;
+#[cfg(opfs)]
+use std::{
+    cell::RefCell,
+    io::{self, ErrorKind},
+    rc::Rc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, OpfsBackendBuilder, Result, file::File};
+#[cfg(opfs)]
+use crate::{thread_bound::ThreadBound, web_lock::WebLock};
+
+/// What opening the file yields.
+#[cfg(opfs)]
+pub(crate) type Acquired = (File, Option<WebLock>);
+#[cfg(not(opfs))]
+pub(crate) type Acquired = File;
+
+/// How far opening the file of a lazy backend has come.
+enum State {
+    /// Not started yet, or to be retried after a failure was reported.
+    Pending,
+    /// Underway in the background; the promise settles once it is done.
+    #[cfg(opfs)]
+    Acquiring(js_sys::Promise),
+    /// Completed in the background, waiting for the next operation to take the file.
+    #[cfg(opfs)]
+    Acquired(Acquired),
+    /// Failed in the background, waiting for the next operation to report the error.
+    #[cfg(opfs)]
+    Failed(io::Error),
+    /// The file was taken by the backend, or the backend was closed.
+    Done,
+}
+
+/// How to open the file, and how far that has come.
+struct Inner {
+    builder: OpfsBackendBuilder,
+    path: String,
+    state: State,
+}
+
+/// On wasm, shared with the task opening the file in the background.
+#[cfg(opfs)]
+type Shared = ThreadBound<Rc<RefCell<Inner>>>;
+#[cfg(not(opfs))]
+type Shared = Inner;
+
+/// The file of a lazy backend, until it is opened.
+pub(crate) struct Deferred {
+    inner: Mutex<Shared>,
+}
+
+impl fmt::Debug for Deferred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Deferred").finish_non_exhaustive()
+    }
+}
+
+impl Deferred {
+    pub(crate) fn new(builder: OpfsBackendBuilder, path: String) -> Self {
+        let inner = Inner {
+            builder,
+            path,
+            state: State::Pending,
+        };
+        #[cfg(opfs)]
+        let inner = ThreadBound::new(Rc::new(RefCell::new(inner)));
+        Self {
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// Whether the file was taken, or abandoned.
+    pub(crate) fn is_done(&self) -> bool {
+        #[cfg(opfs)]
+        {
+            self.inner
+                .lock()
+                .get()
+                .is_ok_and(|inner| matches!(inner.borrow().state, State::Done))
+        }
+
+        #[cfg(not(opfs))]
+        {
+            matches!(self.inner.lock().state, State::Done)
+        }
+    }
+
+    /// Abandon opening the file; a file opened in the background in the meantime is closed again.
+    pub(crate) fn abandon(&self) -> IoResult<()> {
+        #[cfg(opfs)]
+        {
+            let state = std::mem::replace(
+                &mut self.inner.lock().get()?.borrow_mut().state,
+                State::Done,
+            );
+            drop(state);
+        }
+
+        #[cfg(not(opfs))]
+        {
+            self.inner.lock().state = State::Done;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(opfs))]
+impl Deferred {
+    /// Open the file, and take it.
+    ///
+    /// Returns `None` once the file was taken before, or abandoned.
+    pub(crate) fn take(&self) -> IoResult<Option<Acquired>> {
+        let mut inner = self.inner.lock();
+        if matches!(inner.state, State::Done) {
+            return Ok(None);
+        }
+        // only waits for retries, or for a lock or a busy file to free up
+        let acquired = crate::retry::block_on(inner.builder.acquire(&inner.path))?;
+        inner.state = State::Done;
+        Ok(Some(acquired))
+    }
+
+    /// Open the file, and take it.
+    ///
+    /// Returns `None` once the file was taken before, or abandoned.
+    pub(crate) async fn acquire(&self) -> Result<Option<Acquired>> {
+        self.take()
+    }
+}
+
+#[cfg(opfs)]
+impl Deferred {
+    /// Take the opened file, starting to open it in the background if that hasn't happened yet.
+    ///
+    /// Fails with [`ErrorKind::ResourceBusy`] until the file is opened. Returns `None` once the file was taken
+    /// before, or abandoned.
+    pub(crate) fn take(&self) -> IoResult<Option<Acquired>> {
+        let guard = self.inner.lock();
+        let shared = guard.get()?;
+        let state = std::mem::replace(&mut shared.borrow_mut().state, State::Done);
+        match state {
+            State::Pending => {
+                start(shared);
+                Err(busy())
+            }
+            State::Acquiring(promise) => {
+                shared.borrow_mut().state = State::Acquiring(promise);
+                Err(busy())
+            }
+            State::Acquired(acquired) => Ok(Some(acquired)),
+            State::Failed(err) => {
+                // report the failure once, then try again
+                shared.borrow_mut().state = State::Pending;
+                Err(err)
+            }
+            State::Done => Ok(None),
+        }
+    }
+
+    /// Open the file, or wait for it to be opened in the background, and take it.
+    ///
+    /// Returns `None` once the file was taken before, or abandoned.
+    pub(crate) async fn acquire(&self) -> Result<Option<Acquired>> {
+        loop {
+            let promise = {
+                let guard = self.inner.lock();
+                let shared = guard.get()?;
+                let state = std::mem::replace(&mut shared.borrow_mut().state, State::Done);
+                match state {
+                    State::Pending => start(shared),
+                    State::Acquiring(promise) => {
+                        shared.borrow_mut().state = State::Acquiring(promise.clone());
+                        promise
+                    }
+                    State::Acquired(acquired) => return Ok(Some(acquired)),
+                    State::Failed(err) => {
+                        shared.borrow_mut().state = State::Pending;
+                        return Err(err.into());
+                    }
+                    State::Done => return Ok(None),
+                }
+            };
+            // the outcome is recorded in the state, and picked up on the next iteration
+            let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+        }
+    }
+}
+
+#[cfg(opfs)]
+fn busy() -> io::Error {
+    io::Error::new(
+        ErrorKind::ResourceBusy,
+        "the file of a lazy backend is being opened; retry later, or await `acquire` first",
+    )
+}
+
+/// Start opening the file in the background, and record that in `shared`.
+#[cfg(opfs)]
+fn start(shared: &Rc<RefCell<Inner>>) -> js_sys::Promise {
+    let task = Rc::clone(shared);
+    let promise = wasm_bindgen_futures::future_to_promise(async move {
+        let (builder, path) = {
+            let inner = task.borrow();
+            (inner.builder.clone(), inner.path.clone())
+        };
+        let acquired = builder.acquire(&path).await;
+        let mut inner = task.borrow_mut();
+        // if the backend was closed in the meantime, the file is dropped here, closing it
+        if matches!(inner.state, State::Acquiring(_)) {
+            inner.state = match acquired {
+                Ok(acquired) => State::Acquired(acquired),
+                Err(err) => State::Failed(err.into_inner()),
+            };
+        }
+        Ok(wasm_bindgen::JsValue::UNDEFINED)
+    });
+    shared.borrow_mut().state = State::Acquiring(promise.clone());
+    promise
+}
@@ -1,108 +1,1293 @@
 //! [`OpfsBackend`] mplements a [`StorageBackend`] which delegates to [OPFS] when built for wasm.
 //!
+//! On other targets, including `wasm32-wasip1`, which has no JS host, it delegates to [`std::fs`] instead.
+//!
 //! [OPFS]: https://developer.mozilla.org/en-US/docs/Web/API/File_System_API/Origin_private_file_system
 
-#[cfg(target_family = "wasm")]
+mod bounded;
+mod builder;
+mod busy;
+mod changes;
+#[cfg(feature = "checksums")]
+pub mod checksummed;
+#[cfg(any(feature = "checksums", feature = "compression", feature = "encryption"))]
+mod chunks;
+#[cfg(feature = "compression")]
+pub mod compressed;
+mod console_log;
+#[cfg(feature = "database")]
+pub mod database;
+mod dir_entry;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+#[cfg(opfs)]
 mod error;
-#[cfg(not(target_family = "wasm"))]
+#[cfg(not(opfs))]
 mod file {
     pub use std::fs::File;
 }
-#[cfg(target_family = "wasm")]
+#[cfg(opfs)]
 mod file;
 mod file_abstraction;
+#[cfg(all(opfs, feature = "http-backup"))]
+mod http_backup;
+#[cfg(all(opfs, feature = "idb-migration"))]
+pub mod idb_migration;
+mod journal;
+#[cfg(opfs)]
+mod js_callback;
+#[cfg(feature = "kv")]
+mod kv;
+mod lazy;
+#[cfg(all(opfs, feature = "main-thread"))]
+mod main_thread;
+#[cfg(feature = "database")]
+mod manager;
+mod memory;
+mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod mirrored;
+#[cfg(all(not(target_family = "wasm"), feature = "mmap"))]
+mod mmap;
+#[cfg(all(opfs, feature = "node"))]
+mod node;
+#[cfg(opfs)]
+mod notify;
+mod observed;
+mod page_cache;
+mod panics;
+#[cfg(feature = "redb2")]
+mod redb2_adapter;
+mod registry;
+mod retry;
+#[cfg(feature = "sharding")]
+pub mod sharded;
+mod storage_backend;
+mod storage_estimate;
+mod support;
+#[cfg(all(opfs, feature = "sync-bridge"))]
+mod sync_bridge;
+mod sync_mode;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(opfs)]
+mod thread_bound;
+mod timestamp;
+#[cfg(all(not(target_family = "wasm"), feature = "tokio"))]
+mod tokio_backend;
+#[cfg(feature = "redb3")]
+mod verify;
+mod vfs;
+#[cfg(opfs)]
+mod web_lock;
+#[cfg(all(opfs, feature = "worker"))]
+mod worker;
+mod write_buffer;
 
-use std::io::{Read as _, Seek as _, SeekFrom, Write as _};
+use std::io::{self, ErrorKind, Read, Seek as _, SeekFrom, Write};
 
+use console_log::debug_log;
 use file::File;
-use file_abstraction::FileAbstraction;
-use parking_lot::Mutex;
-use redb::StorageBackend;
+use file_abstraction::{FileAbstraction, OpenOptions};
+use page_cache::PageCache;
+use parking_lot::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+#[cfg(opfs)]
+use thread_bound::ThreadBound;
+use write_buffer::WriteBuffer;
 
-#[cfg(target_family = "wasm")]
+#[cfg(opfs)]
 use wasm_bindgen::prelude::*;
 
-#[cfg(target_family = "wasm")]
+pub use bounded::BoundedBackend;
+pub use builder::OpfsBackendBuilder;
+pub use busy::{FileBusy, OpenStep};
+pub use changes::IncrementalManifest;
+#[cfg(feature = "checksums")]
+pub use checksummed::ChecksummedBackend;
+#[cfg(feature = "compression")]
+pub use compressed::CompressedBackend;
+#[cfg(all(opfs, feature = "console-log"))]
+pub use console_log::init_console_log;
+#[cfg(feature = "database")]
+pub use database::{open_database, open_database_with};
+pub use dir_entry::DirEntry;
+#[cfg(feature = "encryption")]
+pub use encrypted::{BackupReader, BackupWriter, EncryptedBackend};
+#[cfg(opfs)]
 pub use error::Error;
+#[cfg(all(opfs, feature = "http-backup"))]
+pub use http_backup::HttpBackup;
+#[cfg(all(opfs, feature = "idb-migration"))]
+pub use idb_migration::IdbMigration;
+pub use journal::{Journal, JournalEntry, JournaledBackend};
+#[cfg(opfs)]
+pub use js_callback::JsCallbackBackend;
+#[cfg(feature = "kv")]
+pub use kv::KvStore;
+#[cfg(all(opfs, feature = "main-thread"))]
+pub use main_thread::MainThreadBackend;
+#[cfg(feature = "database")]
+pub use manager::DatabaseManager;
+pub use memory::MemoryBackend;
+pub use metadata::Metadata;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use mirrored::MirroredBackend;
+#[cfg(all(not(target_family = "wasm"), feature = "mmap"))]
+pub use mmap::MmapBackend;
+pub use observed::{ObservedBackend, Observer, Operation};
+#[cfg(opfs)]
+pub use panics::set_panic_hook_js;
+pub use panics::{CaughtPanic, set_panic_hook};
+#[cfg(feature = "redb2")]
+pub use redb2_adapter::Redb2Backend;
+pub use registry::AlreadyOpen;
+#[cfg(feature = "sharding")]
+pub use sharded::ShardedBackend;
+pub use storage_backend::StorageBackend;
+pub use storage_estimate::{InsufficientQuota, StorageEstimate};
+pub use support::Support;
+#[cfg(all(opfs, feature = "sync-bridge"))]
+pub use sync_bridge::{SyncBridge, serve_sync_bridge};
+pub use sync_mode::SyncMode;
+#[cfg(all(not(target_family = "wasm"), feature = "tokio"))]
+pub use tokio_backend::TokioBackend;
+#[cfg(feature = "redb3")]
+pub use verify::{VerifyReport, verify};
+#[cfg(not(opfs))]
+pub use vfs::StdVfs;
+pub use vfs::{Vfs, VfsBackend};
+#[cfg(all(opfs, feature = "worker"))]
+pub use worker::{WorkerHandle, serve_worker};
 
-#[cfg(not(target_family = "wasm"))]
+#[cfg(not(opfs))]
 type Error = std::io::Error;
 
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 type IoResult<T> = std::io::Result<T>;
 
+/// The file handle underlying an [`OpfsBackend`], as exposed by [`OpfsBackend::with_raw_handle`]: a
+/// [`FileSystemSyncAccessHandle`][web_sys::FileSystemSyncAccessHandle] on wasm, and a [`std::fs::File`] natively.
+#[cfg(opfs)]
+pub type RawHandle = web_sys::FileSystemSyncAccessHandle;
+/// The file handle underlying an [`OpfsBackend`], as exposed by [`OpfsBackend::with_raw_handle`]: a
+/// `FileSystemSyncAccessHandle` on wasm, and a [`std::fs::File`] natively.
+#[cfg(not(opfs))]
+pub type RawHandle = std::fs::File;
+
 /// Implementataion of a [`StorageBackend`] which delegates to [OPFS] when built for wasm.
 ///
 /// **IMPORTANT**: This can only ever be used within a web worker.
 /// This _may_ instantiate within the main thread, but as it blocks internally,
 /// it will fail at runtime on the main thread if you attempt to actually use it.
 ///
-/// In native contexts, this targets the local file system.
+/// In native contexts, this targets the local file system. Reads use positioned I/O, so redb's concurrent read
+/// transactions read the file concurrently rather than one after another.
+///
+/// If the sync access handle is invalidated, for example because the file was removed and recreated, operations
+/// fail with [`ErrorKind::ResourceBusy`] while the handle is re-acquired in the background, then succeed again.
 ///
 /// [OPFS]: https://developer.mozilla.org/en-US/docs/Web/API/File_System_API/Origin_private_file_system
-#[cfg_attr(target_family = "wasm", wasm_bindgen)]
+#[cfg_attr(opfs, wasm_bindgen)]
 #[derive(Debug)]
 pub struct OpfsBackend {
-    file: Mutex<File>,
+    /// `None` once the backend has been [closed][OpfsBackend::close].
+    ///
+    /// Reads share the lock, using positioned I/O; everything else takes it exclusively.
+    pub(crate) file: RwLock<FileSlot>,
+    pub(crate) read_only: bool,
+    /// Only invalidated while the file is locked exclusively; see [`OpfsBackendBuilder::cache_pages`].
+    pub(crate) cache: Option<Mutex<PageCache>>,
+    /// Only modified while the file is locked exclusively; see [`OpfsBackendBuilder::write_buffer`].
+    pub(crate) write_buffer: Option<Mutex<WriteBuffer>>,
+    /// See [`OpfsBackendBuilder::retries`].
+    pub(crate) retry: retry::RetryPolicy,
+    /// See [`OpfsBackendBuilder::sync_mode`].
+    pub(crate) flush: sync_mode::FlushSchedule,
+    /// Held until the backend is closed or dropped; see [`OpfsBackendBuilder::lock`].
+    #[cfg(opfs)]
+    pub(crate) web_lock: Mutex<ThreadBound<Option<web_lock::WebLock>>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Mutex<metrics::Recorder>,
+    /// The path of the file, if it is to be removed once closed; see [`OpfsBackend::temp`].
+    pub(crate) temp_path: Option<String>,
+    /// How to open the file, until it is opened; see [`OpfsBackendBuilder::lazy`].
+    pub(crate) deferred: Option<lazy::Deferred>,
+    /// Whether opening the deferred file creates it; see [`OpfsBackendBuilder::create_on_write`].
+    pub(crate) create_on_write: bool,
+    /// Marks the file as open in this process until the backend is closed or dropped.
+    pub(crate) registration: Mutex<Option<registry::Registration>>,
+    /// See [`OpfsBackendBuilder::notify_channel`].
+    #[cfg(opfs)]
+    pub(crate) notifier: Option<ThreadBound<notify::Notifier>>,
+    /// Only modified while the file is locked exclusively; see [`OpfsBackendBuilder::track_changes`].
+    pub(crate) changes: Option<Mutex<changes::ChangeTracker>>,
+    /// Only recorded while the file is locked exclusively; see [`OpfsBackendBuilder::dirty_pages`].
+    pub(crate) dirty_pages: Option<Mutex<changes::DirtyPages>>,
+}
+
+/// The file of an [`OpfsBackend`].
+///
+/// On wasm, it holds JS objects, which [`ThreadBound`] keeps on the thread which opened it.
+#[cfg(opfs)]
+pub(crate) type FileSlot = ThreadBound<Option<File>>;
+#[cfg(not(opfs))]
+pub(crate) type FileSlot = Option<File>;
+
+/// Access the file in `slot`, failing on wasm if this isn't the thread which opened it.
+fn slot_mut(slot: &mut FileSlot) -> IoResult<&mut Option<File>> {
+    #[cfg(opfs)]
+    {
+        slot.get_mut()
+    }
+
+    #[cfg(not(opfs))]
+    {
+        Ok(slot)
+    }
+}
+
+/// Like [`slot_mut`], for shared access.
+fn slot_ref(slot: &FileSlot) -> IoResult<&Option<File>> {
+    #[cfg(opfs)]
+    {
+        slot.get()
+    }
+
+    #[cfg(not(opfs))]
+    {
+        Ok(slot)
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(ErrorKind::NotConnected, "backend is closed")
 }
 
-// Safety: when targeting wasm, we're really working in a single-threaded context anyway, so
-// literally everything is trivially `Send`, because there are no other threads to send it to.
-//
-// Note that we only need to manually implement this for wasm; in native contexts, `async_fs::File`
-// already implements `Send`.
-#[cfg(target_family = "wasm")]
-unsafe impl Send for OpfsBackend {}
+/// A name which no other file will have, from a random UUID.
+///
+/// Fails with [`ErrorKind::Unsupported`] if `crypto.randomUUID` is missing, which it is outside secure contexts, rather
+/// than falling back to a name which may not be unique.
+#[cfg(opfs)]
+fn unique_name() -> Result<String> {
+    let unavailable =
+        || io::Error::new(ErrorKind::Unsupported, "crypto.randomUUID is not available");
+    let crypto = js_sys::Reflect::get(&js_sys::global(), &"crypto".into())?;
+    if !crypto.is_object() {
+        return Err(unavailable().into());
+    }
+    let random_uuid = js_sys::Reflect::get(&crypto, &"randomUUID".into())?
+        .dyn_into::<js_sys::Function>()
+        .map_err(|_| unavailable())?;
+    let uuid = random_uuid
+        .call0(&crypto)?
+        .as_string()
+        .filter(|uuid| !uuid.is_empty())
+        .ok_or_else(|| io::Error::other("crypto.randomUUID did not return a UUID"))?;
+    Ok(uuid)
+}
+
+/// A name which no other file will have, from the process id, the time, and a counter.
+#[cfg(not(opfs))]
+fn unique_name() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{nanos:x}-{count}", std::process::id())
+}
+
+/// Remove the closed temporary file at `path`, ignoring failures, as there is no one to report them to.
+fn remove_temp(path: &str) {
+    #[cfg(opfs)]
+    {
+        let path = path.to_owned();
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = File::delete(path).await;
+        });
+    }
+
+    #[cfg(not(opfs))]
+    {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Write the exported data of the ranges in `manifest`, read from `data`, to `file`, then set its length and flush it.
+fn apply_ranges(
+    file: &mut File,
+    manifest: &IncrementalManifest,
+    data: &mut impl Read,
+) -> IoResult<()> {
+    const CHUNK_SIZE: usize = 1 << 20;
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    for &(offset, len) in &manifest.ranges {
+        let mut done = 0;
+        while done < len {
+            let n = (len - done).min(CHUNK_SIZE as u64) as usize;
+            data.read_exact(&mut buf[..n])?;
+            file.write_all_at(&buf[..n], offset + done)?;
+            done += n as u64;
+        }
+    }
+    file.set_len(manifest.len)?;
+    Write::flush(file)
+}
+
+/// Overwrite all of `file` with zeros, in chunks, then truncate it, flushing after each step.
+fn zero_out(file: &mut File) -> IoResult<()> {
+    const CHUNK_SIZE: usize = 1 << 20;
+
+    let len = file.len()?;
+    let zeros = vec![0; CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < len {
+        let n = (len - offset).min(CHUNK_SIZE as u64) as usize;
+        file.write_all_at(&zeros[..n], offset)?;
+        offset += n as u64;
+    }
+    Write::flush(file)?;
+    file.set_len(0)?;
+    Write::flush(file)
+}
 
-// Safety: when targeting wasm, we don't have multiple threads to send things between, but we
-// very often need to coordinate between various async contexts. For this reason we put a mutex
-// around the file handle, so contention is explicitly resolved.
-#[cfg(target_family = "wasm")]
-unsafe impl Sync for OpfsBackend {}
+/// Evaluate `$op`, recording it in the backend's metrics as an operation of kind `$kind` transferring `$bytes`.
+///
+/// Without the `metrics` feature, this just evaluates `$op`.
+macro_rules! measured {
+    ($backend:expr, $kind:ident, $bytes:expr, $op:expr) => {{
+        #[cfg(feature = "metrics")]
+        let started = timestamp::Timestamp::now();
+        let result = $op;
+        #[cfg(feature = "metrics")]
+        $backend.metrics.lock().record(
+            metrics::Op::$kind,
+            $bytes,
+            started.elapsed(),
+            result.is_ok(),
+        );
+        result
+    }};
+}
 
-#[cfg_attr(target_family = "wasm", wasm_bindgen)]
+#[cfg_attr(opfs, wasm_bindgen)]
 impl OpfsBackend {
     /// Open the file at the specified path.
-    #[cfg_attr(target_family = "wasm", wasm_bindgen(js_name = open))]
+    ///
+    /// The file is opened read+write, and created if it does not exist.
+    /// Use [`OpfsBackend::builder`] for other options.
+    ///
+    /// On wasm, paths are relative to the OPFS root, and `..` components are resolved against the components before
+    /// them, so `accounts/tmp/../db.redb` opens `accounts/db.redb`. Paths which would escape the root are rejected.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = open))]
     pub async fn new(path: &str) -> Result<Self> {
-        let file = <File as FileAbstraction>::open(path).await?;
-        let file = Mutex::new(file);
-        Ok(Self { file })
+        Self::builder().open(path).await
+    }
+
+    /// Create a new, uniquely named file, which is removed once the backend is closed or dropped.
+    ///
+    /// This suits throwaway databases, such as for staging imports, and tests. On wasm, the file is created in the
+    /// OPFS directory `tmp`, and removed in the background after closing, since OPFS removes files asynchronously;
+    /// files left behind by a crash stay in `tmp` until removed, for example with
+    /// [`clear_namespace("tmp")`][Self::clear_namespace]. The file is named with `crypto.randomUUID`, and this fails
+    /// with [`ErrorKind::Unsupported`] where that is missing. Natively, the file is created in
+    /// [`std::env::temp_dir`].
+    pub async fn temp() -> Result<Self> {
+        #[cfg(opfs)]
+        let path = format!("tmp/{}.redb", unique_name()?);
+        #[cfg(not(opfs))]
+        let path = std::env::temp_dir()
+            .join(format!("redb-opfs-{}.redb", unique_name()))
+            .to_string_lossy()
+            .into_owned();
+
+        let mut backend = Self::builder().create_new(true).open(&path).await?;
+        backend.temp_path = Some(path);
+        Ok(backend)
+    }
+
+    /// Open the file at the specified path, discarding any existing content.
+    ///
+    /// This is the building block for "reset my local data" flows: the file is created if necessary,
+    /// and truncated to 0 length otherwise, without deleting and recreating the OPFS entry.
+    pub async fn reset(path: &str) -> Result<Self> {
+        Self::builder().truncate(true).open(path).await
+    }
+
+    /// Remove the file at the specified path.
+    ///
+    /// This fails if the file does not exist. On wasm, it also fails if the file is currently open,
+    /// in this or any other worker.
+    pub async fn delete(path: &str) -> Result<()> {
+        <File as FileAbstraction>::delete(path).await?;
+        Ok(())
+    }
+
+    /// Overwrite the content of the file at the specified path with zeros, flush it, truncate it, and remove it.
+    ///
+    /// This is best-effort destruction of local data for privacy-sensitive applications, beyond what
+    /// [`delete`][Self::delete] does. It can't reach copies the browser or the operating system keep elsewhere, such
+    /// as on copy-on-write file systems, SSDs which remap blocks, or in backups. Like `delete`, this fails if the file
+    /// does not exist, and on wasm, if it is currently open, in this or any other worker. If overwriting fails, the
+    /// file is left in place.
+    pub async fn wipe(path: &str) -> Result<()> {
+        let options = OpenOptions {
+            create: false,
+            ..OpenOptions::default()
+        };
+        let mut file = <File as FileAbstraction>::open(path, &options).await?;
+        let wiped = zero_out(&mut file);
+        file.close();
+        wiped?;
+        <File as FileAbstraction>::delete(path).await?;
+        debug_log!("wiped {path}");
+        Ok(())
+    }
+
+    /// Move the file at `from` to `to`, replacing `to` if it already exists.
+    ///
+    /// This is intended for migrating a database to a new path, for example when an account
+    /// identifier changes. The file must not be open. On wasm, missing parent directories of `to`
+    /// are created; browsers which lack `FileSystemHandle.move` fall back to copying the file
+    /// and deleting the original.
+    pub async fn rename(from: &str, to: &str) -> Result<()> {
+        <File as FileAbstraction>::rename(from, to).await?;
+        Ok(())
+    }
+
+    /// Copy the file at `from` to `to`, replacing `to` if it already exists, and return the number of bytes copied.
+    ///
+    /// This is intended for duplicating a closed database, for example before a risky migration; to copy an open
+    /// one, use [`snapshot`][Self::snapshot]. Neither file may be open. On wasm, missing parent directories of `to`
+    /// are created, and where the browser supports `createWritable`, it copies the content itself, without it passing
+    /// through wasm memory, and leaves `to` untouched if copying fails. Otherwise, a failed copy leaves `to` with
+    /// part of the content. Either way, a `to` which didn't exist before is removed again on failure. Fails with
+    /// [`ErrorKind::InvalidInput`] if both paths name the same file.
+    pub async fn copy(from: &str, to: &str) -> Result<u64> {
+        let copied = <File as FileAbstraction>::copy_file(from, to).await?;
+        Ok(copied)
+    }
+
+    /// Remove the directory at `prefix` and everything in it, recursively.
+    ///
+    /// This is intended for logout flows which must purge all of a user's local data in one call, so it succeeds
+    /// if nothing exists at `prefix`. If `prefix` names a file, only that file is removed. On wasm, this fails if
+    /// any affected file is currently open, in this or any other worker; entries removed before the failure
+    /// stay removed.
+    ///
+    /// `prefix` must name an entry; to guard against accidentally wiping everything, this fails with
+    /// [`ErrorKind::InvalidInput`] for an empty path or the root.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = clearNamespace))]
+    pub async fn clear_namespace(prefix: &str) -> Result<()> {
+        if std::path::Path::new(prefix).file_name().is_none() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "refusing to clear the root directory",
+            )
+            .into());
+        }
+        <File as FileAbstraction>::remove_all(prefix).await?;
+        Ok(())
+    }
+
+    /// Determine whether a database file exists at the specified path.
+    ///
+    /// Unlike opening, this never creates the file or any parent directories.
+    pub async fn exists(path: &str) -> Result<bool> {
+        let exists = <File as FileAbstraction>::exists(path).await?;
+        Ok(exists)
+    }
+
+    /// Get the size and modification time of the file at the specified path.
+    ///
+    /// This is a cheap way to tell whether a database changed, for example since it was last synchronized, without
+    /// opening it. On wasm, this reads `getFile()` of the file's handle, which fails while the file is open, in
+    /// this or any other worker, so check the file before opening it or after closing it.
+    pub async fn metadata(path: &str) -> Result<Metadata> {
+        let metadata = <File as FileAbstraction>::metadata(path).await?;
+        Ok(metadata)
+    }
+
+    /// Estimate how much storage is used, and how much is available.
+    ///
+    /// On wasm, this reports the browser's `navigator.storage.estimate()` for the origin; writes start failing
+    /// with quota errors once usage reaches the quota. Natively, this reports the file system containing
+    /// the current directory; [`min_free_space`][OpfsBackendBuilder::min_free_space] checks the one containing the
+    /// database instead.
+    pub async fn quota() -> Result<StorageEstimate> {
+        let estimate = <File as FileAbstraction>::estimate("").await?;
+        Ok(estimate)
+    }
+
+    /// Request that the browser never evicts this origin's storage under storage pressure.
+    ///
+    /// Evicting OPFS silently destroys every database in it, so call this before creating a database whose
+    /// content can't be recovered from elsewhere. Returns whether storage is now persistent; browsers may refuse,
+    /// or prompt the user. Natively, storage is always persistent and this returns `true`.
+    pub async fn persist() -> Result<bool> {
+        let persisted = <File as FileAbstraction>::persist().await?;
+        Ok(persisted)
+    }
+
+    /// Determine whether this origin's storage is persistent, without requesting it.
+    ///
+    /// See [`OpfsBackend::persist`].
+    pub async fn persisted() -> Result<bool> {
+        let persisted = <File as FileAbstraction>::persisted().await?;
+        Ok(persisted)
+    }
+
+    /// Report whether an `OpfsBackend` can be opened in the current context, and which of the browser features it
+    /// relies on are available, without side effects.
+    ///
+    /// Call this at startup to choose between OPFS and fallback storage.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = isSupported))]
+    pub fn is_supported() -> Support {
+        Support::probe()
+    }
+
+    /// Configure how the file is opened.
+    pub fn builder() -> OpfsBackendBuilder {
+        OpfsBackendBuilder::default()
+    }
+
+    /// Flush all changes and release the file, so that it can be opened again, in this or any other worker.
+    ///
+    /// This happens implicitly when the backend is dropped, but then errors can't be reported.
+    ///
+    /// On wasm, this closes the sync access handle and releases the Web Lock. Afterwards, every operation on this
+    /// backend fails with [`ErrorKind::NotConnected`], which is `Error::Closed` on wasm. Closing an already closed
+    /// backend does nothing.
+    pub fn close(&self) -> Result<()> {
+        if let Some(deferred) = &self.deferred {
+            deferred.abandon()?;
+        }
+        #[cfg(opfs)]
+        let _web_lock = self.web_lock.lock().get_mut()?.take();
+        let Some(mut file) = slot_mut(&mut self.file.write())?.take() else {
+            // a lazy backend registers its file before opening it
+            self.registration.lock().take();
+            return Ok(());
+        };
+        // read-only handles can't be flushed, but have nothing to flush either
+        let flushed = match self.read_only {
+            true => Ok(()),
+            false => self.write_out(&file).and_then(|_| Write::flush(&mut file)),
+        };
+        if let Some(cache) = &self.cache {
+            cache.lock().clear();
+        }
+        file.close();
+        self.registration.lock().take();
+        if let Some(path) = &self.temp_path {
+            remove_temp(path);
+        }
+        flushed?;
+        debug_log!("closed");
+        Ok(())
+    }
+
+    /// Whether this backend has been [closed][Self::close].
+    #[cfg_attr(opfs, wasm_bindgen(js_name = isClosed))]
+    pub fn is_closed(&self) -> bool {
+        // from another thread, the backend is unusable but not closed
+        slot_ref(&self.file.read()).is_ok_and(|file| file.is_none())
+            && self.deferred.as_ref().is_none_or(lazy::Deferred::is_done)
+    }
+
+    /// Open the file of a [lazy][OpfsBackendBuilder::lazy] backend now, or wait until it is opened.
+    ///
+    /// This reports the errors opening would have reported, and does nothing if the file is already open, or if
+    /// the backend is not lazy. Fails with [`ErrorKind::NotConnected`] if the backend was closed.
+    pub async fn acquire(&self) -> Result<()> {
+        let Some(deferred) = &self.deferred else {
+            return Ok(());
+        };
+        if let Some(acquired) = deferred.acquire().await? {
+            self.install(acquired)?;
+        }
+        self.file()?;
+        Ok(())
+    }
+
+    /// Create the file of a [create-on-write][OpfsBackendBuilder::create_on_write] backend now, or wait until it is
+    /// created.
+    ///
+    /// This is [`acquire`][Self::acquire] under a name which says what it does here: it does nothing if the file
+    /// exists already.
+    pub async fn create(&self) -> Result<()> {
+        self.acquire().await
+    }
+
+    /// Whether this backend was opened read-only.
+    ///
+    /// If so, all attempts to modify the file fail with [`ErrorKind::PermissionDenied`].
+    #[cfg_attr(opfs, wasm_bindgen(js_name = isReadOnly))]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Write `data` at the end of the file, returning the offset it was written at.
+    ///
+    /// The end is found and the data written under a single acquisition of the internal lock, so appends from
+    /// several threads never overwrite each other, as they could with a separate `len()` and `write()`. This suits
+    /// logs and other append-only files kept next to a database. Panics, [retries][OpfsBackendBuilder::retries] and
+    /// metrics are handled as for [`write`][StorageBackend::write].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, data), fields(len = data.len()), ret, err)
+    )]
+    pub fn append(&self, data: &[u8]) -> Result<u64> {
+        let offset = panics::guard("append", || {
+            self.check_writable()?;
+            self.retry.run_sync(|| {
+                let guard = self.file()?;
+                let mut offset = guard.len()?;
+                if let Some(buffer) = &self.write_buffer {
+                    offset = offset.max(buffer.lock().end());
+                }
+                measured!(self, Write, data.len(), self.write_at(&guard, offset, data))?;
+                Ok(offset)
+            })
+        })?;
+        Ok(offset)
+    }
+}
+
+impl OpfsBackend {
+    /// List the entries of the directory at the specified path.
+    ///
+    /// An empty path lists the OPFS root directory on wasm, and the current directory natively.
+    /// This never creates the directory.
+    pub async fn read_dir(path: &str) -> Result<Vec<DirEntry>> {
+        let entries = <File as FileAbstraction>::read_dir(path).await?;
+        Ok(entries)
+    }
+
+    /// Copy the current content of the database to a new file at `dest`.
+    ///
+    /// The internal lock is held for the duration of the copy, so the snapshot is a consistent
+    /// point-in-time copy even while other tasks keep using this backend. Changes which redb has
+    /// not yet committed are included as they stand on disk, so take snapshots between transactions.
+    ///
+    /// This fails with [`ErrorKind::AlreadyExists`] if `dest` already exists.
+    pub async fn snapshot(&self, dest: &str) -> Result<()> {
+        let options = OpenOptions {
+            create_new: true,
+            ..OpenOptions::default()
+        };
+        let mut dest = <File as FileAbstraction>::open(dest, &options).await?;
+
+        let copied = {
+            let mut source = self.file()?;
+            self.write_out(&source)
+                .and_then(|_| source.seek(SeekFrom::Start(0)))
+                .and_then(|_| file_abstraction::copy(&mut *source, &mut dest))
+                .and_then(|_| dest.flush())
+        };
+        dest.close();
+        copied?;
+        Ok(())
+    }
+
+    /// Replace the content of the file at `path` with `bytes`, creating it if necessary.
+    ///
+    /// This is intended for restoring backups, or seeding a database shipped from a server.
+    /// On wasm, this fails with `Error::HandleBusy` if the file is currently open, in this
+    /// or any other worker. Natively, it is up to the caller to ensure that the file is not open.
+    pub async fn import(path: &str, bytes: &[u8]) -> Result<()> {
+        let mut file = Self::open_for_import(path).await?;
+        let written = file.write_all(bytes).and_then(|_| file.flush());
+        file.close();
+        written?;
+        Ok(())
+    }
+
+    /// Write the entire content of the database to `out`, in chunks.
+    ///
+    /// `progress` is called after each chunk with the number of bytes written so far and the total size,
+    /// so that large databases can be moved out incrementally. The internal lock is held throughout,
+    /// so the export is a consistent point-in-time copy. Returns the number of bytes written.
+    pub fn export_to(
+        &self,
+        out: &mut impl Write,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<u64> {
+        let mut file = self.file()?;
+        self.write_out(&file)?;
+        let total = file.len()?;
+        file.seek(SeekFrom::Start(0))?;
+        let copied =
+            file_abstraction::copy_with_progress(&mut *file, out, |done| progress(done, total))?;
+        Ok(copied)
+    }
+
+    /// Replace the content of the file at `path` with everything read from `source`, creating it if necessary.
+    ///
+    /// `progress` is called after each chunk with the number of bytes written so far.
+    /// Like [`import`][Self::import], this fails if the file is currently open.
+    /// Returns the number of bytes written.
+    pub async fn import_from(
+        path: &str,
+        source: &mut impl Read,
+        progress: impl FnMut(u64),
+    ) -> Result<u64> {
+        let mut file = Self::open_for_import(path).await?;
+        let written = file_abstraction::copy_with_progress(source, &mut file, progress)
+            .and_then(|copied| file.flush().map(|_| copied));
+        file.close();
+        let written = written?;
+        Ok(written)
+    }
+
+    /// Like [`import_from`][Self::import_from], but read `source` into a new file next to `path`, and only replace
+    /// the file at `path` with it once `source` was read to its end, so that a failure leaves `path` as it was.
+    ///
+    /// This suits sources which may fail partway, such as the `BackupReader` of an encrypted backup given the wrong
+    /// key, or a backup which was tampered with. Like `import_from`, this fails if the file at `path` is currently
+    /// open.
+    pub async fn import_staged(
+        path: &str,
+        source: &mut impl Read,
+        progress: impl FnMut(u64),
+    ) -> Result<u64> {
+        Self::check_not_open(path).await?;
+        #[cfg(opfs)]
+        let staging = format!("{path}.{}.import", unique_name()?);
+        #[cfg(not(opfs))]
+        let staging = format!("{path}.{}.import", unique_name());
+        let imported = match Self::import_from(&staging, source, progress).await {
+            Ok(written) => Self::rename(&staging, path).await.map(|()| written),
+            Err(err) => Err(err),
+        };
+        if imported.is_err() {
+            // the staging file only holds what was read before the failure
+            let _ = <File as FileAbstraction>::remove_all(&staging).await;
+        }
+        imported
+    }
+
+    /// Write what changed since the incremental export which returned the token `since` to `out`, or all of the file
+    /// if `since` is `None`, and describe it in the returned manifest.
+    ///
+    /// This needs [`OpfsBackendBuilder::track_changes`], and fails with [`ErrorKind::Unsupported`] otherwise. Changes
+    /// are only known from when the backend was opened, so a token from before fails with
+    /// [`ErrorKind::InvalidInput`]; take a full export then. Like [`export_to`][Self::export_to], the internal lock is
+    /// held throughout, so the export is a consistent point-in-time copy, which should be taken between transactions.
+    ///
+    /// The next incremental export builds on this one only if it succeeds; if writing to `out` fails, export again
+    /// from the same token.
+    pub fn export_incremental(
+        &self,
+        since: Option<&str>,
+        out: &mut impl Write,
+    ) -> Result<IncrementalManifest> {
+        const CHUNK_SIZE: usize = 1 << 20;
+
+        let changes = self.changes.as_ref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::Unsupported,
+                "changes are not tracked; see OpfsBackendBuilder::track_changes",
+            )
+        })?;
+        let file = self.file()?;
+        self.write_out(&file)?;
+        let len = file.len()?;
+        let mut changes = changes.lock();
+        let ranges = changes.changed_since(since, len)?;
+
+        let mut buf = vec![0; CHUNK_SIZE];
+        for &(offset, range_len) in &ranges {
+            let mut done = 0;
+            while done < range_len {
+                let n = (range_len - done).min(CHUNK_SIZE as u64) as usize;
+                file.read_exact_at(&mut buf[..n], offset + done)?;
+                out.write_all(&buf[..n])?;
+                done += n as u64;
+            }
+        }
+        Ok(IncrementalManifest {
+            token: changes.advance(),
+            len,
+            full: since.is_none(),
+            ranges,
+        })
+    }
+
+    /// Apply an export taken by [`export_incremental`][Self::export_incremental] to the file at `path`, reading the
+    /// exported data from `data`.
+    ///
+    /// A full export replaces the content of the file, creating it if necessary. Any other export must be applied to
+    /// a file holding the export it was taken since, with the exports in between applied in order; nothing checks
+    /// this, and the file must exist. Like [`import`][Self::import], this fails if the file is currently open.
+    pub async fn apply_incremental(
+        path: &str,
+        manifest: &IncrementalManifest,
+        data: &mut impl Read,
+    ) -> Result<()> {
+        let options = OpenOptions {
+            create: manifest.full,
+            truncate: manifest.full,
+            ..OpenOptions::default()
+        };
+        let mut file = Self::open_unshared(path, &options).await?;
+        let applied = apply_ranges(&mut file, manifest, data);
+        file.close();
+        applied?;
+        Ok(())
+    }
+
+    /// Write each `(offset, data)` pair, in order, under a single acquisition of the internal lock.
+    ///
+    /// This is equivalent to calling [`StorageBackend::write`] for each pair, but without the per-call overhead,
+    /// and no other task can observe the file between the writes. If a write fails, the following ones are not
+    /// attempted.
+    pub fn write_vectored(&self, writes: &[(u64, &[u8])]) -> Result<()> {
+        self.check_writable()?;
+        let guard = self.file()?;
+        for &(offset, data) in writes {
+            measured!(self, Write, data.len(), self.write_at(&guard, offset, data))?;
+        }
+        Ok(())
+    }
+
+    /// Read each `(offset, len)` range, in order, under a single acquisition of the internal lock.
+    ///
+    /// This is equivalent to calling [`StorageBackend::read`] for each range, but without the per-call overhead,
+    /// and no other task can modify the file between the reads. Fails if any range extends beyond the end of the file.
+    pub fn read_ranges(&self, ranges: &[(u64, usize)]) -> Result<Vec<Vec<u8>>> {
+        let guard = self.file_shared()?;
+        let buffers = ranges
+            .iter()
+            .map(|&(offset, len)| {
+                let mut buf = vec![0; len];
+                measured!(self, Read, len, self.read_at(&guard, offset, &mut buf))?;
+                Ok(buf)
+            })
+            .collect::<IoResult<Vec<_>>>()?;
+        Ok(buffers)
+    }
+
+    /// Run `f` with the underlying file handle, for operations this backend doesn't wrap.
+    ///
+    /// Buffered writes are written out before `f` runs, and the page cache is discarded after it returns, so `f`
+    /// sees the current content of the file, and the backend sees whatever `f` changed. Beyond that, the backend
+    /// can't protect its invariants, so `f` must honor these constraints:
+    ///
+    /// - The internal lock is held exclusively while `f` runs, and it is not reentrant: calling back into this
+    ///   backend from `f` deadlocks.
+    /// - `f` must not close the handle, nor keep it, or a clone of it on wasm, beyond its return. On wasm, the handle
+    ///   is replaced whenever it is re-acquired, and the one kept would no longer be used.
+    /// - Changes made by `f` bypass redb, so they must not touch the database file while redb is using it, other
+    ///   than in ways redb does not observe, such as reading.
+    pub fn with_raw_handle<T>(&self, f: impl FnOnce(&RawHandle) -> T) -> Result<T> {
+        let file = self.file()?;
+        self.write_out(&file)?;
+        let len_before = if self.changes.is_some() || self.dirty_pages.is_some() {
+            file.len()?
+        } else {
+            0
+        };
+        // the tracked length of the file is queried anew, as `f` may have changed it
+        #[cfg(opfs)]
+        let output = file.with_raw_handle(f)?;
+        #[cfg(not(opfs))]
+        let output = f(&file);
+        if let Some(cache) = &self.cache {
+            cache.lock().clear();
+        }
+        // there's no telling what `f` wrote
+        if let Some(changes) = &self.changes {
+            changes.lock().record_write(0, len_before.max(file.len()?));
+        }
+        if let Some(dirty_pages) = &self.dirty_pages {
+            let len = file.len()?;
+            let mut dirty_pages = dirty_pages.lock();
+            dirty_pages.record_set_len(len_before, len);
+            dirty_pages.record_write(0, len);
+        }
+        Ok(output)
+    }
+
+    /// Take the indices of the pages written and synced since the last call, in order, for replication.
+    ///
+    /// Pages are of the size passed to [`OpfsBackendBuilder::dirty_pages`], so page `i` starts at byte
+    /// `i * page_size`. A page counts once it was written, or its part of the file truncated or extended, and then
+    /// [synced][StorageBackend::sync_data], which redb does on every durable commit; pages written since the last
+    /// sync stay recorded until the next one. Pages the file was truncated past are forgotten, so replicate the
+    /// [length][StorageBackend::len] of the file along with the pages.
+    ///
+    /// Fails with [`ErrorKind::Unsupported`] if dirty pages aren't recorded.
+    pub fn take_dirty_pages(&self) -> Result<Vec<u64>> {
+        let dirty_pages = self.dirty_pages.as_ref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::Unsupported,
+                "dirty pages are not recorded; see OpfsBackendBuilder::dirty_pages",
+            )
+        })?;
+        Ok(dirty_pages.lock().take())
+    }
+
+    /// Metrics recorded since the backend was opened, or since [`reset_metrics`][Self::reset_metrics].
+    ///
+    /// Each read and write counts once, including the individual ranges of [`read_ranges`][Self::read_ranges] and
+    /// [`write_vectored`][Self::write_vectored]. Operations rejected before reaching the file, for example because
+    /// the backend is closed, are not recorded.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.lock().snapshot()
+    }
+
+    /// Discard the metrics recorded so far.
+    #[cfg(feature = "metrics")]
+    pub fn reset_metrics(&self) {
+        *self.metrics.lock() = Default::default();
+    }
+
+    /// Open the file at `path` to replace its content, failing if it is currently open.
+    async fn open_for_import(path: &str) -> Result<File> {
+        let options = OpenOptions {
+            truncate: true,
+            ..OpenOptions::default()
+        };
+        Self::open_unshared(path, &options).await
+    }
+
+    /// Fail if the file at `path` is currently open, like [`open_for_import`][Self::open_for_import], but without
+    /// changing it, nor creating it if it does not exist.
+    async fn check_not_open(path: &str) -> Result<()> {
+        let options = OpenOptions {
+            create: false,
+            ..OpenOptions::default()
+        };
+        match Self::open_unshared(path, &options).await {
+            Ok(file) => {
+                file.close();
+                Ok(())
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Open the file at `path` with `options`, failing if it is currently open.
+    async fn open_unshared(path: &str, options: &OpenOptions) -> Result<File> {
+        let file = <File as FileAbstraction>::open(path, options).await;
+        // the sync access handle is exclusive, so acquiring it is what fails if the file is open
+        #[cfg(opfs)]
+        let file = file.map_err(|err| match err.kind() {
+            ErrorKind::ResourceBusy => {
+                io::Error::new(ErrorKind::ResourceBusy, format!("{path} is currently open"))
+            }
+            _ => err,
+        });
+        let file = file?;
+        Ok(file)
+    }
+
+    /// Write `data` at `offset`, through the write buffer if enabled.
+    fn write_at(&self, file: &File, offset: u64, data: &[u8]) -> IoResult<()> {
+        if offset.checked_add(data.len() as u64).is_none() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            ));
+        }
+        if let Some(changes) = &self.changes {
+            changes.lock().record_write(offset, data.len() as u64);
+        }
+        if let Some(dirty_pages) = &self.dirty_pages {
+            dirty_pages.lock().record_write(offset, data.len() as u64);
+        }
+        if let Some(buffer) = &self.write_buffer {
+            let mut buffer = buffer.lock();
+            buffer.insert(offset, data);
+            if buffer.is_full() {
+                drop(buffer);
+                self.write_out(file)?;
+            }
+            return Ok(());
+        }
+        if let Some(cache) = &self.cache {
+            cache.lock().invalidate_write(offset, data.len());
+        }
+        file.write_all_at(data, offset)
+    }
+
+    /// Fill `out` with the data at `offset`, including buffered writes.
+    fn read_at(&self, file: &File, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        // no file extends this far, but seeking there would fail with a different error natively
+        if offset
+            .checked_add(out.len() as u64)
+            .is_none_or(|end| end > i64::MAX as u64)
+        {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(buffer) = &self.write_buffer {
+            let buffer = buffer.lock();
+            if !buffer.is_empty() {
+                // buffered writes may have extended the file, with zeros in any gap
+                let file_len = file.len()?;
+                let end = offset
+                    .checked_add(out.len() as u64)
+                    .filter(|&end| end <= file_len.max(buffer.end()))
+                    .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+                let from_file = end.min(file_len).saturating_sub(offset) as usize;
+                let (head, tail) = out.split_at_mut(from_file);
+                if !head.is_empty() {
+                    self.read_file(file, offset, head)?;
+                }
+                tail.fill(0);
+                buffer.overlay(offset, out);
+                return Ok(());
+            }
+        }
+        self.read_file(file, offset, out)
+    }
+
+    /// Read from the file itself, through the page cache if enabled, ignoring the write buffer.
+    fn read_file(&self, file: &File, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        if let Some(cache) = &self.cache {
+            return cache
+                .lock()
+                .read(|buf, offset| file.read_at(buf, offset), offset, out);
+        }
+        file.read_exact_at(out, offset)
+    }
+
+    /// Write all buffered writes to `file`, in offset order, without flushing it.
+    ///
+    /// If a write fails, it and all following writes remain buffered.
+    fn write_out(&self, file: &File) -> IoResult<()> {
+        let Some(buffer) = &self.write_buffer else {
+            return Ok(());
+        };
+        let mut buffer = buffer.lock();
+        while let Some((offset, data)) = buffer.pop_first() {
+            if let Some(cache) = &self.cache {
+                cache.lock().invalidate_write(offset, data.len());
+            }
+            if let Err(err) = file.write_all_at(&data, offset) {
+                buffer.insert(offset, &data);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lock the file exclusively, failing if the backend has been closed.
+    fn file(&self) -> IoResult<MappedRwLockWriteGuard<'_, File>> {
+        if let Some(deferred) = &self.deferred {
+            if slot_mut(&mut self.file.write())?.is_none() {
+                if let Some(acquired) = deferred.take()? {
+                    self.install(acquired)?;
+                }
+            }
+        }
+        let mut guard = self.file.write();
+        slot_mut(&mut guard)?;
+        RwLockWriteGuard::try_map(guard, |slot| slot_mut(slot).ok()?.as_mut()).map_err(|_| closed())
+    }
+
+    /// Lock the file for reading, shared with other readers, failing if the backend has been closed.
+    fn file_shared(&self) -> IoResult<MappedRwLockReadGuard<'_, File>> {
+        // opening a lazy backend's file needs exclusive access
+        if self
+            .deferred
+            .as_ref()
+            .is_some_and(|deferred| !deferred.is_done())
+        {
+            self.file()?;
+        }
+        let guard = self.file.read();
+        slot_ref(&guard)?;
+        RwLockReadGuard::try_map(guard, |slot| slot_ref(slot).ok()?.as_ref()).map_err(|_| closed())
+    }
+
+    /// Put the file of a lazy backend in place, once opened.
+    fn install(&self, acquired: lazy::Acquired) -> IoResult<()> {
+        #[cfg(opfs)]
+        let (file, web_lock) = acquired;
+        #[cfg(not(opfs))]
+        let file = acquired;
+        #[cfg(opfs)]
+        {
+            *self.web_lock.lock().get_mut()? = web_lock;
+        }
+        *slot_mut(&mut self.file.write())? = Some(file);
+        Ok(())
+    }
+
+    /// Whether the file of a [create-on-write][OpfsBackendBuilder::create_on_write] backend is yet to be created, so
+    /// that it reads as empty.
+    fn is_unborn(&self) -> bool {
+        self.create_on_write
+            && self
+                .deferred
+                .as_ref()
+                .is_some_and(|deferred| !deferred.is_done())
+    }
+
+    fn check_writable(&self) -> IoResult<()> {
+        if self.read_only {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "backend was opened read-only",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OpfsBackend {
+    fn drop(&mut self) {
+        // Errors can't be reported from here; callers who care close explicitly beforehand.
+        let _ = self.close();
     }
 }
 
 impl StorageBackend for OpfsBackend {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, ret, err)
+    )]
     fn len(&self) -> IoResult<u64> {
-        self.file.lock().len()
+        panics::guard("len", || {
+            if self.is_unborn() {
+                return Ok(0);
+            }
+            let len = self.file_shared()?.len()?;
+            match &self.write_buffer {
+                Some(buffer) => Ok(len.max(buffer.lock().end())),
+                None => Ok(len),
+            }
+        })
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err)
+    )]
     fn set_len(&self, len: u64) -> IoResult<()> {
-        self.file.lock().set_len(len)
+        panics::guard("set_len", || {
+            self.check_writable()?;
+            // each attempt repeats the bookkeeping, which is idempotent, so that the lock is released in between
+            self.retry.run_sync(|| {
+                let mut guard = self.file()?;
+                if self.changes.is_some() || self.dirty_pages.is_some() {
+                    let buffered_end = self
+                        .write_buffer
+                        .as_ref()
+                        .map_or(0, |buffer| buffer.lock().end());
+                    let old_len = guard.len()?.max(buffered_end);
+                    if let Some(changes) = &self.changes {
+                        changes.lock().record_set_len(old_len, len);
+                    }
+                    if let Some(dirty_pages) = &self.dirty_pages {
+                        dirty_pages.lock().record_set_len(old_len, len);
+                    }
+                }
+                if let Some(buffer) = &self.write_buffer {
+                    buffer.lock().truncate(len);
+                }
+                if let Some(cache) = &self.cache {
+                    cache.lock().invalidate_set_len(len);
+                }
+                measured!(self, SetLen, 0, guard.set_len(len))
+            })
+        })
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, err)
+    )]
     fn sync_data(&self) -> IoResult<()> {
-        self.file.lock().flush()
+        panics::guard("sync_data", || {
+            if self.read_only || self.is_unborn() {
+                // nothing can have changed
+                return Ok(());
+            }
+            self.retry.run_sync(|| {
+                let mut guard = self.file()?;
+                measured!(
+                    self,
+                    Sync,
+                    0,
+                    self.write_out(&guard).and_then(|()| self.flush.run(|| {
+                        debug_log!("flushing");
+                        guard.flush()
+                    }))
+                )?;
+                // before the lock is released, so that no write slips in between
+                if let Some(dirty_pages) = &self.dirty_pages {
+                    dirty_pages.lock().sync();
+                }
+                Ok(())
+            })?;
+            #[cfg(opfs)]
+            if let Some(notifier) = &self.notifier {
+                notifier.get()?.notify();
+            }
+            Ok(())
+        })
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, out), fields(len = out.len()), err)
+    )]
     fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
-        let mut guard = self.file.lock();
-        guard.seek(SeekFrom::Start(offset))?;
-        guard.read_exact(out)?;
-        Ok(())
+        panics::guard("read", || {
+            if self.is_unborn() {
+                return match out.is_empty() {
+                    true => Ok(()),
+                    false => Err(ErrorKind::UnexpectedEof.into()),
+                };
+            }
+            self.retry.run_sync(|| {
+                let guard = self.file_shared()?;
+                measured!(self, Read, out.len(), self.read_at(&guard, offset, out))
+            })
+        })
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, data), fields(len = data.len()), err)
+    )]
     fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
-        let mut guard = self.file.lock();
-        guard.seek(SeekFrom::Start(offset))?;
-        guard.write_all(data)?;
-        Ok(())
+        panics::guard("write", || {
+            self.check_writable()?;
+            self.retry.run_sync(|| {
+                let guard = self.file()?;
+                measured!(self, Write, data.len(), self.write_at(&guard, offset, data))
+            })
+        })
     }
 }
 
-#[cfg(target_family = "wasm")]
+#[cfg(opfs)]
 #[wasm_bindgen]
 #[expect(clippy::len_without_is_empty)]
 impl OpfsBackend {
+    /// Open the file a `FileSystemFileHandle` obtained by the host refers to, read+write.
+    ///
+    /// The crate's path resolution is skipped entirely. For other options, use
+    /// [`OpfsBackendBuilder::open_file_handle`].
+    #[wasm_bindgen(js_name = fromFileHandle)]
+    pub async fn from_file_handle(handle: web_sys::FileSystemFileHandle) -> Result<Self> {
+        Self::builder().open_file_handle(handle).await
+    }
+
     /// Returns the size of the file, in bytes
     //
     // Files have length but no trivial `is_empty` impl, so we skip that
@@ -115,6 +1300,25 @@ impl OpfsBackend {
         <Self as StorageBackend>::read(self, offset, out).map_err(Into::into)
     }
 
+    /// Reads `len` bytes from the file at the given offset, returning them in a new `Uint8Array`.
+    ///
+    /// Fails if the range extends beyond the end of the file.
+    #[wasm_bindgen(js_name = "readAt")]
+    pub fn read_at_js(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut out = vec![0; len];
+        <Self as StorageBackend>::read(self, offset, &mut out)?;
+        Ok(out)
+    }
+
+    /// Reads the whole file, returning it in a new `Uint8Array`.
+    #[wasm_bindgen(js_name = "readAll")]
+    pub fn read_all_js(&self) -> Result<Vec<u8>> {
+        let len = usize::try_from(<Self as StorageBackend>::len(self)?).map_err(|_| {
+            io::Error::new(ErrorKind::OutOfMemory, "file too large to read at once")
+        })?;
+        self.read_at_js(0, len)
+    }
+
     /// Truncates or extends the underlying file, updating the size of this file to become `size`.
     ///
     /// If `size` is less than the current file's size, then the file will be shrunk.
@@ -138,4 +1342,367 @@ impl OpfsBackend {
     pub fn write(&self, offset: u64, data: &[u8]) -> Result<()> {
         <Self as StorageBackend>::write(self, offset, data).map_err(Into::into)
     }
+
+    /// Performs several reads, in order, under a single acquisition of the internal lock.
+    ///
+    /// `ranges` is an array of `[offset, len]` pairs. Returns an array holding a `Uint8Array` for each range.
+    #[wasm_bindgen(js_name = "readRanges")]
+    pub fn read_ranges_js(&self, ranges: js_sys::Array) -> Result<js_sys::Array> {
+        let ranges = ranges
+            .iter()
+            .map(|pair| {
+                let pair = pair.dyn_into::<js_sys::Array>()?;
+                let number = |value: JsValue| {
+                    value.as_f64().ok_or_else(|| {
+                        io::Error::new(ErrorKind::InvalidInput, "ranges must hold numbers")
+                    })
+                };
+                Ok((number(pair.get(0))? as u64, number(pair.get(1))? as usize))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let buffers = self.read_ranges(&ranges)?;
+        Ok(buffers
+            .iter()
+            .map(|buf| JsValue::from(js_sys::Uint8Array::from(buf.as_slice())))
+            .collect())
+    }
+
+    /// Performs several writes, in order, under a single acquisition of the internal lock.
+    ///
+    /// `writes` is an array of `[offset, data]` pairs, where `data` is a `Uint8Array`.
+    #[wasm_bindgen(js_name = "writeVectored")]
+    pub fn write_vectored_js(&self, writes: js_sys::Array) -> Result<()> {
+        let writes = writes
+            .iter()
+            .map(|pair| {
+                let pair = pair.dyn_into::<js_sys::Array>()?;
+                let offset = pair.get(0).as_f64().ok_or_else(|| {
+                    io::Error::new(ErrorKind::InvalidInput, "offset must be a number")
+                })?;
+                let data = pair.get(1).dyn_into::<js_sys::Uint8Array>()?.to_vec();
+                Ok((offset as u64, data))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let writes = writes
+            .iter()
+            .map(|(offset, data)| (*offset, data.as_slice()))
+            .collect::<Vec<_>>();
+        self.write_vectored(&writes)
+    }
+
+    /// Exports the entire content of the file as a `Blob`, e.g. to offer it for download.
+    ///
+    /// The file is read in chunks which are copied out to JS one at a time, so exporting a large
+    /// database never requires a buffer of its full size in wasm memory. The internal lock is held
+    /// throughout, so the export is a consistent point-in-time copy.
+    pub fn export(&self) -> Result<web_sys::Blob> {
+        let chunks = js_sys::Array::new();
+        let mut sink = ChunkSink(|chunk: &[u8]| {
+            chunks.push(&js_sys::Uint8Array::from(chunk));
+            Ok(())
+        });
+        self.export_to(&mut sink, |_, _| {})?;
+
+        let blob = web_sys::Blob::new_with_u8_array_sequence(&chunks)?;
+        Ok(blob)
+    }
+
+    /// Exports the entire content of the file by calling `on_chunk` with each chunk, as a `Uint8Array`.
+    ///
+    /// Unlike `export`, no chunk is retained after `on_chunk` returns, so this can move databases
+    /// larger than the available memory, e.g. into a `FileSystemWritableFileStream`.
+    /// `on_progress`, if given, is called after each chunk with the number of bytes exported so far
+    /// and the total size; exceptions it throws are ignored. An exception thrown by `on_chunk` aborts
+    /// the export.
+    #[wasm_bindgen(js_name = "exportChunks")]
+    pub fn export_chunks(
+        &self,
+        on_chunk: &js_sys::Function,
+        on_progress: Option<js_sys::Function>,
+    ) -> Result<()> {
+        let mut sink = ChunkSink(|chunk: &[u8]| {
+            on_chunk
+                .call1(&JsValue::NULL, &js_sys::Uint8Array::from(chunk))
+                .map(|_| ())
+                .map_err(Error::to_io)
+        });
+        self.export_to(&mut sink, |done, total| {
+            report_progress(on_progress.as_ref(), done, Some(total))
+        })?;
+        Ok(())
+    }
+
+    /// Exports the entire content of the file as a `Blob`, like `export`, but encrypted with `key`, 32 bytes for
+    /// AES-256-GCM, so that backups leaving the browser aren't plaintext.
+    ///
+    /// Restore it with `importEncrypted`; the format is documented in the `encrypted` module.
+    #[cfg(feature = "encryption")]
+    #[wasm_bindgen(js_name = "exportEncrypted")]
+    pub fn export_encrypted(&self, key: &[u8]) -> Result<web_sys::Blob> {
+        self.export_backup(encrypted::KeySource::Key(&backup_key(key)?))
+    }
+
+    /// Like `exportEncrypted`, but with a key derived from `password`.
+    #[cfg(feature = "encryption")]
+    #[wasm_bindgen(js_name = "exportEncryptedWithPassword")]
+    pub fn export_encrypted_with_password(&self, password: &str) -> Result<web_sys::Blob> {
+        self.export_backup(encrypted::KeySource::Password(password.as_bytes()))
+    }
+
+    /// Replaces the content of the file at `path` with the decrypted content of `backup`, a `Uint8Array` exported
+    /// by `exportEncrypted` with `key`.
+    ///
+    /// Like `import`, this fails if the file is currently open. The backup is decrypted into a new file, which only
+    /// replaces the file at `path` once all of it was authenticated, so if the backup was tampered with, or the key is
+    /// wrong, this fails and leaves the file as it was.
+    #[cfg(feature = "encryption")]
+    #[wasm_bindgen(js_name = "importEncrypted")]
+    pub async fn import_encrypted(path: &str, backup: &[u8], key: &[u8]) -> Result<()> {
+        let mut reader = BackupReader::new(backup, &backup_key(key)?)?;
+        Self::import_staged(path, &mut reader, |_| {}).await?;
+        Ok(())
+    }
+
+    /// Like `importEncrypted`, for a backup exported by `exportEncryptedWithPassword` with `password`.
+    #[cfg(feature = "encryption")]
+    #[wasm_bindgen(js_name = "importEncryptedWithPassword")]
+    pub async fn import_encrypted_with_password(
+        path: &str,
+        backup: &[u8],
+        password: &str,
+    ) -> Result<()> {
+        let mut reader = BackupReader::with_password(backup, password)?;
+        Self::import_staged(path, &mut reader, |_| {}).await?;
+        Ok(())
+    }
+
+    /// Replaces the content of the file at `path` with `source`, a `Uint8Array` or a `Blob` such as a `File`.
+    ///
+    /// The content is copied in chunks, so importing a large database never requires a buffer of its
+    /// full size in wasm memory. This fails if the file is currently open, in this or any other worker.
+    #[wasm_bindgen(js_name = "import")]
+    pub async fn import_js(path: &str, source: JsValue) -> Result<()> {
+        let mut file = Self::open_for_import(path).await?;
+        let written = import_from_js(&mut file, &source).await;
+        file.close();
+        written
+    }
+
+    /// Replaces the content of the file at `path` with the bytes produced by `stream`, a `ReadableStream`
+    /// of `Uint8Array`s such as `Response.body` or `Blob.stream()`.
+    ///
+    /// Chunks are written as they arrive, so the full database is never buffered in memory.
+    /// `on_progress`, if given, is called after each chunk with the number of bytes imported so far;
+    /// exceptions it throws are ignored. This fails if the file is currently open, in this or any other worker.
+    #[wasm_bindgen(js_name = "importStream")]
+    pub async fn import_stream(
+        path: &str,
+        stream: web_sys::ReadableStream,
+        on_progress: Option<js_sys::Function>,
+    ) -> Result<()> {
+        let mut file = Self::open_for_import(path).await?;
+        let written = import_from_stream(&mut file, &stream, on_progress.as_ref()).await;
+        file.close();
+        written
+    }
+
+    /// Exports what changed since the incremental export which returned the token `since`, or all of the file if
+    /// `since` is omitted; requires `trackChanges`.
+    ///
+    /// Returns `{ token, len, full, ranges, data }`, where `data` is a `Blob` of the content of `ranges`, an array of
+    /// `[offset, len]` pairs. Pass `token` to the next export, and everything else to `applyIncremental`.
+    #[wasm_bindgen(js_name = "exportIncremental")]
+    pub fn export_incremental_js(&self, since: Option<String>) -> Result<js_sys::Object> {
+        let chunks = js_sys::Array::new();
+        let mut sink = ChunkSink(|chunk: &[u8]| {
+            chunks.push(&js_sys::Uint8Array::from(chunk));
+            Ok(())
+        });
+        let manifest = self.export_incremental(since.as_deref(), &mut sink)?;
+
+        let object = manifest.to_js();
+        let blob = web_sys::Blob::new_with_u8_array_sequence(&chunks)?;
+        js_sys::Reflect::set(&object, &"data".into(), &blob)?;
+        Ok(object)
+    }
+
+    /// Applies an export taken by `exportIncremental` to the file at `path`: `manifest` is the object it returned,
+    /// and `data` the content of its `data` as a `Uint8Array`.
+    ///
+    /// Exports must be applied in the order they were taken, starting from a full one. This fails if the file is
+    /// currently open.
+    #[wasm_bindgen(js_name = "applyIncremental")]
+    pub async fn apply_incremental_js(path: &str, manifest: JsValue, data: &[u8]) -> Result<()> {
+        let manifest = IncrementalManifest::from_js(&manifest)?;
+        let mut data = data;
+        Self::apply_incremental(path, &manifest, &mut data).await
+    }
+
+    /// Takes the indices of the pages written and synced since the last call, as an array of numbers in order; see
+    /// `dirtyPages`.
+    #[wasm_bindgen(js_name = "takeDirtyPages")]
+    pub fn take_dirty_pages_js(&self) -> Result<js_sys::Array> {
+        let pages = self.take_dirty_pages()?;
+        Ok(pages
+            .into_iter()
+            .map(|page| JsValue::from(page as f64))
+            .collect())
+    }
+
+    /// Returns the metrics recorded so far, as a plain object.
+    ///
+    /// The object has `reads`, `writes`, `setLens` and `syncs` fields, each of the form
+    /// `{ count, errors, bytes, latency: { p50, p90, p99, max } }`, with latencies in milliseconds.
+    #[cfg(feature = "metrics")]
+    #[wasm_bindgen(js_name = "metrics")]
+    pub fn metrics_js(&self) -> js_sys::Object {
+        self.metrics().to_js()
+    }
+
+    /// Discards the metrics recorded so far.
+    #[cfg(feature = "metrics")]
+    #[wasm_bindgen(js_name = "resetMetrics")]
+    pub fn reset_metrics_js(&self) {
+        self.reset_metrics();
+    }
+
+    /// Lists the entries of the directory at the given path, as an array of `DirEntry`.
+    ///
+    /// An empty path lists the OPFS root directory. This never creates the directory.
+    #[wasm_bindgen(js_name = "readDir")]
+    pub async fn read_dir_js(path: &str) -> Result<js_sys::Array> {
+        let entries = Self::read_dir(path).await?;
+        Ok(entries.into_iter().map(JsValue::from).collect())
+    }
+}
+
+/// Write the content of `source`, a `Uint8Array` or a `Blob`, to `file` in chunks.
+#[cfg(opfs)]
+async fn import_from_js(file: &mut File, source: &JsValue) -> Result<()> {
+    const CHUNK_SIZE: u32 = 1 << 20;
+
+    if let Some(bytes) = source.dyn_ref::<js_sys::Uint8Array>() {
+        let len = bytes.length();
+        let mut buf = vec![0; CHUNK_SIZE as usize];
+        let mut start = 0;
+        while start < len {
+            let end = len.min(start + CHUNK_SIZE);
+            let chunk = &mut buf[..(end - start) as usize];
+            bytes.subarray(start, end).copy_to(chunk);
+            file.write_all(chunk)?;
+            start = end;
+        }
+    } else if let Some(blob) = source.dyn_ref::<web_sys::Blob>() {
+        let len = blob.size();
+        let mut start = 0.0;
+        while start < len {
+            let end = len.min(start + f64::from(CHUNK_SIZE));
+            let chunk = blob.slice_with_f64_and_f64(start, end)?.array_buffer();
+            let chunk = wasm_bindgen_futures::JsFuture::from(chunk).await?;
+            file.write_all(&js_sys::Uint8Array::new(&chunk).to_vec())?;
+            start = end;
+        }
+    } else {
+        return Err(
+            io::Error::new(ErrorKind::InvalidInput, "expected a Uint8Array or a Blob").into(),
+        );
+    }
+
+    file.flush()?;
+    Ok(())
+}
+
+/// Write the chunks produced by `stream`, a `ReadableStream` of `Uint8Array`s, to `file`.
+#[cfg(opfs)]
+async fn import_from_stream(
+    file: &mut File,
+    stream: &web_sys::ReadableStream,
+    on_progress: Option<&js_sys::Function>,
+) -> Result<()> {
+    // without options, `getReader` always returns a default reader
+    let reader = stream
+        .get_reader()
+        .unchecked_into::<web_sys::ReadableStreamDefaultReader>();
+
+    let copied: Result<()> = async {
+        let mut written = 0;
+        loop {
+            let result = wasm_bindgen_futures::JsFuture::from(reader.read()).await?;
+            if js_sys::Reflect::get(&result, &"done".into())?.is_truthy() {
+                break;
+            }
+            let chunk =
+                js_sys::Reflect::get(&result, &"value".into())?.dyn_into::<js_sys::Uint8Array>()?;
+            file.write_all(&chunk.to_vec())?;
+            written += u64::from(chunk.length());
+            report_progress(on_progress, written, None);
+        }
+        file.flush()?;
+        Ok(())
+    }
+    .await;
+
+    reader.release_lock();
+    copied
+}
+
+/// Call the JS progress callback `on_progress`, if any, with `done` and `total` bytes.
+///
+/// Progress reporting is best-effort, so exceptions thrown by the callback are ignored.
+#[cfg(opfs)]
+fn report_progress(on_progress: Option<&js_sys::Function>, done: u64, total: Option<u64>) {
+    let Some(on_progress) = on_progress else {
+        return;
+    };
+    let done = JsValue::from_f64(done as _);
+    let _ = match total {
+        Some(total) => on_progress.call2(&JsValue::NULL, &done, &JsValue::from_f64(total as _)),
+        None => on_progress.call1(&JsValue::NULL, &done),
+    };
+}
+
+#[cfg(all(opfs, feature = "encryption"))]
+impl OpfsBackend {
+    /// Export the entire content of the file as a `Blob`, encrypted into a backup with the key from `source`.
+    fn export_backup(&self, source: encrypted::KeySource<'_>) -> Result<web_sys::Blob> {
+        let chunks = js_sys::Array::new();
+        let sink = ChunkSink(|chunk: &[u8]| {
+            chunks.push(&js_sys::Uint8Array::from(chunk));
+            Ok(())
+        });
+        let mut writer = BackupWriter::with_source(sink, source)?;
+        self.export_to(&mut writer, |_, _| {})?;
+        writer.finish()?;
+
+        let blob = web_sys::Blob::new_with_u8_array_sequence(&chunks)?;
+        Ok(blob)
+    }
+}
+
+/// Check that `key`, passed from JS, is an AES-256 key.
+#[cfg(all(opfs, feature = "encryption"))]
+fn backup_key(key: &[u8]) -> Result<encrypted::Key> {
+    let key = encrypted::Key::try_from(key).map_err(|_| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("key must be {} bytes", encrypted::KEY_LEN),
+        )
+    })?;
+    Ok(key)
+}
+
+/// Adapts a closure receiving each chunk into a [`Write`] sink.
+#[cfg(opfs)]
+struct ChunkSink<F: FnMut(&[u8]) -> IoResult<()>>(F);
+
+#[cfg(opfs)]
+impl<F: FnMut(&[u8]) -> IoResult<()>> Write for ChunkSink<F> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        (self.0)(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
 }
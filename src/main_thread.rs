@@ -0,0 +1,207 @@
+//! An asynchronous backend for the main thread and service workers, where sync access handles are unavailable.
+//!
+//! Reads take a fresh snapshot of the file with `getFile()`. Writes and truncation go to a writable stream from
+//! `createWritable()`, which the browser stages in a swap file and applies atomically when the stream is closed.
+//! Syncing closes the stream; so does every read, since staged writes are invisible until then. Each of these
+//! steps is a round trip through the event loop, and each commit copies the file, so this is far slower than
+//! [`OpfsBackend`][crate::OpfsBackend]. Prefer running the database in a worker wherever possible.
+
+use std::{
+    cell::RefCell,
+    io::{self, ErrorKind},
+};
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::FileSystemFileHandle;
+
+use crate::{
+    Result,
+    file::{file_handle, path_of, virtualize_path},
+    file_abstraction::OpenOptions,
+    web_lock::WebLock,
+};
+
+const MAX_SAFE_INTEGER: u64 = js_sys::Number::MAX_SAFE_INTEGER as _;
+
+/// An asynchronous OPFS backend which works on the main thread, and in service workers.
+///
+/// `redb` requires a synchronous backend, so this can't back a `redb::Database` directly. It serves hosts which
+/// can't use a worker at all, and drive storage themselves. Operations are queued, and run one at a time in the
+/// order they were called. Writes become durable, and visible to other contexts, on [`sync_data`][Self::sync_data];
+/// writes not yet synced when the backend is dropped are discarded.
+///
+/// Like [`OpfsBackend`][crate::OpfsBackend], it holds the Web Lock named after the file while open, so it excludes
+/// backends opening the same file in workers.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct MainThreadBackend {
+    handle: FileSystemFileHandle,
+    /// The writable stream staging writes since the last sync, if any.
+    writable: RefCell<Option<JsValue>>,
+    /// Settles once every operation queued so far has completed.
+    queue: RefCell<Promise>,
+    _web_lock: WebLock,
+}
+
+#[wasm_bindgen]
+impl MainThreadBackend {
+    /// Open the file at `path` read+write, creating it if it does not exist.
+    pub async fn open(path: &str) -> Result<MainThreadBackend> {
+        let path = virtualize_path(path)?;
+        let name = format!("redb-opfs:{}", path.display());
+        let web_lock = WebLock::acquire(&name, false, None).await?;
+        let handle = file_handle(path, &OpenOptions::default()).await?;
+        Ok(Self::new(handle, web_lock))
+    }
+
+    /// Open the file a `FileSystemFileHandle` obtained by the host refers to, read+write.
+    #[wasm_bindgen(js_name = fromFileHandle)]
+    pub async fn from_file_handle(handle: FileSystemFileHandle) -> Result<MainThreadBackend> {
+        let name = match path_of(&handle).await? {
+            Some(path) => format!("redb-opfs:{}", path.display()),
+            None => format!("redb-opfs:[handle]/{}", handle.name()),
+        };
+        let web_lock = WebLock::acquire(&name, false, None).await?;
+        Ok(Self::new(handle, web_lock))
+    }
+
+    /// Returns the size of the file, in bytes, including unsynced writes.
+    pub async fn len(&self) -> Result<u64> {
+        let _turn = self.enqueue().await?;
+        Ok(self.snapshot().await?.size() as _)
+    }
+
+    /// Reads `len` bytes from the file at the given offset, including unsynced writes.
+    ///
+    /// Fails with [`ErrorKind::UnexpectedEof`] if the file ends first.
+    pub async fn read(&self, offset: u64, len: u32) -> Result<Vec<u8>> {
+        let _turn = self.enqueue().await?;
+        let end = offset.saturating_add(len.into());
+        let file = self.snapshot().await?;
+        if end > file.size() as u64 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("read of {len} bytes at {offset} past the end of the file"),
+            )
+            .into());
+        }
+        let slice = file.slice_with_f64_and_f64(offset as _, end as _)?;
+        let buffer = JsFuture::from(slice.array_buffer()).await?;
+        Ok(Uint8Array::new(&buffer).to_vec())
+    }
+
+    /// Writes some bytes to the file at the given offset, extending it with zeros if necessary.
+    pub async fn write(&self, offset: u64, data: &[u8]) -> Result<()> {
+        check_size(offset.saturating_add(data.len() as _))?;
+        let _turn = self.enqueue().await?;
+        let params = Object::new();
+        Reflect::set(&params, &"type".into(), &"write".into())?;
+        Reflect::set(&params, &"position".into(), &(offset as f64).into())?;
+        Reflect::set(&params, &"data".into(), &Uint8Array::from(data))?;
+        let writable = self.writable().await?;
+        call(&writable, "write", &[params.into()]).await?;
+        Ok(())
+    }
+
+    /// Truncates or extends the file to `len` bytes, filling any extension with zeros.
+    #[wasm_bindgen(js_name = setLen)]
+    pub async fn set_len(&self, len: u64) -> Result<()> {
+        check_size(len)?;
+        let _turn = self.enqueue().await?;
+        let writable = self.writable().await?;
+        call(&writable, "truncate", &[(len as f64).into()]).await?;
+        Ok(())
+    }
+
+    /// Applies all writes since the last sync to the file.
+    #[wasm_bindgen(js_name = syncData)]
+    pub async fn sync_data(&self) -> Result<()> {
+        let _turn = self.enqueue().await?;
+        self.commit().await
+    }
+}
+
+impl MainThreadBackend {
+    fn new(handle: FileSystemFileHandle, web_lock: WebLock) -> Self {
+        Self {
+            handle,
+            writable: RefCell::new(None),
+            queue: RefCell::new(Promise::resolve(&JsValue::UNDEFINED)),
+            _web_lock: web_lock,
+        }
+    }
+
+    /// Wait for all previously queued operations to complete.
+    ///
+    /// The next operation starts once the returned [`Turn`] is dropped.
+    async fn enqueue(&self) -> Result<Turn> {
+        let mut release = None;
+        let done = Promise::new(&mut |resolve, _reject| release = Some(resolve));
+        let turn = Turn(release.expect("promise executor runs synchronously"));
+        // Waiting on `previous` too keeps the queue ordered even if this operation is cancelled while waiting.
+        let previous = self.queue.borrow().clone();
+        *self.queue.borrow_mut() = Promise::all(&Array::of2(&previous, &done));
+        // Turns never reject.
+        JsFuture::from(previous).await?;
+        Ok(turn)
+    }
+
+    /// The writable stream staging writes, opened if necessary.
+    async fn writable(&self) -> Result<JsValue> {
+        if let Some(writable) = self.writable.borrow().as_ref() {
+            return Ok(writable.clone());
+        }
+        let options = Object::new();
+        Reflect::set(&options, &"keepExistingData".into(), &true.into())?;
+        let writable = call(&self.handle, "createWritable", &[options.into()]).await?;
+        *self.writable.borrow_mut() = Some(writable.clone());
+        Ok(writable)
+    }
+
+    /// Close the writable stream, if any, applying its staged writes.
+    async fn commit(&self) -> Result<()> {
+        let writable = self.writable.borrow_mut().take();
+        if let Some(writable) = writable {
+            call(&writable, "close", &[]).await?;
+        }
+        Ok(())
+    }
+
+    /// Apply staged writes, then take a snapshot of the file.
+    async fn snapshot(&self) -> Result<web_sys::File> {
+        self.commit().await?;
+        let file = JsFuture::from(self.handle.get_file()).await?;
+        Ok(file.unchecked_into())
+    }
+}
+
+/// A place at the front of the operation queue.
+struct Turn(Function);
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let _ = self.0.call0(&JsValue::UNDEFINED);
+    }
+}
+
+/// Call the method `name` of `target`, and await the promise it returns.
+async fn call(target: &JsValue, name: &str, args: &[JsValue]) -> Result<JsValue> {
+    let method = Reflect::get(target, &name.into())?.dyn_into::<Function>()?;
+    let args = args.iter().collect::<Array>();
+    let promise = method.apply(target, &args)?.dyn_into::<Promise>()?;
+    Ok(JsFuture::from(promise).await?)
+}
+
+/// OPFS sizes and offsets are JS numbers, which are only exact up to [`MAX_SAFE_INTEGER`].
+fn check_size(size: u64) -> Result<()> {
+    if size > MAX_SAFE_INTEGER {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("requested size {size} too large, max allowed is {MAX_SAFE_INTEGER}"),
+        )
+        .into());
+    }
+    Ok(())
+}
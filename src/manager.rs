@@ -0,0 +1,119 @@
+//! Several named databases, opened and closed through one owner.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use redb::Database;
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+use crate::{OpfsBackendBuilder, Result, open_database_with};
+
+/// Owner of any number of databases, each opened under a name of the caller's choosing.
+///
+/// Multi-account apps juggle several databases per worker; this keeps them in one place instead of one static per
+/// database. Each database is opened with the same [`OpfsBackendBuilder`], as by
+/// [`open_database_with`][crate::open_database_with]:
+///
+/// ```ignore
+/// let manager = DatabaseManager::new();
+/// manager.open("alice", "accounts/alice.redb").await?;
+/// manager.open("bob", "accounts/bob.redb").await?;
+/// let tx = manager.get("alice").expect("opened above").begin_write()?;
+/// ```
+///
+/// From JS, the manager controls which databases are open, for Rust code in the same module to use.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Default)]
+pub struct DatabaseManager {
+    builder: OpfsBackendBuilder,
+    databases: Mutex<BTreeMap<String, Arc<Database>>>,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl DatabaseManager {
+    /// Create a manager which opens databases with the default options.
+    #[cfg_attr(opfs, wasm_bindgen(constructor))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a manager which opens databases with `builder`.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = withBuilder))]
+    pub fn with_builder(builder: OpfsBackendBuilder) -> Self {
+        Self {
+            builder,
+            databases: Default::default(),
+        }
+    }
+
+    /// Open the database at `path`, creating it if it does not exist, under `name`.
+    ///
+    /// Fails with [`ErrorKind::AlreadyExists`] if a database is already open under `name`.
+    pub async fn open(&self, name: &str, path: &str) -> Result<()> {
+        if self.is_open(name) {
+            return Err(already_open(name).into());
+        }
+        let database = open_database_with(self.builder.clone(), path).await?;
+        // another database may have been opened under `name` while this one was opening; it is kept
+        let mut databases = self.databases.lock();
+        if databases.contains_key(name) {
+            return Err(already_open(name).into());
+        }
+        databases.insert(name.to_owned(), Arc::new(database));
+        Ok(())
+    }
+
+    /// Close the database open under `name`, returning whether there was one.
+    ///
+    /// The database is closed once all references obtained from [`get`][Self::get] are dropped as well.
+    pub fn close(&self, name: &str) -> bool {
+        // dropped outside the lock, as closing flushes the file
+        let database = self.databases.lock().remove(name);
+        database.is_some()
+    }
+
+    /// Close all databases; see [`close`][Self::close].
+    #[cfg_attr(opfs, wasm_bindgen(js_name = closeAll))]
+    pub fn close_all(&self) {
+        let databases = std::mem::take(&mut *self.databases.lock());
+        drop(databases);
+    }
+
+    /// Whether a database is open under `name`.
+    #[cfg_attr(opfs, wasm_bindgen(js_name = isOpen))]
+    pub fn is_open(&self, name: &str) -> bool {
+        self.databases.lock().contains_key(name)
+    }
+
+    /// The names of the open databases, in lexicographic order.
+    pub fn names(&self) -> Vec<String> {
+        self.databases.lock().keys().cloned().collect()
+    }
+}
+
+impl DatabaseManager {
+    /// Get the database open under `name`, if any.
+    pub fn get(&self, name: &str) -> Option<Arc<Database>> {
+        self.databases.lock().get(name).cloned()
+    }
+
+    /// Add an already opened `database` under `name`, replacing and returning the database previously open under it.
+    pub fn insert(&self, name: &str, database: Database) -> Option<Arc<Database>> {
+        self.databases
+            .lock()
+            .insert(name.to_owned(), Arc::new(database))
+    }
+}
+
+fn already_open(name: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::AlreadyExists,
+        format!("a database is already open under {name:?}"),
+    )
+}
@@ -0,0 +1,127 @@
+//! A [`StorageBackend`] which holds the entire database in memory.
+//!
+//! Nothing is ever persisted: when the backend is dropped, the data is gone. This is useful for
+//! unit tests and for ephemeral ("incognito") sessions which must not leave anything behind in OPFS.
+
+use std::io::{self, ErrorKind};
+
+use parking_lot::Mutex;
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+#[cfg(opfs)]
+use crate::Result;
+use crate::{IoResult, StorageBackend};
+
+/// Implementation of a [`StorageBackend`] which keeps all data in memory.
+///
+/// This has the same semantics as [`OpfsBackend`][crate::OpfsBackend], and can be used in any context,
+/// including the main thread.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    data: Mutex<Vec<u8>>,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl MemoryBackend {
+    /// Create a new, empty in-memory backend.
+    #[cfg_attr(opfs, wasm_bindgen(constructor))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn len(&self) -> IoResult<u64> {
+        Ok(self.data.lock().len() as _)
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        let len = usize::try_from(len)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "requested size too large"))?;
+        self.data.lock().resize(len, 0);
+        Ok(())
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let guard = self.data.lock();
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|start| guard.get(start..start.checked_add(out.len())?))
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+        out.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let too_large = || {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond addressable memory",
+            )
+        };
+        let start = usize::try_from(offset).map_err(|_| too_large())?;
+        let end = start.checked_add(data.len()).ok_or_else(too_large)?;
+
+        let mut guard = self.data.lock();
+        if guard.len() < end {
+            guard.resize(end, 0);
+        }
+        guard[start..end].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[cfg(opfs)]
+#[wasm_bindgen]
+#[expect(clippy::len_without_is_empty)]
+impl MemoryBackend {
+    /// Returns the size of the data, in bytes
+    pub fn len(&self) -> Result<u64> {
+        <Self as StorageBackend>::len(self).map_err(Into::into)
+    }
+
+    /// Reads some bytes at the given offset.
+    pub fn read(&self, offset: u64, out: &mut [u8]) -> Result<()> {
+        <Self as StorageBackend>::read(self, offset, out).map_err(Into::into)
+    }
+
+    /// Reads `len` bytes at the given offset, returning them in a new `Uint8Array`.
+    #[wasm_bindgen(js_name = "readAt")]
+    pub fn read_at(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut out = vec![0; len];
+        <Self as StorageBackend>::read(self, offset, &mut out)?;
+        Ok(out)
+    }
+
+    /// Returns a copy of all data, in a new `Uint8Array`.
+    #[wasm_bindgen(js_name = "readAll")]
+    pub fn read_all(&self) -> Vec<u8> {
+        self.data.lock().clone()
+    }
+
+    /// Truncates or extends the data to become `len` bytes long.
+    ///
+    /// If extended, all intermediate data is filled with 0s.
+    #[wasm_bindgen(js_name = "setLen")]
+    pub fn set_len(&self, len: u64) -> Result<()> {
+        <Self as StorageBackend>::set_len(self, len).map_err(Into::into)
+    }
+
+    /// No-op: there is nothing to synchronize.
+    #[wasm_bindgen(js_name = "syncData")]
+    pub fn sync_data(&self) -> Result<()> {
+        <Self as StorageBackend>::sync_data(self).map_err(Into::into)
+    }
+
+    /// Writes some bytes at the given offset.
+    pub fn write(&self, offset: u64, data: &[u8]) -> Result<()> {
+        <Self as StorageBackend>::write(self, offset, data).map_err(Into::into)
+    }
+}
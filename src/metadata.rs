@@ -0,0 +1,36 @@
+//! File metadata, as reported by [`OpfsBackend::metadata`][crate::OpfsBackend::metadata].
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+/// Metadata of a file.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub(crate) size: u64,
+    pub(crate) last_modified: u64,
+    pub(crate) created: Option<u64>,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl Metadata {
+    /// Size of the file in bytes.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Time of the last modification of the file, in milliseconds since the Unix epoch.
+    #[cfg_attr(opfs, wasm_bindgen(getter = lastModified))]
+    pub fn last_modified(&self) -> u64 {
+        self.last_modified
+    }
+
+    /// Time the file was created, in milliseconds since the Unix epoch.
+    ///
+    /// `None` where the file system does not record it, as OPFS doesn't.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn created(&self) -> Option<u64> {
+        self.created
+    }
+}
@@ -0,0 +1,165 @@
+//! Operation counts, bytes transferred, and latency histograms, recorded per backend.
+//!
+//! Enabled by the `metrics` feature. See [`OpfsBackend::metrics`][crate::OpfsBackend::metrics].
+
+use std::time::Duration;
+
+/// Number of latency buckets. Bucket `i` holds latencies below 2<sup>`i`</sup> microseconds.
+const BUCKETS: usize = 32;
+
+/// Kinds of operation which are recorded.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Op {
+    Read,
+    Write,
+    SetLen,
+    Sync,
+}
+
+/// Latency distribution of an operation, as percentiles.
+///
+/// Latencies are recorded in power-of-two buckets, so each percentile is an upper bound which is at most twice the
+/// true value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Metrics for one kind of operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpMetrics {
+    /// Number of operations, including failed ones.
+    pub count: u64,
+    /// Number of operations which failed.
+    pub errors: u64,
+    /// Bytes transferred by successful operations.
+    pub bytes: u64,
+    pub latency: LatencySummary,
+}
+
+/// Metrics recorded by a backend since it was opened, or since they were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub reads: OpMetrics,
+    pub writes: OpMetrics,
+    pub set_lens: OpMetrics,
+    pub syncs: OpMetrics,
+}
+
+/// Accumulates the metrics of one kind of operation.
+#[derive(Debug, Clone, Default)]
+struct OpRecorder {
+    count: u64,
+    errors: u64,
+    bytes: u64,
+    max: Duration,
+    histogram: [u64; BUCKETS],
+}
+
+impl OpRecorder {
+    fn record(&mut self, bytes: usize, elapsed: Duration, ok: bool) {
+        self.count += 1;
+        if ok {
+            self.bytes += bytes as u64;
+        } else {
+            self.errors += 1;
+        }
+        self.max = self.max.max(elapsed);
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.histogram[bucket.min(BUCKETS - 1)] += 1;
+    }
+
+    /// Upper bound of the latency below which `fraction` of operations completed.
+    fn percentile(&self, fraction: f64) -> Duration {
+        let target = (self.count as f64 * fraction).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, &n) in self.histogram.iter().enumerate() {
+            seen += n;
+            if n > 0 && seen >= target {
+                return Duration::from_micros(1 << bucket).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn summary(&self) -> OpMetrics {
+        OpMetrics {
+            count: self.count,
+            errors: self.errors,
+            bytes: self.bytes,
+            latency: LatencySummary {
+                p50: self.percentile(0.5),
+                p90: self.percentile(0.9),
+                p99: self.percentile(0.99),
+                max: self.max,
+            },
+        }
+    }
+}
+
+/// Accumulates the metrics of a backend.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    reads: OpRecorder,
+    writes: OpRecorder,
+    set_lens: OpRecorder,
+    syncs: OpRecorder,
+}
+
+impl Recorder {
+    pub(crate) fn record(&mut self, op: Op, bytes: usize, elapsed: Duration, ok: bool) {
+        let recorder = match op {
+            Op::Read => &mut self.reads,
+            Op::Write => &mut self.writes,
+            Op::SetLen => &mut self.set_lens,
+            Op::Sync => &mut self.syncs,
+        };
+        recorder.record(bytes, elapsed, ok);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            reads: self.reads.summary(),
+            writes: self.writes.summary(),
+            set_lens: self.set_lens.summary(),
+            syncs: self.syncs.summary(),
+        }
+    }
+}
+
+#[cfg(opfs)]
+impl Metrics {
+    /// Convert to a plain JS object, with latencies in (fractional) milliseconds.
+    pub(crate) fn to_js(&self) -> js_sys::Object {
+        use js_sys::{Object, Reflect};
+
+        fn set(object: &Object, key: &str, value: f64) {
+            let _ = Reflect::set(object, &key.into(), &value.into());
+        }
+
+        let object = Object::new();
+        for (name, op) in [
+            ("reads", &self.reads),
+            ("writes", &self.writes),
+            ("setLens", &self.set_lens),
+            ("syncs", &self.syncs),
+        ] {
+            let op_object = Object::new();
+            set(&op_object, "count", op.count as _);
+            set(&op_object, "errors", op.errors as _);
+            set(&op_object, "bytes", op.bytes as _);
+            let latency = Object::new();
+            set(&latency, "p50", op.latency.p50.as_secs_f64() * 1000.0);
+            set(&latency, "p90", op.latency.p90.as_secs_f64() * 1000.0);
+            set(&latency, "p99", op.latency.p99.as_secs_f64() * 1000.0);
+            set(&latency, "max", op.latency.max.as_secs_f64() * 1000.0);
+            let _ = Reflect::set(&op_object, &"latency".into(), &latency);
+            let _ = Reflect::set(&object, &name.into(), &op_object);
+        }
+        object
+    }
+}
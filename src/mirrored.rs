@@ -0,0 +1,87 @@
+//! A [`StorageBackend`] wrapper which replicates every change to a second backend.
+
+use std::io;
+
+use crate::{IoResult, StorageBackend};
+
+/// Size of the chunks in which the primary is copied to the secondary when mirroring starts.
+const COPY_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Implementation of a [`StorageBackend`] which applies every write, truncation and sync to two backends.
+///
+/// Reads are served by the primary alone. Every change is applied to the primary first, then to the secondary, and
+/// fails if either fails, so the secondary holds a live copy of the primary: for example an in-memory or second-file
+/// shadow copy, to validate a migration or to capture the state of a database which is about to crash.
+#[derive(Debug)]
+pub struct MirroredBackend<A, B> {
+    primary: A,
+    secondary: B,
+}
+
+impl<A: StorageBackend, B: StorageBackend> MirroredBackend<A, B> {
+    /// Mirror `primary` to `secondary`, first replacing the content of `secondary` with a copy of `primary`.
+    pub fn new(primary: A, secondary: B) -> IoResult<Self> {
+        let len = primary.len()?;
+        secondary.set_len(len)?;
+        let mut buf = vec![0; len.min(COPY_CHUNK_SIZE) as usize];
+        let mut offset = 0;
+        while offset < len {
+            let chunk = &mut buf[..(len - offset).min(COPY_CHUNK_SIZE) as usize];
+            primary.read(offset, chunk)?;
+            secondary.write(offset, chunk)?;
+            offset += chunk.len() as u64;
+        }
+        secondary.sync_data()?;
+        Ok(Self::new_unchecked(primary, secondary))
+    }
+
+    /// Mirror `primary` to `secondary`, which must already hold the same content.
+    ///
+    /// Nothing is copied or checked; if the contents differ, so will the secondary from the primary.
+    pub fn new_unchecked(primary: A, secondary: B) -> Self {
+        Self { primary, secondary }
+    }
+
+    /// Get a reference to the primary backend, which serves reads.
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    /// Get a reference to the secondary backend, which receives a copy of every change.
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+
+    /// Unwrap this backend, returning the primary and secondary backends.
+    pub fn into_inner(self) -> (A, B) {
+        (self.primary, self.secondary)
+    }
+
+    /// Apply `op` to the primary, then to the secondary.
+    fn both(&self, op: impl Fn(&dyn StorageBackend) -> io::Result<()>) -> IoResult<()> {
+        op(&self.primary)?;
+        op(&self.secondary)
+    }
+}
+
+impl<A: StorageBackend, B: StorageBackend> StorageBackend for MirroredBackend<A, B> {
+    fn len(&self) -> IoResult<u64> {
+        self.primary.len()
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.both(|backend| backend.set_len(len))
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        self.both(|backend| backend.sync_data())
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        self.primary.read(offset, out)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        self.both(|backend| backend.write(offset, data))
+    }
+}
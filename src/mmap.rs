@@ -0,0 +1,137 @@
+//! A native [`StorageBackend`] which serves reads from a memory map of the file.
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+};
+
+use memmap2::MmapMut;
+use parking_lot::RwLock;
+
+use crate::{IoResult, StorageBackend};
+
+/// Implementation of a [`StorageBackend`] which maps the file into memory.
+///
+/// Reads and writes copy from and into the mapping, so they cost no system call, unlike [`OpfsBackend`]'s, which
+/// suits read-heavy workloads. Reads run concurrently; writes, and resizing, which remaps the file, exclude them.
+/// [`sync_data`][StorageBackend::sync_data] flushes the mapping to disk.
+///
+/// The file must not be truncated or modified by anything else while it is mapped: on most platforms, reading
+/// beyond its end then kills the process. Nothing stops other processes from doing so; see
+/// [`OpfsBackendBuilder::lock`][crate::OpfsBackendBuilder::lock] for how [`OpfsBackend`] guards against that.
+///
+/// [`OpfsBackend`]: crate::OpfsBackend
+#[derive(Debug)]
+pub struct MmapBackend {
+    file: File,
+    /// `None` while the file is empty, as empty files can't be mapped.
+    map: RwLock<Option<MmapMut>>,
+}
+
+impl MmapBackend {
+    /// Open the file at the specified path read+write, creating it if it does not exist, as
+    /// [`OpfsBackend::new`][crate::OpfsBackend::new] does.
+    pub async fn new(path: &str) -> IoResult<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Self::from_file(file)
+    }
+
+    /// Map `file`, which must be open for reading and writing.
+    pub fn from_file(file: File) -> IoResult<Self> {
+        let map = map(&file)?;
+        Ok(Self {
+            file,
+            map: RwLock::new(map),
+        })
+    }
+
+    /// Unmap the file, returning it.
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+/// Map the whole of `file`, unless it is empty.
+fn map(file: &File) -> IoResult<Option<MmapMut>> {
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // Safety: the file is only modified through the mapping, and resized while it is unmapped; other processes
+    // modifying it are ruled out by the documented contract of `MmapBackend`
+    let map = unsafe { MmapMut::map_mut(file)? };
+    Ok(Some(map))
+}
+
+fn map_len(map: &Option<MmapMut>) -> u64 {
+    map.as_ref().map_or(0, |map| map.len() as u64)
+}
+
+impl MmapBackend {
+    /// Resize the file to `len`, remapping it.
+    fn resize(&self, map: &mut Option<MmapMut>, len: u64) -> IoResult<()> {
+        // the old mapping must not outlive a truncation; its changes are already in the file, as it is shared
+        drop(map.take());
+        let resized = self.file.set_len(len);
+        // remap even if resizing failed, so that the backend remains usable
+        *map = self::map(&self.file)?;
+        resized
+    }
+}
+
+impl StorageBackend for MmapBackend {
+    fn len(&self) -> IoResult<u64> {
+        Ok(map_len(&self.map.read()))
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        let mut map = self.map.write();
+        if map_len(&map) == len {
+            return Ok(());
+        }
+        self.resize(&mut map, len)
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        match &*self.map.read() {
+            Some(map) => map.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let map = self.map.read();
+        let data = map.as_deref().unwrap_or_default();
+        let range = usize::try_from(offset)
+            .ok()
+            .and_then(|start| Some(start..start.checked_add(out.len())?))
+            .filter(|range| range.end <= data.len())
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+        out.copy_from_slice(&data[range]);
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let end = offset
+            .checked_add(data.len() as u64)
+            .filter(|&end| usize::try_from(end).is_ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "write extends beyond max file size",
+                )
+            })?;
+        let mut map = self.map.write();
+        if end > map_len(&map) {
+            self.resize(&mut map, end)?;
+        }
+        if let Some(map) = map.as_mut() {
+            map[offset as usize..end as usize].copy_from_slice(data);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+//! A `node:fs` stand-in for OPFS, for when the wasm build runs under Node.js rather than in a browser.
+//!
+//! Node has no OPFS, but it does have synchronous file system calls. An open file descriptor is presented through the
+//! same interface as a [`FileSystemSyncAccessHandle`], so [`File`][crate::file::File] works unchanged on top of it.
+//!
+//! Node is detected by `process.getBuiltinModule`, which Node has provided since 20.16 and 22.3. Paths are resolved as
+//! they are in OPFS, then relative to the process's working directory.
+
+use std::{
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use js_sys::{Array, Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+use web_sys::FileSystemSyncAccessHandle;
+
+use crate::{DirEntry, Error, Metadata, Result, StorageEstimate, file_abstraction::OpenOptions};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The `node:fs` module.
+    type Fs;
+
+    #[wasm_bindgen(method, catch, js_name = openSync)]
+    fn open_sync(this: &Fs, path: &str, flags: u32) -> Result<u32, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = unlinkSync)]
+    fn unlink_sync(this: &Fs, path: &str) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = copyFileSync)]
+    fn copy_file_sync(this: &Fs, from: &str, to: &str) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = renameSync)]
+    fn rename_sync(this: &Fs, from: &str, to: &str) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = rmSync)]
+    fn rm_sync(this: &Fs, path: &str, options: &Object) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = mkdirSync)]
+    fn mkdir_sync(this: &Fs, path: &str, options: &Object) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = readdirSync)]
+    fn readdir_sync(this: &Fs, path: &str) -> Result<Array, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = statSync)]
+    fn stat_sync(this: &Fs, path: &str) -> Result<Stats, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = statfsSync)]
+    fn statfs_sync(this: &Fs, path: &str) -> Result<StatFs, JsValue>;
+
+    #[wasm_bindgen(method, getter)]
+    fn constants(this: &Fs) -> Constants;
+
+    type Constants;
+
+    #[wasm_bindgen(method, getter = O_RDONLY)]
+    fn read_only(this: &Constants) -> u32;
+
+    #[wasm_bindgen(method, getter = O_RDWR)]
+    fn read_write(this: &Constants) -> u32;
+
+    #[wasm_bindgen(method, getter = O_CREAT)]
+    fn create(this: &Constants) -> u32;
+
+    #[wasm_bindgen(method, getter = O_EXCL)]
+    fn exclusive(this: &Constants) -> u32;
+
+    #[wasm_bindgen(method, getter = O_TRUNC)]
+    fn truncate(this: &Constants) -> u32;
+
+    type Stats;
+
+    #[wasm_bindgen(method, js_name = isDirectory)]
+    fn is_directory(this: &Stats) -> bool;
+
+    #[wasm_bindgen(method, getter)]
+    fn size(this: &Stats) -> f64;
+
+    #[wasm_bindgen(method, getter = mtimeMs)]
+    fn mtime_ms(this: &Stats) -> f64;
+
+    #[wasm_bindgen(method, getter = birthtimeMs)]
+    fn birthtime_ms(this: &Stats) -> f64;
+
+    type StatFs;
+
+    #[wasm_bindgen(method, getter)]
+    fn bsize(this: &StatFs) -> f64;
+
+    #[wasm_bindgen(method, getter)]
+    fn blocks(this: &StatFs) -> f64;
+
+    #[wasm_bindgen(method, getter)]
+    fn bavail(this: &StatFs) -> f64;
+}
+
+/// The body of a function of `fs` and `fd`, returning the methods of a sync access handle to `fd`.
+const SYNC_ACCESS_HANDLE: &str = "
+    return {
+        read: (buffer, options) => fs.readSync(fd, buffer, 0, buffer.length, options?.at ?? null),
+        write: (buffer, options) => fs.writeSync(fd, buffer, 0, buffer.length, options?.at ?? null),
+        truncate: (size) => fs.ftruncateSync(fd, size),
+        getSize: () => fs.fstatSync(fd).size,
+        flush: () => fs.fdatasyncSync(fd),
+        close: () => fs.closeSync(fd),
+    };
+";
+
+/// The `node:fs` module, if running under Node.
+fn fs() -> Option<Fs> {
+    let process = Reflect::get(&js_sys::global(), &"process".into()).ok()?;
+    let get_builtin_module = Reflect::get(&process, &"getBuiltinModule".into())
+        .ok()?
+        .dyn_into::<Function>()
+        .ok()?;
+    let fs = get_builtin_module.call1(&process, &"node:fs".into()).ok()?;
+    (!fs.is_undefined()).then(|| fs.unchecked_into())
+}
+
+/// Whether the module is running under Node, and should use `node:fs` instead of OPFS.
+pub(crate) fn detected() -> bool {
+    fs().is_some()
+}
+
+fn fs_or_unsupported() -> Result<Fs> {
+    fs().ok_or_else(|| io::Error::new(ErrorKind::Unsupported, "not running under Node").into())
+}
+
+/// Map the `code` of a Node system error, such as `ENOENT`, to an error of the matching kind.
+pub(crate) fn system_error(err: &js_sys::Error) -> Option<Error> {
+    let code = Reflect::get(err, &"code".into()).ok()?.as_string()?;
+    let kind = match code.as_str() {
+        "ENOENT" => ErrorKind::NotFound,
+        "EEXIST" => ErrorKind::AlreadyExists,
+        "EACCES" | "EPERM" | "EROFS" => ErrorKind::PermissionDenied,
+        "EBUSY" => ErrorKind::ResourceBusy,
+        "EDQUOT" => ErrorKind::QuotaExceeded,
+        "ENOSPC" => ErrorKind::StorageFull,
+        "EISDIR" => ErrorKind::IsADirectory,
+        "ENOTDIR" => ErrorKind::NotADirectory,
+        "ENOTEMPTY" => ErrorKind::DirectoryNotEmpty,
+        "EINVAL" => ErrorKind::InvalidInput,
+        _ => return None,
+    };
+    Some(io::Error::new(kind, String::from(err.message())).into())
+}
+
+/// Open the file at the virtualized `path` according to `options`.
+pub(crate) fn open(path: &Path, options: &OpenOptions) -> Result<FileSystemSyncAccessHandle> {
+    let fs = fs_or_unsupported()?;
+    let constants = fs.constants();
+    let mut flags = if options.read_only {
+        constants.read_only()
+    } else {
+        constants.read_write()
+    };
+    if options.creates() {
+        flags |= constants.create();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs.mkdir_sync(&parent.to_string_lossy(), &recursive())?;
+        }
+    }
+    if options.create_new {
+        flags |= constants.exclusive();
+    }
+    if options.truncate {
+        flags |= constants.truncate();
+    }
+    let fd = fs.open_sync(&path.to_string_lossy(), flags)?;
+
+    let handle = Function::new_with_args("fs, fd", SYNC_ACCESS_HANDLE).call2(
+        &JsValue::NULL,
+        &fs,
+        &fd.into(),
+    )?;
+    Ok(handle.unchecked_into())
+}
+
+pub(crate) fn delete(path: &Path) -> Result<()> {
+    fs_or_unsupported()?.unlink_sync(&path.to_string_lossy())?;
+    Ok(())
+}
+
+pub(crate) fn rename(from: &Path, to: &Path) -> Result<()> {
+    let fs = fs_or_unsupported()?;
+    if let Some(parent) = to.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs.mkdir_sync(&parent.to_string_lossy(), &recursive())?;
+    }
+    fs.rename_sync(&from.to_string_lossy(), &to.to_string_lossy())?;
+    Ok(())
+}
+
+pub(crate) fn copy(from: &Path, to: &Path) -> Result<u64> {
+    let fs = fs_or_unsupported()?;
+    if let Some(parent) = to.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs.mkdir_sync(&parent.to_string_lossy(), &recursive())?;
+    }
+    fs.copy_file_sync(&from.to_string_lossy(), &to.to_string_lossy())?;
+    Ok(fs.stat_sync(&to.to_string_lossy())?.size() as u64)
+}
+
+pub(crate) fn remove_all(path: &Path) -> Result<()> {
+    // OPFS can't remove its root either
+    if path.as_os_str().is_empty() {
+        return Err(
+            io::Error::new(ErrorKind::InvalidInput, "cannot remove the root directory").into(),
+        );
+    }
+    let options = recursive();
+    Reflect::set(&options, &"force".into(), &true.into())?;
+    fs_or_unsupported()?.rm_sync(&path.to_string_lossy(), &options)?;
+    Ok(())
+}
+
+/// Returns `false` if `path` names a directory, as in OPFS.
+pub(crate) fn exists(path: &Path) -> Result<bool> {
+    match fs_or_unsupported()?.stat_sync(&path.to_string_lossy()) {
+        Ok(stats) => Ok(!stats.is_directory()),
+        Err(err) => match Error::from(err) {
+            err if err.kind() == ErrorKind::NotFound => Ok(false),
+            err => Err(err),
+        },
+    }
+}
+
+/// An empty path lists the working directory.
+pub(crate) fn read_dir(path: &Path) -> Result<Vec<DirEntry>> {
+    let fs = fs_or_unsupported()?;
+    let dir = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    fs.readdir_sync(&dir.to_string_lossy())?
+        .iter()
+        .filter_map(|name| name.as_string())
+        .map(|name| -> Result<DirEntry> {
+            let stats = fs.stat_sync(&dir.join(&name).to_string_lossy())?;
+            let is_dir = stats.is_directory();
+            Ok(DirEntry {
+                name,
+                is_dir,
+                size: (!is_dir).then(|| stats.size() as _),
+                last_modified: (!is_dir).then(|| stats.mtime_ms() as _),
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn metadata(path: &Path) -> Result<Metadata> {
+    let stats = fs_or_unsupported()?.stat_sync(&path.to_string_lossy())?;
+    if stats.is_directory() {
+        return Err(io::Error::new(
+            ErrorKind::IsADirectory,
+            format!("{} is a directory", path.display()),
+        )
+        .into());
+    }
+    // file systems which don't record the creation time report 0
+    let created = stats.birthtime_ms();
+    Ok(Metadata {
+        size: stats.size() as _,
+        last_modified: stats.mtime_ms() as _,
+        created: (created > 0.0).then_some(created as _),
+    })
+}
+
+/// Estimate the space used and available on the file system where the file at `path` is, or would be, stored.
+pub(crate) fn estimate(path: &Path) -> Result<StorageEstimate> {
+    let fs = fs_or_unsupported()?;
+    // the file, and the directories leading to it, may not exist yet
+    let dir = path
+        .ancestors()
+        .skip(1)
+        .find(|dir| !dir.as_os_str().is_empty() && fs.stat_sync(&dir.to_string_lossy()).is_ok())
+        .unwrap_or(Path::new("."));
+    let stats = fs.statfs_sync(&dir.to_string_lossy())?;
+    let quota = (stats.blocks() * stats.bsize()) as u64;
+    let available = (stats.bavail() * stats.bsize()) as u64;
+    Ok(StorageEstimate {
+        usage: quota.saturating_sub(available),
+        quota,
+    })
+}
+
+fn recursive() -> Object {
+    let options = Object::new();
+    Reflect::set(&options, &"recursive".into(), &true.into()).expect("setting on a plain object");
+    options
+}
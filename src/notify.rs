@@ -0,0 +1,41 @@
+//! Announcing committed changes to other tabs and workers via a [`BroadcastChannel`].
+//!
+//! Every successful [`sync_data`][crate::StorageBackend::sync_data] of a writable backend posts `{ path }` on the
+//! channel, which is what redb does once per durable commit. Listeners receive it as the `data` of a `message`
+//! event, and can then refresh whatever they show from the database. The posting context itself receives nothing.
+//!
+//! [`BroadcastChannel`]: https://developer.mozilla.org/en-US/docs/Web/API/BroadcastChannel
+
+use js_sys::{Object, Reflect};
+use web_sys::BroadcastChannel;
+
+use crate::Result;
+
+/// A channel to announce changes to the file at `path` on, closed when dropped.
+#[derive(Debug)]
+pub(crate) struct Notifier {
+    channel: BroadcastChannel,
+    message: Object,
+}
+
+impl Notifier {
+    pub(crate) fn new(channel: &str, path: &str) -> Result<Self> {
+        let channel = BroadcastChannel::new(channel)?;
+        let message = Object::new();
+        Reflect::set(&message, &"path".into(), &path.into())?;
+        Ok(Self { channel, message })
+    }
+
+    /// Announce that the file changed.
+    ///
+    /// Failures are ignored: the change itself succeeded, and listeners are merely not told about it.
+    pub(crate) fn notify(&self) {
+        let _ = self.channel.post_message(&self.message);
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        self.channel.close();
+    }
+}
@@ -0,0 +1,129 @@
+//! A [`StorageBackend`] wrapper which reports every operation to an [`Observer`].
+
+use std::{fmt, time::Duration};
+
+use crate::{IoResult, StorageBackend, timestamp::Timestamp};
+
+/// An operation on a backend, as reported to an [`Observer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Reading `len` bytes at `offset`.
+    Read { offset: u64, len: usize },
+    /// Writing `len` bytes at `offset`.
+    Write { offset: u64, len: usize },
+    /// Truncating or extending the file to `len` bytes.
+    SetLen { len: u64 },
+    /// Flushing written data to storage.
+    Sync,
+}
+
+/// Callbacks invoked by an [`ObservedBackend`] around each operation.
+///
+/// Both methods do nothing by default, so implementations only override the ones they need. They run synchronously
+/// on the thread performing the operation, so slow observers slow down redb.
+pub trait Observer: Send + Sync + 'static {
+    /// Called before `op` is applied to the wrapped backend.
+    fn before(&self, op: &Operation) {
+        let _ = op;
+    }
+
+    /// Called after `op` was applied to the wrapped backend, with how long that took and its result.
+    fn after(&self, op: &Operation, elapsed: Duration, result: &IoResult<()>) {
+        let _ = (op, elapsed, result);
+    }
+}
+
+/// Implementation of a [`StorageBackend`] which reports every read, write, truncation and sync to an [`Observer`].
+///
+/// This is a drop-in point for logging, tracing or replication, without changing the wrapped backend:
+///
+/// ```rust
+/// use std::{io, time::Duration};
+///
+/// use redb_opfs::{MemoryBackend, ObservedBackend, Observer, Operation};
+///
+/// struct Logger;
+///
+/// impl Observer for Logger {
+///     fn after(&self, op: &Operation, elapsed: Duration, result: &io::Result<()>) {
+///         println!("{op:?} took {elapsed:?}: {result:?}");
+///     }
+/// }
+///
+/// let backend = ObservedBackend::new(MemoryBackend::new(), Logger);
+/// ```
+///
+/// Calls to [`len`][StorageBackend::len] are passed through without being reported.
+pub struct ObservedBackend<B, O> {
+    inner: B,
+    observer: O,
+}
+
+impl<B: fmt::Debug, O> fmt::Debug for ObservedBackend<B, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObservedBackend")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: StorageBackend, O: Observer> ObservedBackend<B, O> {
+    /// Report every operation on `inner` to `observer`.
+    pub fn new(inner: B, observer: O) -> Self {
+        Self { inner, observer }
+    }
+
+    /// Get a reference to the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get a reference to the observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Unwrap this backend, returning the wrapped backend and the observer.
+    pub fn into_inner(self) -> (B, O) {
+        (self.inner, self.observer)
+    }
+
+    /// Apply `op` to the wrapped backend by calling `apply`, reporting it to the observer before and after.
+    fn observed(&self, op: Operation, apply: impl FnOnce(&B) -> IoResult<()>) -> IoResult<()> {
+        self.observer.before(&op);
+        let started = Timestamp::now();
+        let result = apply(&self.inner);
+        self.observer.after(&op, started.elapsed(), &result);
+        result
+    }
+}
+
+impl<B: StorageBackend, O: Observer> StorageBackend for ObservedBackend<B, O> {
+    fn len(&self) -> IoResult<u64> {
+        self.inner.len()
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.observed(Operation::SetLen { len }, |inner| inner.set_len(len))
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        self.observed(Operation::Sync, |inner| inner.sync_data())
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let op = Operation::Read {
+            offset,
+            len: out.len(),
+        };
+        self.observed(op, |inner| inner.read(offset, out))
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let op = Operation::Write {
+            offset,
+            len: data.len(),
+        };
+        self.observed(op, |inner| inner.write(offset, data))
+    }
+}
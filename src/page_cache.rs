@@ -0,0 +1,196 @@
+//! A fixed-size LRU cache of file pages, to spare reads a round-trip into OPFS.
+//!
+//! The cache never holds modified data: writes go straight to the file and invalidate the pages they touch.
+//!
+//! When reads look sequential, as in a range scan, a missing page is read together with the pages following it, so
+//! that the next reads find them cached.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+};
+
+use crate::IoResult;
+
+/// Size of each cached page. This matches redb's default page size.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+#[derive(Debug)]
+struct CachedPage {
+    data: Box<[u8]>,
+    /// Number of valid bytes in `data`. Only the last page of the file may be partial.
+    len: usize,
+    last_used: u64,
+}
+
+/// An LRU cache of up to `capacity` pages.
+#[derive(Debug)]
+pub(crate) struct PageCache {
+    capacity: usize,
+    /// Number of pages to read along with a missing page when reads are sequential.
+    read_ahead: usize,
+    pages: HashMap<u64, CachedPage>,
+    /// Page indices by the tick at which they were last used.
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    /// The last page touched by the previous read.
+    last_read: Option<u64>,
+}
+
+impl PageCache {
+    /// Create a cache of up to `capacity` pages, which prefetches up to `read_ahead` pages on sequential reads.
+    pub(crate) fn new(capacity: usize, read_ahead: usize) -> Self {
+        Self {
+            capacity,
+            // prefetching must not evict the page it was prefetched along with
+            read_ahead: read_ahead.min(capacity.saturating_sub(1)),
+            pages: HashMap::with_capacity(capacity),
+            recency: BTreeMap::new(),
+            tick: 0,
+            last_read: None,
+        }
+    }
+
+    /// Fill `out` with the content of the file at `offset`, reading missing pages with `read_at`.
+    ///
+    /// `read_at(buf, offset)` reads into `buf` from `offset`, returning the number of bytes read, and 0 at the end.
+    pub(crate) fn read(
+        &mut self,
+        read_at: impl Fn(&mut [u8], u64) -> IoResult<usize>,
+        offset: u64,
+        out: &mut [u8],
+    ) -> IoResult<()> {
+        let end = offset
+            .checked_add(out.len() as u64)
+            .ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof))?;
+
+        if end == offset {
+            return Ok(());
+        }
+        let first = offset / PAGE_SIZE as u64;
+        let sequential = self
+            .last_read
+            .is_some_and(|last| first == last || first == last + 1);
+        let read_ahead = if sequential { self.read_ahead } else { 0 };
+        self.last_read = Some((end - 1) / PAGE_SIZE as u64);
+
+        let mut pos = offset;
+        while pos < end {
+            let index = pos / PAGE_SIZE as u64;
+            let within = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - within).min((end - pos) as usize);
+            let page = self.page(&read_at, index, read_ahead)?;
+            if within + n > page.len {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            let buf_start = (pos - offset) as usize;
+            out[buf_start..buf_start + n].copy_from_slice(&page.data[within..within + n]);
+            pos += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Discard all pages which may no longer reflect the file after `len` bytes were written at `offset`.
+    ///
+    /// Besides the pages overlapping the write, this includes a partial last page, as the write may have extended
+    /// the file beyond it.
+    pub(crate) fn invalidate_write(&mut self, offset: u64, len: usize) {
+        let first = offset / PAGE_SIZE as u64;
+        let last = (offset + len as u64).div_ceil(PAGE_SIZE as u64);
+        self.retain(|index, page| (index < first || index >= last) && page.len == PAGE_SIZE);
+    }
+
+    /// Discard all pages which may no longer reflect the file after it was truncated or extended to `len` bytes.
+    pub(crate) fn invalidate_set_len(&mut self, len: u64) {
+        let first = len / PAGE_SIZE as u64;
+        self.retain(|index, page| index < first && page.len == PAGE_SIZE);
+    }
+
+    /// Discard all pages.
+    pub(crate) fn clear(&mut self) {
+        self.pages.clear();
+        self.recency.clear();
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(u64, &CachedPage) -> bool) {
+        let recency = &mut self.recency;
+        self.pages.retain(|&index, page| {
+            let kept = keep(index, page);
+            if !kept {
+                recency.remove(&page.last_used);
+            }
+            kept
+        });
+    }
+
+    /// Get page `index`, reading it with `read_at`, along with up to `read_ahead` pages following it, if it is not
+    /// cached.
+    fn page(
+        &mut self,
+        read_at: &impl Fn(&mut [u8], u64) -> IoResult<usize>,
+        index: u64,
+        read_ahead: usize,
+    ) -> IoResult<&CachedPage> {
+        if !self.pages.contains_key(&index) {
+            self.fetch(read_at, index, read_ahead)?;
+        }
+
+        self.tick += 1;
+        let page = self.pages.get_mut(&index).expect("page is cached");
+        self.recency.remove(&page.last_used);
+        page.last_used = self.tick;
+        self.recency.insert(self.tick, index);
+        Ok(page)
+    }
+
+    /// Read page `index` with `read_at` into the cache, along with the uncached pages among the `read_ahead` pages
+    /// following it, in a single read.
+    fn fetch(
+        &mut self,
+        read_at: &impl Fn(&mut [u8], u64) -> IoResult<usize>,
+        index: u64,
+        read_ahead: usize,
+    ) -> IoResult<()> {
+        let uncached = (1..=read_ahead as u64)
+            .take_while(|ahead| !self.pages.contains_key(&(index + ahead)))
+            .count();
+        let mut data = vec![0; (1 + uncached) * PAGE_SIZE];
+        let start = index * PAGE_SIZE as u64;
+        let mut len = 0;
+        while len < data.len() {
+            match read_at(&mut data[len..], start + len as u64) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        // the requested page goes last, so that it is the most recently used; pages past the end of the file are
+        // only cached if requested
+        for (i, chunk) in data.chunks(PAGE_SIZE).enumerate().rev() {
+            let page_len = len.saturating_sub(i * PAGE_SIZE).min(PAGE_SIZE);
+            if i == 0 || page_len > 0 {
+                self.insert(index + i as u64, chunk.into(), page_len);
+            }
+        }
+        Ok(())
+    }
+
+    /// Cache `len` bytes of `data` as page `index`, evicting the least recently used page if the cache is full.
+    fn insert(&mut self, index: u64, data: Box<[u8]>, len: usize) {
+        if self.pages.len() >= self.capacity
+            && let Some((_, evicted)) = self.recency.pop_first()
+        {
+            self.pages.remove(&evicted);
+        }
+        self.tick += 1;
+        let page = CachedPage {
+            data,
+            len,
+            last_used: self.tick,
+        };
+        self.pages.insert(index, page);
+        self.recency.insert(self.tick, index);
+    }
+}
@@ -0,0 +1,100 @@
+//! Turning panics within the operations of an [`OpfsBackend`][crate::OpfsBackend] into errors.
+//!
+//! redb calls into the backend synchronously, and a panic there would otherwise unwind through redb, or, on wasm,
+//! abort the whole instance, killing the worker and leaking the sync access handle. Instead, each operation fails
+//! with an [`io::Error`] wrapping a [`CaughtPanic`], after reporting it to the hook set with [`set_panic_hook`].
+//!
+//! Panics can only be caught where they unwind. `wasm32-unknown-unknown` aborts on panic by default; catching them
+//! there requires building with `-C panic=unwind`, which takes a nightly toolchain and `-Zbuild-std`.
+
+use std::{
+    any::Any,
+    fmt, io,
+    panic::{AssertUnwindSafe, catch_unwind},
+};
+
+use parking_lot::RwLock;
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+use crate::IoResult;
+
+type Hook = Box<dyn Fn(&CaughtPanic) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+#[cfg(opfs)]
+thread_local! {
+    static JS_HOOK: std::cell::RefCell<Option<js_sys::Function>> = const { std::cell::RefCell::new(None) };
+}
+
+/// A panic caught within an operation of a backend, which failed with it instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaughtPanic {
+    /// The operation which panicked, such as `"read"` or `"sync_data"`.
+    pub operation: &'static str,
+    /// The message the panic was raised with, if it was a string.
+    pub message: String,
+}
+
+impl fmt::Display for CaughtPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} panicked: {}", self.operation, self.message)
+    }
+}
+
+impl std::error::Error for CaughtPanic {}
+
+/// Call `hook` with every panic caught within an operation of a backend, for example to report it.
+///
+/// The hook applies to every backend in the process, and replaces any previous hook.
+pub fn set_panic_hook(hook: impl Fn(&CaughtPanic) + Send + Sync + 'static) {
+    *HOOK.write() = Some(Box::new(hook));
+}
+
+/// Call `callback(operation, message)` with every panic caught within an operation of a backend in this worker.
+///
+/// Pass `undefined` to remove it. Exceptions thrown by the callback are ignored.
+#[cfg(opfs)]
+#[wasm_bindgen(js_name = setPanicHook)]
+pub fn set_panic_hook_js(callback: Option<js_sys::Function>) {
+    JS_HOOK.with(|hook| *hook.borrow_mut() = callback);
+}
+
+/// Run `f`, the body of `operation`, converting a panic into an error.
+pub(crate) fn guard<T>(operation: &'static str, f: impl FnOnce() -> IoResult<T>) -> IoResult<T> {
+    let payload = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => return result,
+        Err(payload) => payload,
+    };
+    let caught = CaughtPanic {
+        operation,
+        message: message_of(&*payload),
+    };
+    if let Some(hook) = &*HOOK.read() {
+        hook(&caught);
+    }
+    #[cfg(opfs)]
+    JS_HOOK.with(|hook| {
+        if let Some(hook) = &*hook.borrow() {
+            let _ = hook.call2(
+                &JsValue::NULL,
+                &caught.operation.into(),
+                &caught.message.as_str().into(),
+            );
+        }
+    });
+    Err(io::Error::other(caught))
+}
+
+/// The message of a panic, as raised by `panic!` with or without formatting arguments.
+fn message_of(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return (*message).to_owned();
+    }
+    match payload.downcast_ref::<String>() {
+        Some(message) => message.clone(),
+        None => "non-string panic payload".into(),
+    }
+}
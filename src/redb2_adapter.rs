@@ -0,0 +1,62 @@
+//! An adapter for applications which still use redb 2, enabled by the `redb2` feature.
+
+use crate::{IoResult, StorageBackend};
+
+/// Implementation of redb 2's `StorageBackend` which delegates to any backend of this crate.
+///
+/// This crate's backends implement [`StorageBackend`], which is redb 3's, or a copy of it without the `redb3`
+/// feature: its `read` fills a caller-provided buffer and its `sync_data` takes no arguments. Wrapping a backend in
+/// this lets a `redb2::Database` use it, so that applications can upgrade redb on their own schedule:
+///
+/// ```ignore
+/// let backend = Redb2Backend::new(OpfsBackend::new("my-db").await?);
+/// let database = redb2::Builder::new().create_with_backend(backend)?;
+/// ```
+///
+/// redb 2 asks for an eventual sync when committing with `Durability::Eventual`; such syncs are performed like any
+/// other. To defer flushes, configure the backend's [`SyncMode`][crate::SyncMode] instead.
+#[derive(Debug)]
+pub struct Redb2Backend<B> {
+    inner: B,
+}
+
+impl<B: StorageBackend> Redb2Backend<B> {
+    /// Make `inner` usable with redb 2.
+    pub fn new(inner: B) -> Self {
+        Self { inner }
+    }
+
+    /// Get a reference to the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwrap the wrapped backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: StorageBackend> redb2::StorageBackend for Redb2Backend<B> {
+    fn len(&self) -> IoResult<u64> {
+        self.inner.len()
+    }
+
+    fn read(&self, offset: u64, len: usize) -> IoResult<Vec<u8>> {
+        let mut out = vec![0; len];
+        self.inner.read(offset, &mut out)?;
+        Ok(out)
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.inner.set_len(len)
+    }
+
+    fn sync_data(&self, _eventual: bool) -> IoResult<()> {
+        self.inner.sync_data()
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        self.inner.write(offset, data)
+    }
+}
@@ -0,0 +1,87 @@
+//! The files opened by backends in this process, so that opening one twice fails clearly.
+//!
+//! On wasm, opening a file which another backend in the same worker holds would otherwise fail with an opaque
+//! `NoModificationAllowedError` from `createSyncAccessHandle`, or wait on a Web Lock which this very worker holds. This
+//! is a common mistake during hot reloading in development, when the previous instance of a module is still around.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, ErrorKind},
+};
+
+use parking_lot::Mutex;
+
+/// How each registered file is open: `None` for a writable backend, or the number of read-only backends.
+static OPEN: Mutex<BTreeMap<String, Option<usize>>> = Mutex::new(BTreeMap::new());
+
+/// The error which opening a file fails with while another backend in this process has it open.
+///
+/// Read-only backends may share a file with each other, but not with a writable backend. The error has the kind
+/// [`ErrorKind::ResourceBusy`]; on wasm, it becomes `Error::AlreadyOpen`, which is thrown to JS as an
+/// `AlreadyOpenError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyOpen {
+    /// The file, as resolved by the builder which tried to open it.
+    pub path: String,
+}
+
+impl fmt::Display for AlreadyOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is already open by another backend in this process; close it first",
+            self.path
+        )
+    }
+}
+
+impl std::error::Error for AlreadyOpen {}
+
+/// Whether `err` is an [`AlreadyOpen`] error.
+#[cfg(opfs)]
+pub(crate) fn is_already_open(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<AlreadyOpen>())
+}
+
+/// A file registered as open, until this is dropped.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    key: String,
+}
+
+impl Registration {
+    /// Register the file identified by `key` as open, shared with other read-only backends if `read_only`.
+    pub(crate) fn new(key: String, read_only: bool) -> io::Result<Self> {
+        let mut open = OPEN.lock();
+        match (open.get_mut(&key), read_only) {
+            (None, false) => {
+                open.insert(key.clone(), None);
+            }
+            (None, true) => {
+                open.insert(key.clone(), Some(1));
+            }
+            (Some(Some(readers)), true) => *readers += 1,
+            (Some(_), _) => {
+                return Err(io::Error::new(
+                    ErrorKind::ResourceBusy,
+                    AlreadyOpen { path: key },
+                ));
+            }
+        }
+        Ok(Self { key })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut open = OPEN.lock();
+        if let Some(Some(readers)) = open.get_mut(&self.key)
+            && *readers > 1
+        {
+            *readers -= 1;
+        } else {
+            open.remove(&self.key);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Retrying operations which fail transiently.
+//!
+//! Right after another context releases a file, acquiring its sync access handle can briefly fail with
+//! `NoModificationAllowedError` or `InvalidStateError`. Both surface as [`ErrorKind::ResourceBusy`], and are worth
+//! retrying after a short delay. A closed backend fails with [`ErrorKind::NotConnected`] instead, which is never
+//! retried.
+
+use std::io::ErrorKind;
+#[cfg(not(opfs))]
+use std::time::Duration;
+#[cfg(not(any(opfs, target_family = "wasm")))]
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+#[cfg(not(any(opfs, target_family = "wasm")))]
+use parking_lot::Mutex;
+
+use crate::{IoResult, Result};
+
+/// How often to retry transient failures, and how long to wait in between.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    /// Retries after the first attempt; `0` disables retrying.
+    pub(crate) retries: u32,
+    /// Delay before the first retry, doubling for each one after it.
+    pub(crate) backoff_ms: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff_ms: 10,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry`, counting from 0.
+    fn delay_ms(&self, retry: u32) -> u32 {
+        self.backoff_ms.saturating_mul(1 << retry.min(16))
+    }
+
+    /// Run `f`, retrying while it fails with a kind of error for which `transient` holds.
+    pub(crate) async fn run<T>(
+        &self,
+        transient: impl Fn(ErrorKind) -> bool,
+        mut f: impl AsyncFnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            match f().await {
+                Err(err) if retry < self.retries && transient(err.kind()) => {
+                    sleep(self.delay_ms(retry)).await?;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run a synchronous operation, retrying while it fails with [`ErrorKind::ResourceBusy`].
+    ///
+    /// `f` takes the backend's internal lock itself, so that it is released while waiting for the next attempt. As
+    /// operations of a [`StorageBackend`][crate::StorageBackend] are synchronous, the calling thread waits.
+    ///
+    /// On wasm, this runs `f` only once: operations are synchronous there, so a busy handle can only be re-acquired
+    /// once control returns to the event loop, and retrying without yielding would be futile.
+    pub(crate) fn run_sync<T>(&self, mut f: impl FnMut() -> IoResult<T>) -> IoResult<T> {
+        #[cfg(opfs)]
+        {
+            f()
+        }
+
+        #[cfg(not(opfs))]
+        {
+            let mut retry = 0;
+            loop {
+                match f() {
+                    Err(err) if retry < self.retries && err.kind() == ErrorKind::ResourceBusy => {
+                        let delay = Duration::from_millis(self.delay_ms(retry).into());
+                        std::thread::sleep(delay);
+                        retry += 1;
+                    }
+                    result => return result,
+                }
+            }
+        }
+    }
+}
+
+/// Wait for `ms` milliseconds without blocking the event loop.
+#[cfg(opfs)]
+pub(crate) async fn sleep(ms: u32) -> Result<()> {
+    use js_sys::{Function, Promise, Reflect};
+    use wasm_bindgen::{JsCast as _, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+
+    let global = js_sys::global();
+    let set_timeout = Reflect::get(&global, &"setTimeout".into())?.dyn_into::<Function>()?;
+    let mut scheduled = Ok(JsValue::UNDEFINED);
+    let elapsed = Promise::new(&mut |resolve, _reject| {
+        scheduled = set_timeout.call2(&global, &resolve, &ms.into());
+    });
+    scheduled?;
+    JsFuture::from(elapsed).await?;
+    Ok(())
+}
+
+/// Wait for `ms` milliseconds without blocking the executor: a thread of its own waits out the delay, and then wakes
+/// the task.
+#[cfg(not(any(opfs, target_family = "wasm")))]
+pub(crate) async fn sleep(ms: u32) -> Result<()> {
+    Sleep {
+        deadline: Instant::now() + Duration::from_millis(ms.into()),
+        waker: None,
+    }
+    .await;
+    Ok(())
+}
+
+/// Run `future` to completion on the current thread, parking it while the future waits.
+///
+/// This serves synchronous callers of asynchronous code, such as the first operation of a lazy backend.
+#[cfg(not(opfs))]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    use std::{
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// WASI has no threads to wait on, and nothing else to run in the meantime, so blocking is fine there.
+#[cfg(all(not(opfs), target_family = "wasm"))]
+pub(crate) async fn sleep(ms: u32) -> Result<()> {
+    std::thread::sleep(Duration::from_millis(ms.into()));
+    Ok(())
+}
+
+/// Future of [`sleep`].
+#[cfg(not(any(opfs, target_family = "wasm")))]
+struct Sleep {
+    deadline: Instant,
+    /// The waker of the latest poll, which the timer thread wakes once started.
+    waker: Option<Arc<Mutex<Waker>>>,
+}
+
+#[cfg(not(any(opfs, target_family = "wasm")))]
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => waker.lock().clone_from(cx.waker()),
+            None => {
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                let timer = Arc::clone(&waker);
+                std::thread::spawn(move || {
+                    std::thread::sleep(remaining);
+                    timer.lock().wake_by_ref();
+                });
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,268 @@
+//! A [`StorageBackend`] which splits the database across several files of bounded size.
+//!
+//! Some browsers handle very large OPFS files poorly. [`ShardedBackend`] presents a sequence of shards as a single
+//! logical file: shard `n` holds the logical bytes `n * shard_size..(n + 1) * shard_size`. Every shard before the one
+//! holding the logical end is exactly `shard_size` bytes long, and every shard after it is empty, so the logical length
+//! is recovered from the shards' lengths alone, and no header is needed.
+//!
+//! Opening a file is asynchronous on wasm, while [`StorageBackend`] is synchronous, so shards can't be opened as the
+//! database grows. Instead, every shard the database may ever need is opened up front, which bounds the database's
+//! size; writes beyond it fail with [`ErrorKind::StorageFull`]. Empty shards occupy no storage.
+//!
+//! Only shards written since the last [`sync_data`][StorageBackend::sync_data] are synced, and
+//! [`ShardedBackend::modified_shards`] reports which shards changed, so that backups can copy only those.
+
+use std::{
+    io::{self, ErrorKind},
+    ops::Range,
+};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, OpfsBackend, Result, StorageBackend};
+
+/// The shard size used by [`ShardedBackend::open`].
+pub const DEFAULT_SHARD_SIZE: u64 = 256 << 20;
+
+/// Mutable state of a [`ShardedBackend`].
+#[derive(Debug)]
+struct State {
+    /// Logical length of the database.
+    len: u64,
+    /// Whether each shard has been written since the last sync.
+    unsynced: Vec<bool>,
+    /// Whether each shard has been written since [`ShardedBackend::clear_modified`] was last called.
+    modified: Vec<bool>,
+}
+
+/// Implementation of a [`StorageBackend`] which splits data across several inner backends, the shards.
+///
+/// See the [module documentation][self] for details.
+#[derive(Debug)]
+pub struct ShardedBackend<B = OpfsBackend> {
+    shards: Vec<B>,
+    shard_size: u64,
+    state: Mutex<State>,
+}
+
+impl ShardedBackend {
+    /// Open the shards of the database at `path`, with room for at least `max_len` bytes in shards of
+    /// [`DEFAULT_SHARD_SIZE`].
+    ///
+    /// Shard `n` is stored at `{path}.{n}`, and created if it does not exist.
+    pub async fn open(path: &str, max_len: u64) -> Result<Self> {
+        Self::open_with(path, max_len, DEFAULT_SHARD_SIZE).await
+    }
+
+    /// Open the shards of the database at `path`, with room for at least `max_len` bytes in shards of `shard_size`.
+    ///
+    /// If more shards than needed already exist, as when reopening with a lower `max_len`, they are opened too.
+    pub async fn open_with(path: &str, max_len: u64, shard_size: u64) -> Result<Self> {
+        if shard_size == 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "shard size must not be 0").into());
+        }
+        let mut count = max_len.div_ceil(shard_size).max(1);
+        while OpfsBackend::exists(&shard_path(path, count)).await? {
+            count += 1;
+        }
+
+        let mut shards = Vec::with_capacity(count as _);
+        for index in 0..count {
+            shards.push(OpfsBackend::new(&shard_path(path, index)).await?);
+        }
+        let backend = Self::new(shards, shard_size)?;
+        Ok(backend)
+    }
+}
+
+impl<B: StorageBackend> ShardedBackend<B> {
+    /// Combine `shards`, each holding up to `shard_size` bytes, into a single logical file.
+    ///
+    /// Fails with [`ErrorKind::InvalidData`] if the shards' lengths are inconsistent, which means that shards are
+    /// missing, out of order, or were not written by a `ShardedBackend` with the same shard size.
+    pub fn new(shards: Vec<B>, shard_size: u64) -> IoResult<Self> {
+        if shard_size == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "shard size must not be 0",
+            ));
+        }
+
+        let mut len = 0;
+        let mut ended = false;
+        for (index, shard) in shards.iter().enumerate() {
+            let shard_len = shard.len()?;
+            if shard_len > shard_size || (ended && shard_len > 0) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("shard {index} has an unexpected length of {shard_len} bytes"),
+                ));
+            }
+            len += shard_len;
+            ended |= shard_len < shard_size;
+        }
+
+        let count = shards.len();
+        Ok(Self {
+            shards,
+            shard_size,
+            state: Mutex::new(State {
+                len,
+                unsynced: vec![false; count],
+                modified: vec![false; count],
+            }),
+        })
+    }
+
+    /// The maximum length of the database, in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.shards.len() as u64 * self.shard_size
+    }
+
+    /// The shards, in order.
+    pub fn shards(&self) -> &[B] {
+        &self.shards
+    }
+
+    /// The indices of the shards written since [`clear_modified`][Self::clear_modified] was last called, or since
+    /// the backend was created.
+    pub fn modified_shards(&self) -> Vec<usize> {
+        let state = self.state.lock();
+        (0..self.shards.len())
+            .filter(|&index| state.modified[index])
+            .collect()
+    }
+
+    /// Forget which shards have been modified, for example once they have been backed up.
+    pub fn clear_modified(&self) {
+        self.state.lock().modified.fill(false);
+    }
+
+    /// Unwrap this backend, returning the shards.
+    pub fn into_inner(self) -> Vec<B> {
+        self.shards
+    }
+
+    fn check_capacity(&self, len: u64) -> IoResult<()> {
+        if len > self.capacity() {
+            return Err(io::Error::new(
+                ErrorKind::StorageFull,
+                format!(
+                    "requested size {len} exceeds the capacity of {} shards of {} bytes",
+                    self.shards.len(),
+                    self.shard_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The length shard `index` has when the logical length is `len`.
+    fn shard_len(&self, index: usize, len: u64) -> u64 {
+        len.saturating_sub(index as u64 * self.shard_size)
+            .min(self.shard_size)
+    }
+
+    /// Truncate or extend the shards to a logical length of `new_len`.
+    fn resize(&self, state: &mut State, new_len: u64) -> IoResult<()> {
+        for (index, shard) in self.shards.iter().enumerate() {
+            let shard_len = self.shard_len(index, new_len);
+            if shard_len != self.shard_len(index, state.len) {
+                shard.set_len(shard_len)?;
+                state.unsynced[index] = true;
+                state.modified[index] = true;
+            }
+        }
+        state.len = new_len;
+        Ok(())
+    }
+
+    /// Call `f` for each shard touched by the logical range `start..end`.
+    ///
+    /// `f` receives the shard index, the offset within the shard, and the range within a buffer which starts at
+    /// `start`.
+    fn for_each_shard(
+        &self,
+        start: u64,
+        end: u64,
+        mut f: impl FnMut(usize, u64, Range<usize>) -> IoResult<()>,
+    ) -> IoResult<()> {
+        let mut pos = start;
+        while pos < end {
+            let index = pos / self.shard_size;
+            let within = pos % self.shard_size;
+            let n = (self.shard_size - within).min(end - pos) as usize;
+            let buf_start = (pos - start) as usize;
+            f(index as _, within, buf_start..buf_start + n)?;
+            pos += n as u64;
+        }
+        Ok(())
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for ShardedBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        Ok(self.state.lock().len)
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.check_capacity(len)?;
+        let mut state = self.state.lock();
+        self.resize(&mut state, len)
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let mut state = self.state.lock();
+        for (index, shard) in self.shards.iter().enumerate() {
+            if state.unsynced[index] {
+                shard.sync_data()?;
+                state.unsynced[index] = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let state = self.state.lock();
+        let end = offset
+            .checked_add(out.len() as u64)
+            .filter(|&end| end <= state.len)
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+
+        self.for_each_shard(offset, end, |index, within, in_buf| {
+            self.shards[index].read(within, &mut out[in_buf])
+        })
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            )
+        })?;
+        self.check_capacity(end)?;
+
+        let mut state = self.state.lock();
+        // Shards before the one written must be full, so fill any gap with zeros first.
+        if offset > state.len {
+            self.resize(&mut state, offset)?;
+        }
+        self.for_each_shard(offset, end, |index, within, in_buf| {
+            self.shards[index].write(within, &data[in_buf])?;
+            state.unsynced[index] = true;
+            state.modified[index] = true;
+            Ok(())
+        })?;
+
+        if end > state.len {
+            state.len = end;
+        }
+        Ok(())
+    }
+}
+
+/// The path of shard `index` of the database at `path`.
+fn shard_path(path: &str, index: u64) -> String {
+    format!("{path}.{index}")
+}
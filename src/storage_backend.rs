@@ -0,0 +1,33 @@
+//! The trait every backend of this crate implements.
+//!
+//! With the `redb3` feature, which is on by default, this is redb 3's own `StorageBackend`, so the backends plug
+//! straight into a `redb::Database`. Without it, redb 3 isn't a dependency at all, and this is a copy of that trait,
+//! which applications still on redb 2 reach through [`Redb2Backend`][crate::Redb2Backend].
+
+#[cfg(feature = "redb3")]
+pub use redb::StorageBackend;
+
+#[cfg(not(feature = "redb3"))]
+use crate::IoResult;
+
+/// Implementation of a file as redb 3 sees it: a growable array of bytes, read and written at offsets.
+///
+/// This mirrors redb 3's `StorageBackend`, which takes its place with the `redb3` feature.
+#[cfg(not(feature = "redb3"))]
+pub trait StorageBackend: 'static + std::fmt::Debug + Send + Sync {
+    /// Get the length of the storage in bytes.
+    fn len(&self) -> IoResult<u64>;
+
+    /// Fill `out` with the bytes starting at `offset`, failing with
+    /// [`ErrorKind::UnexpectedEof`][std::io::ErrorKind::UnexpectedEof] if they extend past the end.
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()>;
+
+    /// Truncate or extend the storage to `len` bytes, with zeros.
+    fn set_len(&self, len: u64) -> IoResult<()>;
+
+    /// Make everything written so far durable.
+    fn sync_data(&self) -> IoResult<()>;
+
+    /// Write `data` at `offset`, extending the storage as needed.
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()>;
+}
@@ -0,0 +1,84 @@
+//! Storage usage, as reported by [`OpfsBackend::quota`][crate::OpfsBackend::quota].
+
+use std::fmt;
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+use crate::Error;
+
+/// An estimate of the storage used and available.
+///
+/// On wasm, this is the browser's estimate for the whole origin, so it covers more than this crate's files.
+/// Natively, it describes the file system containing the current directory.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageEstimate {
+    pub(crate) usage: u64,
+    pub(crate) quota: u64,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl StorageEstimate {
+    /// Bytes currently in use.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn usage(&self) -> u64 {
+        self.usage
+    }
+
+    /// Total bytes which may be used, including those already in use.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn quota(&self) -> u64 {
+        self.quota
+    }
+
+    /// Bytes which may still be used before writes start failing.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn available(&self) -> u64 {
+        self.quota.saturating_sub(self.usage)
+    }
+}
+
+/// The error which opening a file fails with if less storage is available than
+/// [`OpfsBackendBuilder::min_free_space`][crate::OpfsBackendBuilder::min_free_space] requires.
+///
+/// The error has the kind [`ErrorKind::QuotaExceeded`][std::io::ErrorKind::QuotaExceeded]; on wasm, it becomes
+/// `Error::InsufficientQuota`, which is thrown to JS as an `InsufficientQuotaError`. Find it with
+/// [`InsufficientQuota::of`], for example to ask the user to free up space before the first commit fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsufficientQuota {
+    /// Bytes required to be available.
+    pub required: u64,
+    /// Bytes available, according to the [`StorageEstimate`].
+    pub available: u64,
+}
+
+impl fmt::Display for InsufficientQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "only {} bytes of storage are available, but {} are required",
+            self.available, self.required
+        )
+    }
+}
+
+impl std::error::Error for InsufficientQuota {}
+
+impl InsufficientQuota {
+    /// Find the [`InsufficientQuota`] error within `err`, if it has one.
+    pub fn of(err: &Error) -> Option<&InsufficientQuota> {
+        #[cfg(opfs)]
+        let Error::InsufficientQuota(err) = err else {
+            return None;
+        };
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+/// Whether `err` is an [`InsufficientQuota`] error.
+#[cfg(opfs)]
+pub(crate) fn is_insufficient_quota(err: &std::io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<InsufficientQuota>())
+}
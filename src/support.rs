@@ -0,0 +1,98 @@
+//! Whether the current context can run a backend, as reported by [`OpfsBackend::is_supported`][crate::OpfsBackend::is_supported].
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+/// What the current context offers an [`OpfsBackend`][crate::OpfsBackend].
+///
+/// Probing has no side effects: nothing is opened, and no permission is requested. Natively, the backend uses the
+/// local file system, so it is always [`supported`][Self::supported], and the browser features are all reported
+/// missing.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Support {
+    pub(crate) supported: bool,
+    pub(crate) reason: Option<String>,
+    pub(crate) worker: bool,
+    pub(crate) opfs: bool,
+    pub(crate) sync_access_handles: bool,
+    pub(crate) web_locks: bool,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl Support {
+    /// Whether an [`OpfsBackend`][crate::OpfsBackend] can be opened here.
+    ///
+    /// If not, fall back to other storage, or to `MainThreadBackend` on the main thread.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn supported(&self) -> bool {
+        self.supported
+    }
+
+    /// Why an [`OpfsBackend`][crate::OpfsBackend] can't be opened here; the message opening would fail with.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn reason(&self) -> Option<String> {
+        self.reason.clone()
+    }
+
+    /// Whether this is a worker which may block, that is any worker but a service worker.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn worker(&self) -> bool {
+        self.worker
+    }
+
+    /// Whether OPFS itself is available, as `navigator.storage.getDirectory`.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn opfs(&self) -> bool {
+        self.opfs
+    }
+
+    /// Whether `FileSystemFileHandle.createSyncAccessHandle` is available.
+    #[cfg_attr(opfs, wasm_bindgen(getter = syncAccessHandles))]
+    pub fn sync_access_handles(&self) -> bool {
+        self.sync_access_handles
+    }
+
+    /// Whether the Web Locks API is available; without it, [`lock`][crate::OpfsBackendBuilder::lock] fails.
+    #[cfg_attr(opfs, wasm_bindgen(getter = webLocks))]
+    pub fn web_locks(&self) -> bool {
+        self.web_locks
+    }
+}
+
+impl Support {
+    /// Probe the current context.
+    #[cfg(opfs)]
+    pub(crate) fn probe() -> Self {
+        use js_sys::Reflect;
+
+        use crate::file;
+
+        let global = js_sys::global();
+        let reason = file::check_context().err().map(|err| err.to_string());
+        let web_locks = Reflect::get(&global, &"navigator".into())
+            .and_then(|navigator| Reflect::get(&navigator, &"locks".into()))
+            .is_ok_and(|locks| !locks.is_undefined());
+        Self {
+            supported: reason.is_none(),
+            reason,
+            worker: file::is_worker(&global) && !file::is_service_worker(&global),
+            opfs: file::has_opfs(),
+            sync_access_handles: file::has_sync_access_handles(&global),
+            web_locks,
+        }
+    }
+
+    /// Probe the current context.
+    #[cfg(not(opfs))]
+    pub(crate) fn probe() -> Self {
+        Self {
+            supported: true,
+            reason: None,
+            worker: false,
+            opfs: false,
+            sync_access_handles: false,
+            web_locks: false,
+        }
+    }
+}
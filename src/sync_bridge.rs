@@ -0,0 +1,361 @@
+//! Synchronous access from the main thread to an [`OpfsBackend`] in a worker, over shared memory.
+//!
+//! The main thread writes each request into a `SharedArrayBuffer`, signals the worker, and blocks until the worker
+//! has written the response. The worker blocks in `Atomics.wait` between requests, so it does nothing else once it
+//! serves the bridge. Browsers forbid `Atomics.wait` on the main thread, so the main thread spins instead; requests
+//! should be short, which redb's page-sized I/O is.
+//!
+//! A caller blocks until its request completes, so there is never more than one request in flight, and the buffer
+//! holds a single slot: a header, then a data region for the payload of reads and writes. Payloads larger than the
+//! data region are split into several requests.
+//!
+//! The header also records whether the worker serves the bridge. Once it stops, or fails to take up a request within
+//! [`PICKUP_TIMEOUT_MS`], for example because it was terminated, the bridge fails with `ClosedError` rather than
+//! spinning forever.
+//!
+//! `SharedArrayBuffer` is only available to [cross-origin isolated] pages, which are served with the headers
+//! `Cross-Origin-Opener-Policy: same-origin` and `Cross-Origin-Embedder-Policy: require-corp`.
+//!
+//! [cross-origin isolated]: https://developer.mozilla.org/en-US/docs/Web/API/Window/crossOriginIsolated
+
+use std::{
+    io::{self, ErrorKind},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use js_sys::{Atomics, Date, Float64Array, Int32Array, Reflect, SharedArrayBuffer, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    Error, IoResult, OpfsBackend, Result, StorageBackend, closed, thread_bound::ThreadBound,
+};
+
+// Indices into the header, as `i32`s.
+const STATE: u32 = 0;
+const OP: u32 = 1;
+const STATUS: u32 = 2;
+const LEN: u32 = 3;
+const WORKER: u32 = 4;
+const HEADER_INTS: u32 = 5;
+/// Byte offset of the `f64` holding the request's offset or size, or the response's value.
+const NUMBER_OFFSET: u32 = 24;
+const HEADER_BYTES: u32 = 32;
+
+// Values of `STATE`.
+const IDLE: i32 = 0;
+const REQUEST: i32 = 1;
+const RESPONSE: i32 = 2;
+/// The worker took up the request, and is serving it.
+const SERVING: i32 = 3;
+
+// Values of `WORKER`, which is 0 while the worker opens the file.
+const RUNNING: i32 = 1;
+const STOPPED: i32 = 2;
+
+/// How long a running worker may take to take up a request before it is assumed dead, in milliseconds.
+///
+/// A worker blocked in `Atomics.wait` wakes up at once, so this only expires if the worker was terminated or its event
+/// loop is stuck. Serving the request, and opening the file before the first one, may take as long as they need.
+const PICKUP_TIMEOUT_MS: f64 = 10_000.0;
+
+// Values of `OP`.
+const OP_LEN: i32 = 1;
+const OP_READ: i32 = 2;
+const OP_WRITE: i32 = 3;
+const OP_SET_LEN: i32 = 4;
+const OP_SYNC_DATA: i32 = 5;
+const OP_CLOSE: i32 = 6;
+
+// Values of `STATUS`; on error, the data region holds the error's name and message, separated by a newline.
+const OK: i32 = 0;
+const FAILED: i32 = 1;
+
+/// Views of the shared buffer.
+#[derive(Debug)]
+struct Shared {
+    buffer: SharedArrayBuffer,
+    header: Int32Array,
+    number: Float64Array,
+    data: Uint8Array,
+}
+
+impl Shared {
+    fn new(buffer: SharedArrayBuffer) -> Result<Self> {
+        let data_len = buffer.byte_length().saturating_sub(HEADER_BYTES);
+        if data_len == 0 {
+            return Err(
+                io::Error::new(ErrorKind::InvalidInput, "shared buffer is too small").into(),
+            );
+        }
+        Ok(Self {
+            header: Int32Array::new_with_byte_offset_and_length(&buffer, 0, HEADER_INTS),
+            number: Float64Array::new_with_byte_offset_and_length(&buffer, NUMBER_OFFSET, 1),
+            data: Uint8Array::new_with_byte_offset_and_length(&buffer, HEADER_BYTES, data_len),
+            buffer,
+        })
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.length() as _
+    }
+
+    fn load(&self, index: u32) -> IoResult<i32> {
+        Atomics::load(&self.header, index).map_err(Error::to_io)
+    }
+
+    fn store(&self, index: u32, value: i32) -> IoResult<()> {
+        Atomics::store(&self.header, index, value).map_err(Error::to_io)?;
+        Ok(())
+    }
+}
+
+/// The main thread's end of a synchronous bridge to an [`OpfsBackend`] in a worker.
+///
+/// Create the bridge, hand [`buffer`][Self::buffer] to a worker which calls [`serve_sync_bridge`] with it, then use the
+/// bridge like any other [`StorageBackend`], for example as the backend of a `redb::Database` on the main thread.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct SyncBridge {
+    shared: ThreadBound<Shared>,
+    /// Set once the bridge was closed, or the worker stopped serving it.
+    closed: AtomicBool,
+}
+
+#[wasm_bindgen]
+impl SyncBridge {
+    /// Create a bridge which transfers up to `capacity` bytes per request.
+    ///
+    /// Fails with `UnsupportedContextError` unless the page is cross-origin isolated.
+    #[wasm_bindgen(constructor)]
+    pub fn new(capacity: u32) -> Result<SyncBridge> {
+        let isolated = Reflect::get(&js_sys::global(), &"crossOriginIsolated".into())?;
+        if !isolated.is_truthy() {
+            return Err(Error::UnsupportedContext(
+                "`SyncBridge` requires `SharedArrayBuffer`, which is only available to cross-origin isolated \
+                 pages; serve them with `Cross-Origin-Opener-Policy: same-origin` and \
+                 `Cross-Origin-Embedder-Policy: require-corp`"
+                    .into(),
+            ));
+        }
+        let buffer = SharedArrayBuffer::new(HEADER_BYTES + capacity);
+        Ok(Self {
+            shared: ThreadBound::new(Shared::new(buffer)?),
+            closed: AtomicBool::new(false),
+        })
+    }
+
+    /// The shared buffer, to post to the worker which serves the bridge.
+    #[wasm_bindgen(getter)]
+    pub fn buffer(&self) -> Result<SharedArrayBuffer> {
+        Ok(self.shared.get()?.buffer.clone())
+    }
+
+    /// Close the backend in the worker, which then stops serving the bridge.
+    ///
+    /// Every request after this fails with `ClosedError`; closing again does nothing.
+    pub fn close(&self) -> Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let result = self.call(OP_CLOSE, 0.0, 0);
+        self.closed.store(true, Ordering::Release);
+        result?;
+        Ok(())
+    }
+}
+
+impl SyncBridge {
+    /// Post request `op` to the worker and block until it responds, returning the response's value and length.
+    ///
+    /// Fails with [`ErrorKind::NotConnected`] if the bridge was closed, or the worker stopped serving it.
+    fn call(&self, op: i32, number: f64, len: usize) -> IoResult<(f64, usize)> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(closed());
+        }
+        let shared = self.shared.get()?;
+        if shared.load(WORKER)? == STOPPED {
+            self.closed.store(true, Ordering::Release);
+            return Err(closed());
+        }
+        shared.store(OP, op)?;
+        shared.store(LEN, len as _)?;
+        shared.number.set_index(0, number);
+        shared.store(STATE, REQUEST)?;
+        Atomics::notify(&shared.header, STATE).map_err(Error::to_io)?;
+
+        // `Atomics.wait` throws on the main thread, where this is meant to be used, so spin instead
+        let posted = Date::now();
+        loop {
+            let state = shared.load(STATE)?;
+            if state == RESPONSE {
+                break;
+            }
+            // the worker stores the response before it stops, so look once more before giving up
+            let stopped = shared.load(WORKER)? == STOPPED && shared.load(STATE)? != RESPONSE;
+            let dead = state == REQUEST
+                && shared.load(WORKER)? == RUNNING
+                && Date::now() - posted > PICKUP_TIMEOUT_MS;
+            if stopped || dead {
+                self.closed.store(true, Ordering::Release);
+                return Err(closed());
+            }
+            std::hint::spin_loop();
+        }
+
+        let status = shared.load(STATUS)?;
+        let len = shared.load(LEN)? as usize;
+        let value = shared.number.get_index(0);
+        let result = match status {
+            OK => Ok((value, len)),
+            _ => {
+                let mut message = vec![0; len];
+                shared.data.subarray(0, len as _).copy_to(&mut message);
+                let message = String::from_utf8_lossy(&message);
+                let (name, message) = message.split_once('\n').unwrap_or(("Error", &message));
+                Err(Error::from_name(name, message.into()).into_inner())
+            }
+        };
+        shared.store(STATE, IDLE)?;
+        result
+    }
+}
+
+impl StorageBackend for SyncBridge {
+    fn len(&self) -> IoResult<u64> {
+        let (len, _) = self.call(OP_LEN, 0.0, 0)?;
+        Ok(len as _)
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let shared = self.shared.get()?;
+        let mut offset = offset;
+        for chunk in out.chunks_mut(shared.capacity()) {
+            self.call(OP_READ, offset as _, chunk.len())?;
+            shared.data.subarray(0, chunk.len() as _).copy_to(chunk);
+            offset += chunk.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.call(OP_SET_LEN, len as _, 0)?;
+        Ok(())
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        self.call(OP_SYNC_DATA, 0.0, 0)?;
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        let shared = self.shared.get()?;
+        let mut offset = offset;
+        for chunk in data.chunks(shared.capacity()) {
+            shared.data.subarray(0, chunk.len() as _).copy_from(chunk);
+            self.call(OP_WRITE, offset as _, chunk.len())?;
+            offset += chunk.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Open the file at `path`, then serve it to the [`SyncBridge`] which created `buffer` until the bridge is closed.
+///
+/// Call this from a dedicated worker. Once the file is open, the worker blocks between requests, so it can't handle
+/// any other events; if opening fails, every request fails with the same error.
+#[wasm_bindgen(js_name = serveSyncBridge)]
+pub async fn serve_sync_bridge(buffer: SharedArrayBuffer, path: String) -> Result<()> {
+    let shared = Shared::new(buffer)?;
+    let _stopped = Stopped(&shared);
+    let backend = OpfsBackend::new(&path).await;
+    shared.store(WORKER, RUNNING)?;
+
+    loop {
+        // returns immediately unless idle
+        Atomics::wait(&shared.header, STATE, IDLE)?;
+        if shared.load(STATE)? != REQUEST {
+            continue;
+        }
+        shared.store(STATE, SERVING)?;
+
+        let op = shared.load(OP)?;
+        let number = shared.number.get_index(0);
+        let len = shared.load(LEN)? as usize;
+        let result = match &backend {
+            Ok(backend) => serve(backend, &shared, op, number, len),
+            Err(err) => Err(Error::from_name(&err.name(), err.to_string())),
+        };
+
+        match result {
+            Ok((value, len)) => {
+                shared.number.set_index(0, value);
+                shared.store(LEN, len as _)?;
+                shared.store(STATUS, OK)?;
+            }
+            Err(err) => {
+                let message = format!("{}\n{err}", err.name());
+                let message = &message.as_bytes()[..message.len().min(shared.capacity())];
+                shared
+                    .data
+                    .subarray(0, message.len() as _)
+                    .copy_from(message);
+                shared.store(LEN, message.len() as _)?;
+                shared.store(STATUS, FAILED)?;
+            }
+        }
+        shared.store(STATE, RESPONSE)?;
+        Atomics::notify(&shared.header, STATE)?;
+
+        if op == OP_CLOSE {
+            return Ok(());
+        }
+    }
+}
+
+/// Marks the worker as stopped when dropped, however [`serve_sync_bridge`] returns, so the bridge stops waiting for it.
+struct Stopped<'a>(&'a Shared);
+
+impl Drop for Stopped<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.store(WORKER, STOPPED);
+    }
+}
+
+/// Perform request `op`, returning the response's value and length.
+fn serve(
+    backend: &OpfsBackend,
+    shared: &Shared,
+    op: i32,
+    number: f64,
+    len: usize,
+) -> Result<(f64, usize)> {
+    let data = || shared.data.subarray(0, len as _);
+    match op {
+        OP_LEN => Ok((backend.len()? as _, 0)),
+        OP_READ => {
+            let mut out = vec![0; len];
+            backend.read(number as _, &mut out)?;
+            data().copy_from(&out);
+            Ok((0.0, len))
+        }
+        OP_WRITE => {
+            backend.write(number as _, &data().to_vec())?;
+            Ok((0.0, 0))
+        }
+        OP_SET_LEN => {
+            backend.set_len(number as _)?;
+            Ok((0.0, 0))
+        }
+        OP_SYNC_DATA => {
+            backend.sync_data()?;
+            Ok((0.0, 0))
+        }
+        OP_CLOSE => {
+            backend.close()?;
+            Ok((0.0, 0))
+        }
+        op => {
+            Err(io::Error::new(ErrorKind::InvalidInput, format!("unknown operation {op}")).into())
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! How [`sync_data`][crate::StorageBackend::sync_data] persists changes; see [`OpfsBackendBuilder::sync_mode`].
+
+#[cfg(not(opfs))]
+use std::time::Instant;
+
+use parking_lot::Mutex;
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+use crate::IoResult;
+#[cfg(doc)]
+use crate::OpfsBackendBuilder;
+
+/// Interval between flushes in [`SyncMode::Deferred`], unless configured otherwise.
+pub(crate) const DEFAULT_FLUSH_INTERVAL_MS: u32 = 1000;
+
+/// How [`sync_data`][crate::StorageBackend::sync_data], which redb calls on every durable commit, persists changes.
+///
+/// In every mode, `sync_data` writes out buffered writes, so that other readers of the file see them, and
+/// [closing][crate::OpfsBackend::close] the backend flushes. The modes differ in whether `sync_data` also flushes
+/// the file, which makes changes survive a crash, but dominates commit latency on some devices.
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// Flush on every `sync_data`, so every commit survives a crash.
+    #[default]
+    Full,
+    /// Flush on `sync_data` only if the last flush is older than the
+    /// [flush interval][OpfsBackendBuilder::flush_interval], so a crash loses at most the commits made since.
+    ///
+    /// Flushes are not scheduled in the background: a commit is only flushed by a later `sync_data`, or by closing.
+    Deferred,
+    /// Never flush on `sync_data`, for throwaway databases whose content need not survive a crash.
+    Skip,
+}
+
+#[cfg(opfs)]
+type Stamp = f64;
+#[cfg(not(opfs))]
+type Stamp = Instant;
+
+/// When `sync_data` flushes, according to a [`SyncMode`].
+#[derive(Debug)]
+pub(crate) struct FlushSchedule {
+    mode: SyncMode,
+    interval_ms: u32,
+    /// When the file was last flushed, if ever.
+    last: Mutex<Option<Stamp>>,
+}
+
+impl FlushSchedule {
+    pub(crate) fn new(mode: SyncMode, interval_ms: u32) -> Self {
+        Self {
+            mode,
+            interval_ms,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Run `flush` if the mode calls for it.
+    pub(crate) fn run(&self, flush: impl FnOnce() -> IoResult<()>) -> IoResult<()> {
+        match self.mode {
+            SyncMode::Full => flush(),
+            SyncMode::Skip => Ok(()),
+            SyncMode::Deferred => {
+                let mut last = self.last.lock();
+                if last.is_some_and(|last| elapsed_ms(last) < f64::from(self.interval_ms)) {
+                    return Ok(());
+                }
+                flush()?;
+                *last = Some(now());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(opfs)]
+fn now() -> Stamp {
+    js_sys::Date::now()
+}
+
+#[cfg(not(opfs))]
+fn now() -> Stamp {
+    Instant::now()
+}
+
+#[cfg(opfs)]
+fn elapsed_ms(since: Stamp) -> f64 {
+    now() - since
+}
+
+#[cfg(not(opfs))]
+fn elapsed_ms(since: Stamp) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
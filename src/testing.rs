@@ -0,0 +1,393 @@
+//! Tools for testing storage backends, and how applications cope with their failures.
+//!
+//! [`FaultyBackend`] wraps any other backend and injects failures on demand, so that redb's recovery paths, and the
+//! application's handling of their errors, can be exercised deterministically on wasm and native alike.
+//!
+//! [`SimulatedOpfs`] mimics OPFS natively, with its size limits, flushing and handle exclusivity, so that code written
+//! against OPFS can be tested in plain `cargo test`. Its crashes may persist part of the unflushed writes, or tear one,
+//! to check that a database recovers from wherever a crash interrupts it.
+//!
+//! [`conformance`] checks that a backend implements the semantics redb relies on, so that every backend, whether in
+//! this crate or not, can be validated identically.
+
+use std::{
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, StorageBackend};
+
+mod simulated;
+
+pub use simulated::{Crash, MAX_SAFE_INTEGER, SimulatedHandle, SimulatedOpfs};
+
+/// A kind of [`StorageBackend`] operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Len,
+    Read,
+    Write,
+    SetLen,
+    SyncData,
+}
+
+/// A failure to inject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail without touching the inner backend.
+    Error(ErrorKind),
+    /// Fail with [`ErrorKind::QuotaExceeded`] without touching the inner backend, as when the origin runs out of
+    /// storage.
+    QuotaExceeded,
+    /// Transfer only the first `n` bytes of a read or write, then fail.
+    ///
+    /// A short read fills the start of the buffer and fails with [`ErrorKind::UnexpectedEof`]; a short write stores
+    /// a prefix of the data, leaving a torn write behind, and fails with [`ErrorKind::WriteZero`]. Other operations
+    /// fail as with [`Fault::Error`] of [`ErrorKind::Other`].
+    Short(usize),
+}
+
+/// When to inject a fault.
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    /// `None` matches every operation.
+    op: Option<Op>,
+    /// Number of matching operations to let through before failing.
+    skip: u64,
+    /// Whether to keep failing after the first failure.
+    sticky: bool,
+    fault: Fault,
+}
+
+impl Rule {
+    /// Whether this rule fails the operation, updating its state.
+    fn triggers(&mut self, op: Op) -> bool {
+        if self.op.is_some_and(|rule_op| rule_op != op) {
+            return false;
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    rules: Vec<Rule>,
+    operations: u64,
+    injected: u64,
+}
+
+impl State {
+    /// Count an operation, and return the fault to inject into it, if any.
+    fn fault(&mut self, op: Op) -> Option<Fault> {
+        self.operations += 1;
+        let index = self.rules.iter_mut().position(|rule| rule.triggers(op))?;
+        let rule = self.rules[index];
+        if !rule.sticky {
+            self.rules.remove(index);
+        }
+        self.injected += 1;
+        Some(rule.fault)
+    }
+}
+
+/// Shared configuration of the faults a [`FaultyBackend`] injects.
+///
+/// Clones refer to the same configuration, so faults can still be set up after the backend has been handed to redb.
+#[derive(Debug, Clone, Default)]
+pub struct Faults(Arc<Mutex<State>>);
+
+impl Faults {
+    /// Fail the `n`th operation from now which matches `op`, counting from 1, once.
+    ///
+    /// `None` matches every operation.
+    pub fn fail_nth(&self, op: impl Into<Option<Op>>, n: u64, fault: Fault) {
+        self.add_rule(op.into(), n, false, fault);
+    }
+
+    /// Fail the `n`th operation from now which matches `op`, counting from 1, and every matching operation after it.
+    ///
+    /// `None` matches every operation.
+    pub fn fail_from(&self, op: impl Into<Option<Op>>, n: u64, fault: Fault) {
+        self.add_rule(op.into(), n, true, fault);
+    }
+
+    /// Stop injecting faults, discarding any which have not triggered yet.
+    pub fn clear(&self) {
+        self.0.lock().rules.clear();
+    }
+
+    /// Number of operations performed so far, including failed ones.
+    ///
+    /// Running a scenario once without faults and reading this tells how many operations a fault can be injected into.
+    pub fn operations(&self) -> u64 {
+        self.0.lock().operations
+    }
+
+    /// Number of faults injected so far.
+    pub fn injected(&self) -> u64 {
+        self.0.lock().injected
+    }
+
+    fn add_rule(&self, op: Option<Op>, n: u64, sticky: bool, fault: Fault) {
+        self.0.lock().rules.push(Rule {
+            op,
+            skip: n.saturating_sub(1),
+            sticky,
+            fault,
+        });
+    }
+
+    fn fault(&self, op: Op) -> Option<Fault> {
+        self.0.lock().fault(op)
+    }
+}
+
+/// Implementation of a [`StorageBackend`] which delegates to an inner backend, failing operations on demand.
+///
+/// ```ignore
+/// let backend = FaultyBackend::new(MemoryBackend::new());
+/// let faults = backend.faults().clone();
+/// let database = redb::Database::builder().create_with_backend(backend)?;
+///
+/// // the third write from now stores half a page, then fails
+/// faults.fail_nth(Op::Write, 3, Fault::Short(2048));
+/// // every sync from now on fails as if the origin were out of storage
+/// faults.fail_from(Op::SyncData, 1, Fault::QuotaExceeded);
+/// ```
+#[derive(Debug)]
+pub struct FaultyBackend<B> {
+    inner: B,
+    faults: Faults,
+}
+
+impl<B> FaultyBackend<B> {
+    /// Wrap `inner`, initially without injecting any faults.
+    pub fn new(inner: B) -> Self {
+        Self::with_faults(inner, Faults::default())
+    }
+
+    /// Wrap `inner`, injecting the faults configured through `faults`.
+    pub fn with_faults(inner: B, faults: Faults) -> Self {
+        Self { inner, faults }
+    }
+
+    /// The configuration of the faults to inject.
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Access the inner backend, bypassing fault injection.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwrap the inner backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for FaultyBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        match self.faults.fault(Op::Len) {
+            Some(fault) => Err(injected(fault)),
+            None => self.inner.len(),
+        }
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        match self.faults.fault(Op::Read) {
+            Some(Fault::Short(n)) => {
+                let n = n.min(out.len());
+                self.inner.read(offset, &mut out[..n])?;
+                Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "injected short read",
+                ))
+            }
+            Some(fault) => Err(injected(fault)),
+            None => self.inner.read(offset, out),
+        }
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        match self.faults.fault(Op::SetLen) {
+            Some(fault) => Err(injected(fault)),
+            None => self.inner.set_len(len),
+        }
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        match self.faults.fault(Op::SyncData) {
+            Some(fault) => Err(injected(fault)),
+            None => self.inner.sync_data(),
+        }
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        match self.faults.fault(Op::Write) {
+            Some(Fault::Short(n)) => {
+                let n = n.min(data.len());
+                self.inner.write(offset, &data[..n])?;
+                Err(io::Error::new(ErrorKind::WriteZero, "injected short write"))
+            }
+            Some(fault) => Err(injected(fault)),
+            None => self.inner.write(offset, data),
+        }
+    }
+}
+
+/// The error reported for `fault`, when it doesn't touch the inner backend.
+fn injected(fault: Fault) -> io::Error {
+    match fault {
+        Fault::Error(kind) => io::Error::new(kind, "injected fault"),
+        Fault::QuotaExceeded => io::Error::new(ErrorKind::QuotaExceeded, "injected quota exceeded"),
+        Fault::Short(_) => io::Error::other("injected fault"),
+    }
+}
+
+/// Check that `backend` has the semantics of a plain file, as redb expects, panicking on the first violation.
+///
+/// This covers reads at and beyond the end of the file, writes which leave a gap or extend the file, truncation
+/// followed by extension, writes spanning many pages, offsets near `u64::MAX`, and persistence across
+/// [`sync_data`][StorageBackend::sync_data].
+///
+/// All existing content of `backend` is discarded. Afterwards it is left empty.
+///
+/// # Panics
+///
+/// Panics with a description of the violation if `backend` doesn't behave like a file, or if any operation which
+/// should succeed fails.
+pub fn conformance(backend: &impl StorageBackend) {
+    let check = |what: &str, result: IoResult<()>| {
+        if let Err(err) = result {
+            panic!("{what} failed: {err}");
+        }
+    };
+    let file_len = || {
+        backend
+            .len()
+            .unwrap_or_else(|err| panic!("len failed: {err}"))
+    };
+    let read = |offset: u64, len: usize| {
+        let mut out = vec![0xee; len];
+        check(
+            &format!("read of {len} bytes at {offset}"),
+            backend.read(offset, &mut out),
+        );
+        out
+    };
+    let expect_eof = |offset: u64, len: usize| {
+        let mut out = vec![0; len];
+        match backend.read(offset, &mut out) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {}
+            other => panic!(
+                "read of {len} bytes at {offset} past the end returned {other:?}, not UnexpectedEof"
+            ),
+        }
+    };
+
+    check("set_len(0)", backend.set_len(0));
+    assert_eq!(file_len(), 0, "len after set_len(0)");
+    assert!(read(0, 0).is_empty(), "empty read of an empty file");
+    expect_eof(0, 1);
+
+    // extending writes
+    let head = pattern(1, 100);
+    check("write at 0", backend.write(0, &head));
+    assert_eq!(file_len(), 100, "len after writing 100 bytes");
+    assert_eq!(read(0, 100), head, "content after writing at 0");
+    assert!(read(100, 0).is_empty(), "empty read at the end of the file");
+    assert_eq!(read(90, 10), head[90..], "read up to the end of the file");
+    expect_eof(90, 11);
+
+    // a write past the end leaves a zero-filled gap
+    check("write at 200", backend.write(200, b"abc"));
+    assert_eq!(file_len(), 203, "len after writing past the end");
+    assert_eq!(
+        read(100, 100),
+        vec![0; 100],
+        "gap left by a write past the end"
+    );
+    assert_eq!(read(200, 3), b"abc", "content written past the end");
+
+    // overwrites
+    check("overwrite at 50", backend.write(50, &pattern(2, 100)));
+    assert_eq!(file_len(), 203, "len after an overwrite within the file");
+    let mut expected = [&head[..50], &pattern(2, 100)[..], &[0; 50][..], &b"abc"[..]].concat();
+    assert_eq!(read(0, 203), expected, "content after an overwrite");
+
+    // interleaved truncation and extension
+    check("set_len(60)", backend.set_len(60));
+    assert_eq!(file_len(), 60, "len after shrinking");
+    expect_eof(50, 11);
+    expected.truncate(60);
+    assert_eq!(read(0, 60), expected, "content after shrinking");
+    check("set_len(150)", backend.set_len(150));
+    assert_eq!(file_len(), 150, "len after extending");
+    expected.resize(150, 0);
+    assert_eq!(
+        read(0, 150),
+        expected,
+        "content after extending; truncated data must not reappear"
+    );
+    check("write at 140", backend.write(140, &pattern(3, 20)));
+    assert_eq!(file_len(), 160, "len after a write across the end");
+    expected.truncate(140);
+    expected.extend(pattern(3, 20));
+    assert_eq!(
+        read(0, 160),
+        expected,
+        "content after a write across the end"
+    );
+
+    // writes spanning many pages, at unaligned offsets
+    let large = pattern(4, 200 * 1024 + 7);
+    let offset = 12345;
+    check("large write", backend.write(offset, &large));
+    assert_eq!(
+        file_len(),
+        offset + large.len() as u64,
+        "len after a large write"
+    );
+    assert_eq!(read(offset, large.len()), large, "content of a large write");
+    assert_eq!(
+        read(offset + 65530, 20),
+        large[65530..65550],
+        "read within a large write"
+    );
+    assert_eq!(read(0, 160), expected, "content before a large write");
+
+    // offsets near the limit fail without side effects
+    let len_before = file_len();
+    assert!(
+        backend.write(u64::MAX - 1, &[1, 2, 3, 4]).is_err(),
+        "write overflowing u64 succeeded"
+    );
+    assert_eq!(file_len(), len_before, "len after a failed write");
+    expect_eof(u64::MAX, 1);
+    expect_eof(u64::MAX - 1, 4);
+
+    // syncing keeps everything
+    check("sync_data", backend.sync_data());
+    assert_eq!(file_len(), len_before, "len after sync_data");
+    assert_eq!(read(offset, large.len()), large, "content after sync_data");
+    check("sync_data without changes", backend.sync_data());
+
+    check("set_len(0)", backend.set_len(0));
+    assert_eq!(file_len(), 0, "len after clearing");
+    expect_eof(0, 1);
+}
+
+/// `len` bytes which differ from those of other seeds and repeat with an odd period, so misplaced data is noticed.
+fn pattern(seed: u8, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i % 251) as u8 ^ seed.wrapping_mul(0x5b))
+        .collect()
+}
@@ -0,0 +1,299 @@
+//! A native stand-in for OPFS, with its limits and failure modes.
+
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{IoResult, StorageBackend};
+
+/// Largest size and offset OPFS accepts: sizes and offsets cross the JS boundary as `f64`.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// A change made since the last flush, which a [crash][SimulatedOpfs::crash_with] may or may not persist.
+#[derive(Debug)]
+enum Pending {
+    Write { offset: usize, data: Vec<u8> },
+    SetLen(usize),
+}
+
+impl Pending {
+    /// Apply this change to `data`, keeping only the first `keep` bytes of a write.
+    fn apply(&self, data: &mut Vec<u8>, keep: usize) {
+        match self {
+            Self::Write {
+                offset,
+                data: written,
+            } => {
+                let written = &written[..keep.min(written.len())];
+                let end = offset + written.len();
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[*offset..end].copy_from_slice(written);
+            }
+            Self::SetLen(len) => data.resize(*len, 0),
+        }
+    }
+}
+
+/// What a [crash][SimulatedOpfs::crash_with] persists of the changes made to each file since its last flush.
+///
+/// Browsers make no promise about which unflushed writes survive a crash, nor in which order they reach the disk.
+/// Crashing with every variant, at every point of a commit, checks that a database recovers from all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crash {
+    /// Persist nothing since the last flush.
+    LoseUnsynced,
+    /// Persist the first `n` changes since the last flush, in order, and lose the rest.
+    Prefix(usize),
+    /// Persist the first `applied` changes since the last flush, then only the first `keep` bytes of the next one if
+    /// it is a write, leaving a torn page behind, and lose the rest.
+    Torn { applied: usize, keep: usize },
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    data: Vec<u8>,
+    /// Content as of the last flush; what survives a [crash][SimulatedOpfs::crash].
+    durable: Vec<u8>,
+    /// Changes made since the last flush, in order.
+    pending: Vec<Pending>,
+    /// Identifies the open sync access handle, if any.
+    handle: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Namespace {
+    entries: HashMap<PathBuf, Entry>,
+    next_handle: u64,
+}
+
+/// An in-memory origin private file system, for running code written against OPFS in plain `cargo test`.
+///
+/// Files are opened as [`SimulatedHandle`]s, which implement [`StorageBackend`] with the semantics
+/// [`OpfsBackend`][crate::OpfsBackend] has on wasm, rather than those of the native file system:
+///
+/// - Sizes and offsets above [`MAX_SAFE_INTEGER`] are rejected, as they can't be represented exactly in JS.
+/// - Only one handle to a file can be open at a time; opening another fails with [`ErrorKind::PermissionDenied`],
+///   as OPFS does when a sync access handle already exists.
+/// - Data is only durable once flushed, by [`sync_data`][StorageBackend::sync_data] or by dropping the handle.
+///   [`crash`][Self::crash] discards everything else and invalidates all open handles; [`crash_with`][Self::crash_with]
+///   persists part of it, possibly tearing a write.
+///
+/// Clones refer to the same file system.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedOpfs(Arc<Mutex<Namespace>>);
+
+impl SimulatedOpfs {
+    /// Create an empty file system.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a sync access handle to the file at `path`, creating it if necessary.
+    pub fn open(&self, path: impl AsRef<Path>) -> IoResult<SimulatedHandle> {
+        let path = path.as_ref().to_owned();
+        let mut namespace = self.0.lock();
+        namespace.next_handle += 1;
+        let id = namespace.next_handle;
+        let entry = namespace.entries.entry(path.clone()).or_default();
+        if entry.handle.is_some() {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} already has an open sync access handle", path.display()),
+            ));
+        }
+        entry.handle = Some(id);
+        Ok(SimulatedHandle {
+            fs: self.clone(),
+            path,
+            id,
+        })
+    }
+
+    /// Whether a file exists at `path`.
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        self.0.lock().entries.contains_key(path.as_ref())
+    }
+
+    /// Remove the file at `path`.
+    ///
+    /// Like OPFS, this fails if the file doesn't exist, or if a sync access handle to it is open.
+    pub fn remove(&self, path: impl AsRef<Path>) -> IoResult<()> {
+        let path = path.as_ref();
+        let mut namespace = self.0.lock();
+        match namespace.entries.get(path) {
+            None => Err(ErrorKind::NotFound.into()),
+            Some(entry) if entry.handle.is_some() => Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} has an open sync access handle", path.display()),
+            )),
+            Some(_) => {
+                namespace.entries.remove(path);
+                Ok(())
+            }
+        }
+    }
+
+    /// Simulate the page being closed or the browser crashing.
+    ///
+    /// Every file reverts to its content as of its last flush, and every open handle is invalidated: operations on
+    /// it fail with [`ErrorKind::ResourceBusy`], as OPFS's `InvalidStateError` does.
+    pub fn crash(&self) {
+        self.crash_with(Crash::LoseUnsynced);
+    }
+
+    /// Simulate a crash which persists part of the changes made since the last flush, as described by `crash`.
+    ///
+    /// Each file reverts to its content as of its last flush, with the changes `crash` persists applied on top. Every
+    /// open handle is invalidated, as with [`crash`][Self::crash].
+    pub fn crash_with(&self, crash: Crash) {
+        let (applied, torn) = match crash {
+            Crash::LoseUnsynced => (0, None),
+            Crash::Prefix(n) => (n, None),
+            Crash::Torn { applied, keep } => (applied, Some(keep)),
+        };
+        for entry in self.0.lock().entries.values_mut() {
+            let mut data = std::mem::take(&mut entry.durable);
+            let pending = std::mem::take(&mut entry.pending);
+            for change in pending.iter().take(applied) {
+                change.apply(&mut data, usize::MAX);
+            }
+            if let (Some(keep), Some(change @ Pending::Write { .. })) = (torn, pending.get(applied))
+            {
+                change.apply(&mut data, keep);
+            }
+            entry.durable.clone_from(&data);
+            entry.data = data;
+            entry.handle = None;
+        }
+    }
+
+    /// Number of changes made to the file at `path` since its last flush, which [`crash_with`][Self::crash_with] can
+    /// persist part of; 0 if it doesn't exist.
+    pub fn pending_changes(&self, path: impl AsRef<Path>) -> usize {
+        self.0
+            .lock()
+            .entries
+            .get(path.as_ref())
+            .map_or(0, |entry| entry.pending.len())
+    }
+}
+
+/// A sync access handle to a file of a [`SimulatedOpfs`].
+///
+/// The handle is flushed and closed when dropped.
+#[derive(Debug)]
+pub struct SimulatedHandle {
+    fs: SimulatedOpfs,
+    path: PathBuf,
+    id: u64,
+}
+
+impl SimulatedHandle {
+    /// Run `f` against the file, failing if this handle was invalidated.
+    fn with_entry<T>(&self, f: impl FnOnce(&mut Entry) -> IoResult<T>) -> IoResult<T> {
+        let mut namespace = self.fs.0.lock();
+        match namespace.entries.get_mut(&self.path) {
+            Some(entry) if entry.handle == Some(self.id) => f(entry),
+            _ => Err(io::Error::new(
+                ErrorKind::ResourceBusy,
+                "sync access handle was invalidated",
+            )),
+        }
+    }
+}
+
+impl Drop for SimulatedHandle {
+    fn drop(&mut self) {
+        let _ = self.with_entry(|entry| {
+            entry.durable.clone_from(&entry.data);
+            entry.pending.clear();
+            entry.handle = None;
+            Ok(())
+        });
+    }
+}
+
+impl StorageBackend for SimulatedHandle {
+    fn len(&self) -> IoResult<u64> {
+        self.with_entry(|entry| Ok(entry.data.len() as _))
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        if len > MAX_SAFE_INTEGER {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("requested size {len} too large, max allowed is {MAX_SAFE_INTEGER}"),
+            ));
+        }
+        let len = usize::try_from(len).map_err(|_| too_large())?;
+        self.with_entry(|entry| {
+            entry.data.resize(len, 0);
+            entry.pending.push(Pending::SetLen(len));
+            Ok(())
+        })
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        self.with_entry(|entry| {
+            entry.durable.clone_from(&entry.data);
+            entry.pending.clear();
+            Ok(())
+        })
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        self.with_entry(|entry| {
+            let data = usize::try_from(offset)
+                .ok()
+                .and_then(|start| entry.data.get(start..start.checked_add(out.len())?))
+                .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+            out.copy_from_slice(data);
+            Ok(())
+        })
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        if offset > MAX_SAFE_INTEGER {
+            // the handle rejects `at` options which are not safe integers
+            return Err(io::Error::other(
+                "TypeError: write offset is outside the range of safe integers",
+            ));
+        }
+        let end = offset
+            .checked_add(data.len() as u64)
+            .filter(|&end| end <= MAX_SAFE_INTEGER)
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "write extends beyond max file size",
+                )
+            })?;
+        let start = usize::try_from(offset).map_err(|_| too_large())?;
+        let end = usize::try_from(end).map_err(|_| too_large())?;
+        self.with_entry(|entry| {
+            if entry.data.len() < end {
+                entry.data.resize(end, 0);
+            }
+            entry.data[start..end].copy_from_slice(data);
+            entry.pending.push(Pending::Write {
+                offset: start,
+                data: data.to_vec(),
+            });
+            Ok(())
+        })
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidInput,
+        "file would exceed addressable memory",
+    )
+}
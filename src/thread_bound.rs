@@ -0,0 +1,78 @@
+//! Values which may only be used on the thread which created them.
+//!
+//! JS objects live in the JS heap of one worker. Without wasm threads, that is the only thread there is. When built
+//! with `-C target-feature=+atomics`, several workers share the wasm memory, and a JS object reached from another
+//! worker's thread is a different object or none at all. [`ThreadBound`] checks which thread it is used on, so that
+//! the types holding it can be `Send` and `Sync` soundly either way.
+
+use std::{
+    io::{self, ErrorKind},
+    mem::ManuallyDrop,
+};
+
+/// A value which may only be used on the thread which created it.
+#[derive(Debug)]
+pub(crate) struct ThreadBound<T> {
+    value: ManuallyDrop<T>,
+    #[cfg(target_feature = "atomics")]
+    thread: std::thread::ThreadId,
+}
+
+// Safety: the value is only ever accessed, and dropped, on the thread which created it: with atomics, this is checked
+// at runtime; without, there is no other thread.
+unsafe impl<T> Send for ThreadBound<T> {}
+unsafe impl<T> Sync for ThreadBound<T> {}
+
+impl<T> ThreadBound<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            value: ManuallyDrop::new(value),
+            #[cfg(target_feature = "atomics")]
+            thread: std::thread::current().id(),
+        }
+    }
+
+    /// Whether this is the thread which created the value.
+    fn is_owner(&self) -> bool {
+        #[cfg(target_feature = "atomics")]
+        {
+            std::thread::current().id() == self.thread
+        }
+
+        #[cfg(not(target_feature = "atomics"))]
+        {
+            true
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if !self.is_owner() {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "JS objects can only be used on the thread which created them; \
+                 use the backend on the worker which opened it",
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get(&self) -> io::Result<&T> {
+        self.check()?;
+        Ok(&self.value)
+    }
+
+    pub(crate) fn get_mut(&mut self) -> io::Result<&mut T> {
+        self.check()?;
+        Ok(&mut self.value)
+    }
+}
+
+impl<T> Drop for ThreadBound<T> {
+    fn drop(&mut self) {
+        // Dropping JS objects elsewhere is unsound, so they leak instead.
+        if self.is_owner() {
+            // Safety: `value` is never used again.
+            unsafe { ManuallyDrop::drop(&mut self.value) };
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Measuring elapsed time on every target.
+
+use std::time::Duration;
+
+/// A monotonic timestamp.
+///
+/// `std::time::Instant` panics on `wasm32-unknown-unknown`, so the browser's `performance.now()` is used there.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timestamp(#[cfg(opfs)] f64, #[cfg(not(opfs))] std::time::Instant);
+
+impl Timestamp {
+    #[cfg(not(opfs))]
+    pub(crate) fn now() -> Self {
+        Self(std::time::Instant::now())
+    }
+
+    #[cfg(not(opfs))]
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+
+    #[cfg(opfs)]
+    pub(crate) fn now() -> Self {
+        use js_sys::{Function, Reflect};
+        use wasm_bindgen::{JsCast as _, JsValue};
+
+        thread_local! {
+            static PERFORMANCE: Option<(JsValue, Function)> = {
+                let performance = Reflect::get(&js_sys::global(), &"performance".into()).ok();
+                performance.and_then(|performance| {
+                    let now = Reflect::get(&performance, &"now".into()).ok()?.dyn_into().ok()?;
+                    Some((performance, now))
+                })
+            };
+        }
+
+        let millis = PERFORMANCE.with(|performance| {
+            performance
+                .as_ref()
+                .and_then(|(performance, now)| now.call0(performance).ok()?.as_f64())
+                .unwrap_or_else(js_sys::Date::now)
+        });
+        Self(millis)
+    }
+
+    #[cfg(opfs)]
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64((Self::now().0 - self.0).max(0.0) / 1000.0)
+    }
+}
@@ -0,0 +1,85 @@
+//! A [`StorageBackend`] wrapper which keeps blocking file I/O from stalling a tokio runtime.
+
+use std::io;
+
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::{IoResult, OpfsBackend, StorageBackend};
+
+/// Implementation of a [`StorageBackend`] which tells tokio before blocking on the wrapped backend.
+///
+/// redb's transactions are synchronous, and so is every call into a backend: natively, [`OpfsBackend`] reads and
+/// writes with blocking `std::fs` calls. Run from a task on a multi-threaded tokio runtime, each of them would stall
+/// the worker thread and every task queued on it. This wrapper runs each operation in
+/// [`block_in_place`][tokio::task::block_in_place], so that the runtime first hands those tasks to another worker.
+///
+/// Outside a runtime, and on a current-thread runtime, where there is no other worker, operations run directly.
+/// Where possible, running whole transactions with [`spawn_blocking`][tokio::task::spawn_blocking] is cheaper still,
+/// as it moves the work once rather than on every operation; this wrapper makes the occasional transaction on a
+/// worker thread harmless.
+#[derive(Debug)]
+pub struct TokioBackend<B = OpfsBackend> {
+    inner: B,
+}
+
+impl TokioBackend {
+    /// Open the file at the specified path on tokio's blocking thread pool, as [`OpfsBackend::new`] does.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn open(path: &str) -> IoResult<Self> {
+        let runtime = Handle::current();
+        let path = path.to_owned();
+        let backend =
+            tokio::task::spawn_blocking(move || runtime.block_on(OpfsBackend::new(&path)))
+                .await
+                .map_err(io::Error::other)??;
+        Ok(Self::new(backend))
+    }
+}
+
+impl<B: StorageBackend> TokioBackend<B> {
+    /// Wrap `inner`.
+    pub fn new(inner: B) -> Self {
+        Self { inner }
+    }
+
+    /// Get a reference to the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwrap this backend, returning the wrapped backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Run `op` against the wrapped backend, in `block_in_place` if on a multi-threaded runtime.
+    fn blocking<T>(&self, op: impl FnOnce(&B) -> IoResult<T>) -> IoResult<T> {
+        match Handle::try_current().map(|runtime| runtime.runtime_flavor()) {
+            Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(|| op(&self.inner)),
+            _ => op(&self.inner),
+        }
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for TokioBackend<B> {
+    fn len(&self) -> IoResult<u64> {
+        self.blocking(|inner| inner.len())
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        self.blocking(|inner| inner.set_len(len))
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        self.blocking(|inner| inner.sync_data())
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        self.blocking(|inner| inner.read(offset, out))
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        self.blocking(|inner| inner.write(offset, data))
+    }
+}
@@ -0,0 +1,138 @@
+//! Checking whether a database file is intact, as reported by [`verify`].
+
+use redb::{Database, StorageBackend};
+
+#[cfg(opfs)]
+use wasm_bindgen::prelude::*;
+
+use crate::{OpfsBackend, Result};
+
+/// The magic number at the start of every redb file.
+const MAGIC: [u8; 9] = [b'r', b'e', b'd', b'b', 0x1A, 0x0A, 0xA9, 0x0D, 0x0A];
+const GOD_BYTE_OFFSET: usize = MAGIC.len();
+/// Set in the god byte when the database was not shut down cleanly.
+const RECOVERY_REQUIRED: u8 = 0b10;
+const PAGE_SIZE_OFFSET: usize = GOD_BYTE_OFFSET + 3;
+const HEADER_PREFIX_LEN: usize = PAGE_SIZE_OFFSET + 4;
+
+/// The findings of [`verify`].
+#[cfg_attr(opfs, wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub(crate) len: u64,
+    pub(crate) is_redb: bool,
+    pub(crate) page_size: Option<u32>,
+    pub(crate) recovery_required: bool,
+    pub(crate) integrity: Option<bool>,
+    pub(crate) error: Option<String>,
+}
+
+#[cfg_attr(opfs, wasm_bindgen)]
+impl VerifyReport {
+    /// Size of the file in bytes.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the file is empty, as a database which was created but never written is.
+    #[cfg_attr(opfs, wasm_bindgen(getter = isEmpty))]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the file starts with redb's magic number.
+    #[cfg_attr(opfs, wasm_bindgen(getter = isRedb))]
+    pub fn is_redb(&self) -> bool {
+        self.is_redb
+    }
+
+    /// The page size recorded in the header, if it is a power of two no smaller than 512 bytes, as redb's are.
+    #[cfg_attr(opfs, wasm_bindgen(getter = pageSize))]
+    pub fn page_size(&self) -> Option<u32> {
+        self.page_size
+    }
+
+    /// Whether the database was not shut down cleanly, so that redb will repair it when it is next opened.
+    ///
+    /// This is expected after a crash or a closed tab, and is no cause for concern by itself.
+    #[cfg_attr(opfs, wasm_bindgen(getter = recoveryRequired))]
+    pub fn recovery_required(&self) -> bool {
+        self.recovery_required
+    }
+
+    /// The result of redb's integrity check: `true` if no corruption was found.
+    ///
+    /// `None` if the check was not requested, or could not run, as when the header is invalid or opening the
+    /// database failed.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn integrity(&self) -> Option<bool> {
+        self.integrity
+    }
+
+    /// The error which prevented redb from opening or checking the database, if any.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+
+    /// Whether the database can be expected to open, and to hold its data.
+    ///
+    /// Holds for an empty file, which redb initializes on open, and for a file with a valid header which passed
+    /// redb's integrity check, if it was run.
+    #[cfg_attr(opfs, wasm_bindgen(getter))]
+    pub fn salvageable(&self) -> bool {
+        self.is_empty()
+            || (self.is_redb
+                && self.page_size.is_some()
+                && self.error.is_none()
+                && self.integrity != Some(false))
+    }
+}
+
+/// Check whether the database at `path` is intact.
+///
+/// This reads the file's header, which is cheap, and fails only if the file can't be opened, for example because it
+/// does not exist or is in use. A damaged database is reported, not an error.
+///
+/// If `check_integrity` is set, and the header is valid, the database is also opened with redb, and redb's integrity
+/// check is run, which reads the whole database. Note that redb repairs what it can while doing so, so the file may
+/// be modified, and the report describes the database as it was found.
+#[cfg_attr(opfs, wasm_bindgen)]
+pub async fn verify(path: &str, check_integrity: bool) -> Result<VerifyReport> {
+    let backend = OpfsBackend::builder().create(false).open(path).await?;
+    let len = backend.len()?;
+    let mut header = [0; HEADER_PREFIX_LEN];
+    let prefix = &mut header[..len.min(HEADER_PREFIX_LEN as u64) as usize];
+    backend.read(0, prefix)?;
+
+    let is_redb = len >= HEADER_PREFIX_LEN as u64 && header[..MAGIC.len()] == MAGIC;
+    let page_size = is_redb
+        .then(|| {
+            u32::from_le_bytes(
+                header[PAGE_SIZE_OFFSET..PAGE_SIZE_OFFSET + 4]
+                    .try_into()
+                    .expect("len 4"),
+            )
+        })
+        .filter(|page_size| page_size.is_power_of_two() && *page_size >= 512);
+    let mut report = VerifyReport {
+        len,
+        is_redb,
+        page_size,
+        recovery_required: is_redb && header[GOD_BYTE_OFFSET] & RECOVERY_REQUIRED != 0,
+        integrity: None,
+        error: None,
+    };
+
+    if check_integrity && page_size.is_some() {
+        let checked = Database::builder()
+            .create_with_backend(backend)
+            .and_then(|mut database| database.check_integrity());
+        match checked {
+            Ok(intact) => report.integrity = Some(intact),
+            Err(err) => report.error = Some(err.to_string()),
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,214 @@
+//! A public file layer, so that embedders can run the backend on file plumbing of their own.
+
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+};
+
+use parking_lot::{Mutex, RwLock};
+
+#[cfg(not(opfs))]
+use crate::file_abstraction::FileAbstraction;
+use crate::{
+    IoResult, StorageBackend, closed,
+    file_abstraction::{read_exact_with, write_all_with},
+    page_cache::PageCache,
+};
+
+/// The file operations a [`VfsBackend`] needs.
+///
+/// Implement this for environments [`OpfsBackend`][crate::OpfsBackend] does not support, such as wasm hosts without
+/// `web-sys`, or sandboxes which hand out file descriptors of their own. Operations are synchronous, as redb is.
+///
+/// [`StdVfs`] implements it natively, on top of [`std::fs`].
+pub trait Vfs: fmt::Debug + Send + Sync + 'static {
+    /// An open file.
+    type File: fmt::Debug + Send + Sync + 'static;
+
+    /// Open the file at `path` read+write, creating it if it does not exist and `create` is set.
+    fn open(&self, path: &str, create: bool) -> IoResult<Self::File>;
+
+    /// Read into `buf` from `offset`, returning the number of bytes read, which is 0 at the end of the file.
+    ///
+    /// Reads may run concurrently with each other, but never with writes, truncations or flushes.
+    fn read_at(&self, file: &Self::File, buf: &mut [u8], offset: u64) -> IoResult<usize>;
+
+    /// Write from `buf` at `offset`, returning the number of bytes written.
+    ///
+    /// Writing beyond the end of the file extends it, with zeros in any gap.
+    fn write_at(&self, file: &Self::File, buf: &[u8], offset: u64) -> IoResult<usize>;
+
+    /// Get the length of `file` in bytes.
+    fn len(&self, file: &Self::File) -> IoResult<u64>;
+
+    /// Truncate or extend `file` to `len` bytes, with zeros.
+    fn set_len(&self, file: &Self::File, len: u64) -> IoResult<()>;
+
+    /// Make everything written to `file` so far durable.
+    fn flush(&self, file: &Self::File) -> IoResult<()>;
+}
+
+/// Implementation of a [`Vfs`] on the local file system, through [`std::fs`].
+#[cfg(not(opfs))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdVfs;
+
+#[cfg(not(opfs))]
+impl Vfs for StdVfs {
+    type File = std::fs::File;
+
+    fn open(&self, path: &str, create: bool) -> IoResult<Self::File> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(create)
+            .truncate(false)
+            .open(path)
+    }
+
+    fn read_at(&self, file: &Self::File, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+        FileAbstraction::read_at(file, buf, offset)
+    }
+
+    fn write_at(&self, file: &Self::File, buf: &[u8], offset: u64) -> IoResult<usize> {
+        FileAbstraction::write_at(file, buf, offset)
+    }
+
+    fn len(&self, file: &Self::File) -> IoResult<u64> {
+        FileAbstraction::len(file)
+    }
+
+    fn set_len(&self, file: &Self::File, len: u64) -> IoResult<()> {
+        file.set_len(len)
+    }
+
+    fn flush(&self, file: &Self::File) -> IoResult<()> {
+        file.sync_data()
+    }
+}
+
+/// Implementation of a [`StorageBackend`] on top of a [`Vfs`].
+///
+/// Reads run concurrently, everything else runs alone, and an optional [page cache][Self::cache_pages] spares reads a
+/// call into the file layer. Beyond that, this is a plain adapter: unlike [`OpfsBackend`][crate::OpfsBackend], it
+/// doesn't lock the file against other openers, retry failed operations, buffer writes, or record metrics, so the
+/// [`Vfs`] must provide whatever of that the environment needs. `OpfsBackend` itself stays specific to OPFS, as types
+/// exported to JS can't be generic.
+///
+/// ```rust
+/// # #[cfg(all(not(target_family = "wasm"), feature = "redb3"))]
+/// # fn main() -> std::io::Result<()> {
+/// use redb_opfs::{StdVfs, VfsBackend};
+///
+/// # let path = std::env::temp_dir().join("redb-opfs-vfs-doctest.redb");
+/// # let path = path.to_str().unwrap();
+/// let backend = VfsBackend::open(StdVfs, path)?.cache_pages(256);
+/// let database = redb::Database::builder()
+///     .create_with_backend(backend)
+///     .map_err(std::io::Error::other)?;
+/// # drop(database);
+/// # std::fs::remove_file(path)
+/// # }
+/// # #[cfg(any(target_family = "wasm", not(feature = "redb3")))]
+/// # fn main() {}
+/// ```
+pub struct VfsBackend<V: Vfs> {
+    vfs: V,
+    /// `None` once the backend has been [closed][Self::close].
+    file: RwLock<Option<V::File>>,
+    /// Only invalidated while the file is locked exclusively.
+    cache: Option<Mutex<PageCache>>,
+}
+
+impl<V: Vfs> fmt::Debug for VfsBackend<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VfsBackend")
+            .field("vfs", &self.vfs)
+            .field("file", &self.file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V: Vfs> VfsBackend<V> {
+    /// Open the file at `path` through `vfs`, creating it if it does not exist.
+    pub fn open(vfs: V, path: &str) -> IoResult<Self> {
+        let file = vfs.open(path, true)?;
+        Ok(Self {
+            vfs,
+            file: RwLock::new(Some(file)),
+            cache: None,
+        })
+    }
+
+    /// Keep up to `pages` 4 KiB pages of the file in memory; see
+    /// [`OpfsBackendBuilder::cache_pages`][crate::OpfsBackendBuilder::cache_pages].
+    pub fn cache_pages(mut self, pages: usize) -> Self {
+        self.cache = (pages > 0).then(|| Mutex::new(PageCache::new(pages, 0)));
+        self
+    }
+
+    /// Get a reference to the file layer.
+    pub fn vfs(&self) -> &V {
+        &self.vfs
+    }
+
+    /// Flush and release the file, after which every operation fails with [`ErrorKind::NotConnected`].
+    ///
+    /// Closing an already closed backend does nothing.
+    pub fn close(&self) -> IoResult<()> {
+        let Some(file) = self.file.write().take() else {
+            return Ok(());
+        };
+        self.vfs.flush(&file)
+    }
+}
+
+impl<V: Vfs> StorageBackend for VfsBackend<V> {
+    fn len(&self) -> IoResult<u64> {
+        let file = self.file.read();
+        self.vfs.len(file.as_ref().ok_or_else(closed)?)
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> IoResult<()> {
+        let file = self.file.read();
+        let file = file.as_ref().ok_or_else(closed)?;
+        let read_at = |buf: &mut [u8], offset| self.vfs.read_at(file, buf, offset);
+        match &self.cache {
+            Some(cache) => cache.lock().read(read_at, offset, out),
+            None => read_exact_with(read_at, out, offset),
+        }
+    }
+
+    fn set_len(&self, len: u64) -> IoResult<()> {
+        let file = self.file.write();
+        let file = file.as_ref().ok_or_else(closed)?;
+        if let Some(cache) = &self.cache {
+            cache.lock().invalidate_set_len(len);
+        }
+        self.vfs.set_len(file, len)
+    }
+
+    fn sync_data(&self) -> IoResult<()> {
+        let file = self.file.write();
+        self.vfs.flush(file.as_ref().ok_or_else(closed)?)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> IoResult<()> {
+        if offset.checked_add(data.len() as u64).is_none() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "write extends beyond max file size",
+            ));
+        }
+        let file = self.file.write();
+        let file = file.as_ref().ok_or_else(closed)?;
+        if let Some(cache) = &self.cache {
+            cache.lock().invalidate_write(offset, data.len());
+        }
+        write_all_with(
+            |buf, offset| self.vfs.write_at(file, buf, offset),
+            data,
+            offset,
+        )
+    }
+}
@@ -0,0 +1,111 @@
+//! Exclusion across tabs and workers via the [Web Locks API].
+//!
+//! A sync access handle is exclusive, but failing to acquire one produces an opaque `NoModificationAllowedError`,
+//! and a handle released by one context is raced for by all others. Holding a Web Lock named after the file for
+//! the lifetime of the backend gives deterministic single-writer semantics instead.
+//!
+//! [Web Locks API]: https://developer.mozilla.org/en-US/docs/Web/API/Web_Locks_API
+
+use std::io::{self, ErrorKind};
+
+use js_sys::{Function, Object, Promise, Reflect};
+use wasm_bindgen::{JsCast as _, JsValue, closure::Closure};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AbortController, DomException};
+
+use crate::{Error, Result};
+
+/// A held Web Lock, released when dropped.
+#[derive(Debug)]
+pub(crate) struct WebLock {
+    /// Resolves the promise whose settlement releases the lock.
+    release: Function,
+}
+
+impl WebLock {
+    /// Acquire the lock `name`, in shared or exclusive mode.
+    ///
+    /// If the lock is held elsewhere, this waits for up to `timeout_ms`, or not at all if `None`,
+    /// before failing with [`ErrorKind::ResourceBusy`].
+    pub(crate) async fn acquire(name: &str, shared: bool, timeout_ms: Option<u32>) -> Result<Self> {
+        let global = js_sys::global();
+        let locks = Reflect::get(
+            &Reflect::get(&global, &"navigator".into())?,
+            &"locks".into(),
+        )?;
+        if locks.is_undefined() {
+            return Err(
+                io::Error::new(ErrorKind::Unsupported, "the Web Locks API is unavailable").into(),
+            );
+        }
+        let request = Reflect::get(&locks, &"request".into())?.dyn_into::<Function>()?;
+
+        let options = Object::new();
+        let mode = if shared { "shared" } else { "exclusive" };
+        Reflect::set(&options, &"mode".into(), &mode.into())?;
+        match timeout_ms {
+            None => {
+                Reflect::set(&options, &"ifAvailable".into(), &true.into())?;
+            }
+            Some(timeout_ms) => {
+                let controller = AbortController::new()?;
+                Reflect::set(&options, &"signal".into(), &controller.signal())?;
+                // aborting after the lock has been granted has no effect
+                let abort = Closure::once_into_js(move || controller.abort());
+                Reflect::get(&global, &"setTimeout".into())?
+                    .dyn_into::<Function>()?
+                    .call2(&global, &abort, &timeout_ms.into())?;
+            }
+        }
+
+        // The lock is held until the promise returned by the callback settles, which happens on `release`.
+        let mut release = None;
+        let held = Promise::new(&mut |resolve, _reject| release = Some(resolve));
+        let release = release.expect("promise executor runs synchronously");
+
+        let mut settle = None;
+        let granted = Promise::new(&mut |resolve, reject| settle = Some((resolve, reject)));
+        let (grant, fail) = settle.expect("promise executor runs synchronously");
+
+        // With `ifAvailable`, the callback receives `null` if the lock is held elsewhere.
+        let callback = Closure::once_into_js(move |lock: JsValue| -> Promise {
+            let _ = grant.call1(&JsValue::NULL, &lock);
+            if lock.is_null() {
+                Promise::resolve(&JsValue::UNDEFINED)
+            } else {
+                held
+            }
+        });
+        let requested = request.call3(&locks, &name.into(), &options, &callback)?;
+        // If the request is aborted before the lock is granted, it rejects without invoking the callback.
+        Reflect::get(&requested, &"catch".into())?
+            .dyn_into::<Function>()?
+            .call1(&requested, &fail)?;
+
+        let lock = JsFuture::from(granted).await.map_err(|err| {
+            match err.dyn_ref::<DomException>().map(DomException::name) {
+                Some(error_name) if error_name == "AbortError" => io::Error::new(
+                    ErrorKind::ResourceBusy,
+                    format!("timed out waiting for lock {name}"),
+                )
+                .into(),
+                _ => Error::from(err),
+            }
+        })?;
+        if lock.is_null() {
+            return Err(io::Error::new(
+                ErrorKind::ResourceBusy,
+                format!("lock {name} is held elsewhere"),
+            )
+            .into());
+        }
+
+        Ok(Self { release })
+    }
+}
+
+impl Drop for WebLock {
+    fn drop(&mut self) {
+        let _ = self.release.call0(&JsValue::NULL);
+    }
+}
@@ -0,0 +1,333 @@
+//! Using an [`OpfsBackend`] from the main thread, by proxying to a dedicated worker which owns it.
+//!
+//! The worker runs a script which initializes the wasm module and calls [`serve_worker`]; `worker-shim.js` at the
+//! root of this repository is such a script. On the main thread, [`WorkerHandle`] posts each operation to the worker
+//! and resolves once the worker responds. A worker handles messages one at a time, in the order they were posted, so
+//! operations take effect in the order they were called.
+//!
+//! Requests are plain objects `{ id, op, path, offset, len, data }`, with only the members `op` needs. Responses are
+//! `{ id, value }`, or `{ id, error: { name, message } }`. Once it is ready, the worker posts a response with id 0.
+//! Buffers are transferred rather than copied in both directions.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    DedicatedWorkerGlobalScope, ErrorEvent, MessageEvent, Worker, WorkerOptions, WorkerType,
+};
+
+use crate::{Error, OpfsBackend, Result};
+
+/// The id of the response announcing that the worker is ready.
+const READY: u32 = 0;
+
+thread_local! {
+    /// The backend served by this worker.
+    static BACKEND: RefCell<Option<OpfsBackend>> = const { RefCell::new(None) };
+}
+
+/// Serve requests from a [`WorkerHandle`] on the main thread.
+///
+/// Call this once from the worker's script, after initializing the wasm module.
+#[wasm_bindgen(js_name = serveWorker)]
+pub fn serve_worker() -> Result<()> {
+    let scope = js_sys::global()
+        .dyn_into::<DedicatedWorkerGlobalScope>()
+        .map_err(|_| {
+            Error::UnsupportedContext("`serveWorker` must run in a dedicated worker".into())
+        })?;
+
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(|event: MessageEvent| {
+        let request = event.data();
+        let id = member(&request, "id").as_f64().unwrap_or_default() as u32;
+        let op = member(&request, "op").as_string().unwrap_or_default();
+        // opening is the only operation which doesn't complete synchronously
+        if op == "open" {
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = open(&request).await;
+                respond(id, result);
+            });
+        } else {
+            respond(id, handle(&op, &request));
+        }
+        if op == "close" {
+            // after responding, so that the response is delivered
+            if let Ok(scope) = js_sys::global().dyn_into::<DedicatedWorkerGlobalScope>() {
+                scope.close();
+            }
+        }
+    });
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    // the handler lives as long as the worker
+    on_message.forget();
+
+    respond(READY, Ok((JsValue::UNDEFINED, None)));
+    Ok(())
+}
+
+/// A response value, and the buffer to transfer along with it.
+type Response = (JsValue, Option<JsValue>);
+
+async fn open(request: &JsValue) -> Result<Response> {
+    let path = member(request, "path").as_string().unwrap_or_default();
+    let backend = OpfsBackend::new(&path).await?;
+    BACKEND.with_borrow_mut(|slot| *slot = Some(backend));
+    Ok((JsValue::UNDEFINED, None))
+}
+
+fn handle(op: &str, request: &JsValue) -> Result<Response> {
+    let offset = || member(request, "offset").as_f64().unwrap_or_default() as u64;
+    if op == "close" {
+        let backend = BACKEND.with_borrow_mut(Option::take);
+        backend.as_ref().map_or(Ok(()), OpfsBackend::close)?;
+        return Ok((JsValue::UNDEFINED, None));
+    }
+
+    BACKEND.with_borrow(|backend| {
+        let Some(backend) = backend else {
+            return Err(Error::Closed);
+        };
+        match op {
+            "len" => Ok(((backend.len()? as f64).into(), None)),
+            "read" => {
+                let len = member(request, "len").as_f64().unwrap_or_default() as usize;
+                let mut out = vec![0; len];
+                backend.read(offset(), &mut out)?;
+                let out = Uint8Array::from(out.as_slice());
+                Ok((out.clone().into(), Some(out.buffer().into())))
+            }
+            "write" => {
+                let data = Uint8Array::new(&member(request, "data")).to_vec();
+                backend.write(offset(), &data)?;
+                Ok((JsValue::UNDEFINED, None))
+            }
+            "setLen" => {
+                let len = member(request, "len").as_f64().unwrap_or_default() as u64;
+                backend.set_len(len)?;
+                Ok((JsValue::UNDEFINED, None))
+            }
+            "syncData" => {
+                backend.sync_data()?;
+                Ok((JsValue::UNDEFINED, None))
+            }
+            op => Err(Error::Js {
+                name: "TypeError".into(),
+                message: format!("unknown operation {op:?}"),
+            }),
+        }
+    })
+}
+
+/// Post the response to request `id` back to the main thread.
+fn respond(id: u32, result: Result<Response>) {
+    let response = Object::new();
+    set(&response, "id", &id.into());
+    let transfer = Array::new();
+    match result {
+        Ok((value, buffer)) => {
+            set(&response, "value", &value);
+            if let Some(buffer) = buffer {
+                transfer.push(&buffer);
+            }
+        }
+        Err(err) => {
+            let error = Object::new();
+            set(&error, "name", &err.name().into());
+            set(&error, "message", &err.to_string().into());
+            set(&response, "error", &error);
+        }
+    }
+    let scope = js_sys::global().unchecked_into::<DedicatedWorkerGlobalScope>();
+    // posting a plain object can't fail
+    let _ = scope.post_message_with_transfer(&response, &transfer);
+}
+
+/// A proxy on the main thread for an [`OpfsBackend`] owned by a dedicated worker.
+///
+/// Every method is asynchronous, and resolves once the worker has completed the operation. The worker is shut down
+/// when the handle is closed or dropped.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct WorkerHandle {
+    worker: Worker,
+    /// Resolve and reject functions of the requests awaiting a response, by id.
+    pending: Rc<RefCell<HashMap<u32, (Function, Function)>>>,
+    next_id: Cell<u32>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_error: Closure<dyn FnMut(ErrorEvent)>,
+}
+
+#[wasm_bindgen]
+impl WorkerHandle {
+    /// Spawn a module worker running the script at `script_url`, and open the file at `path` in it.
+    ///
+    /// The script must call `serveWorker()` once the wasm module is initialized.
+    pub async fn spawn(script_url: &str, path: &str) -> Result<WorkerHandle> {
+        let options = WorkerOptions::new();
+        options.set_type(WorkerType::Module);
+        let worker = Worker::new_with_options(script_url, &options)?;
+        Self::attach(worker, path).await
+    }
+
+    /// Take ownership of `worker`, which was just created from a script calling `serveWorker()`, and open the file
+    /// at `path` in it.
+    ///
+    /// This suits bundlers, which only recognize worker scripts in `new Worker(new URL(...))` expressions.
+    pub async fn attach(worker: Worker, path: &str) -> Result<WorkerHandle> {
+        let pending = Rc::new(RefCell::new(HashMap::<u32, (Function, Function)>::new()));
+
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new({
+            let pending = Rc::clone(&pending);
+            move |event: MessageEvent| {
+                let response = event.data();
+                let id = member(&response, "id").as_f64().unwrap_or_default() as u32;
+                let Some((resolve, reject)) = pending.borrow_mut().remove(&id) else {
+                    return;
+                };
+                let error = member(&response, "error");
+                let _ = match error.is_undefined() {
+                    true => resolve.call1(&JsValue::NULL, &member(&response, "value")),
+                    false => reject.call1(&JsValue::NULL, &error),
+                };
+            }
+        });
+        worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        // if the script fails to load or throws, no response will ever arrive
+        let on_error = Closure::<dyn FnMut(ErrorEvent)>::new({
+            let pending = Rc::clone(&pending);
+            move |event: ErrorEvent| {
+                let error = Object::new();
+                set(&error, "name", &"WorkerError".into());
+                set(&error, "message", &event.message().into());
+                for (_, (_, reject)) in pending.borrow_mut().drain() {
+                    let _ = reject.call1(&JsValue::NULL, &error);
+                }
+            }
+        });
+        worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+        let handle = Self {
+            worker,
+            pending,
+            next_id: Cell::new(READY + 1),
+            _on_message: on_message,
+            _on_error: on_error,
+        };
+        handle.response(READY).await?;
+
+        let request = Object::new();
+        set(&request, "path", &path.into());
+        handle.request("open", request, None).await?;
+        Ok(handle)
+    }
+
+    /// Returns the size of the file, in bytes.
+    pub async fn len(&self) -> Result<u64> {
+        let len = self.request("len", Object::new(), None).await?;
+        Ok(len.as_f64().unwrap_or_default() as _)
+    }
+
+    /// Reads `len` bytes from the file at the given offset.
+    pub async fn read(&self, offset: u64, len: u32) -> Result<Vec<u8>> {
+        let request = Object::new();
+        set(&request, "offset", &(offset as f64).into());
+        set(&request, "len", &len.into());
+        let data = self.request("read", request, None).await?;
+        Ok(Uint8Array::new(&data).to_vec())
+    }
+
+    /// Writes some bytes to the file at the given offset.
+    pub async fn write(&self, offset: u64, data: &[u8]) -> Result<()> {
+        let data = Uint8Array::from(data);
+        let request = Object::new();
+        set(&request, "offset", &(offset as f64).into());
+        set(&request, "data", &data);
+        self.request("write", request, Some(data.buffer().into()))
+            .await?;
+        Ok(())
+    }
+
+    /// Truncates or extends the file to `len` bytes.
+    #[wasm_bindgen(js_name = setLen)]
+    pub async fn set_len(&self, len: u64) -> Result<()> {
+        let request = Object::new();
+        set(&request, "len", &(len as f64).into());
+        self.request("setLen", request, None).await?;
+        Ok(())
+    }
+
+    /// Attempts to sync all file content to disk.
+    #[wasm_bindgen(js_name = syncData)]
+    pub async fn sync_data(&self) -> Result<()> {
+        self.request("syncData", Object::new(), None).await?;
+        Ok(())
+    }
+
+    /// Close the backend, then shut down the worker.
+    pub async fn close(&self) -> Result<()> {
+        self.request("close", Object::new(), None).await?;
+        Ok(())
+    }
+}
+
+impl WorkerHandle {
+    /// Post the request `op` to the worker, and wait for its response.
+    async fn request(
+        &self,
+        op: &str,
+        request: Object,
+        transfer: Option<JsValue>,
+    ) -> Result<JsValue> {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1).max(READY + 1));
+        set(&request, "id", &id.into());
+        set(&request, "op", &op.into());
+        let response = self.response(id);
+        let transfer = transfer.into_iter().collect::<Array>();
+        self.worker
+            .post_message_with_transfer(&request, &transfer)?;
+        response.await
+    }
+
+    /// Wait for the response to request `id`.
+    fn response(&self, id: u32) -> impl Future<Output = Result<JsValue>> + use<> {
+        let mut settle = None;
+        let promise = Promise::new(&mut |resolve, reject| settle = Some((resolve, reject)));
+        let settle = settle.expect("promise executor runs synchronously");
+        self.pending.borrow_mut().insert(id, settle);
+        async move {
+            JsFuture::from(promise).await.map_err(|error| {
+                let name = member(&error, "name").as_string().unwrap_or_default();
+                let message = member(&error, "message").as_string().unwrap_or_default();
+                Error::from_name(&name, message)
+            })
+        }
+    }
+}
+
+impl Drop for WorkerHandle {
+    fn drop(&mut self) {
+        // closing lets the worker flush and release the file; it shuts down once done
+        let request = Object::new();
+        set(&request, "id", &self.next_id.get().into());
+        set(&request, "op", &"close".into());
+        let _ = self.worker.post_message(&request);
+        self.worker.set_onmessage(None);
+        self.worker.set_onerror(None);
+    }
+}
+
+fn member(object: &JsValue, name: &str) -> JsValue {
+    Reflect::get(object, &name.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+fn set(object: &Object, name: &str, value: &JsValue) {
+    Reflect::set(object, &name.into(), value).expect("setting on a plain object");
+}
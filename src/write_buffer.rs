@@ -0,0 +1,120 @@
+//! A buffer which coalesces writes until they are written out in bulk.
+//!
+//! redb issues many small writes per commit, each of which would otherwise cross into OPFS separately.
+//! Buffered writes which touch or overlap are merged into a single extent; on
+//! [`sync_data`][crate::StorageBackend::sync_data], extents are written out in offset order before the file is flushed.
+
+use std::collections::BTreeMap;
+
+/// Non-overlapping, non-adjacent extents of data which have yet to be written to the file.
+#[derive(Debug)]
+pub(crate) struct WriteBuffer {
+    /// Buffered data by offset.
+    extents: BTreeMap<u64, Vec<u8>>,
+    /// Total bytes held in `extents`.
+    bytes: usize,
+    /// Number of buffered bytes above which the buffer should be written out.
+    capacity: usize,
+}
+
+impl WriteBuffer {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            extents: BTreeMap::new(),
+            bytes: 0,
+            capacity,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.extents.is_empty()
+    }
+
+    /// Whether the buffer holds more than its capacity, and should be written out.
+    pub(crate) fn is_full(&self) -> bool {
+        self.bytes > self.capacity
+    }
+
+    /// End of the last buffered extent, or 0 if the buffer is empty.
+    pub(crate) fn end(&self) -> u64 {
+        self.extents
+            .last_key_value()
+            .map(|(&offset, data)| offset + data.len() as u64)
+            .unwrap_or_default()
+    }
+
+    /// Buffer `data` to be written at `offset`, merging it with any extents it touches.
+    pub(crate) fn insert(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        // extents are disjoint and sorted, so those touching `offset..end` are the last ones starting at or before `end`
+        let touching = self
+            .extents
+            .range(..=end)
+            .rev()
+            .take_while(|&(&start, extent)| start + extent.len() as u64 >= offset)
+            .map(|(&start, _)| start)
+            .collect::<Vec<_>>();
+
+        let merged_start = touching.last().copied().unwrap_or(offset).min(offset);
+        let mut merged = Vec::new();
+        for start in touching.into_iter().rev() {
+            let extent = self.extents.remove(&start).expect("extent is buffered");
+            self.bytes -= extent.len();
+            let at = (start - merged_start) as usize;
+            if merged.len() < at + extent.len() {
+                merged.resize(at + extent.len(), 0);
+            }
+            merged[at..at + extent.len()].copy_from_slice(&extent);
+        }
+        let at = (offset - merged_start) as usize;
+        if merged.len() < at + data.len() {
+            merged.resize(at + data.len(), 0);
+        }
+        merged[at..at + data.len()].copy_from_slice(data);
+
+        self.bytes += merged.len();
+        self.extents.insert(merged_start, merged);
+    }
+
+    /// Discard all buffered data at or beyond `len`.
+    pub(crate) fn truncate(&mut self, len: u64) {
+        for (_, extent) in self.extents.split_off(&len) {
+            self.bytes -= extent.len();
+        }
+        if let Some((&start, extent)) = self.extents.last_key_value()
+            && start + extent.len() as u64 > len
+        {
+            let extent = self.extents.get_mut(&start).expect("extent is buffered");
+            let keep = (len - start) as usize;
+            self.bytes -= extent.len() - keep;
+            extent.truncate(keep);
+        }
+    }
+
+    /// Overwrite the parts of `out`, which holds data from `offset`, for which newer data is buffered.
+    pub(crate) fn overlay(&self, offset: u64, out: &mut [u8]) {
+        let end = offset + out.len() as u64;
+        let first = self
+            .extents
+            .range(..=offset)
+            .next_back()
+            .map(|(&start, _)| start)
+            .unwrap_or(offset);
+        for (&start, extent) in self.extents.range(first..end) {
+            let from = start.max(offset);
+            let to = (start + extent.len() as u64).min(end);
+            if from >= to {
+                continue;
+            }
+            out[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&extent[(from - start) as usize..(to - start) as usize]);
+        }
+    }
+
+    /// Remove and return the buffered extent with the lowest offset.
+    pub(crate) fn pop_first(&mut self) -> Option<(u64, Vec<u8>)> {
+        let (offset, extent) = self.extents.pop_first()?;
+        self.bytes -= extent.len();
+        Some((offset, extent))
+    }
+}
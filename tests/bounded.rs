@@ -0,0 +1,79 @@
+//! The limit of `BoundedBackend`, checked to stop growth before it reaches the inner backend, and to let a database
+//! which is already too large shrink.
+
+#![cfg(all(not(target_family = "wasm"), feature = "testing"))]
+
+use std::io::{self, ErrorKind};
+
+use redb_opfs::{
+    BoundedBackend, MemoryBackend, StorageBackend,
+    testing::{Fault, Faults, FaultyBackend, Op},
+};
+
+const MAX_LEN: u64 = 4096;
+
+fn bounded(faults: &Faults) -> BoundedBackend<FaultyBackend<MemoryBackend>> {
+    let inner = FaultyBackend::with_faults(MemoryBackend::new(), faults.clone());
+    BoundedBackend::new(inner, MAX_LEN)
+}
+
+fn assert_full(result: io::Result<()>) {
+    let err = result.expect_err("growth past the limit went through");
+    assert_eq!(err.kind(), ErrorKind::StorageFull, "{err}");
+}
+
+#[test]
+fn growth_past_the_limit_never_reaches_the_inner_backend() {
+    let faults = Faults::default();
+    let backend = bounded(&faults);
+    backend
+        .write(0, &[1; MAX_LEN as usize])
+        .expect("write up to the limit");
+
+    // anything which gets through fails with another error
+    faults.fail_from(Op::Write, 1, Fault::Error(ErrorKind::Other));
+    faults.fail_from(Op::SetLen, 1, Fault::Error(ErrorKind::Other));
+    assert_full(backend.write(MAX_LEN, &[1]));
+    assert_full(backend.write(MAX_LEN - 1, &[1; 2]));
+    assert_full(backend.set_len(MAX_LEN + 1));
+    assert_eq!(faults.injected(), 0);
+    assert_eq!(backend.len().expect("len"), MAX_LEN);
+}
+
+#[test]
+fn database_over_the_limit_can_shrink() {
+    let inner = MemoryBackend::new();
+    inner.write(0, &[1; 3 * MAX_LEN as usize]).expect("fill");
+    let backend = BoundedBackend::new(inner, MAX_LEN);
+
+    backend.write(10, b"within").expect("write within the file");
+    backend
+        .write(3 * MAX_LEN - 6, b"at end")
+        .expect("write up to the end");
+    backend.set_len(2 * MAX_LEN).expect("shrink");
+    assert_full(backend.set_len(2 * MAX_LEN + 1));
+    assert_full(backend.write(2 * MAX_LEN, &[1]));
+    backend
+        .set_len(MAX_LEN / 2)
+        .expect("shrink below the limit");
+    backend.set_len(MAX_LEN).expect("grow up to the limit");
+}
+
+#[test]
+fn changed_limit_takes_effect() {
+    let backend = bounded(&Faults::default());
+    assert_full(backend.write(0, &[1; MAX_LEN as usize + 1]));
+
+    backend.set_max_len(2 * MAX_LEN);
+    assert_eq!(backend.max_len(), 2 * MAX_LEN);
+    backend
+        .write(0, &[1; MAX_LEN as usize + 1])
+        .expect("write within the raised limit");
+
+    backend.set_max_len(10);
+    backend
+        .write(0, &[2; MAX_LEN as usize])
+        .expect("write within the file");
+    assert_full(backend.set_len(MAX_LEN + 2));
+    assert_eq!(backend.len().expect("len"), MAX_LEN + 1);
+}
@@ -0,0 +1,98 @@
+//! Damage to the blocks of a `ChecksummedBackend`, checked to be reported rather than read back.
+
+#![cfg(all(not(target_family = "wasm"), feature = "checksums"))]
+
+use std::io::ErrorKind;
+
+use redb_opfs::{
+    ChecksummedBackend, MemoryBackend, StorageBackend,
+    checksummed::{BLOCK_SIZE, HEADER_LEN, is_checksum_mismatch},
+};
+
+/// Bytes taken by a block and its checksum.
+const PHYSICAL_BLOCK_SIZE: u64 = BLOCK_SIZE as u64 + 4;
+
+/// A backend holding three blocks of data.
+fn filled() -> ChecksummedBackend<MemoryBackend> {
+    let backend = ChecksummedBackend::new(MemoryBackend::new()).expect("wrap");
+    let data = (0..3 * BLOCK_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+    backend.write(0, &data).expect("write");
+    backend
+}
+
+/// Apply `damage` to the physical file of `backend`, then wrap it again.
+fn damaged(
+    backend: ChecksummedBackend<MemoryBackend>,
+    damage: impl FnOnce(&MemoryBackend),
+) -> ChecksummedBackend<MemoryBackend> {
+    let inner = backend.into_inner();
+    damage(&inner);
+    ChecksummedBackend::new(inner).expect("rewrap")
+}
+
+fn block_offset(index: u64) -> u64 {
+    HEADER_LEN as u64 + index * PHYSICAL_BLOCK_SIZE
+}
+
+fn assert_mismatch(backend: &ChecksummedBackend<MemoryBackend>, block: u64) {
+    let mut out = [0; 16];
+    let err = backend
+        .read(block * BLOCK_SIZE as u64, &mut out)
+        .expect_err("damaged block was read");
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(is_checksum_mismatch(&err), "{err}");
+    assert_eq!(backend.corrupted_blocks().expect("scan"), [block]);
+}
+
+#[test]
+fn intact_data_reads_back() {
+    let backend = filled();
+    let mut out = vec![0; 3 * BLOCK_SIZE];
+    backend.read(0, &mut out).expect("read");
+    assert!(out.iter().enumerate().all(|(i, &b)| b == i as u8));
+    assert!(backend.corrupted_blocks().expect("scan").is_empty());
+}
+
+#[test]
+fn corrupted_block_is_detected() {
+    let backend = damaged(filled(), |inner| {
+        inner
+            .write(block_offset(1) + 100, &[0xff])
+            .expect("corrupt");
+    });
+    assert_mismatch(&backend, 1);
+}
+
+#[test]
+fn zeroed_block_is_detected() {
+    let backend = damaged(filled(), |inner| {
+        let zeros = vec![0; PHYSICAL_BLOCK_SIZE as usize];
+        inner.write(block_offset(2), &zeros).expect("zero");
+    });
+    assert_mismatch(&backend, 2);
+}
+
+#[test]
+fn extended_blocks_are_checksummed() {
+    let backend = ChecksummedBackend::new(MemoryBackend::new()).expect("wrap");
+    // a gap left by a write, and blocks added by extending
+    backend
+        .write(2 * BLOCK_SIZE as u64, &[1; 10])
+        .expect("write past the end");
+    backend.set_len(5 * BLOCK_SIZE as u64).expect("extend");
+    assert!(backend.corrupted_blocks().expect("scan").is_empty());
+
+    let mut out = vec![0xaa; BLOCK_SIZE];
+    backend.read(0, &mut out).expect("read gap");
+    assert!(out.iter().all(|&b| b == 0));
+    backend
+        .read(4 * BLOCK_SIZE as u64, &mut out)
+        .expect("read extension");
+    assert!(out.iter().all(|&b| b == 0));
+
+    let backend = damaged(backend, |inner| {
+        let zeros = vec![0; PHYSICAL_BLOCK_SIZE as usize];
+        inner.write(block_offset(4), &zeros).expect("zero");
+    });
+    assert_mismatch(&backend, 4);
+}
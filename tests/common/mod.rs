@@ -0,0 +1,33 @@
+//! Helpers shared by the native integration tests.
+
+#![allow(dead_code)]
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+pub use pollster::block_on;
+
+/// A file in the temp dir which is removed when dropped.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// A path no other `TempPath` has, in this or any other test process, with `name` in it for recognition.
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("redb-opfs-{name}-{}-{n}.redb", std::process::id());
+        Self(std::env::temp_dir().join(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().expect("temp dir is valid UTF-8")
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
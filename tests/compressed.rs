@@ -0,0 +1,93 @@
+//! Commits of a `CompressedBackend`, checked to reopen with the content they were made with, even after a write
+//! failed halfway.
+
+#![cfg(all(
+    not(target_family = "wasm"),
+    feature = "compression",
+    feature = "testing"
+))]
+
+use std::io::ErrorKind;
+
+use redb_opfs::{
+    CompressedBackend, MemoryBackend, StorageBackend,
+    compressed::{CACHED_GROUPS, GROUP_SIZE},
+    testing::{Fault, Faults, FaultyBackend, Op},
+};
+
+type Backend = CompressedBackend<FaultyBackend<MemoryBackend>>;
+
+/// `len` bytes which compress, but not to nothing.
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i / 100 % 251) as u8).collect()
+}
+
+fn create(faults: &Faults) -> Backend {
+    let inner = FaultyBackend::with_faults(MemoryBackend::new(), faults.clone());
+    CompressedBackend::new(inner).expect("create")
+}
+
+fn reopen(backend: Backend) -> Backend {
+    CompressedBackend::new(backend.into_inner()).expect("reopen")
+}
+
+fn read_all(backend: &Backend) -> Vec<u8> {
+    let mut out = vec![0; backend.len().expect("len") as usize];
+    backend.read(0, &mut out).expect("read");
+    out
+}
+
+#[test]
+fn commits_round_trip() {
+    let backend = create(&Faults::default());
+    let first = data(3 * GROUP_SIZE + 123);
+    backend.write(0, &first).expect("write");
+    backend.sync_data().expect("sync");
+
+    let backend = reopen(backend);
+    assert_eq!(read_all(&backend), first);
+
+    // a second commit, which shrinks the file and rewrites a group
+    backend.set_len(GROUP_SIZE as u64 + 10).expect("shrink");
+    backend.write(5, b"second").expect("overwrite");
+    backend.sync_data().expect("sync");
+    let mut second = first[..GROUP_SIZE + 10].to_vec();
+    second[5..11].copy_from_slice(b"second");
+
+    let backend = reopen(backend);
+    assert_eq!(read_all(&backend), second);
+}
+
+#[test]
+fn uncommitted_changes_are_discarded() {
+    let backend = create(&Faults::default());
+    backend.write(0, &data(GROUP_SIZE)).expect("write");
+    backend.sync_data().expect("sync");
+    backend.write(GROUP_SIZE as u64, &data(10)).expect("extend");
+
+    let backend = reopen(backend);
+    assert_eq!(read_all(&backend), data(GROUP_SIZE));
+}
+
+#[test]
+fn failed_extending_write_commits_consistently() {
+    let faults = Faults::default();
+    let backend = create(&faults);
+    // fill the cache with dirty groups, so that the next group spills them
+    let cached = data(CACHED_GROUPS * GROUP_SIZE);
+    backend.write(0, &cached).expect("write");
+
+    faults.fail_nth(Op::Write, 1, Fault::Error(ErrorKind::Other));
+    let end = cached.len() + 2 * GROUP_SIZE;
+    backend
+        .write(cached.len() as u64, &data(2 * GROUP_SIZE))
+        .expect_err("spilling should fail");
+    faults.clear();
+    assert_eq!(backend.len().expect("len"), end as u64);
+    backend.sync_data().expect("sync");
+
+    let backend = reopen(backend);
+    let content = read_all(&backend);
+    assert_eq!(content.len(), end);
+    assert_eq!(content[..cached.len()], cached);
+}
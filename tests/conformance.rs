@@ -0,0 +1,85 @@
+//! The conformance suite of the `testing` module, run against every backend and wrapper which works natively.
+
+#![cfg(all(not(target_family = "wasm"), feature = "testing"))]
+
+mod common;
+
+use common::{TempPath, block_on};
+use redb_opfs::{
+    BoundedBackend, MemoryBackend, MirroredBackend, OpfsBackend, StdVfs, VfsBackend,
+    testing::conformance,
+};
+
+#[test]
+fn memory() {
+    conformance(&MemoryBackend::new());
+}
+
+#[test]
+fn native() {
+    let path = TempPath::new("conformance");
+    let backend =
+        block_on(OpfsBackend::builder().create(true).open(path.as_str())).expect("open backend");
+    conformance(&backend);
+}
+
+#[test]
+fn native_with_cache_and_write_buffer() {
+    let path = TempPath::new("conformance");
+    let backend = block_on(
+        OpfsBackend::builder()
+            .create(true)
+            .cache_pages(8)
+            .write_buffer(8192)
+            .open(path.as_str()),
+    )
+    .expect("open backend");
+    conformance(&backend);
+}
+
+#[test]
+fn vfs() {
+    let path = TempPath::new("conformance");
+    conformance(&VfsBackend::open(StdVfs, path.as_str()).expect("open backend"));
+}
+
+#[test]
+fn bounded() {
+    conformance(&BoundedBackend::new(MemoryBackend::new(), 1 << 20));
+}
+
+#[test]
+fn mirrored() {
+    let backend = MirroredBackend::new(MemoryBackend::new(), MemoryBackend::new()).expect("mirror");
+    conformance(&backend);
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted() {
+    let backend = redb_opfs::EncryptedBackend::new(MemoryBackend::new(), &[7; 32]).expect("wrap");
+    conformance(&backend);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed() {
+    let backend = redb_opfs::CompressedBackend::new(MemoryBackend::new()).expect("wrap");
+    conformance(&backend);
+}
+
+#[cfg(feature = "checksums")]
+#[test]
+fn checksummed() {
+    let backend = redb_opfs::ChecksummedBackend::new(MemoryBackend::new()).expect("wrap");
+    conformance(&backend);
+}
+
+#[cfg(feature = "sharding")]
+#[test]
+fn sharded() {
+    // small shards, so that the suite's large write spans several
+    let shards = (0..8).map(|_| MemoryBackend::new()).collect();
+    let backend = redb_opfs::ShardedBackend::new(shards, 64 * 1024).expect("shard");
+    conformance(&backend);
+}
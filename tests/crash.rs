@@ -0,0 +1,106 @@
+//! Crashes at every point of a redb commit, checked to leave a database which opens with either the old or the new
+//! content.
+//!
+//! redb assumes that data reaches storage once `sync_data` returns, and nothing about writes which were not synced.
+//! `SimulatedOpfs` flushes like OPFS does, and its crashes persist any prefix of the unsynced writes, or tear one.
+
+#![cfg(all(not(target_family = "wasm"), feature = "redb3", feature = "testing"))]
+
+use std::io::ErrorKind;
+
+use redb::{Database, ReadableDatabase as _, ReadableTable as _, TableDefinition};
+use redb_opfs::testing::{Crash, Fault, Faults, FaultyBackend, Op, SimulatedOpfs};
+
+const TABLE: TableDefinition<&str, u64> = TableDefinition::new("data");
+const PATH: &str = "crash.redb";
+
+/// Bytes of a torn write which reach storage: less than a sector, a sector, and half a page.
+const TORN_AT: [usize; 3] = [1, 512, 2048];
+
+fn open(fs: &SimulatedOpfs, faults: Faults) -> Database {
+    let handle = fs.open(PATH).expect("open simulated file");
+    Database::builder()
+        .create_with_backend(FaultyBackend::with_faults(handle, faults))
+        .expect("open database")
+}
+
+fn set(database: &Database, value: u64) -> Result<(), redb::Error> {
+    let tx = database.begin_write()?;
+    tx.open_table(TABLE)?.insert("key", value)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn get(database: &Database) -> Option<u64> {
+    let tx = database.begin_read().expect("begin read");
+    let table = tx.open_table(TABLE).expect("open table");
+    table.get("key").expect("get").map(|value| value.value())
+}
+
+/// Commit 1, then try to commit 2 with the `failing_sync`th sync failing, leaving its writes unsynced.
+///
+/// Returns `None` if the commit syncs fewer times, and so succeeds.
+fn interrupted_commit(failing_sync: u64) -> Option<(SimulatedOpfs, Database)> {
+    let fs = SimulatedOpfs::new();
+    let faults = Faults::default();
+    let database = open(&fs, faults.clone());
+    set(&database, 1).expect("commit 1");
+    faults.fail_nth(Op::SyncData, failing_sync, Fault::Error(ErrorKind::Other));
+    match set(&database, 2) {
+        Ok(()) => None,
+        Err(_) => Some((fs, database)),
+    }
+}
+
+/// Every crash which persists part of `pending` unsynced changes.
+fn crashes(pending: usize) -> Vec<Crash> {
+    let mut crashes = vec![Crash::LoseUnsynced];
+    crashes.extend((1..=pending).map(Crash::Prefix));
+    for applied in 0..pending {
+        crashes.extend(TORN_AT.map(|keep| Crash::Torn { applied, keep }));
+    }
+    crashes
+}
+
+#[test]
+fn interrupted_commits_recover() {
+    for failing_sync in 1.. {
+        let Some((fs, database)) = interrupted_commit(failing_sync) else {
+            assert!(failing_sync > 1, "commit does not sync");
+            break;
+        };
+        let pending = fs.pending_changes(PATH);
+        fs.crash();
+        drop(database);
+
+        for crash in crashes(pending) {
+            let (fs, database) =
+                interrupted_commit(failing_sync).expect("commits are deterministic");
+            fs.crash_with(crash);
+            drop(database);
+
+            let database = open(&fs, Faults::default());
+            let value = get(&database);
+            assert!(
+                matches!(value, Some(1 | 2)),
+                "{crash:?} while sync {failing_sync} failed left {value:?}"
+            );
+            // the recovered database stays usable
+            set(&database, 3).expect("commit after recovery");
+            assert_eq!(get(&database), Some(3));
+        }
+    }
+}
+
+#[test]
+fn committed_data_survives_a_crash() {
+    let fs = SimulatedOpfs::new();
+    let database = open(&fs, Faults::default());
+    set(&database, 1).expect("commit 1");
+    set(&database, 2).expect("commit 2");
+    fs.crash();
+    drop(database);
+
+    let database = open(&fs, Faults::default());
+    assert_eq!(get(&database), Some(2));
+}
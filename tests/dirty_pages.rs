@@ -0,0 +1,97 @@
+//! The dirty pages handed out for replication, checked to count only once synced, and to follow truncation.
+
+#![cfg(not(target_family = "wasm"))]
+
+mod common;
+
+use std::io::ErrorKind;
+
+use common::{TempPath, block_on};
+use redb_opfs::{OpfsBackend, StorageBackend};
+
+const PAGE: u64 = 4096;
+
+fn open(path: &TempPath, page_size: u32) -> OpfsBackend {
+    block_on(
+        OpfsBackend::builder()
+            .create(true)
+            .dirty_pages(Some(page_size))
+            .open(path.as_str()),
+    )
+    .expect("open backend")
+}
+
+fn synced_pages(backend: &OpfsBackend) -> Vec<u64> {
+    backend.sync_data().expect("sync");
+    backend.take_dirty_pages().expect("take dirty pages")
+}
+
+#[test]
+fn pages_count_once_synced() {
+    let path = TempPath::new("dirty-pages");
+    let backend = open(&path, PAGE as u32);
+    backend.write(10, b"first").expect("write");
+    // spanning pages 2 and 3
+    backend.write(3 * PAGE - 2, b"second").expect("write");
+    assert_eq!(backend.take_dirty_pages().expect("take"), [] as [u64; 0]);
+
+    backend.sync_data().expect("sync");
+    backend.write(PAGE, b"pending").expect("write");
+    assert_eq!(backend.take_dirty_pages().expect("take"), [0, 2, 3]);
+    assert_eq!(backend.take_dirty_pages().expect("take"), [] as [u64; 0]);
+    assert_eq!(synced_pages(&backend), [1]);
+}
+
+#[test]
+fn truncation_forgets_pages_past_the_end() {
+    let path = TempPath::new("dirty-pages");
+    let backend = open(&path, PAGE as u32);
+    backend.write(0, &[1; 4 * PAGE as usize]).expect("write");
+    synced_pages(&backend);
+
+    // page 3 is gone, and page 2 is now partial
+    backend.write(3 * PAGE, b"gone").expect("write");
+    backend.set_len(2 * PAGE + 100).expect("shrink");
+    assert_eq!(synced_pages(&backend), [2]);
+
+    backend.set_len(4 * PAGE).expect("grow");
+    assert_eq!(synced_pages(&backend), [2, 3]);
+}
+
+#[test]
+fn truncation_at_a_word_boundary() {
+    let path = TempPath::new("dirty-pages");
+    // one page per byte, so that a few bytes span several words of the bitmap
+    let backend = open(&path, 1);
+    backend.write(0, &[1; 200]).expect("write");
+    backend.sync_data().expect("sync");
+    backend.set_len(128).expect("shrink");
+    assert_eq!(
+        backend.take_dirty_pages().expect("take"),
+        (0..128).collect::<Vec<_>>()
+    );
+
+    backend.write(0, &[1; 200]).expect("write");
+    backend.set_len(64).expect("shrink");
+    assert_eq!(synced_pages(&backend), (0..64).collect::<Vec<_>>());
+}
+
+#[test]
+fn raw_handle_marks_the_whole_file() {
+    let path = TempPath::new("dirty-pages");
+    let backend = open(&path, PAGE as u32);
+    backend.write(0, &[1; 3 * PAGE as usize]).expect("write");
+    synced_pages(&backend);
+
+    backend.with_raw_handle(|_| ()).expect("raw handle");
+    assert_eq!(synced_pages(&backend), [0, 1, 2]);
+}
+
+#[test]
+fn unrecorded_backend_has_no_dirty_pages() {
+    let path = TempPath::new("dirty-pages");
+    let backend =
+        block_on(OpfsBackend::builder().create(true).open(path.as_str())).expect("open backend");
+    let err = backend.take_dirty_pages().expect_err("not recorded");
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+}
@@ -0,0 +1,421 @@
+//! The page format of `EncryptedBackend`, checked to authenticate the header and every page, key rotation, checked
+//! to resume after an interruption, and encrypted backups, checked to be rejected whole if tampered with.
+
+#![cfg(all(not(target_family = "wasm"), feature = "encryption"))]
+
+mod common;
+
+use std::{
+    io::{self, ErrorKind, Read as _, Write as _},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use common::{TempPath, block_on};
+use redb_opfs::{
+    EncryptedBackend, MemoryBackend, OpfsBackend, StorageBackend,
+    encrypted::{BACKUP_HEADER_LEN, BackupReader, BackupWriter, HEADER_LEN, Key, PAGE_SIZE},
+};
+
+/// Bytes taken by a page, its nonce and its tag.
+const PHYSICAL_PAGE_SIZE: u64 = 12 + PAGE_SIZE as u64 + 16;
+
+/// Plaintext held by each chunk of a backup, but the last.
+const BACKUP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes taken by a full chunk of a backup, with its length, nonce and tag.
+const PHYSICAL_CHUNK_SIZE: usize = 4 + 12 + BACKUP_CHUNK_SIZE + 16;
+
+const KEY: Key = [7; 32];
+const OTHER_KEY: Key = [8; 32];
+
+/// Storage which outlives the backends wrapping it, and fails writes once a budget is used up.
+#[derive(Debug, Clone, Default)]
+struct Shared {
+    storage: Arc<MemoryBackend>,
+    /// Writes which may still succeed, or `u64::MAX` for any number.
+    writes_left: Arc<AtomicU64>,
+}
+
+impl Shared {
+    fn new() -> Self {
+        let shared = Self::default();
+        shared.writes_left.store(u64::MAX, Ordering::SeqCst);
+        shared
+    }
+
+    fn physical(&self) -> Vec<u8> {
+        let mut out = vec![0; self.storage.len().expect("len") as usize];
+        self.storage.read(0, &mut out).expect("read");
+        out
+    }
+
+    fn corrupt(&self, offset: u64, data: &[u8]) {
+        self.storage.write(offset, data).expect("corrupt");
+    }
+}
+
+impl StorageBackend for Shared {
+    fn len(&self) -> io::Result<u64> {
+        self.storage.len()
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> io::Result<()> {
+        self.storage.read(offset, out)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.storage.set_len(len)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.storage.sync_data()
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let left = self.writes_left.load(Ordering::SeqCst);
+        if left == 0 {
+            return Err(io::Error::other("interrupted"));
+        }
+        if left != u64::MAX {
+            self.writes_left.store(left - 1, Ordering::SeqCst);
+        }
+        self.storage.write(offset, data)
+    }
+}
+
+fn page_offset(index: u64) -> u64 {
+    HEADER_LEN as u64 + index * PHYSICAL_PAGE_SIZE
+}
+
+/// Three pages of recognizable data.
+fn data() -> Vec<u8> {
+    (0..3 * PAGE_SIZE).map(|i| (i % 251) as u8).collect()
+}
+
+fn filled(shared: &Shared) -> EncryptedBackend<Shared> {
+    let backend = EncryptedBackend::new(shared.clone(), &KEY).expect("create");
+    backend.write(0, &data()).expect("write");
+    backend
+}
+
+fn read_all(backend: &impl StorageBackend) -> io::Result<Vec<u8>> {
+    let mut out = vec![0; backend.len()? as usize];
+    backend.read(0, &mut out)?;
+    Ok(out)
+}
+
+fn assert_invalid(result: io::Result<impl std::fmt::Debug>) {
+    let err = result.expect_err("tampering went unnoticed");
+    assert_eq!(err.kind(), ErrorKind::InvalidData, "{err}");
+}
+
+#[test]
+fn pages_are_encrypted_in_place() {
+    let shared = Shared::new();
+    let backend = filled(&shared);
+    let physical = shared.physical();
+    assert_eq!(physical.len() as u64, page_offset(3));
+    for (index, plaintext) in data().chunks(PAGE_SIZE).enumerate() {
+        let ciphertext = page_offset(index as u64) as usize + 12;
+        assert_ne!(&physical[ciphertext..ciphertext + PAGE_SIZE], plaintext);
+    }
+    drop(backend);
+
+    let backend = EncryptedBackend::new(shared, &KEY).expect("reopen");
+    assert_eq!(read_all(&backend).expect("read"), data());
+}
+
+#[test]
+fn wrong_key_fails_at_open() {
+    let shared = Shared::new();
+    drop(filled(&shared));
+    assert_invalid(EncryptedBackend::new(shared.clone(), &OTHER_KEY));
+    let err = EncryptedBackend::open_with_password(shared, "password")
+        .expect_err("not password-protected");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn passwords_round_trip() {
+    let shared = Shared::new();
+    let backend = EncryptedBackend::open_with_password(shared.clone(), "correct").expect("create");
+    backend.write(0, &data()).expect("write");
+    drop(backend);
+
+    assert_invalid(EncryptedBackend::open_with_password(
+        shared.clone(),
+        "wrong",
+    ));
+    let backend = EncryptedBackend::open_with_password(shared, "correct").expect("reopen");
+    assert_eq!(read_all(&backend).expect("read"), data());
+}
+
+#[test]
+fn excessive_iteration_count_is_rejected() {
+    let shared = Shared::new();
+    drop(EncryptedBackend::open_with_password(shared.clone(), "password").expect("create"));
+    // the KDF iteration count, which is read before the header can be authenticated
+    shared.corrupt(56, &u32::MAX.to_le_bytes());
+    assert_invalid(EncryptedBackend::open_with_password(shared, "password"));
+}
+
+#[test]
+fn tampered_header_is_detected() {
+    let shared = Shared::new();
+    drop(filled(&shared));
+    // the logical length
+    shared.corrupt(16, &[0xff]);
+    assert_invalid(EncryptedBackend::new(shared, &KEY));
+}
+
+#[test]
+fn tampered_page_is_detected() {
+    let shared = Shared::new();
+    drop(filled(&shared));
+    shared.corrupt(page_offset(1) + 100, &[0xff]);
+    let backend = EncryptedBackend::new(shared, &KEY).expect("reopen");
+    assert_invalid(read_all(&backend));
+    let mut page = [0; PAGE_SIZE];
+    backend.read(0, &mut page).expect("intact page");
+}
+
+#[test]
+fn zeroed_page_is_detected() {
+    let shared = Shared::new();
+    drop(filled(&shared));
+    shared.corrupt(page_offset(2), &[0; PHYSICAL_PAGE_SIZE as usize]);
+    let backend = EncryptedBackend::new(shared, &KEY).expect("reopen");
+    assert_invalid(read_all(&backend));
+}
+
+#[test]
+fn moved_page_is_detected() {
+    let shared = Shared::new();
+    drop(filled(&shared));
+    let physical = shared.physical();
+    let first = &physical[page_offset(0) as usize..page_offset(1) as usize];
+    shared.corrupt(page_offset(1), first);
+    let backend = EncryptedBackend::new(shared, &KEY).expect("reopen");
+    assert_invalid(read_all(&backend));
+}
+
+#[test]
+fn extended_pages_are_authenticated() {
+    let shared = Shared::new();
+    let backend = EncryptedBackend::new(shared.clone(), &KEY).expect("create");
+    // a gap left by a write, and pages added by extending
+    backend
+        .write(2 * PAGE_SIZE as u64, &[1; 10])
+        .expect("write past the end");
+    backend.set_len(5 * PAGE_SIZE as u64).expect("extend");
+    let contents = read_all(&backend).expect("read");
+    assert!(contents[..2 * PAGE_SIZE].iter().all(|&b| b == 0));
+    assert!(contents[3 * PAGE_SIZE..].iter().all(|&b| b == 0));
+    drop(backend);
+
+    shared.corrupt(page_offset(4), &[0; PHYSICAL_PAGE_SIZE as usize]);
+    let backend = EncryptedBackend::new(shared, &KEY).expect("reopen");
+    assert_invalid(read_all(&backend));
+}
+
+#[test]
+fn rotation_replaces_the_key() {
+    let shared = Shared::new();
+    drop(filled(&shared));
+    let backend = EncryptedBackend::rotate_key(shared.clone(), &KEY, &OTHER_KEY).expect("rotate");
+    assert_eq!(read_all(&backend).expect("read"), data());
+    drop(backend);
+
+    assert_invalid(EncryptedBackend::new(shared.clone(), &KEY));
+    let backend = EncryptedBackend::new(shared, &OTHER_KEY).expect("reopen");
+    assert_eq!(read_all(&backend).expect("read"), data());
+}
+
+#[test]
+fn interrupted_rotation_resumes() {
+    // the header recording the rotation, then each page, then the final header
+    for writes in 0..5 {
+        let shared = Shared::new();
+        drop(filled(&shared));
+        shared.writes_left.store(writes, Ordering::SeqCst);
+        assert!(
+            EncryptedBackend::rotate_key(shared.clone(), &KEY, &OTHER_KEY).is_err(),
+            "rotation completed in {writes} writes"
+        );
+        shared.writes_left.store(u64::MAX, Ordering::SeqCst);
+
+        if writes > 0 {
+            assert_invalid(EncryptedBackend::new(shared.clone(), &KEY));
+        }
+        let backend =
+            EncryptedBackend::rotate_key(shared.clone(), &KEY, &OTHER_KEY).expect("resume");
+        assert_eq!(
+            read_all(&backend).expect("read"),
+            data(),
+            "after {writes} writes"
+        );
+        drop(backend);
+
+        let backend = EncryptedBackend::new(shared, &OTHER_KEY).expect("reopen");
+        assert_eq!(read_all(&backend).expect("read"), data());
+    }
+}
+
+/// Three full chunks of recognizable data, and a partial one.
+fn backup_data() -> Vec<u8> {
+    (0..3 * BACKUP_CHUNK_SIZE + 100)
+        .map(|i| (i / 7 % 251) as u8)
+        .collect()
+}
+
+fn backup(key: &Key) -> Vec<u8> {
+    let mut writer = BackupWriter::new(Vec::new(), key).expect("start backup");
+    writer.write_all(&backup_data()).expect("write backup");
+    writer.finish().expect("finish backup")
+}
+
+/// The range of the full chunk `index` within a backup.
+fn chunk(index: usize) -> std::ops::Range<usize> {
+    let start = BACKUP_HEADER_LEN + index * PHYSICAL_CHUNK_SIZE;
+    start..start + PHYSICAL_CHUNK_SIZE
+}
+
+fn restore(backup: &[u8], key: &Key) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    BackupReader::new(backup, key)?.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[test]
+fn backups_round_trip() {
+    let backup = backup(&KEY);
+    assert_eq!(
+        backup.len(),
+        BACKUP_HEADER_LEN + 3 * PHYSICAL_CHUNK_SIZE + 4 + 12 + 100 + 16
+    );
+    assert_eq!(restore(&backup, &KEY).expect("restore"), backup_data());
+}
+
+#[test]
+fn backup_with_wrong_key_is_rejected() {
+    assert_invalid(restore(&backup(&KEY), &OTHER_KEY));
+}
+
+#[test]
+fn backup_passwords_round_trip() {
+    let mut writer = BackupWriter::with_password(Vec::new(), "correct").expect("start backup");
+    writer.write_all(&backup_data()).expect("write backup");
+    let backup = writer.finish().expect("finish backup");
+
+    let mut out = Vec::new();
+    BackupReader::with_password(&backup[..], "correct")
+        .expect("open backup")
+        .read_to_end(&mut out)
+        .expect("restore");
+    assert_eq!(out, backup_data());
+
+    let mut reader = BackupReader::with_password(&backup[..], "wrong").expect("open backup");
+    assert_invalid(reader.read_to_end(&mut Vec::new()));
+    let err = BackupReader::new(&backup[..], &KEY).expect_err("password-protected");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn backup_with_excessive_iteration_count_is_rejected() {
+    let mut backup = backup(&KEY);
+    // claim a PBKDF2 key with an absurd iteration count, which is read before anything is authenticated
+    backup[48..52].copy_from_slice(&u32::MAX.to_le_bytes());
+    backup[52] = 1;
+    assert_invalid(BackupReader::with_password(&backup[..], "password"));
+}
+
+#[test]
+fn tampered_backup_chunk_is_rejected() {
+    let mut backup = backup(&KEY);
+    backup[chunk(1).start + 100] ^= 1;
+    assert_invalid(restore(&backup, &KEY));
+}
+
+#[test]
+fn truncated_backup_is_rejected() {
+    let backup = backup(&KEY);
+    // without the partial last chunk, and in the middle of a chunk
+    for len in [chunk(3).start, chunk(1).start + 100] {
+        let err = restore(&backup[..len], &KEY).expect_err("truncation went unnoticed");
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof, "{err}");
+    }
+}
+
+#[test]
+fn reordered_backup_chunks_are_rejected() {
+    let backup = backup(&KEY);
+    let mut reordered = backup[..chunk(0).start].to_vec();
+    reordered.extend_from_slice(&backup[chunk(1)]);
+    reordered.extend_from_slice(&backup[chunk(0)]);
+    reordered.extend_from_slice(&backup[chunk(2).start..]);
+    assert_invalid(restore(&reordered, &KEY));
+}
+
+#[test]
+fn duplicated_backup_chunk_is_rejected() {
+    let backup = backup(&KEY);
+    let mut duplicated = backup[..chunk(1).end].to_vec();
+    duplicated.extend_from_slice(&backup[chunk(1)]);
+    duplicated.extend_from_slice(&backup[chunk(2).start..]);
+    assert_invalid(restore(&duplicated, &KEY));
+}
+
+#[test]
+fn trailing_data_after_backup_is_rejected() {
+    let mut backup = backup(&KEY);
+    backup.push(0);
+    assert_invalid(restore(&backup, &KEY));
+}
+
+#[test]
+fn rejected_import_leaves_target_untouched() {
+    let path = TempPath::new("encrypted-import");
+    block_on(OpfsBackend::import(path.as_str(), b"original")).expect("seed target");
+    let mut tampered = backup(&KEY);
+    // authenticated only after the first chunks were staged
+    tampered[chunk(2).start + 100] ^= 1;
+
+    let mut reader = BackupReader::new(&tampered[..], &KEY).expect("open backup");
+    let imported = block_on(OpfsBackend::import_staged(
+        path.as_str(),
+        &mut reader,
+        |_| {},
+    ));
+    assert_invalid(imported);
+    assert_eq!(
+        std::fs::read(path.as_str()).expect("read target"),
+        b"original"
+    );
+
+    let dir = std::env::temp_dir();
+    let name = std::path::Path::new(path.as_str())
+        .file_name()
+        .expect("file name");
+    let staged = std::fs::read_dir(dir).expect("list temp dir").any(|entry| {
+        let entry = entry.expect("entry").file_name();
+        let entry = entry.to_string_lossy();
+        entry.starts_with(&*name.to_string_lossy()) && entry.ends_with(".import")
+    });
+    assert!(!staged, "the staging file was left behind");
+
+    let intact = backup(&KEY);
+    let mut reader = BackupReader::new(&intact[..], &KEY).expect("open backup");
+    block_on(OpfsBackend::import_staged(
+        path.as_str(),
+        &mut reader,
+        |_| {},
+    ))
+    .expect("import");
+    assert_eq!(
+        std::fs::read(path.as_str()).expect("read target"),
+        backup_data()
+    );
+}
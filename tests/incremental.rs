@@ -0,0 +1,174 @@
+//! Incremental exports, checked to export only the chunks changed since the token they are given, coalesced, and to
+//! restore the file when applied in order.
+
+#![cfg(not(target_family = "wasm"))]
+
+mod common;
+
+use std::io::{self, ErrorKind, Write};
+
+use common::{TempPath, block_on};
+use redb_opfs::{IncrementalManifest, OpfsBackend, StorageBackend};
+
+/// Granularity of change tracking.
+const CHUNK: u64 = 64 * 1024;
+
+fn open(path: &TempPath) -> OpfsBackend {
+    block_on(
+        OpfsBackend::builder()
+            .create(true)
+            .track_changes(true)
+            .open(path.as_str()),
+    )
+    .expect("open backend")
+}
+
+fn export(backend: &OpfsBackend, since: Option<&str>) -> (IncrementalManifest, Vec<u8>) {
+    let mut data = Vec::new();
+    let manifest = backend
+        .export_incremental(since, &mut data)
+        .expect("export");
+    (manifest, data)
+}
+
+fn read_all(backend: &OpfsBackend) -> Vec<u8> {
+    let mut out = vec![0; backend.len().expect("len") as usize];
+    backend.read(0, &mut out).expect("read");
+    out
+}
+
+/// The generation a token names, which follows the last `.`.
+fn generation(token: &str) -> u64 {
+    let (_, generation) = token.rsplit_once('.').expect("token has a generation");
+    generation.parse().expect("generation is a number")
+}
+
+/// Fails every write.
+struct Failing;
+
+impl Write for Failing {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("out of space"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn adjacent_chunks_are_coalesced() {
+    let path = TempPath::new("incremental");
+    let backend = open(&path);
+    backend.write(0, &[1; 5 * CHUNK as usize]).expect("write");
+    let (full, data) = export(&backend, None);
+    assert!(full.full);
+    assert_eq!(full.ranges, [(0, 5 * CHUNK)]);
+    assert_eq!(data.len() as u64, 5 * CHUNK);
+
+    // chunks 1 and 2, which are adjacent, and chunk 4
+    backend.write(CHUNK + 10, &[2; 10]).expect("write");
+    backend.write(2 * CHUNK + 10, &[3; 10]).expect("write");
+    backend.write(4 * CHUNK, &[4; 10]).expect("write");
+    let (manifest, data) = export(&backend, Some(&full.token));
+    assert!(!manifest.full);
+    assert_eq!(manifest.ranges, [(CHUNK, 2 * CHUNK), (4 * CHUNK, CHUNK)]);
+    assert_eq!(data.len() as u64, 3 * CHUNK);
+
+    let (manifest, data) = export(&backend, Some(&manifest.token));
+    assert!(manifest.ranges.is_empty());
+    assert!(data.is_empty());
+}
+
+#[test]
+fn foreign_future_and_malformed_tokens_are_rejected() {
+    let path = TempPath::new("incremental");
+    let backend = open(&path);
+    backend.write(0, b"data").expect("write");
+    let (full, _) = export(&backend, None);
+
+    let other_path = TempPath::new("incremental");
+    let other = open(&other_path);
+    let (foreign, _) = export(&other, None);
+
+    let (session, _) = full.token.rsplit_once('.').expect("token has a session");
+    let future = format!("{session}.{}", generation(&full.token) + 1);
+    let not_a_number = format!("{session}.next");
+    for token in [
+        foreign.token.as_str(),
+        &future,
+        &not_a_number,
+        "garbage",
+        "",
+    ] {
+        let err = backend
+            .export_incremental(Some(token), &mut Vec::new())
+            .expect_err(token);
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{token}: {err}");
+    }
+    export(&backend, Some(&full.token));
+}
+
+#[test]
+fn failed_export_does_not_advance() {
+    let path = TempPath::new("incremental");
+    let backend = open(&path);
+    backend.write(0, &[1; CHUNK as usize]).expect("write");
+    let (full, _) = export(&backend, None);
+
+    backend.write(10, b"changed").expect("write");
+    backend
+        .export_incremental(Some(&full.token), &mut Failing)
+        .expect_err("writing the export fails");
+    let (manifest, _) = export(&backend, Some(&full.token));
+    assert_eq!(manifest.ranges, [(0, CHUNK)]);
+    assert_eq!(generation(&manifest.token), generation(&full.token) + 1);
+}
+
+#[test]
+fn applying_exports_follows_shrinking_and_growing() {
+    let path = TempPath::new("incremental");
+    let replica = TempPath::new("incremental-replica");
+    let backend = open(&path);
+    let apply = |manifest: &IncrementalManifest, data: &[u8]| {
+        block_on(OpfsBackend::apply_incremental(
+            replica.as_str(),
+            manifest,
+            &mut &data[..],
+        ))
+        .expect("apply");
+        assert_eq!(
+            std::fs::read(replica.as_str()).expect("read replica"),
+            read_all(&backend)
+        );
+    };
+
+    let content: Vec<u8> = (0..4 * CHUNK).map(|i| (i % 251) as u8).collect();
+    backend.write(0, &content).expect("write");
+    let (manifest, data) = export(&backend, None);
+    apply(&manifest, &data);
+
+    // into the middle of a chunk
+    backend.set_len(CHUNK + 100).expect("shrink");
+    let (manifest, data) = export(&backend, Some(&manifest.token));
+    assert_eq!(manifest.len, CHUNK + 100);
+    apply(&manifest, &data);
+
+    // the part grown into is exported too, as zeros
+    backend.set_len(3 * CHUNK).expect("grow");
+    backend.write(3 * CHUNK - 10, b"tail").expect("write");
+    let (manifest, data) = export(&backend, Some(&manifest.token));
+    assert_eq!(manifest.ranges, [(CHUNK, 2 * CHUNK)]);
+    apply(&manifest, &data);
+}
+
+#[test]
+fn untracked_backend_cannot_export_incrementally() {
+    let path = TempPath::new("incremental");
+    let backend =
+        block_on(OpfsBackend::builder().create(true).open(path.as_str())).expect("open backend");
+    let err = backend
+        .export_incremental(None, &mut Vec::new())
+        .expect_err("changes are not tracked");
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+}
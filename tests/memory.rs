@@ -0,0 +1,72 @@
+//! `MemoryBackend`, checked to behave like a file and to back a redb database, as `OpfsBackend` does.
+
+#![cfg(not(target_family = "wasm"))]
+
+use std::io::ErrorKind;
+
+use redb_opfs::{MemoryBackend, OpfsBackend, StorageBackend};
+
+#[test]
+fn reads_and_writes() {
+    let backend = MemoryBackend::new();
+    backend.write(4, b"data").expect("write past the end");
+    assert_eq!(backend.len().expect("len"), 8);
+    let mut out = [0xff; 8];
+    backend.read(0, &mut out).expect("read");
+    assert_eq!(&out, b"\0\0\0\0data");
+
+    let err = backend.read(6, &mut out).expect_err("read past the end");
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert!(backend.write(u64::MAX, b"x").is_err());
+}
+
+#[test]
+fn truncated_data_does_not_reappear() {
+    let backend = MemoryBackend::new();
+    backend.write(0, &[1; 100]).expect("write");
+    backend.set_len(10).expect("shrink");
+    backend.set_len(100).expect("extend");
+    let mut out = [0xff; 100];
+    backend.read(0, &mut out).expect("read");
+    assert_eq!(out[..10], [1; 10]);
+    assert_eq!(out[10..], [0; 90]);
+}
+
+#[cfg(feature = "redb3")]
+#[test]
+fn builder_opens_in_memory() {
+    use redb::{Database, ReadableDatabase as _, TableDefinition};
+
+    const TABLE: TableDefinition<&str, u64> = TableDefinition::new("data");
+
+    let backend = OpfsBackend::builder()
+        .cache_pages(16)
+        .in_memory()
+        .expect("open in memory");
+    let database = Database::builder()
+        .create_with_backend(backend)
+        .expect("create database");
+
+    let tx = database.begin_write().expect("begin write");
+    tx.open_table(TABLE)
+        .expect("open table")
+        .insert("key", 1)
+        .expect("insert");
+    tx.commit().expect("commit");
+
+    let tx = database.begin_read().expect("begin read");
+    let table = tx.open_table(TABLE).expect("open table");
+    assert_eq!(
+        table.get("key").expect("get").map(|value| value.value()),
+        Some(1)
+    );
+}
+
+#[test]
+fn builder_rejects_read_only_in_memory() {
+    let err = OpfsBackend::builder()
+        .read_only(true)
+        .in_memory()
+        .expect_err("read-only in memory");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,136 @@
+//! Random sequences of operations against the native `OpfsBackend`, checked byte for byte against a reference model.
+//!
+//! Cursor and length bugs tend to hide in odd interleavings of reads, writes and truncations, particularly with the
+//! page cache and write buffer enabled, so every configuration is exercised.
+
+#![cfg(not(target_family = "wasm"))]
+
+mod common;
+
+use std::io::ErrorKind;
+
+use common::{TempPath, block_on};
+use proptest::prelude::*;
+use redb_opfs::{OpfsBackend, StorageBackend};
+
+/// Large enough for operations to span several 4 KiB pages.
+const MAX_OFFSET: u64 = 5 * 4096;
+const MAX_LEN: usize = 3 * 4096;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Len,
+    Read { offset: u64, len: usize },
+    Write { offset: u64, data: Vec<u8> },
+    SetLen(u64),
+    SyncData,
+}
+
+/// Zero-length operations are left out: past the end of the file, a file and a buffer in memory legitimately
+/// disagree about them.
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        1 => Just(Op::Len),
+        4 => (0..MAX_OFFSET, 1..MAX_LEN).prop_map(|(offset, len)| Op::Read { offset, len }),
+        4 => (0..MAX_OFFSET, any::<u8>(), 1..MAX_LEN).prop_map(|(offset, seed, len)| {
+            // distinct neighbouring bytes, so misplaced data is noticed
+            let data = (0..len).map(|i| (i as u8).wrapping_add(seed)).collect();
+            Op::Write { offset, data }
+        }),
+        1 => (0..MAX_OFFSET).prop_map(Op::SetLen),
+        1 => Just(Op::SyncData),
+    ]
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    cache_pages: u32,
+    read_ahead: u32,
+    write_buffer: u32,
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (
+        prop_oneof![Just(0), Just(1), Just(8)],
+        prop_oneof![Just(0), Just(1), Just(4)],
+        prop_oneof![Just(0), Just(1), Just(8192)],
+    )
+        .prop_map(|(cache_pages, read_ahead, write_buffer)| Config {
+            cache_pages,
+            read_ahead,
+            write_buffer,
+        })
+}
+
+/// The obvious implementation of a file.
+#[derive(Debug, Default)]
+struct Model(Vec<u8>);
+
+impl Model {
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, ErrorKind> {
+        let start = offset as usize;
+        self.0
+            .get(start..start + len)
+            .map(<[u8]>::to_vec)
+            .ok_or(ErrorKind::UnexpectedEof)
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        let (start, end) = (offset as usize, offset as usize + data.len());
+        if self.0.len() < end {
+            self.0.resize(end, 0);
+        }
+        self.0[start..end].copy_from_slice(data);
+    }
+}
+
+async fn open(path: &TempPath, config: Config) -> OpfsBackend {
+    OpfsBackend::builder()
+        .create(true)
+        .cache_pages(config.cache_pages)
+        .read_ahead(config.read_ahead)
+        .write_buffer(config.write_buffer)
+        .open(path.as_str())
+        .await
+        .expect("open backend")
+}
+
+proptest! {
+    #[test]
+    fn matches_model(config in config(), ops in prop::collection::vec(op(), 1..64)) {
+        let path = TempPath::new("model");
+        let backend = block_on(open(&path, config));
+        let mut model = Model::default();
+
+        for (step, op) in ops.iter().enumerate() {
+            match op {
+                Op::Len => {
+                    prop_assert_eq!(backend.len().unwrap(), model.0.len() as u64, "step {}: {:?}", step, op);
+                }
+                Op::Read { offset, len } => {
+                    let mut out = vec![0; *len];
+                    let actual = backend.read(*offset, &mut out).map(|()| out).map_err(|err| err.kind());
+                    prop_assert_eq!(actual, model.read(*offset, *len), "step {}: {:?}", step, op);
+                }
+                Op::Write { offset, data } => {
+                    backend.write(*offset, data).unwrap();
+                    model.write(*offset, data);
+                }
+                Op::SetLen(len) => {
+                    backend.set_len(*len).unwrap();
+                    model.0.resize(*len as usize, 0);
+                }
+                Op::SyncData => backend.sync_data().unwrap(),
+            }
+        }
+
+        // everything must have reached the file once it is closed
+        backend.close().unwrap();
+        drop(backend);
+        let reopened = block_on(open(&path, Config { cache_pages: 0, read_ahead: 0, write_buffer: 0 }));
+        prop_assert_eq!(reopened.len().unwrap(), model.0.len() as u64);
+        let mut content = vec![0; model.0.len()];
+        reopened.read(0, &mut content).unwrap();
+        prop_assert!(content == model.0, "content differs after reopening");
+    }
+}
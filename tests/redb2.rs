@@ -0,0 +1,39 @@
+//! A `redb2::Database`, checked to open, commit and reopen through `Redb2Backend`.
+
+#![cfg(all(not(target_family = "wasm"), feature = "redb2"))]
+
+mod common;
+
+use common::{TempPath, block_on};
+use redb_opfs::{OpfsBackend, Redb2Backend};
+use redb2::{Database, ReadableTable as _, TableDefinition};
+
+const TABLE: TableDefinition<&str, u64> = TableDefinition::new("data");
+
+fn open(path: &TempPath) -> Database {
+    let backend = block_on(OpfsBackend::new(path.as_str())).expect("open backend");
+    Database::builder()
+        .create_with_backend(Redb2Backend::new(backend))
+        .expect("open database")
+}
+
+#[test]
+fn database_round_trips() {
+    let path = TempPath::new("redb2");
+    let database = open(&path);
+    let tx = database.begin_write().expect("begin write");
+    tx.open_table(TABLE)
+        .expect("open table")
+        .insert("key", 1)
+        .expect("insert");
+    tx.commit().expect("commit");
+    drop(database);
+
+    let database = open(&path);
+    let tx = database.begin_read().expect("begin read");
+    let table = tx.open_table(TABLE).expect("open table");
+    assert_eq!(
+        table.get("key").expect("get").map(|value| value.value()),
+        Some(1)
+    );
+}
@@ -0,0 +1,68 @@
+//! Opening a file which another backend in this process has open, checked to fail with `AlreadyOpen` unless both
+//! backends are read-only, until the file is released.
+
+#![cfg(not(target_family = "wasm"))]
+
+mod common;
+
+use std::io;
+
+use common::{TempPath, block_on};
+use redb_opfs::{AlreadyOpen, OpfsBackend};
+
+fn open(path: &TempPath, read_only: bool) -> io::Result<OpfsBackend> {
+    block_on(
+        OpfsBackend::builder()
+            .create(!read_only)
+            .read_only(read_only)
+            .open(path.as_str()),
+    )
+}
+
+fn assert_already_open(result: io::Result<OpfsBackend>) {
+    let err = result.expect_err("opened a file which is already open");
+    let already_open = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<AlreadyOpen>())
+        .unwrap_or_else(|| panic!("not AlreadyOpen: {err}"));
+    assert!(
+        already_open.path.ends_with(".redb"),
+        "{}",
+        already_open.path
+    );
+    assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+}
+
+#[test]
+fn writable_backend_excludes_others() {
+    let path = TempPath::new("registry");
+    let backend = open(&path, false).expect("open");
+    assert_already_open(open(&path, false));
+    assert_already_open(open(&path, true));
+    drop(backend);
+}
+
+#[test]
+fn readers_share_a_file() {
+    let path = TempPath::new("registry");
+    drop(open(&path, false).expect("create"));
+    let first = open(&path, true).expect("open first reader");
+    let second = open(&path, true).expect("open second reader");
+    assert_already_open(open(&path, false));
+
+    // the file stays registered until the last reader is gone
+    drop(first);
+    assert_already_open(open(&path, false));
+    drop(second);
+    open(&path, false).expect("open after the readers are gone");
+}
+
+#[test]
+fn closing_or_dropping_releases_the_file() {
+    let path = TempPath::new("registry");
+    let backend = open(&path, false).expect("open");
+    backend.close().expect("close");
+    let backend = open(&path, false).expect("open after closing");
+    drop(backend);
+    open(&path, false).expect("open after dropping");
+}
@@ -0,0 +1,7 @@
+// Worker script for `WorkerHandle`: serves an `OpfsBackend` to the main thread.
+//
+// Copy this next to the bindings generated by wasm-pack, and spawn it as a module worker.
+import init, { serveWorker } from "./redb-opfs.js";
+
+await init();
+serveWorker();